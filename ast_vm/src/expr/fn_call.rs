@@ -1,5 +1,5 @@
 use crate::{
-    lang::{Context, LocalScope, LuaValue, ReturnValue, ScopeHolder, TableRef}, opt::call_function, EvalError, TypeError
+    lang::{Context, LocalScope, LuaValue, ReturnValue, ScopeHolder, TableRef}, opt::call_function, tail_values, EvalError, TypeError
 };
 use luar_syn::{FunctionCall, FunctionCallArgs};
 
@@ -38,10 +38,10 @@ fn eval_fn_args(
 ) -> Result<Vec<LuaValue>, EvalError> {
     match args {
         FunctionCallArgs::Arglist(exprs) => exprs
-            .into_iter()
+            .iter()
             .map(|expr| eval_expr(expr, scope))
-            .map(|arg| arg.map(ReturnValue::first_value))
-            .collect(),
+            .collect::<Result<Vec<ReturnValue>, EvalError>>()
+            .map(|args| tail_values(args).collect()),
         FunctionCallArgs::Table(table) => eval_tbl_constructor(table, scope)
             .map(TableRef::from)
             .map(LuaValue::Table)
@@ -126,4 +126,21 @@ mod test {
         assert!(ret_values.total_eq(&res));
         Ok(())
     }
+
+    #[test]
+    fn functions_and_modules_without_return_values_return_nothing() -> Result<(), LuaError> {
+        let mut context = Context::new();
+        let module = lua_parser::module(
+            "function noop() end
+            function bare() return end",
+        )?;
+        let res = ast_vm::eval_module(&module, &mut context)?;
+        assert_eq!(res, ReturnValue::EMPTY);
+        for function in ["noop", "bare"] {
+            let module = lua_parser::module(&format!("return {function}()"))?;
+            let res = ast_vm::eval_module(&module, &mut context)?;
+            assert_eq!(res, ReturnValue::EMPTY);
+        }
+        Ok(())
+    }
 }
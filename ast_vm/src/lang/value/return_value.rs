@@ -18,8 +18,12 @@ impl From<LuaValue> for ReturnValue {
 impl ReturnValue {
     pub const NIL: ReturnValue = ReturnValue(smallvec_inline![LuaValue::Nil]);
 
+    /// Returned by `return` without values, and by functions and modules without `return`
+    pub const EMPTY: ReturnValue = ReturnValue(SmallVec::new_const());
+
+    /// Empty return is `nil` where a single value is expected
     pub fn first_value(self) -> LuaValue {
-        self.0.into_iter().next().expect("ReturnValue should have at least one value (even if it is nil)")
+        self.0.into_iter().next().unwrap_or(LuaValue::Nil)
     }
 
     pub fn assert_single(self) -> LuaValue {
@@ -1,4 +1,9 @@
-use std::{cell::RefCell, collections::HashMap, hash::Hash, rc::Rc};
+use std::{
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    hash::Hash,
+    rc::Rc,
+};
 
 use super::{LuaKey, LuaValue};
 
@@ -25,6 +30,10 @@ impl TableRef {
         self.0.borrow().get(key).clone()
     }
 
+    pub fn borrow(&self) -> Ref<'_, TableValue> {
+        self.0.borrow()
+    }
+
     pub fn set(&mut self, key: LuaKey, value: LuaValue) {
         self.0.borrow_mut().set(key, value)
     }
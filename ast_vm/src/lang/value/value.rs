@@ -148,7 +148,7 @@ impl LuaValue {
     pub fn as_number(&self) -> Option<LuaNumber> {
         match self {
             LuaValue::Number(num) => Some(*num),
-            LuaValue::String(str) => str.parse().ok(),
+            LuaValue::String(str) => str.trim_ascii().parse().ok(),
             _ => None,
         }
     }
@@ -11,7 +11,7 @@ pub fn eval_module(module: &Module, context: &mut Context) -> Result<ReturnValue
     }
     match module.ret {
         Some(ref ret) => eval_ret(ret, &mut scope),
-        None => Ok(ReturnValue::NIL),
+        None => Ok(ReturnValue::EMPTY),
     }
 }
 
@@ -88,7 +88,7 @@ pub fn eval_module(module: &Module, context: &mut Context) -> Result<ReturnValue
 
     match module.ret {
         Some(ref ret) => eval_ret(ret, ctx),
-        None => Ok(ReturnValue::NIL),
+        None => Ok(ReturnValue::EMPTY),
     }
 }
 
@@ -113,7 +113,7 @@ pub fn call_function(
 
     match call_block(body, &mut context)? {
         ControlFlow::Return(value) => Ok(value),
-        ControlFlow::Continue => Ok(ReturnValue::NIL),
+        ControlFlow::Continue => Ok(ReturnValue::EMPTY),
     }
 }
 
@@ -246,7 +246,7 @@ fn multiple_assignment<'a>(
 
 fn eval_ret(ret: &Return, ctx: &mut EvalContext) -> Result<ReturnValue> {
     match &ret.0[..] {
-        [] => Ok(ReturnValue::NIL),
+        [] => Ok(ReturnValue::EMPTY),
         // Common case optimization
         [expr] => eval_expr(expr, ctx),
         exprs => {
@@ -345,10 +345,10 @@ fn eval_fn_call(call: &syn::FunctionCall, ctx: &mut EvalContext<'_>) -> Result<R
 fn eval_fn_args(args: &FunctionCallArgs, ctx: &mut EvalContext) -> Result<Vec<LuaValue>> {
     match args {
         FunctionCallArgs::Arglist(exprs) => exprs
-            .into_iter()
+            .iter()
             .map(|expr| eval_expr(expr, ctx))
-            .map(|arg| arg.map(ReturnValue::first_value))
-            .collect(),
+            .collect::<Result<Vec<ReturnValue>>>()
+            .map(|args| tail_values(args).collect()),
         FunctionCallArgs::Table(table) => eval_tbl_constructor(table, ctx)
             .map(TableRef::from)
             .map(LuaValue::Table)
@@ -11,7 +11,7 @@ pub(crate) fn eval_ret(
     scope: &mut LocalScope<impl ScopeHolder>,
 ) -> Result<ReturnValue, EvalError> {
     match &ret.0[..] {
-        [] => Ok(ReturnValue::NIL),
+        [] => Ok(ReturnValue::EMPTY),
         // Common case optimization
         [expr] => eval_expr(expr, scope),
         exprs => {
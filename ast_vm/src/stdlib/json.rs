@@ -0,0 +1,206 @@
+use serde_json::{Map, Number, Value};
+
+use luar_error::ExpectedType;
+
+use crate::{
+    lang::{LuaKey, LuaValue, TableRef, TableValue},
+    EvalError, TypeError,
+};
+
+/// Tables, which keys are exactly the integers from 1 up, become arrays, and all the other tables
+/// become objects. Numbers without a fractional part are encoded as integers, NaNs and infinities
+/// become nulls, as json has no way to represent them.
+pub fn json_encode(args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let value = args.first().unwrap_or(&LuaValue::Nil);
+    let json = to_json(value, &mut Vec::new()).map_err(EvalError::JsonEncode)?;
+    Ok(LuaValue::string(json.to_string()))
+}
+
+/// Arrays become tables with keys from 1 up, and objects become tables with string keys. There
+/// are no booleans in lua, so `true` becomes 1 and `false` becomes nil.
+pub fn json_decode(args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let arg = args.first().unwrap_or(&LuaValue::Nil);
+    let str = arg
+        .coerce_to_string()
+        .ok_or_else(|| TypeError::ArgumentType {
+            position: 0,
+            expected: ExpectedType::String,
+            got: arg.clone(),
+        })?;
+    let json: Value = serde_json::from_str(&str).map_err(|err| {
+        let (line, column) = (err.line(), err.column());
+        // Position is reported in the fields, so it is left out of the message
+        let message = err.to_string();
+        let message = message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .unwrap_or(&message);
+        EvalError::JsonDecode {
+            message: message.to_owned(),
+            line,
+            column,
+        }
+    })?;
+    Ok(from_json(json))
+}
+
+fn to_json(value: &LuaValue, visiting: &mut Vec<*const TableValue>) -> Result<Value, String> {
+    match value {
+        LuaValue::Nil => Ok(Value::Null),
+        LuaValue::Number(num) => Ok(number_to_json(num.as_f64())),
+        LuaValue::String(str) => Ok(Value::String(str.to_string())),
+        LuaValue::Function(_) | LuaValue::NativeFunction(_) => {
+            Err("functions can't be encoded".to_owned())
+        }
+        LuaValue::Table(table) => {
+            if visiting.contains(&table.addr()) {
+                return Err("recursive tables can't be encoded".to_owned());
+            }
+            visiting.push(table.addr());
+            let res = table_to_json(table, visiting);
+            visiting.pop();
+            res
+        }
+    }
+}
+
+fn number_to_json(num: f64) -> Value {
+    if num.fract() == 0.0 && num >= i64::MIN as f64 && num < i64::MAX as f64 {
+        Value::Number(Number::from(num as i64))
+    } else {
+        Value::from(num)
+    }
+}
+
+fn table_to_json(table: &TableRef, visiting: &mut Vec<*const TableValue>) -> Result<Value, String> {
+    let table = table.borrow();
+    let entries: Vec<_> = table
+        .keys()
+        .filter(|key| !table.get(key).is_nil())
+        .collect();
+    let len = entries.len();
+    let is_array = entries.iter().all(|key| match key {
+        LuaKey::Number(num) => {
+            let num = num.as_f64();
+            num.fract() == 0.0 && num >= 1.0 && num <= len as f64
+        }
+        _ => false,
+    });
+
+    if is_array {
+        (1..=len)
+            .map(|index| to_json(table.get(&LuaKey::number(index)), visiting))
+            .collect::<Result<_, _>>()
+            .map(Value::Array)
+    } else {
+        let mut object = Map::new();
+        for key in entries {
+            let name = match key {
+                LuaKey::Number(num) => num.to_string(),
+                LuaKey::String(str) => str.to_string(),
+                _ => return Err("only numbers and strings can be encoded as keys".to_owned()),
+            };
+            object.insert(name, to_json(table.get(key), visiting)?);
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+fn from_json(json: Value) -> LuaValue {
+    match json {
+        Value::Null => LuaValue::Nil,
+        Value::Bool(bool) => LuaValue::from_bool(bool),
+        Value::Number(num) => LuaValue::number(num.as_f64().unwrap_or(f64::NAN)),
+        Value::String(str) => LuaValue::string(str),
+        Value::Array(array) => {
+            let mut table = TableValue::new();
+            for (index, element) in array.into_iter().enumerate() {
+                let element = from_json(element);
+                if !element.is_nil() {
+                    table.set(LuaKey::number(index + 1), element);
+                }
+            }
+            LuaValue::table(table)
+        }
+        Value::Object(object) => {
+            let mut table = TableValue::new();
+            for (key, value) in object {
+                let value = from_json(value);
+                if !value.is_nil() {
+                    table.set(LuaKey::string(key), value);
+                }
+            }
+            LuaValue::table(table)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{json_decode, json_encode};
+    use crate::{
+        lang::{LuaKey, LuaValue, TableRef, TableValue},
+        EvalError,
+    };
+
+    fn encode(value: LuaValue) -> String {
+        let encoded = json_encode(&[value]).unwrap();
+        encoded.coerce_to_string().unwrap().to_string()
+    }
+
+    #[test]
+    fn numbers_keep_their_fractional_part() {
+        assert_eq!(encode(LuaValue::number(42)), "42");
+        assert_eq!(encode(LuaValue::number(-1.5)), "-1.5");
+        assert_eq!(encode(LuaValue::number(f64::NAN)), "null");
+    }
+
+    #[test]
+    fn sequences_become_arrays_and_other_tables_objects() {
+        let mut array = TableValue::new();
+        array.set(LuaKey::number(1), LuaValue::string("a"));
+        array.set(LuaKey::number(2), LuaValue::number(2));
+        assert_eq!(encode(LuaValue::table(array.clone())), r#"["a",2]"#);
+        assert_eq!(encode(LuaValue::table(TableValue::new())), "[]");
+
+        array.set(LuaKey::string("n"), LuaValue::number(2));
+        array.set(LuaKey::number(2), LuaValue::Nil);
+        assert_eq!(encode(LuaValue::table(array)), r#"{"1":"a","n":2}"#);
+    }
+
+    #[test]
+    fn recursive_tables_are_errors() {
+        let mut table = TableRef::from(TableValue::new());
+        table.set(LuaKey::string("self"), LuaValue::Table(table.clone()));
+        assert!(matches!(
+            json_encode(&[LuaValue::Table(table)]),
+            Err(EvalError::JsonEncode(_))
+        ));
+    }
+
+    #[test]
+    fn decoded_arrays_are_indexed_from_one() {
+        let decoded = json_decode(&[LuaValue::string(r#"{"items": [true, false, 1.5]}"#)]).unwrap();
+        let LuaValue::Table(table) = decoded else {
+            panic!("expected a table, got {:?}", decoded);
+        };
+        let LuaValue::Table(items) = table.get(&LuaKey::string("items")) else {
+            panic!("expected items to be a table");
+        };
+        assert_eq!(items.get(&LuaKey::number(1)), LuaValue::number(1));
+        assert_eq!(items.get(&LuaKey::number(2)), LuaValue::Nil);
+        assert_eq!(items.get(&LuaKey::number(3)), LuaValue::number(1.5));
+    }
+
+    #[test]
+    fn invalid_json_is_reported_with_its_position() {
+        let res = json_decode(&[LuaValue::string("[1,\n 2,,]")]);
+        assert!(matches!(
+            res,
+            Err(EvalError::JsonDecode {
+                line: 2,
+                column: 4,
+                ..
+            })
+        ));
+    }
+}
@@ -4,6 +4,7 @@ use crate::{
 };
 
 pub mod fns;
+pub mod json;
 
 pub fn std_context() -> Context {
     let mut ctx = Context::new();
@@ -20,6 +21,8 @@ pub(crate) fn define_std_lib(ctx: &mut Context) {
     define_fn(ctx, "strlen", fns::strlen);
     define_fn(ctx, "strsub", fns::strsub);
     define_total_fn(ctx, "type", fns::lua_type);
+    define_fn(ctx, "json_encode", json::json_encode);
+    define_fn(ctx, "json_decode", json::json_decode);
 }
 
 fn define_fn(
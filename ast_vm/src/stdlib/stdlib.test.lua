@@ -21,3 +21,12 @@ print(1, nil, "hello", foo, foo, nil, "bye", 42.32)
 -- I could test "randomness" sorta speak, by calculating 
 -- that entropy is sufficient, but yeah... Not today
 
+
+-- json tests
+assert(json_encode({ 1, 2.5, "x" }) == "[1,2.5,\"x\"]")
+assert(json_encode(nil) == "null")
+local decoded = json_decode("{\"list\": [1, 2], \"name\": \"lua\"}")
+assert(decoded.name == "lua")
+assert(decoded.list[2] == 2)
+local round_trip = json_decode(json_encode({ a = { b = 3 } }))
+assert(round_trip.a.b == 3)
@@ -8,6 +8,12 @@ pub enum EvalError<Value, Str> {
     AssertionError(Option<Str>),
     IO(std::io::Error),
     Utf8Error,
+    JsonEncode(String),
+    JsonDecode {
+        message: String,
+        line: usize,
+        column: usize,
+    },
 }
 
 impl<Value: fmt::Display, Str: fmt::Display> fmt::Display for EvalError<Value, Str> {
@@ -18,6 +24,16 @@ impl<Value: fmt::Display, Str: fmt::Display> fmt::Display for EvalError<Value, S
             Self::AssertionError(None) => write!(f, "Assertion failed"),
             Self::IO(err) => write!(f, "IO Error: {}", err),
             Self::Utf8Error => write!(f, "Operation produced invalid utf-8 sequence"),
+            Self::JsonEncode(msg) => write!(f, "Cannot encode value as json: {}", msg),
+            Self::JsonDecode {
+                message,
+                line,
+                column,
+            } => write!(
+                f,
+                "Invalid json at line {}, column {}: {}",
+                line, column, message
+            ),
         }
     }
 }
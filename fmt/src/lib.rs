@@ -0,0 +1,147 @@
+use luar_lex::{format::TokenFormatter, ToTokenStream, Token};
+use luar_syn::{lua_parser, ParseErrorWithSourcePosition};
+
+pub use luar_lex::format::{FormatOptions, Indentation};
+
+mod trivia;
+pub use trivia::*;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Source contains {} syntax error(s)", .0.len())]
+pub struct FormatError(pub Vec<ParseErrorWithSourcePosition>);
+
+/// Reformats lua source into the canonical style. Layout is decided by the [`TokenFormatter`],
+/// based on tokens of the parsed module, while comments and blank lines are carried over from
+/// the original source.
+pub fn format_source(source: &str, options: FormatOptions) -> Result<String, FormatError> {
+    let recovered = lua_parser::module_recovering(source);
+    if !recovered.errors.is_empty() {
+        return Err(FormatError(recovered.errors));
+    }
+    let formatted: Vec<Token> = recovered.module.into_module().to_tokens().collect();
+    let SourceTokens { tokens, trivia } = lex_with_trivia(source);
+
+    let mut trivia_before = vec![Vec::new(); formatted.len() + 1];
+    for (anchor, trivia) in align(&tokens, &formatted).into_iter().zip(trivia) {
+        trivia_before[anchor].extend(trivia);
+    }
+
+    let mut output = String::new();
+    let mut formatter = TokenFormatter::new(&mut output, options);
+    for (token, trivia) in formatted.into_iter().zip(&trivia_before) {
+        write_trivia(&mut formatter, trivia);
+        formatter
+            .token(token)
+            .expect("Writing to string should not fail");
+    }
+    write_trivia(&mut formatter, trivia_before.last().unwrap());
+    formatter
+        .finish()
+        .expect("Writing to string should not fail");
+    Ok(output)
+}
+
+fn write_trivia(formatter: &mut TokenFormatter, trivia: &[Trivia]) {
+    for trivia in trivia {
+        match trivia {
+            Trivia::BlankLine => formatter.blank_line(),
+            Trivia::Comment { text, trailing } => formatter
+                .comment(text, *trailing)
+                .expect("Writing to string should not fail"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+
+    use super::{format_source, FormatOptions, Indentation};
+
+    const OPTIONS: FormatOptions = FormatOptions {
+        indentation: Indentation::Spaces(4),
+        max_width: Some(100),
+    };
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format_source(source, OPTIONS).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format_source(&formatted, OPTIONS).unwrap(),
+            formatted,
+            "Formatting should be idempotent"
+        );
+    }
+
+    #[test]
+    fn formats_empty_source() {
+        assert_formats("", "");
+    }
+
+    #[test]
+    fn formats_module() {
+        assert_formats(
+            indoc! {"
+                function   fib(n) if n<2 then return n end
+                return fib(n-1)+fib((n-2)) end
+                local a,b=1,2;   print(a..b)
+                while a<10 do a=a+(b*2) end
+            "},
+            indoc! {"
+                function fib(n)
+                    if n < 2 then
+                        return n
+                    end
+                    return fib(n - 1) + fib(n - 2)
+                end
+                local a, b = 1, 2
+                print(a .. b)
+                while a < 10 do
+                    a = a + b * 2
+                end
+            "},
+        );
+    }
+
+    #[test]
+    fn keeps_necessary_parens() {
+        assert_formats(
+            "x = (a + b) * -(c ^ d) ^ (e ^ f) == (not g)\n",
+            "x = (a + b) * -(c^d)^e^f == not g\n",
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        assert_formats(
+            indoc! {"
+                -- Module header
+
+
+                local a = 1 -- the answer is too long
+                function f(x)
+                  -- do nothing
+
+
+
+                end
+                -- trailing comment"},
+            indoc! {"
+                -- Module header
+
+                local a = 1 -- the answer is too long
+                function f(x)
+                    -- do nothing
+
+                end
+                -- trailing comment
+            "},
+        );
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let error = format_source("a = = 1\nlocal b = 2\nc = )", OPTIONS).unwrap_err();
+        assert_eq!(error.0.len(), 2);
+    }
+}
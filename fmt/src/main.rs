@@ -0,0 +1,159 @@
+use luar_fmt::{format_source, FormatError, FormatOptions, Indentation};
+use std::{error::Error, process::ExitCode};
+
+const USAGE: &str = "\
+Usage: luarfmt [OPTIONS] [FILES...]
+
+Formats lua files in place. Without files, reads source from stdin and writes it to stdout.
+
+Options:
+    --check            Don't write anything, exit with code 1 if some of the files are not formatted
+    --indent <tabs|N>  Indent with tabs or with N spaces (default: 4)
+    --width <N>        Wrap lines longer than N characters, 0 disables wrapping (default: 100)
+    --help             Print this message";
+
+struct Args {
+    check: bool,
+    options: FormatOptions,
+    files: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        check: false,
+        options: FormatOptions {
+            indentation: Indentation::Spaces(4),
+            max_width: Some(100),
+        },
+        files: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => parsed.check = true,
+            "--indent" => {
+                parsed.options.indentation = match args.next().as_deref() {
+                    Some("tabs") => Indentation::Tabs,
+                    Some(count) => Indentation::Spaces(
+                        count
+                            .parse()
+                            .map_err(|_| format!("Invalid indentation \"{}\"", count))?,
+                    ),
+                    None => return Err("Missing value for --indent".to_string()),
+                }
+            }
+            "--width" => {
+                let width = args.next().ok_or("Missing value for --width")?;
+                parsed.options.max_width = match width.parse() {
+                    Ok(0) => None,
+                    Ok(width) => Some(width),
+                    Err(_) => return Err(format!("Invalid width \"{}\"", width)),
+                }
+            }
+            "--help" | "-h" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            _ => parsed.files.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn report_syntax_errors(filename: &str, FormatError(errors): &FormatError) {
+    for error in errors {
+        match error.start() {
+            Some(position) => eprintln!(
+                "{}:{}:{}: syntax error, expected {}",
+                filename,
+                position.row + 1,
+                position.col + 1,
+                error.expected()
+            ),
+            None => eprintln!(
+                "{}: syntax error at the end of file, expected {}",
+                filename,
+                error.expected()
+            ),
+        }
+    }
+}
+
+enum Outcome {
+    Unchanged,
+    Changed,
+}
+
+fn format_file(filename: &str, args: &Args) -> Result<Outcome, Box<dyn Error>> {
+    let source = std::fs::read_to_string(filename)?;
+    let formatted = format_source(&source, args.options).inspect_err(|error| {
+        report_syntax_errors(filename, error);
+    })?;
+    if formatted == source {
+        return Ok(Outcome::Unchanged);
+    }
+    if args.check {
+        println!("{} is not formatted", filename);
+    } else {
+        std::fs::write(filename, formatted)?;
+    }
+    Ok(Outcome::Changed)
+}
+
+fn format_stdin(args: &Args) -> Result<Outcome, Box<dyn Error>> {
+    use std::io::Read;
+
+    let mut source = String::new();
+    std::io::stdin().lock().read_to_string(&mut source)?;
+    let formatted = format_source(&source, args.options).inspect_err(|error| {
+        report_syntax_errors("<stdin>", error);
+    })?;
+    if !args.check {
+        print!("{}", formatted);
+    }
+    Ok(if formatted == source {
+        Outcome::Unchanged
+    } else {
+        Outcome::Changed
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let results = if args.files.is_empty() {
+        vec![("<stdin>", format_stdin(&args))]
+    } else {
+        args.files
+            .iter()
+            .map(|filename| (filename.as_str(), format_file(filename, &args)))
+            .collect()
+    };
+
+    let mut unformatted = false;
+    let mut failed = false;
+    for (filename, result) in results {
+        match result {
+            Ok(Outcome::Unchanged) => {}
+            Ok(Outcome::Changed) => unformatted = true,
+            Err(error) => {
+                eprintln!("{}: {}", filename, error);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::from(2)
+    } else if unformatted && args.check {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
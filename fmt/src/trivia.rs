@@ -0,0 +1,173 @@
+use logos::Logos;
+use luar_lex::Token;
+
+/// Parts of the source that the parser throws away, but which are worth keeping when
+/// the source is reformatted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    /// `trailing` comments are placed on the same line as the token preceding them
+    Comment {
+        text: String,
+        trailing: bool,
+    },
+    BlankLine,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceTokens {
+    pub tokens: Vec<Token>,
+    /// `trivia[i]` precedes `tokens[i]`. There is one more entry than there are tokens,
+    /// which holds whatever comes after the last token.
+    pub trivia: Vec<Vec<Trivia>>,
+}
+
+pub fn lex_with_trivia(source: &str) -> SourceTokens {
+    let mut tokens = Vec::new();
+    let mut trivia = Vec::new();
+    let mut last_token_end = 0;
+    for (token, span) in Token::lexer(source).spanned() {
+        trivia.push(gap_trivia(
+            &source[last_token_end..span.start],
+            !tokens.is_empty(),
+        ));
+        tokens.push(token);
+        last_token_end = span.end;
+    }
+    trivia.push(gap_trivia(&source[last_token_end..], !tokens.is_empty()));
+    SourceTokens { tokens, trivia }
+}
+
+/// Gaps between tokens consist only of whitespace and comments, since everything else is
+/// a token (even an invalid one). And as comments span until the end of the line, it is enough
+/// to look at the gap line by line.
+fn gap_trivia(gap: &str, after_token: bool) -> Vec<Trivia> {
+    let lines: Vec<_> = gap.split('\n').map(str::trim).collect();
+    let last_line = lines.len() - 1;
+    let mut trivia = Vec::new();
+    let mut blank_line = false;
+    for (idx, line) in lines.into_iter().enumerate() {
+        if line.is_empty() {
+            // First line is the rest of the line of the previous token, and the last one is
+            // the indentation of the next token. Neither of them is a blank line.
+            blank_line |= idx != 0 && idx != last_line;
+            continue;
+        }
+        if std::mem::take(&mut blank_line) {
+            trivia.push(Trivia::BlankLine);
+        }
+        trivia.push(Trivia::Comment {
+            text: line.to_string(),
+            trailing: idx == 0 && after_token,
+        });
+    }
+    if blank_line {
+        trivia.push(Trivia::BlankLine);
+    }
+    trivia
+}
+
+/// Matches tokens of the original source with tokens produced by the formatter. Returns, for
+/// each source token, the index of a formatted token that corresponds to it (or the one that
+/// would have been right after it, if the token got dropped). Has an extra entry for the
+/// end of the file.
+///
+/// The formatter outputs the same tokens as the source, except that it drops optional semicolons
+/// and redundant parens, and inserts parens where they are required.
+pub fn align(source: &[Token], formatted: &[Token]) -> Vec<usize> {
+    let mut anchors = Vec::with_capacity(source.len() + 1);
+    let mut pos = 0;
+    for token in source {
+        while pos < formatted.len() && formatted[pos] != *token && is_paren(&formatted[pos]) {
+            pos += 1;
+        }
+        anchors.push(pos);
+        if pos < formatted.len() && formatted[pos] == *token {
+            pos += 1;
+        }
+    }
+    anchors.push(formatted.len());
+    anchors
+}
+
+fn is_paren(token: &Token) -> bool {
+    matches!(token, Token::OpenRoundBracket | Token::CloseRoundBracket)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use luar_lex::Token;
+
+    use super::{align, lex_with_trivia, Trivia};
+
+    fn comment(text: &str, trailing: bool) -> Trivia {
+        Trivia::Comment {
+            text: text.to_string(),
+            trailing,
+        }
+    }
+
+    #[test]
+    fn collects_comments_and_blank_lines() {
+        let source = indoc! {"
+            -- header
+
+            a = 1 -- one
+            -- before b
+
+
+            b = 2
+            -- eof"};
+        let lexed = lex_with_trivia(source);
+        assert_eq!(lexed.tokens.len(), 6);
+        assert_eq!(
+            lexed.trivia,
+            vec![
+                vec![comment("-- header", false), Trivia::BlankLine],
+                vec![],
+                vec![],
+                vec![
+                    comment("-- one", true),
+                    comment("-- before b", false),
+                    Trivia::BlankLine
+                ],
+                vec![],
+                vec![],
+                vec![comment("-- eof", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn aligns_tokens_around_dropped_and_inserted_parens() {
+        use Token::*;
+        let ident = |name: &str| Token::Ident(luar_lex::Ident::new(name));
+        // a = (b); c = d
+        let source = [
+            ident("a"),
+            Assignment,
+            OpenRoundBracket,
+            ident("b"),
+            CloseRoundBracket,
+            Semicolon,
+            ident("c"),
+            Assignment,
+            ident("d"),
+        ];
+        // a = b c = (d)
+        let formatted = [
+            ident("a"),
+            Assignment,
+            ident("b"),
+            ident("c"),
+            Assignment,
+            OpenRoundBracket,
+            ident("d"),
+            CloseRoundBracket,
+        ];
+        assert_eq!(
+            align(&source, &formatted),
+            vec![0, 1, 2, 2, 3, 3, 3, 4, 6, 8]
+        );
+    }
+}
@@ -134,19 +134,215 @@ pub fn format_tokens(
     tokens: &mut impl Iterator<Item = Token>,
     buf: &mut dyn std::fmt::Write,
 ) -> std::fmt::Result {
-    if let Some(first_token) = tokens.next() {
-        let mut indent = 0;
+    let mut formatter = TokenFormatter::new(buf, FormatOptions::default());
+    for token in tokens {
+        formatter.token(token)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Indentation {
+    Tabs,
+    Spaces(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FormatOptions {
+    pub indentation: Indentation,
+    /// Lines longer than this are wrapped at the nearest space. `None` means lines are never wrapped
+    pub max_width: Option<usize>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indentation: Indentation::Tabs,
+            max_width: None,
+        }
+    }
+}
+
+/// Width of a tab character used when calculating line lengths
+const TAB_WIDTH: usize = 4;
+
+/// Stateful version of [`format_tokens`], which in addition to tokens can be fed comments and
+/// blank lines to be preserved in the output.
+pub struct TokenFormatter<'a> {
+    buf: &'a mut dyn std::fmt::Write,
+    options: FormatOptions,
+    indent: i32,
+    /// Formatting requested by the previously written token. `None` if nothing was written yet
+    pending: Option<FormattingStyle>,
+    column: usize,
+    blank_line: bool,
+    /// Depth of round and square brackets since the last `function` keyword, if we're still
+    /// inside of the function header. Closing the argument list opens up the function body.
+    function_header: Option<(u32, u32)>,
+    /// Whether the last token could be the end of an expression
+    after_operand: bool,
+}
+
+impl<'a> TokenFormatter<'a> {
+    pub fn new(buf: &'a mut dyn std::fmt::Write, options: FormatOptions) -> Self {
+        Self {
+            buf,
+            options,
+            indent: 0,
+            pending: None,
+            column: 0,
+            blank_line: false,
+            function_header: None,
+            after_operand: false,
+        }
+    }
+
+    pub fn token(&mut self, token: Token) -> std::fmt::Result {
+        use FormattingStyle::*;
         let Formatting {
-            after: mut current_format,
-            ..
-        } = first_token.formatting();
-        write!(buf, "{}", first_token)?;
+            mut before,
+            mut after,
+        } = token.formatting();
+        if self.closes_function_header(&token) {
+            after = Indent(IndentationChange::Increase);
+        }
+        // There is no operator that is an identifier. So an identifier right after an operand
+        // can only be the start of the next statement
+        if self.after_operand && matches!(token, Token::Ident(_)) {
+            before = Newline;
+        }
+        self.after_operand = matches!(
+            token,
+            Token::Ident(_)
+                | Token::Number(_)
+                | Token::String(_)
+                | Token::Nil
+                | Token::CloseRoundBracket
+                | Token::CloseSquareBracket
+                | Token::CloseSquigglyBracket
+        );
+        let text = token.to_string();
+        if let Some(pending) = self.pending {
+            match formatting_style_precedence(pending, before) {
+                Condensed => {}
+                Space | StrictSpace if self.exceeds_max_width(text.len() + 1) => {
+                    self.indent += 1;
+                    self.newline()?;
+                    self.indent -= 1;
+                }
+                Space | StrictSpace => self.write(" ")?,
+                Newline => self.newline()?,
+                Indent(change) => {
+                    self.indent += change as i32;
+                    self.newline()?;
+                }
+            }
+        }
+        self.blank_line = false;
+        self.pending = Some(after);
+        self.write(&text)
+    }
+
+    /// Writes out a comment. Trailing comments stay on the line of the previous token, while
+    /// the rest get a line of their own.
+    pub fn comment(&mut self, comment: &str, trailing: bool) -> std::fmt::Result {
+        use FormattingStyle::*;
+        match self.pending {
+            Some(pending) if trailing => {
+                self.write(" ")?;
+                self.write(comment)?;
+                self.pending = Some(formatting_style_precedence(pending, Newline));
+                return Ok(());
+            }
+            Some(pending) => {
+                if let Indent(change) = formatting_style_precedence(pending, Newline) {
+                    self.indent += change as i32;
+                }
+                self.newline()?;
+            }
+            None => {}
+        }
+        self.blank_line = false;
+        self.write(comment)?;
+        self.pending = Some(Newline);
+        Ok(())
+    }
 
-        for token in tokens {
-            format_single_token(token, &mut indent, &mut current_format, buf)?;
+    /// Requests an empty line before the next token or comment, if they are going to start
+    /// a new line anyway.
+    pub fn blank_line(&mut self) {
+        if self.pending.is_some() {
+            self.blank_line = true;
         }
     }
-    Ok(())
+
+    /// Ensures the output ends with a newline
+    pub fn finish(mut self) -> std::fmt::Result {
+        if self.pending.is_some() {
+            self.blank_line = false;
+            self.buf.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    fn closes_function_header(&mut self, token: &Token) -> bool {
+        if let Token::Function = token {
+            self.function_header = Some((0, 0));
+            return false;
+        }
+        let Some((round, square)) = &mut self.function_header else {
+            return false;
+        };
+        match token {
+            Token::OpenRoundBracket => *round += 1,
+            Token::OpenSquareBracket => *square += 1,
+            Token::CloseSquareBracket => *square = square.saturating_sub(1),
+            Token::CloseRoundBracket => {
+                *round = round.saturating_sub(1);
+                if *round == 0 && *square == 0 {
+                    self.function_header = None;
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn exceeds_max_width(&self, len: usize) -> bool {
+        match self.options.max_width {
+            Some(max_width) => self.column + len > max_width,
+            None => false,
+        }
+    }
+
+    fn newline(&mut self) -> std::fmt::Result {
+        if std::mem::take(&mut self.blank_line) {
+            self.buf.write_char('\n')?;
+        }
+        self.buf.write_char('\n')?;
+        self.column = 0;
+        for _ in 0..self.indent {
+            match self.options.indentation {
+                Indentation::Tabs => {
+                    self.buf.write_char('\t')?;
+                    self.column += TAB_WIDTH;
+                }
+                Indentation::Spaces(count) => {
+                    for _ in 0..count {
+                        self.buf.write_char(' ')?;
+                    }
+                    self.column += count as usize;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, str: &str) -> std::fmt::Result {
+        self.column += str.chars().count();
+        self.buf.write_str(str)
+    }
 }
 
 #[macro_export]
@@ -159,3 +355,119 @@ macro_rules! fmt_tokens {
         }
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::{FormatOptions, Indentation, TokenFormatter};
+    use crate::{Ident, NumberLiteral, Token};
+
+    fn format_with(options: FormatOptions, feed: impl FnOnce(&mut TokenFormatter)) -> String {
+        let mut buf = String::new();
+        let mut formatter = TokenFormatter::new(&mut buf, options);
+        feed(&mut formatter);
+        buf
+    }
+
+    fn ident(name: &str) -> Token {
+        Token::Ident(Ident::new(name))
+    }
+
+    fn function_tokens() -> Vec<Token> {
+        vec![
+            Token::Function,
+            ident("f"),
+            Token::OpenRoundBracket,
+            ident("a"),
+            Token::CloseRoundBracket,
+            Token::Return,
+            ident("a"),
+            Token::End,
+        ]
+    }
+
+    #[test]
+    fn indents_function_body() {
+        let res = format_with(FormatOptions::default(), |formatter| {
+            for token in function_tokens() {
+                formatter.token(token).unwrap();
+            }
+        });
+        assert_eq!(res, "function f(a)\n\treturn a\nend");
+    }
+
+    #[test]
+    fn indents_with_spaces() {
+        let options = FormatOptions {
+            indentation: Indentation::Spaces(2),
+            max_width: None,
+        };
+        let res = format_with(options, |formatter| {
+            for token in function_tokens() {
+                formatter.token(token).unwrap();
+            }
+        });
+        assert_eq!(res, "function f(a)\n  return a\nend");
+    }
+
+    #[test]
+    fn puts_statements_on_separate_lines() {
+        let res = format_with(FormatOptions::default(), |formatter| {
+            for token in [
+                ident("a"),
+                Token::Assignment,
+                ident("b"),
+                ident("f"),
+                Token::OpenRoundBracket,
+                Token::CloseRoundBracket,
+                ident("c"),
+                Token::Assignment,
+                Token::Nil,
+            ] {
+                formatter.token(token).unwrap();
+            }
+        });
+        assert_eq!(res, "a = b\nf()\nc = nil");
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let options = FormatOptions {
+            indentation: Indentation::Spaces(4),
+            max_width: Some(12),
+        };
+        let res = format_with(options, |formatter| {
+            for token in [
+                ident("a"),
+                Token::Assignment,
+                ident("bbbb"),
+                Token::Plus,
+                ident("cccc"),
+                Token::Plus,
+                Token::Number(NumberLiteral(1.0)),
+            ] {
+                formatter.token(token).unwrap();
+            }
+        });
+        assert_eq!(res, "a = bbbb +\n    cccc + 1");
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let res = format_with(FormatOptions::default(), |formatter| {
+            formatter.comment("-- header", false).unwrap();
+            for token in function_tokens() {
+                if token == Token::Return {
+                    formatter.comment("-- inside", false).unwrap();
+                }
+                formatter.token(token).unwrap();
+            }
+            formatter.comment("-- trailing", true).unwrap();
+            formatter.blank_line();
+            formatter.token(ident("x")).unwrap();
+        });
+        assert_eq!(
+            res,
+            "-- header\nfunction f(a)\n\t-- inside\n\treturn a\nend -- trailing\n\nx"
+        );
+    }
+}
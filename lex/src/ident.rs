@@ -99,7 +99,7 @@ impl Arbitrary for Ident {
             }
             // Could use unsafe version from_utf8_unchecked(Vec<u8>)
             let str = String::from_utf8(buf).unwrap();
-            if RESERVED_KEYWORDS.binary_search(&str.as_str()).is_err() {
+            if !RESERVED_KEYWORDS.contains(&str.as_str()) {
                 break Self(str);
             }
             buf = str.into_bytes();
@@ -107,6 +107,11 @@ impl Arbitrary for Ident {
     }
 
     fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
-        Box::new(self.0.shrink().filter_map(Ident::try_new))
+        Box::new(
+            self.0
+                .shrink()
+                .filter(|str| !RESERVED_KEYWORDS.contains(&str.as_str()))
+                .filter_map(Ident::try_new),
+        )
     }
 }
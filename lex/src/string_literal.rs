@@ -20,16 +20,27 @@ impl FromStr for StringLiteral {
             return Err(StringLiteralParseError);
         }
         let raw_string: &str = &s[1..s.len() - 1];
-        // Not a very efficient method to escape characters
-        Ok(StringLiteral(
-            raw_string
-                .replace(r"\\", "\\")
-                .replace(r"\r", "\r")
-                .replace(r"\n", "\n")
-                .replace(r"\t", "\t")
-                .replace("\\\"", "\"")
-                .replace(r"\'", "'"),
-        ))
+        let mut res = String::with_capacity(raw_string.len());
+        let mut chars = raw_string.chars();
+        while let Some(char) = chars.next() {
+            if char != '\\' {
+                res.push(char);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => res.push('\n'),
+                Some('r') => res.push('\r'),
+                Some('t') => res.push('\t'),
+                Some(escaped @ ('\\' | '"' | '\'')) => res.push(escaped),
+                // Unknown escape sequences are left as is
+                Some(other) => {
+                    res.push('\\');
+                    res.push(other);
+                }
+                None => res.push('\\'),
+            }
+        }
+        Ok(StringLiteral(res))
     }
 }
 
@@ -81,4 +92,10 @@ mod tests {
         let StringLiteral(res) = r"'hello \n\r\t\\\'world\''".parse().unwrap();
         assert_eq!(res, "hello \n\r\t\\'world'");
     }
+
+    #[test]
+    fn escaped_backslash_does_not_start_another_escape_sequence() {
+        let StringLiteral(res) = r#""\\n\\\"""#.parse().unwrap();
+        assert_eq!(res, r#"\n\""#);
+    }
 }
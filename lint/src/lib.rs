@@ -0,0 +1,350 @@
+use std::{collections::HashSet, fmt};
+
+use luar_syn::{lua_parser, ParseErrorWithSourcePosition, SourcePosition};
+
+mod linter;
+mod positions;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// Global is read, but it is never assigned in the module, and it is not a known global
+    UndefinedGlobal {
+        name: String,
+    },
+    UnusedLocal {
+        name: String,
+    },
+    UnusedArgument {
+        name: String,
+    },
+    /// Local or an argument has the same name as a local visible at the point of declaration
+    ShadowedLocal {
+        name: String,
+        shadowed: Option<SourcePosition>,
+    },
+    /// Code after a statement, which returns on every path
+    UnreachableCode,
+    /// Call of a module level function with a number of arguments other than it is declared with
+    WrongArity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl Lint {
+    /// Identifier of the lint, which can be used to allow it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UndefinedGlobal { .. } => "undefined-global",
+            Lint::UnusedLocal { .. } => "unused-local",
+            Lint::UnusedArgument { .. } => "unused-argument",
+            Lint::ShadowedLocal { .. } => "shadowed-local",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::WrongArity { .. } => "wrong-arity",
+        }
+    }
+
+    pub const NAMES: [&'static str; 6] = [
+        "undefined-global",
+        "unused-local",
+        "unused-argument",
+        "shadowed-local",
+        "unreachable-code",
+        "wrong-arity",
+    ];
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::UndefinedGlobal { name } => write!(f, "global `{}` is never assigned", name),
+            Lint::UnusedLocal { name } => write!(f, "local `{}` is never read", name),
+            Lint::UnusedArgument { name } => write!(f, "argument `{}` is never read", name),
+            Lint::ShadowedLocal {
+                name,
+                shadowed: Some(position),
+            } => write!(
+                f,
+                "`{}` shadows a local declared on line {}",
+                name,
+                position.row + 1
+            ),
+            Lint::ShadowedLocal {
+                name,
+                shadowed: None,
+            } => write!(f, "`{}` shadows another local", name),
+            Lint::UnreachableCode => write!(f, "unreachable code"),
+            Lint::WrongArity {
+                name,
+                expected,
+                got,
+            } => write!(
+                f,
+                "function `{}` takes {} argument(s), but {} are passed",
+                name, expected, got
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Position is unknown only if the linter failed to match the syntax tree with the source
+    pub position: Option<SourcePosition>,
+    pub lint: Lint,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Source contains {} syntax error(s)", .0.len())]
+pub struct LintError(pub Vec<ParseErrorWithSourcePosition>);
+
+#[derive(Debug, Clone)]
+pub struct LintOptions {
+    /// Globals, which are defined outside of the module. Reading them is never reported.
+    pub known_globals: HashSet<String>,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            known_globals: stdlib_globals(),
+        }
+    }
+}
+
+/// Globals, which the standard library of any of the engines defines. Engines are not dependencies
+/// of the linter, so the names are listed here, and a test checks them against the engines.
+const STDLIB_GLOBALS: &[&str] = &[
+    "assert",
+    "coroutine",
+    "dofile",
+    "dostring",
+    "floor",
+    "json_decode",
+    "json_encode",
+    "load",
+    "print",
+    "random",
+    "require",
+    "strlen",
+    "strsub",
+    "tonumber",
+    "type",
+];
+
+/// Names of globals, defined by the standard library of any of the engines
+pub fn stdlib_globals() -> HashSet<String> {
+    STDLIB_GLOBALS.iter().map(|name| name.to_string()).collect()
+}
+
+/// Reports suspicious code in the module. Diagnostics are ordered by their position in the source.
+pub fn lint_source(source: &str, options: &LintOptions) -> Result<Vec<Diagnostic>, LintError> {
+    let recovered = lua_parser::module_recovering(source);
+    if !recovered.errors.is_empty() {
+        return Err(LintError(recovered.errors));
+    }
+    let module = recovered.module.into_module();
+    let mut diagnostics = linter::lint_module(&module, source, options);
+    diagnostics.sort_by_key(|diagnostic| {
+        diagnostic
+            .position
+            .map_or((usize::MAX, usize::MAX), |position| {
+                (position.row, position.col)
+            })
+    });
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+
+    use std::collections::HashSet;
+
+    use super::{lint_source, stdlib_globals, Lint, LintOptions};
+
+    fn lint(source: &str) -> Vec<(usize, Lint)> {
+        lint_source(source, &LintOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.position.unwrap().row + 1, diagnostic.lint))
+            .collect()
+    }
+
+    #[test]
+    fn clean_module_has_no_diagnostics() {
+        let source = indoc! {"
+            local function_count = 0
+            function fib(n)
+                if n < 2 then
+                    return n
+                end
+                return fib(n - 1) + fib(n - 2)
+            end
+            counter = { value = 0 }
+            function counter:increment(by)
+                self.value = self.value + by
+            end
+            counter:increment(fib(10))
+            print(counter.value, function_count)
+        "};
+        assert_eq!(lint(source), vec![]);
+    }
+
+    #[test]
+    fn reports_undefined_globals() {
+        let source = indoc! {"
+            defined = 1
+            print(defined, undefined)
+            function later() return undefined_too end
+            later()
+        "};
+        assert_eq!(
+            lint(source),
+            vec![
+                (
+                    2,
+                    Lint::UndefinedGlobal {
+                        name: "undefined".to_string()
+                    }
+                ),
+                (
+                    3,
+                    Lint::UndefinedGlobal {
+                        name: "undefined_too".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn globals_assigned_after_the_read_are_defined() {
+        let source = indoc! {"
+            function show() print(message) end
+            message = \"hello\"
+            show()
+        "};
+        assert_eq!(lint(source), vec![]);
+    }
+
+    #[test]
+    fn reports_unused_locals_and_arguments() {
+        let source = indoc! {"
+            function f(used, unused, _ignored)
+                local a, b = used, 2
+                local c
+                c = a
+                return b
+            end
+            f(1, 2, 3)
+        "};
+        assert_eq!(
+            lint(source),
+            vec![
+                (
+                    1,
+                    Lint::UnusedArgument {
+                        name: "unused".to_string()
+                    }
+                ),
+                (
+                    3,
+                    Lint::UnusedLocal {
+                        name: "c".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_shadowed_locals() {
+        let source = indoc! {"
+            local value = 1
+            function f(value)
+                return value
+            end
+            if value then
+                local value = value + 1
+                print(value)
+            end
+            print(f(value))
+        "};
+        let shadowed_line_1 = Lint::ShadowedLocal {
+            name: "value".to_string(),
+            shadowed: Some(luar_syn::SourcePosition { row: 0, col: 6 }),
+        };
+        assert_eq!(
+            lint(source),
+            vec![(2, shadowed_line_1.clone()), (6, shadowed_line_1)]
+        );
+    }
+
+    #[test]
+    fn reports_unreachable_code() {
+        let source = indoc! {"
+            function f(a)
+                if a then
+                    return 1
+                else
+                    return 2
+                end
+                print(a)
+                return 3
+            end
+            function g()
+                repeat
+                    return 1
+                until nil
+                return 2
+            end
+            print(f(1), g())
+        "};
+        assert_eq!(
+            lint(source),
+            vec![(7, Lint::UnreachableCode), (14, Lint::UnreachableCode)]
+        );
+    }
+
+    #[test]
+    fn reports_calls_with_wrong_arity() {
+        let source = indoc! {"
+            function add(a, b) return a + b end
+            function reassigned(a) return a end
+            reassigned = print
+            print(add(1), add(1, 2), add(1, 2, 3), add(add(1, 2)), add(1, add(1, 2)))
+            reassigned(1, 2)
+        "};
+        let add = |got| Lint::WrongArity {
+            name: "add".to_string(),
+            expected: 2,
+            got,
+        };
+        assert_eq!(lint(source), vec![(4, add(1)), (4, add(3))]);
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let error = lint_source("local = 1\nlocal b = )", &LintOptions::default()).unwrap_err();
+        assert_eq!(error.0.len(), 2);
+    }
+    #[test]
+    fn stdlib_globals_are_the_globals_of_the_engines() {
+        let ast_vm_context = ast_vm::stdlib::std_context();
+        let reggie_machine = reggie::Machine::with_stdlib();
+        let engine_globals: HashSet<String> = ast_vm_context
+            .globals
+            .mapping
+            .keys()
+            .cloned()
+            .chain(
+                (&reggie_machine.global_values)
+                    .into_iter()
+                    .map(|cell| cell.name.clone()),
+            )
+            .collect();
+        assert_eq!(stdlib_globals(), engine_globals);
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::{HashMap, HashSet};
+
+use luar_lex::{Ident, Token};
+use luar_syn::{
+    Assignment, Block, Chunk, Conditional, ConditionalTail, Declaration, Expression, FunctionCall,
+    FunctionCallArgs, FunctionDeclaration, FunctionName, Module, Return, SourcePosition, Statement,
+    TableConstructor, Var,
+};
+
+use crate::{positions::Positions, Diagnostic, Lint, LintOptions};
+
+pub(crate) fn lint_module(module: &Module, source: &str, options: &LintOptions) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        positions: Positions::new(source),
+        known_globals: &options.known_globals,
+        locals: Vec::new(),
+        scopes: vec![Vec::new()],
+        global_assignments: HashMap::new(),
+        global_reads: Vec::new(),
+        functions: HashMap::new(),
+        calls: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    linter.module(module);
+    linter.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LocalKind {
+    Local,
+    Argument,
+    /// Implicit `self` of methods, which is never reported as unused
+    SelfArgument,
+}
+
+struct Local {
+    name: String,
+    kind: LocalKind,
+    position: Option<SourcePosition>,
+    read: bool,
+}
+
+struct Call {
+    name: String,
+    position: Option<SourcePosition>,
+    arg_count: usize,
+    /// Last argument is a function call, which may produce any number of values
+    open_ended: bool,
+}
+
+/// Resolves names the same way the compilers do, with a stack of scopes, in which locals are
+/// looked up from the innermost scope outwards, and anything not found is a global.
+struct Linter<'a> {
+    positions: Positions,
+    known_globals: &'a HashSet<String>,
+    /// Every local declared so far, scopes refer to them by index
+    locals: Vec<Local>,
+    scopes: Vec<Vec<usize>>,
+    /// How many times each global is assigned, counting function declarations
+    global_assignments: HashMap<String, usize>,
+    global_reads: Vec<(String, Option<SourcePosition>)>,
+    /// Arity of functions declared on the module level
+    functions: HashMap<String, usize>,
+    calls: Vec<Call>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn finish(mut self) -> Vec<Diagnostic> {
+        while !self.scopes.is_empty() {
+            self.pop_scope();
+        }
+        for (name, position) in std::mem::take(&mut self.global_reads) {
+            if !self.global_assignments.contains_key(&name) && !self.known_globals.contains(&name) {
+                self.report(position, Lint::UndefinedGlobal { name });
+            }
+        }
+        for call in std::mem::take(&mut self.calls) {
+            // Functions, which are reassigned, may have any arity at the point of the call
+            if self.global_assignments.get(&call.name) != Some(&1) {
+                continue;
+            }
+            let Some(&expected) = self.functions.get(&call.name) else {
+                continue;
+            };
+            let mismatch = if call.open_ended {
+                call.arg_count - 1 > expected
+            } else {
+                call.arg_count != expected
+            };
+            if mismatch {
+                let lint = Lint::WrongArity {
+                    name: call.name,
+                    expected,
+                    got: call.arg_count,
+                };
+                self.report(call.position, lint);
+            }
+        }
+        self.diagnostics
+    }
+
+    fn report(&mut self, position: Option<SourcePosition>, lint: Lint) {
+        self.diagnostics.push(Diagnostic { position, lint });
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("Scope stack should not be empty");
+        for idx in scope {
+            let local = &self.locals[idx];
+            if local.read || local.name.starts_with('_') {
+                continue;
+            }
+            let lint = match local.kind {
+                LocalKind::Local => Lint::UnusedLocal {
+                    name: local.name.clone(),
+                },
+                LocalKind::Argument => Lint::UnusedArgument {
+                    name: local.name.clone(),
+                },
+                LocalKind::SelfArgument => continue,
+            };
+            self.report(local.position, lint);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .copied()
+            .find(|&idx| self.locals[idx].name == name)
+    }
+
+    fn declare(&mut self, name: &Ident, position: Option<SourcePosition>, kind: LocalKind) {
+        if let Some(shadowed) = self.lookup(name.as_ref()) {
+            let lint = Lint::ShadowedLocal {
+                name: name.to_string(),
+                shadowed: self.locals[shadowed].position,
+            };
+            self.report(position, lint);
+        }
+        self.locals.push(Local {
+            name: name.to_string(),
+            kind,
+            position,
+            read: kind == LocalKind::SelfArgument,
+        });
+        let idx = self.locals.len() - 1;
+        self.scopes
+            .last_mut()
+            .expect("Scope stack should not be empty")
+            .push(idx);
+    }
+
+    fn ident_position(&mut self, ident: &Ident) -> Option<SourcePosition> {
+        self.positions.next(&Token::Ident(ident.clone()))
+    }
+
+    fn module(&mut self, module: &Module) {
+        let mut returned = false;
+        let mut reported = false;
+        for chunk in &module.chunks {
+            if returned && !reported {
+                let position = match chunk {
+                    Chunk::FnDecl(_) => self.positions.peek(&Token::Function),
+                    Chunk::Statement(statement) => self.statement_position(statement),
+                };
+                self.report(position, Lint::UnreachableCode);
+                reported = true;
+            }
+            returned |= match chunk {
+                Chunk::FnDecl(decl) => {
+                    self.function_declaration(decl);
+                    false
+                }
+                Chunk::Statement(statement) => self.statement(statement),
+            };
+        }
+        if let Some(ret) = &module.ret {
+            self.ret(ret, returned && !reported);
+        }
+    }
+
+    fn function_declaration(&mut self, decl: &FunctionDeclaration) {
+        self.positions.next(&Token::Function);
+        match &decl.name {
+            FunctionName::Plain(Var::Named(name)) if self.lookup(name.as_ref()).is_none() => {
+                self.assign(&Var::Named(name.clone()));
+                // Only top level functions can be declared, so this is always the module level
+                self.functions.insert(name.to_string(), decl.args.len());
+            }
+            FunctionName::Plain(var) => self.assign(var),
+            FunctionName::Method(var, method) => {
+                self.read(var);
+                self.ident_position(method);
+            }
+        }
+        self.push_scope();
+        if let FunctionName::Method(..) = decl.name {
+            self.declare(&Ident::new("self"), None, LocalKind::SelfArgument);
+        }
+        for arg in &decl.args {
+            let position = self.ident_position(arg);
+            self.declare(arg, position, LocalKind::Argument);
+        }
+        self.block(&decl.body);
+        self.pop_scope();
+    }
+
+    /// Lints statements of the block in the current scope. Returns whether the block returns
+    /// on every path.
+    fn block(&mut self, block: &Block) -> bool {
+        let mut returned = false;
+        let mut reported = false;
+        for statement in &block.statements {
+            if returned && !reported {
+                let position = self.statement_position(statement);
+                self.report(position, Lint::UnreachableCode);
+                reported = true;
+            }
+            returned |= self.statement(statement);
+        }
+        if let Some(ret) = &block.ret {
+            self.ret(ret, returned && !reported);
+            returned = true;
+        }
+        returned
+    }
+
+    fn scoped_block(&mut self, block: &Block) -> bool {
+        self.push_scope();
+        let returned = self.block(block);
+        self.pop_scope();
+        returned
+    }
+
+    fn ret(&mut self, Return(values): &Return, unreachable: bool) {
+        let position = self.positions.next(&Token::Return);
+        if unreachable {
+            self.report(position, Lint::UnreachableCode);
+        }
+        for value in values {
+            self.expression(value);
+        }
+    }
+
+    fn statement_position(&self, statement: &Statement) -> Option<SourcePosition> {
+        let token = match statement {
+            Statement::LocalDeclaration(_) => Token::Local,
+            Statement::While(_) => Token::While,
+            Statement::Repeat(_) => Token::Repeat,
+            Statement::If(_) => Token::If,
+            Statement::Assignment(Assignment { names, .. }) => {
+                Token::Ident(root_ident(names.first()).clone())
+            }
+            Statement::FunctionCall(
+                FunctionCall::Function { func, .. } | FunctionCall::Method { func, .. },
+            ) => Token::Ident(root_ident(func).clone()),
+        };
+        self.positions.peek(&token)
+    }
+
+    /// Returns whether the statement returns on every path
+    fn statement(&mut self, statement: &Statement) -> bool {
+        match statement {
+            Statement::Assignment(assignment) => {
+                for name in assignment.names.iter() {
+                    self.assign(name);
+                }
+                for value in assignment.values.iter() {
+                    self.expression(value);
+                }
+                false
+            }
+            Statement::LocalDeclaration(decl) => {
+                self.local_declaration(decl);
+                false
+            }
+            Statement::While(while_loop) => {
+                self.positions.next(&Token::While);
+                self.expression(&while_loop.condition);
+                self.scoped_block(&while_loop.body);
+                false
+            }
+            Statement::Repeat(repeat_loop) => {
+                self.positions.next(&Token::Repeat);
+                // Condition sees the locals of the loop body
+                self.push_scope();
+                let returned = self.block(&repeat_loop.body);
+                self.expression(&repeat_loop.condition);
+                self.pop_scope();
+                returned
+            }
+            Statement::If(conditional) => {
+                self.positions.next(&Token::If);
+                self.conditional(conditional)
+            }
+            Statement::FunctionCall(call) => {
+                self.function_call(call);
+                false
+            }
+        }
+    }
+
+    fn local_declaration(&mut self, decl: &Declaration) {
+        self.positions.next(&Token::Local);
+        let positions: Vec<_> = decl
+            .names
+            .iter()
+            .map(|name| self.ident_position(name))
+            .collect();
+        // Locals are not visible to their own initializers
+        for value in &decl.initial_values {
+            self.expression(value);
+        }
+        for (name, position) in decl.names.iter().zip(positions) {
+            self.declare(name, position, LocalKind::Local);
+        }
+    }
+
+    fn conditional(&mut self, conditional: &Conditional) -> bool {
+        self.expression(&conditional.condition);
+        let body_returned = self.scoped_block(&conditional.body);
+        let tail_returned = match &conditional.tail {
+            ConditionalTail::End => false,
+            ConditionalTail::Else(block) => self.scoped_block(block),
+            ConditionalTail::ElseIf(conditional) => self.conditional(conditional),
+        };
+        body_returned && tail_returned
+    }
+
+    fn assign(&mut self, var: &Var) {
+        match var {
+            Var::Named(name) => {
+                self.ident_position(name);
+                // Assignment does not count as a read
+                if self.lookup(name.as_ref()).is_none() {
+                    *self.global_assignments.entry(name.to_string()).or_default() += 1;
+                }
+            }
+            Var::PropertyAccess { from, property } => {
+                self.read(from);
+                self.ident_position(property);
+            }
+            Var::MemberLookup { from, value } => {
+                self.read(from);
+                self.expression(value);
+            }
+        }
+    }
+
+    fn read(&mut self, var: &Var) {
+        match var {
+            Var::Named(name) => {
+                let position = self.ident_position(name);
+                match self.lookup(name.as_ref()) {
+                    Some(idx) => self.locals[idx].read = true,
+                    None => self.global_reads.push((name.to_string(), position)),
+                }
+            }
+            Var::PropertyAccess { from, property } => {
+                self.read(from);
+                self.ident_position(property);
+            }
+            Var::MemberLookup { from, value } => {
+                self.read(from);
+                self.expression(value);
+            }
+        }
+    }
+
+    fn function_call(&mut self, call: &FunctionCall) {
+        match call {
+            FunctionCall::Function {
+                func: Var::Named(name),
+                args,
+            } if self.lookup(name.as_ref()).is_none() => {
+                let position = self.positions.peek(&Token::Ident(name.clone()));
+                self.read(&Var::Named(name.clone()));
+                let (arg_count, open_ended) = match args {
+                    FunctionCallArgs::Table(_) => (1, false),
+                    FunctionCallArgs::Arglist(args) => (
+                        args.len(),
+                        matches!(args.last(), Some(Expression::FunctionCall(_))),
+                    ),
+                };
+                self.calls.push(Call {
+                    name: name.to_string(),
+                    position,
+                    arg_count,
+                    open_ended,
+                });
+                self.call_args(args);
+            }
+            FunctionCall::Function { func, args } => {
+                self.read(func);
+                self.call_args(args);
+            }
+            FunctionCall::Method { func, method, args } => {
+                self.read(func);
+                self.ident_position(method);
+                self.call_args(args);
+            }
+        }
+    }
+
+    fn call_args(&mut self, args: &FunctionCallArgs) {
+        match args {
+            FunctionCallArgs::Table(table) => self.table_constructor(table),
+            FunctionCallArgs::Arglist(args) => {
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+        }
+    }
+
+    fn table_constructor(&mut self, table: &TableConstructor) {
+        // Syntax tree keeps positional and named fields apart, even when they are interleaved
+        // in the source. Each kind is in the source order though, so they are matched separately.
+        let start = self.positions.cursor();
+        for value in &table.lfield {
+            self.expression(value);
+        }
+        let positional_end = self.positions.cursor();
+        self.positions.set_cursor(start);
+        for (name, value) in &table.ffield {
+            self.ident_position(name);
+            self.expression(value);
+        }
+        let named_end = self.positions.cursor();
+        self.positions.set_cursor(positional_end.max(named_end));
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Nil | Expression::String(_) | Expression::Number(_) => {}
+            Expression::Variable(var) => self.read(var),
+            Expression::BinaryOperator { lhs, rhs, .. } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            Expression::UnaryOperator { exp, .. } => self.expression(exp),
+            Expression::TableConstructor(table) => self.table_constructor(table),
+            Expression::FunctionCall(call) => self.function_call(call),
+        }
+    }
+}
+
+fn root_ident(var: &Var) -> &Ident {
+    match var {
+        Var::Named(ident) => ident,
+        Var::PropertyAccess { from, .. } | Var::MemberLookup { from, .. } => root_ident(from),
+    }
+}
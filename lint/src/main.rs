@@ -0,0 +1,155 @@
+use luar_lint::{lint_source, Diagnostic, Lint, LintError, LintOptions};
+use std::{collections::HashSet, error::Error, process::ExitCode};
+
+const USAGE: &str = "\
+Usage: luarlint [OPTIONS] [FILES...]
+
+Reports suspicious code in lua files. Without files, reads source from stdin.
+
+Options:
+    --allow <LINT>     Don't report the lint, can be repeated
+    --global <NAME>    Treat the global as defined outside of the module, can be repeated
+    --help             Print this message
+
+Lints:
+    undefined-global, unused-local, unused-argument, shadowed-local, unreachable-code, wrong-arity";
+
+struct Args {
+    allowed: HashSet<String>,
+    options: LintOptions,
+    files: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        allowed: HashSet::new(),
+        options: LintOptions::default(),
+        files: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allow" => {
+                let lint = args.next().ok_or("Missing value for --allow")?;
+                if !Lint::NAMES.contains(&lint.as_str()) {
+                    return Err(format!("Unknown lint \"{}\"", lint));
+                }
+                parsed.allowed.insert(lint);
+            }
+            "--global" => {
+                let name = args.next().ok_or("Missing value for --global")?;
+                parsed.options.known_globals.insert(name);
+            }
+            "--help" | "-h" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            _ => parsed.files.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn report_syntax_errors(filename: &str, LintError(errors): &LintError) {
+    for error in errors {
+        match error.start() {
+            Some(position) => eprintln!(
+                "{}:{}:{}: syntax error, expected {}",
+                filename,
+                position.row + 1,
+                position.col + 1,
+                error.expected()
+            ),
+            None => eprintln!(
+                "{}: syntax error at the end of file, expected {}",
+                filename,
+                error.expected()
+            ),
+        }
+    }
+}
+
+/// Prints diagnostics, which are not allowed, and returns how many of them were printed
+fn report_diagnostics(filename: &str, diagnostics: Vec<Diagnostic>, args: &Args) -> usize {
+    let mut reported = 0;
+    for Diagnostic { position, lint } in diagnostics {
+        if args.allowed.contains(lint.name()) {
+            continue;
+        }
+        match position {
+            Some(position) => println!(
+                "{}:{}:{}: warning[{}]: {}",
+                filename,
+                position.row + 1,
+                position.col + 1,
+                lint.name(),
+                lint
+            ),
+            None => println!("{}: warning[{}]: {}", filename, lint.name(), lint),
+        }
+        reported += 1;
+    }
+    reported
+}
+
+fn lint_file(filename: &str, source: &str, args: &Args) -> Result<usize, Box<dyn Error>> {
+    let diagnostics = lint_source(source, &args.options).inspect_err(|error| {
+        report_syntax_errors(filename, error);
+    })?;
+    Ok(report_diagnostics(filename, diagnostics, args))
+}
+
+fn read_stdin() -> std::io::Result<String> {
+    use std::io::Read;
+
+    let mut source = String::new();
+    std::io::stdin().lock().read_to_string(&mut source)?;
+    Ok(source)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let results = if args.files.is_empty() {
+        let result = read_stdin()
+            .map_err(Box::from)
+            .and_then(|source| lint_file("<stdin>", &source, &args));
+        vec![("<stdin>", result)]
+    } else {
+        args.files
+            .iter()
+            .map(|filename| {
+                let result = std::fs::read_to_string(filename)
+                    .map_err(Box::from)
+                    .and_then(|source| lint_file(filename, &source, &args));
+                (filename.as_str(), result)
+            })
+            .collect()
+    };
+
+    let mut warned = false;
+    let mut failed = false;
+    for (filename, result) in results {
+        match result {
+            Ok(reported) => warned |= reported > 0,
+            Err(error) => {
+                eprintln!("{}: {}", filename, error);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::from(2)
+    } else if warned {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
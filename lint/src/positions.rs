@@ -0,0 +1,80 @@
+use logos::Logos;
+use luar_lex::Token;
+use luar_syn::SourcePosition;
+
+/// Syntax tree does not keep track of where its nodes came from, so positions are recovered
+/// by matching the tree against the tokens of the source. Linter visits nodes in the order of
+/// their appearance in the source, and looks up the tokens it is interested in, moving the cursor
+/// forward. Tokens the linter does not care about are simply skipped.
+pub(crate) struct Positions {
+    tokens: Vec<(Token, SourcePosition)>,
+    cursor: usize,
+}
+
+impl Positions {
+    pub fn new(source: &str) -> Self {
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        let position = |offset: usize| {
+            let row = line_starts.partition_point(|&start| start <= offset) - 1;
+            let col = source[line_starts[row]..offset].chars().count();
+            SourcePosition { col, row }
+        };
+        let tokens = Token::lexer(source)
+            .spanned()
+            .map(|(token, span)| (token, position(span.start)))
+            .collect();
+        Self { tokens, cursor: 0 }
+    }
+
+    /// Position of the next occurrence of the token, without moving past it
+    pub fn peek(&self, token: &Token) -> Option<SourcePosition> {
+        self.find_from(self.cursor, token)
+            .map(|idx| self.tokens[idx].1)
+    }
+
+    /// Position of the next occurrence of the token. Cursor is moved right past it.
+    pub fn next(&mut self, token: &Token) -> Option<SourcePosition> {
+        let idx = self.find_from(self.cursor, token)?;
+        self.cursor = idx + 1;
+        Some(self.tokens[idx].1)
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor;
+    }
+
+    fn find_from(&self, start: usize, token: &Token) -> Option<usize> {
+        self.tokens[start.min(self.tokens.len())..]
+            .iter()
+            .position(|(candidate, _)| candidate == token)
+            .map(|idx| idx + start)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use luar_lex::{Ident, Token};
+    use luar_syn::SourcePosition;
+
+    use super::Positions;
+
+    #[test]
+    fn finds_tokens_in_order() {
+        let mut positions = Positions::new("a = a\n  local s = \"ä\", a");
+        let a = Token::Ident(Ident::new("a"));
+        assert_eq!(positions.next(&a), Some(SourcePosition { row: 0, col: 0 }));
+        assert_eq!(
+            positions.peek(&Token::Local),
+            Some(SourcePosition { row: 1, col: 2 })
+        );
+        assert_eq!(positions.next(&a), Some(SourcePosition { row: 0, col: 4 }));
+        assert_eq!(positions.next(&a), Some(SourcePosition { row: 1, col: 17 }));
+        assert_eq!(positions.next(&a), None);
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
     LuaValue, NativeFunction, TableRef,
 };
 use enum_map::{enum_map, EnumMap};
-use luar_string::LuaString;
+use crate::LuaString;
 use std::mem::{align_of, size_of, size_of_val};
 
 pub struct CallStack {
@@ -62,16 +62,18 @@ struct StackFrame {
 #[repr(align(8))]
 struct AlignedPC(ProgramCounter);
 
+/// Every local is padded to the alignment of the frame, so that locals of every type stay
+/// aligned, regardless of how many locals of smaller types precede them.
 fn value_sizes() -> EnumMap<DataType, usize> {
+    let padded = |size: usize| size.next_multiple_of(align_of::<AlignedPC>());
     enum_map! {
-        DataType::Dynamic => size_of::<LuaValue>(),
-        DataType::Int => size_of::<i32>(),
-        DataType::Float => size_of::<f64>(),
-        // This is a special case, since
-        DataType::String => size_of::<LuaString>() + align_of::<u32>(),
-        DataType::Function => size_of::<BlockID>(),
-        DataType::NativeFunction => size_of::<NativeFunction>(),
-        DataType::Table => size_of::<TableRef>(),
+        DataType::Dynamic => padded(size_of::<LuaValue>()),
+        DataType::Int => padded(size_of::<i32>()),
+        DataType::Float => padded(size_of::<f64>()),
+        DataType::String => padded(size_of::<LuaString>()),
+        DataType::Function => padded(size_of::<BlockID>()),
+        DataType::NativeFunction => padded(size_of::<NativeFunction>()),
+        DataType::Table => padded(size_of::<TableRef>()),
     }
 }
 
@@ -110,6 +112,15 @@ impl Default for CallStack {
 }
 
 impl CallStack {
+    /// Stack, which starts smaller than the one of the machine, and grows as frames are pushed.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            stack: Vec::with_capacity(capacity),
+            #[cfg(debug_assertions)]
+            frame_sizes: Vec::new(),
+        }
+    }
+
     pub fn push<'a, 'b>(
         &'a mut self,
         meta: &'b CodeMeta,
@@ -148,21 +159,41 @@ impl CallStack {
             &mut *frame_ptr
         };
         frame.return_addr = AlignedPC(return_addr);
-
-        FrameHandle { frame, meta }
+        let mut handle = FrameHandle { frame, meta };
+        // Compact values are not zero-bit initializable. Zero-bit LuaValue is a 0.0 float, and
+        // LuaString is a pointer to a shared allocation.
+        #[cfg(feature = "compact_value")]
+        {
+            for reg in 0..meta.local_count[DataType::Dynamic] {
+                // SAFETY: Zero bits is a float, there is nothing to drop.
+                unsafe { std::ptr::write(handle.get_dyn(LocalRegisterID(reg)), LuaValue::NIL) };
+            }
+            for reg in 0..meta.local_count[DataType::String] {
+                // SAFETY: Zero bits is a null pointer, which should not be dropped.
+                unsafe {
+                    std::ptr::write(handle.get_string(LocalRegisterID(reg)), LuaString::default())
+                };
+            }
+        }
+        handle
     }
 
     /// SAFETY: handle should be of the same CodeBlock as the one that was used to create the top frame.
     pub unsafe fn pop(&mut self, handle: ReleaseHandle) {
-        let frame_size = size_of_val::<StackFrame>(unsafe { &*handle.frame });
+        unsafe { self.pop_frame(handle.frame, &handle.meta.local_count) };
+    }
+
+    /// SAFETY: frame should be the top one, and `count` should be the local count it was pushed
+    /// with.
+    unsafe fn pop_frame(&mut self, frame: *mut StackFrame, count: &LocalRegCount) {
+        let frame_size = size_of_val::<StackFrame>(unsafe { &*frame });
         debug_assert!(self.stack.len() >= frame_size);
         #[cfg(debug_assertions)]
         {
             debug_assert!(self.frame_sizes.pop() == Some(frame_size));
         }
 
-        let base_ptr = unsafe { &mut *handle.frame }.locals.as_mut_ptr();
-        let count = &handle.meta.local_count;
+        let base_ptr = unsafe { &mut *frame }.locals.as_mut_ptr();
         // SAFETY: base_ptr points to the beginning of the correct lua type in the locals.
         //         Order is the same as in the enum_map, and by proxy, as in the StackFrame.
         unsafe {
@@ -206,10 +237,21 @@ impl CallStack {
     /// are used to retrieve the meta of the function in the stack. Meta is required to determine
     /// stack frame sizes. There should be a starting point, since the stack is never empty. That's
     /// why it is required to pass the meta of the top-level function.
-    pub fn clear<'a, 'b>(&'b mut self, mut last_meta: &'a CodeMeta, code_blocks: &'a CodeBlocks) {
-        loop {
+    pub fn clear<'a>(&mut self, last_meta: &'a CodeMeta, code_blocks: &'a CodeBlocks) {
+        self.unwind_to(0, last_meta, code_blocks);
+    }
+
+    /// Same as [`CallStack::clear`], except that the frames below `size` are kept. Used to unwind
+    /// the frames of a nested call, which was made while the machine was running.
+    pub fn unwind_to<'a, 'b>(
+        &'b mut self,
+        size: usize,
+        mut last_meta: &'a CodeMeta,
+        code_blocks: &'a CodeBlocks,
+    ) {
+        while self.stack.len() > size {
             let frame_size = stack_frame_size(last_meta);
-            debug_assert!(self.stack.len() >= frame_size.aligned);
+            debug_assert!(self.stack.len() >= size + frame_size.aligned);
             // This guy lives for as long as the stack is not popped.
             let frame: &'b mut StackFrame = unsafe {
                 // base_ptr is always aligned, since I manually allign every frame.
@@ -226,7 +268,7 @@ impl CallStack {
                 frame: frame as *mut StackFrame,
                 meta: last_meta,
             };
-            if frame_size.aligned == self.stack.len() {
+            if self.stack.len() - frame_size.aligned == size {
                 unsafe { self.pop(handle) };
                 break;
             }
@@ -236,9 +278,66 @@ impl CallStack {
         }
     }
 
+    /// Local counts of the frames on the stack, from the top one down. The top frame belongs to
+    /// `top_block`, and the rest are found by their return addresses. Unlike the metas, the counts
+    /// can be kept along with a stack, that may outlive the code blocks, to drop its frames with
+    /// [`CallStack::clear_frames`].
+    pub(crate) fn frame_layouts(
+        &self,
+        top_block: BlockID,
+        code_blocks: &CodeBlocks,
+    ) -> Vec<LocalRegCount> {
+        let mut layouts = Vec::new();
+        let mut size = self.stack.len();
+        let mut meta = &code_blocks[top_block].meta;
+        while size > 0 {
+            let frame_size = stack_frame_size(meta);
+            debug_assert!(size >= frame_size.aligned);
+            layouts.push(meta.local_count);
+            size -= frame_size.aligned;
+            if size == 0 {
+                break;
+            }
+            // SAFETY: Frames are laid out back to back, and the one below starts right where
+            //         this one ends.
+            let below = unsafe {
+                let base_ptr = self.stack.as_ptr().add(size) as *mut u8;
+                &*from_raw_parts(base_ptr, frame_size.locals)
+            };
+            meta = &code_blocks[below.return_addr.0.block].meta;
+        }
+        layouts
+    }
+
+    /// Drops every frame on the stack, given the layouts from [`CallStack::frame_layouts`].
+    pub(crate) fn clear_frames(&mut self, layouts: &[LocalRegCount]) {
+        for count in layouts {
+            let frame_size = local_frame_size(count);
+            // SAFETY: The layouts were taken from the frames on the stack, from the top one down.
+            unsafe {
+                let base_ptr = self
+                    .stack
+                    .as_mut_ptr()
+                    .add(self.stack.len() - frame_size.aligned);
+                self.pop_frame(from_raw_parts(base_ptr, frame_size.locals), count);
+            }
+        }
+        debug_assert!(self.stack.is_empty());
+    }
+
+    /// Size of the frames on the stack in bytes.
+    pub fn size(&self) -> usize {
+        self.stack.len()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.stack.is_empty()
     }
+
+    #[cfg(test)]
+    pub(crate) fn capacity(&self) -> usize {
+        self.stack.capacity()
+    }
 }
 
 #[derive(Debug)]
@@ -248,9 +347,12 @@ struct FrameSize {
 }
 
 fn stack_frame_size(meta: &CodeMeta) -> FrameSize {
+    local_frame_size(&meta.local_count)
+}
+
+fn local_frame_size(local_count: &LocalRegCount) -> FrameSize {
     let sizes = value_sizes();
-    let locals_size = meta
-        .local_count
+    let locals_size = local_count
         .iter()
         .map(|(dtype, count)| sizes[dtype] * *count as usize)
         .sum::<usize>();
@@ -292,7 +394,7 @@ impl<'a, 'b> FrameHandle<'a, 'b> {
         //         The pointer is aligned, since the frame is aligned.
         unsafe {
             let base_ptr = self.frame.locals.as_mut_ptr();
-            let val_ptr = base_ptr.add(reg as usize * size_of::<LuaValue>());
+            let val_ptr = base_ptr.add(reg as usize * value_sizes()[DataType::Dynamic]);
             &mut *(val_ptr as *mut LuaValue)
         }
     }
@@ -350,25 +452,91 @@ impl<'a, 'b> FrameHandle<'a, 'b> {
         unreachable!("enum_map always contains all of the DataType variants");
     }
 
+    /// Dynamic locals come first in the frame, and are not padded, so they form an array.
+    #[cfg(feature = "jit")]
+    pub(crate) fn dyn_locals_ptr(&mut self) -> *mut LuaValue {
+        debug_assert_eq!(value_sizes()[DataType::Dynamic], size_of::<LuaValue>());
+        self.frame.locals.as_mut_ptr() as *mut LuaValue
+    }
+
     pub fn return_addr(&self) -> ProgramCounter {
         self.frame.return_addr.0
     }
+
+    /// Handle of the same frame, which borrows this one.
+    pub fn reborrow(&mut self) -> FrameHandle<'_, 'b> {
+        FrameHandle {
+            frame: &mut *self.frame,
+            meta: self.meta,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{LuaValue, NativeFunction, TableRef};
-    use luar_string::LuaString;
-    use std::mem::size_of;
+    use super::CallStack;
+    use crate::{
+        ids::{BlockID, LocalRegisterID},
+        machine::ProgramCounter,
+        meta::{reg_count, CodeMeta},
+        LuaValue, NativeFunction, TableRef,
+    };
+    use std::mem::{align_of, size_of};
+
+    #[test]
+    fn locals_after_smaller_types_are_aligned() {
+        let meta = CodeMeta {
+            local_count: reg_count! { I: 1, F: 1, T: 1 },
+            ..Default::default()
+        };
+        let mut stack = CallStack::default();
+        let return_addr = ProgramCounter {
+            block: BlockID(0),
+            position: 0,
+        };
+        let mut frame = stack.push(&meta, return_addr);
+        *frame.get_int(LocalRegisterID(0)) = 42;
+        let float = frame.get_float(LocalRegisterID(0));
+        assert_eq!(float as *mut f64 as usize % align_of::<f64>(), 0);
+        *float = 0.5;
+        let table = frame.get_table(LocalRegisterID(0));
+        assert_eq!(table as *mut _ as usize % align_of::<Option<TableRef>>(), 0);
+        assert_eq!(*frame.get_int(LocalRegisterID(0)), 42);
+        assert_eq!(*frame.get_float(LocalRegisterID(0)), 0.5);
+        let handle = frame.release();
+        unsafe { stack.pop(handle) };
+    }
+
+    #[test]
+    fn pushed_frame_has_nil_and_empty_string_locals() {
+        let meta = CodeMeta {
+            local_count: reg_count! { D: 2, S: 2 },
+            ..Default::default()
+        };
+        let mut stack = CallStack::default();
+        let return_addr = ProgramCounter {
+            block: BlockID(0),
+            position: 0,
+        };
+        let mut frame = stack.push(&meta, return_addr);
+        for reg in 0..2 {
+            assert_eq!(*frame.get_dyn(LocalRegisterID(reg)), LuaValue::NIL);
+            assert_eq!(*frame.get_string(LocalRegisterID(reg)), "");
+        }
+        let handle = frame.release();
+        unsafe { stack.pop(handle) };
+    }
 
     #[test]
+    #[cfg(not(feature = "compact_value"))]
     fn zero_bit_initialized_lua_value_is_nil() {
         let zeros = [0u8; size_of::<LuaValue>()];
         let zero_value: LuaValue = unsafe { std::mem::transmute(zeros) };
-        assert_eq!(zero_value, LuaValue::Nil);
+        assert_eq!(zero_value, LuaValue::NIL);
     }
 
     #[test]
+    #[cfg(not(feature = "compact_value"))]
     fn zero_bit_initialized_lua_string_is_empty() {
         let zeros = [0u8; size_of::<LuaString>()];
         let zero_value: LuaString = unsafe { std::mem::transmute(zeros) };
@@ -0,0 +1,226 @@
+use std::io::{BufRead, Write};
+
+use itertools::Itertools;
+use reggie::{DebugFrame, DebugHook};
+
+const HELP: &str = "\
+break <file>:<line>  stop when the line is reached
+step                 run until the next line
+next                 run until the next line of this function, stepping over calls
+continue             run until a breakpoint
+bt                   show the call stack
+print [<local>]      show a local variable, or all of them
+quit                 stop the program";
+
+/// Interactive debugger, which reads commands from stdin whenever the program stops.
+pub struct CliDebugger {
+    breakpoints: Vec<Breakpoint>,
+    resume: Resume,
+    frames: Vec<Frame>,
+}
+
+struct Breakpoint {
+    file: String,
+    line: u32,
+}
+
+enum Resume {
+    Step,
+    /// Stop at the next line of a frame at most this deep
+    Next { depth: usize },
+    Continue,
+}
+
+struct Frame {
+    function: String,
+    source_name: Option<String>,
+    line: Option<u32>,
+}
+
+enum Command {
+    Step,
+    Next,
+    Continue,
+    Break(Breakpoint),
+    Backtrace,
+    Print(Option<String>),
+    Help,
+    Quit,
+}
+
+impl CliDebugger {
+    /// Program stops at its first line.
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            resume: Resume::Step,
+            frames: Vec::new(),
+        }
+    }
+
+    fn should_stop(&self, source_name: Option<&str>, line: u32) -> bool {
+        let stepped = match self.resume {
+            Resume::Step => true,
+            Resume::Next { depth } => self.frames.len() <= depth,
+            Resume::Continue => false,
+        };
+        stepped
+            || source_name.is_some_and(|source_name| {
+                self.breakpoints
+                    .iter()
+                    .any(|breakpoint| breakpoint.matches(source_name, line))
+            })
+    }
+
+    fn prompt(&mut self, frame: &mut DebugFrame) {
+        let mut stdout = std::io::stdout().lock();
+        let mut input = String::new();
+        loop {
+            let _ = write!(stdout, "(debug) ");
+            let _ = stdout.flush();
+            input.clear();
+            match std::io::stdin().lock().read_line(&mut input) {
+                Ok(0) | Err(_) => {
+                    // Nobody to take commands from, let the program finish
+                    self.resume = Resume::Continue;
+                    return;
+                }
+                Ok(_) => {}
+            }
+
+            let command = match parse_command(&input) {
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(error) => {
+                    let _ = writeln!(stdout, "{error}");
+                    continue;
+                }
+            };
+            let output = match command {
+                Command::Step => {
+                    self.resume = Resume::Step;
+                    return;
+                }
+                Command::Next => {
+                    self.resume = Resume::Next {
+                        depth: self.frames.len(),
+                    };
+                    return;
+                }
+                Command::Continue => {
+                    self.resume = Resume::Continue;
+                    return;
+                }
+                Command::Break(breakpoint) => {
+                    let output = format!("breakpoint at {}:{}", breakpoint.file, breakpoint.line);
+                    self.breakpoints.push(breakpoint);
+                    output
+                }
+                Command::Backtrace => self.backtrace(),
+                Command::Print(Some(name)) => match frame.named_local(&name) {
+                    Some(value) => format!("{name} = {value}"),
+                    None => format!("no local {name} in scope"),
+                },
+                Command::Print(None) => frame
+                    .named_locals()
+                    .into_iter()
+                    .map(|(name, value)| format!("{name} = {value}"))
+                    .join("\n"),
+                Command::Help => HELP.to_owned(),
+                Command::Quit => std::process::exit(0),
+            };
+            if !output.is_empty() {
+                let _ = writeln!(stdout, "{output}");
+            }
+        }
+    }
+
+    /// Frames without debug info, like the ones of dynamic wrappers, are left out.
+    fn backtrace(&self) -> String {
+        self.frames
+            .iter()
+            .rev()
+            .filter_map(|frame| Some((frame, frame.source_name.as_ref()?)))
+            .enumerate()
+            .map(|(index, (frame, source_name))| match frame.line {
+                Some(line) => format!("#{index} {} at {source_name}:{line}", frame.function),
+                None => format!("#{index} {} in {source_name}", frame.function),
+            })
+            .join("\n")
+    }
+}
+
+impl DebugHook for CliDebugger {
+    fn line(&mut self, frame: &mut DebugFrame, line: u32) {
+        if let Some(current) = self.frames.last_mut() {
+            current.line = Some(line);
+        }
+        let source_name = frame.debug_info().map(|info| info.source_name.as_str());
+        if !self.should_stop(source_name, line) {
+            return;
+        }
+        println!(
+            "{}:{line} in {}",
+            source_name.unwrap_or("?"),
+            frame.function_name().unwrap_or("?")
+        );
+        self.prompt(frame);
+    }
+
+    fn call(&mut self, frame: &mut DebugFrame) {
+        self.frames.push(Frame {
+            function: frame.function_name().unwrap_or("?").to_owned(),
+            source_name: frame.debug_info().map(|info| info.source_name.clone()),
+            line: None,
+        });
+    }
+
+    fn ret(&mut self, _frame: &mut DebugFrame) {
+        self.frames.pop();
+    }
+}
+
+impl Breakpoint {
+    /// File of the breakpoint can leave out the directories of the source name.
+    fn matches(&self, source_name: &str, line: u32) -> bool {
+        self.line == line
+            && (source_name == self.file
+                || source_name
+                    .strip_suffix(&self.file)
+                    .is_some_and(|dir| dir.ends_with(std::path::MAIN_SEPARATOR)))
+    }
+}
+
+fn parse_command(input: &str) -> Result<Option<Command>, String> {
+    let mut words = input.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(None);
+    };
+    let argument = words.next();
+    let command = match (command, argument) {
+        ("step" | "s", None) => Command::Step,
+        ("next" | "n", None) => Command::Next,
+        ("continue" | "c", None) => Command::Continue,
+        ("bt", None) => Command::Backtrace,
+        ("print" | "p", name) => Command::Print(name.map(ToOwned::to_owned)),
+        ("break" | "b", Some(location)) => {
+            let parsed = location
+                .rsplit_once(':')
+                .and_then(|(file, line)| Some((file, line.parse().ok()?)));
+            let Some((file, line)) = parsed else {
+                return Err(format!("expected <file>:<line>, got {location}"));
+            };
+            Command::Break(Breakpoint {
+                file: file.to_owned(),
+                line,
+            })
+        }
+        ("help" | "h", None) => Command::Help,
+        ("quit" | "q", None) => Command::Quit,
+        _ => return Err(format!("unknown command {}, try help", input.trim())),
+    };
+    if words.next().is_some() {
+        return Err(format!("unknown command {}, try help", input.trim()));
+    }
+    Ok(Some(command))
+}
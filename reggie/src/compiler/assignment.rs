@@ -63,6 +63,13 @@ fn compile_assignment_tail(
             if let Some(unassigned_reg) = left_unassigned.try_at(0) {
                 state.push_instr(Instruction::StrLD(unassigned_reg))
             }
+            // Registers are reused, the ones left without a value have to be reset to nil
+            if left_unassigned.count > 1 {
+                state.push_instr(Instruction::ConstN);
+                for local_reg in left_unassigned.into_iter().skip(1) {
+                    state.push_instr(Instruction::StrLD(local_reg));
+                }
+            }
         }
     }
 }
@@ -106,7 +113,8 @@ pub fn compile_store(var: &Var, state: &mut LocalScopeCompilationState) {
             state.push_instr(TablePropertyAssignError);
             state.push_label(is_table_lbl);
             state.push_instr(LdaLD(reg));
-            state.push_instr(AssocASD);
+            let cache = state.alloc_property_cache();
+            state.push_instr(AssocASD(cache));
 
             state.reg().free(DataType::Dynamic);
         }
@@ -157,7 +165,10 @@ pub fn compile_local_decl(decl: &Declaration, state: &mut LocalScopeCompilationS
     } else {
         let locals_count = decl.names.len().try_into().unwrap();
         let locals = state.reg().alloc_nonzero(DataType::Dynamic, locals_count);
+        // Registers are reused between scopes and loop iterations, they may hold anything
         for (ident, local_reg) in decl.names.iter().zip(&locals) {
+            state.push_instr(Instruction::ConstN);
+            state.push_instr(Instruction::StrLD(local_reg));
             state.define_local(ident.to_string(), local_reg);
         }
     };
@@ -1,15 +1,25 @@
 use luar_syn::{BinaryOperator, Expression, TableConstructor, UnaryOperator};
 
 use crate::{
-    compiler::{compile_fn_call, compile_var_lookup, LocalScopeCompilationState},
+    compiler::{compile_fn_call, compile_var_lookup, fold_constant, LocalScopeCompilationState},
     ids::{ArgumentRegisterID, JmpLabel, LocalRegisterID},
     machine::DataType,
     ops::Instruction,
+    LuaValue,
 };
 
 pub fn compile_expr(expr: &Expression, state: &mut LocalScopeCompilationState) {
     use Instruction::*;
 
+    if matches!(
+        expr,
+        Expression::BinaryOperator { .. } | Expression::UnaryOperator { .. }
+    ) && let Some(value) = fold_constant(expr)
+    {
+        compile_constant(&value, state);
+        return;
+    }
+
     match expr {
         Expression::Nil => {
             state.push_instr(ConstN);
@@ -23,7 +33,7 @@ pub fn compile_expr(expr: &Expression, state: &mut LocalScopeCompilationState) {
             state.push_instr(WrapF);
         }
         Expression::String(str) => {
-            let str_id = state.alloc_string(str.0.clone());
+            let str_id = state.alloc_string(str.0.as_str());
             state.push_instr(ConstS(str_id));
             state.push_instr(WrapS);
         }
@@ -57,6 +67,26 @@ pub fn compile_expr(expr: &Expression, state: &mut LocalScopeCompilationState) {
     }
 }
 
+/// Loads a constant, produced by folding, into the accumulator (AD)
+fn compile_constant(value: &LuaValue, state: &mut LocalScopeCompilationState) {
+    use Instruction::*;
+
+    if let Some(int) = value.as_int() {
+        state.push_instr(ConstI(int));
+        state.push_instr(WrapI);
+    } else if let Some(float) = value.as_float() {
+        state.push_instr(ConstF(float));
+        state.push_instr(WrapF);
+    } else if let Some(str) = value.as_str() {
+        let str_id = state.alloc_string(str);
+        state.push_instr(ConstS(str_id));
+        state.push_instr(WrapS);
+    } else {
+        debug_assert!(value.is_nil(), "folding produced {value:?}");
+        state.push_instr(ConstN);
+    }
+}
+
 fn compile_not(state: &mut LocalScopeCompilationState) {
     use Instruction::*;
 
@@ -78,6 +108,12 @@ fn compile_binary_op(
     rhs: &Expression,
     state: &mut LocalScopeCompilationState,
 ) {
+    if matches!(op, BinaryOperator::And | BinaryOperator::Or) && fold_constant(lhs).is_some() {
+        // Constant left hand side, which short circuits, would have folded the whole expression.
+        // So the result is always the right hand side.
+        compile_expr(rhs, state);
+        return;
+    }
     compile_expr(lhs, state);
 
     match categorize_op(op) {
@@ -244,11 +280,12 @@ pub fn compile_table_constructor(table: &TableConstructor, state: &mut LocalScop
     }
 
     for (ident, value) in &table.ffield {
-        let ident_id = state.alloc_string(ident.as_ref().to_owned());
+        let ident_id = state.alloc_string(ident.as_ref());
         compile_expr(value, state);
         state.push_instr(ConstS(ident_id));
         state.push_instr(LdaLT(table_reg));
-        state.push_instr(AssocASD);
+        let cache = state.alloc_property_cache();
+        state.push_instr(AssocASD(cache));
     }
 
     state.push_instr(WrapT);
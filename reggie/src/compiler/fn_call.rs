@@ -1,30 +1,62 @@
-use luar_syn::FunctionCall;
+use luar_syn::{Expression, FunctionCall};
 
 use crate::{ids::ArgumentRegisterID, machine::DataType, ops::Instruction, compiler::compile_table_constructor};
 
 use super::{compile_expr, compile_var_lookup, LocalScopeCompilationState};
 
 pub fn compile_fn_call(call: &FunctionCall, state: &mut LocalScopeCompilationState) {
+    compile_call_with(call, Instruction::DCall, state);
+}
+
+/// Call in `return f(...)` position, which reuses the frame of the current function. Return values
+/// of the callee become the return values of the current function as is.
+pub fn compile_tail_call(call: &FunctionCall, state: &mut LocalScopeCompilationState) {
+    compile_call_with(call, Instruction::DTailCall, state);
+}
+
+fn compile_call_with(
+    call: &FunctionCall,
+    call_instr: Instruction,
+    state: &mut LocalScopeCompilationState,
+) {
     use Instruction::*;
 
     match call {
         FunctionCall::Function { func, args } => match args {
             luar_syn::FunctionCallArgs::Arglist(args) => {
+                // Every value of a call in the last argument is passed on
+                let (head, spread_call) = match args.split_last() {
+                    Some((Expression::FunctionCall(call), head)) => (head, Some(call)),
+                    _ => (args.as_slice(), None),
+                };
                 let locals = state
                     .reg()
-                    .alloc_count(DataType::Dynamic, args.len().try_into().unwrap());
-                for (expr, idx) in args.iter().zip(0..) {
+                    .alloc_count(DataType::Dynamic, head.len().try_into().unwrap());
+                for (expr, idx) in head.iter().zip(0..) {
                     compile_expr(expr, state);
                     state.push_instr(StrLD(locals.at(idx)));
                 }
+                if let Some(call) = spread_call {
+                    let tmp = state.reg().alloc(DataType::Int);
+                    compile_fn_call(call, state);
+                    state.push_instr(ConstI(locals.count as i32));
+                    state.push_instr(RDShiftRight);
+                    state.push_instr(StrLI(tmp));
+                    state.push_instr(LdaVC);
+                    state.push_instr(IAddL(tmp));
+                    state.push_instr(StrVC);
+                    state.reg().free(DataType::Int);
+                }
                 for (local, idx) in locals.into_iter().zip(0..) {
                     state.push_instr(LdaLD(local));
                     state.push_instr(StrRD(ArgumentRegisterID(idx)));
                 }
-                state.push_instr(ConstI(locals.count as i32));
-                state.push_instr(StrVC);
+                if spread_call.is_none() {
+                    state.push_instr(ConstI(locals.count as i32));
+                    state.push_instr(StrVC);
+                }
                 compile_var_lookup(func, state);
-                state.push_instr(DCall);
+                state.push_instr(call_instr);
                 state.reg().free_count(DataType::Dynamic, locals.count);
             }
             luar_syn::FunctionCallArgs::Table(table) => {
@@ -33,7 +65,7 @@ pub fn compile_fn_call(call: &FunctionCall, state: &mut LocalScopeCompilationSta
                 state.push_instr(ConstI(1));
                 state.push_instr(StrVC);
                 compile_var_lookup(func, state);
-                state.push_instr(DCall);
+                state.push_instr(call_instr);
             }
         },
         FunctionCall::Method { func, method, args } => {
@@ -0,0 +1,144 @@
+use luar_syn::{BinaryOperator, Expression, UnaryOperator};
+
+use crate::{
+    machine::TestFlag,
+    runtime::{
+        add_dyn, cmp_test_flags, div_dyn, dyn_concat, mul_dyn, neg_dyn_accumulator, sub_dyn,
+    },
+    LuaValue,
+};
+
+/// Evaluates an expression at compile time, if it is made only of literals and operators on them.
+///
+/// Folding goes through the same functions the runtime uses, so folded values are exactly the
+/// values the instructions would produce. Expressions, which would fail at runtime, are not
+/// folded, so that the error is still reported when the code runs.
+pub(crate) fn fold_constant(expr: &Expression) -> Option<LuaValue> {
+    match expr {
+        Expression::Nil => Some(LuaValue::NIL),
+        Expression::Number(num) if num.is_integer() => Some(LuaValue::int(num.as_i32())),
+        Expression::Number(num) => Some(LuaValue::float(num.as_f64())),
+        Expression::String(str) => Some(LuaValue::string(str.0.as_str())),
+        Expression::UnaryOperator {
+            op: UnaryOperator::Not,
+            exp,
+        } => fold_constant(exp).map(|value| LuaValue::from_bool(value.is_falsy())),
+        Expression::UnaryOperator {
+            op: UnaryOperator::Minus,
+            exp,
+        } => neg_dyn_accumulator(&fold_constant(exp)?).ok(),
+        Expression::BinaryOperator { lhs, op, rhs } => fold_binary_op(*op, lhs, rhs),
+        Expression::Variable(_) | Expression::FunctionCall(_) | Expression::TableConstructor(_) => {
+            None
+        }
+    }
+}
+
+fn fold_binary_op(op: BinaryOperator, lhs: &Expression, rhs: &Expression) -> Option<LuaValue> {
+    let lhs = fold_constant(lhs)?;
+    match op {
+        BinaryOperator::And if lhs.is_falsy() => return Some(lhs),
+        BinaryOperator::Or if lhs.is_truthy() => return Some(lhs),
+        BinaryOperator::And | BinaryOperator::Or => return fold_constant(rhs),
+        _ => {}
+    }
+
+    let rhs = fold_constant(rhs)?;
+    match op {
+        BinaryOperator::Plus => add_dyn(&lhs, &rhs).ok(),
+        BinaryOperator::Minus => sub_dyn(&lhs, &rhs).ok(),
+        BinaryOperator::Mul => mul_dyn(&lhs, &rhs).ok(),
+        BinaryOperator::Div => div_dyn(&lhs, &rhs).ok(),
+        BinaryOperator::Concat => dyn_concat(&lhs, &rhs).ok(),
+        BinaryOperator::Equals => Some(LuaValue::from_bool(lhs == rhs)),
+        BinaryOperator::NotEquals => Some(LuaValue::from_bool(lhs != rhs)),
+        BinaryOperator::Less => fold_comparison(&lhs, &rhs, |flag| flag == TestFlag::LT),
+        BinaryOperator::Greater => fold_comparison(&lhs, &rhs, |flag| flag == TestFlag::GT),
+        BinaryOperator::LessOrEquals => fold_comparison(&lhs, &rhs, |flag| {
+            flag == TestFlag::LT || flag == TestFlag::EQ
+        }),
+        BinaryOperator::GreaterOrEquals => fold_comparison(&lhs, &rhs, |flag| {
+            flag == TestFlag::GT || flag == TestFlag::EQ
+        }),
+        // Exponentiation is not supported by the compiler, so there is nothing to fold it into
+        BinaryOperator::Exp => None,
+        BinaryOperator::And | BinaryOperator::Or => unreachable!("handled above"),
+    }
+}
+
+/// Mirrors `TestLD` followed by a conditional jump.
+fn fold_comparison(
+    lhs: &LuaValue,
+    rhs: &LuaValue,
+    jumps: impl FnOnce(TestFlag) -> bool,
+) -> Option<LuaValue> {
+    if !lhs.is_comparable_to(rhs) {
+        return None;
+    }
+    let flag = cmp_test_flags(LuaValue::partial_cmp(lhs, rhs));
+    Some(LuaValue::from_bool(jumps(flag)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::fold_constant;
+    use crate::LuaValue;
+    use luar_syn::lua_parser;
+
+    fn fold(expr: &str) -> Option<LuaValue> {
+        fold_constant(&lua_parser::expression(expr).unwrap())
+    }
+
+    #[test]
+    fn folds_arithmetic_on_literals() {
+        assert_eq!(fold("2 * 3 + 4"), Some(LuaValue::int(10)));
+        assert_eq!(fold("-(1 - 3)"), Some(LuaValue::int(2)));
+        assert_eq!(fold("1 / 2"), Some(LuaValue::float(0.5)));
+        assert_eq!(fold("'10' + 1"), Some(LuaValue::float(11.0)));
+    }
+
+    #[test]
+    fn integer_overflow_folds_into_float() {
+        assert_eq!(fold("2147483647 + 1"), Some(LuaValue::float(2147483648.0)));
+        assert_eq!(
+            fold("-2147483647 - 2"),
+            Some(LuaValue::float(-2147483649.0))
+        );
+        assert_eq!(fold("65536 * 65536"), Some(LuaValue::float(4294967296.0)));
+        assert_eq!(
+            fold("-(-2147483647 - 1)"),
+            Some(LuaValue::float(2147483648.0))
+        );
+    }
+
+    #[test]
+    fn nan_is_folded_with_its_comparison_semantics() {
+        let nan = fold("0 / 0").unwrap();
+        assert!(nan.as_float().unwrap().is_nan());
+        assert_eq!(fold("0 / 0 == 0 / 0"), Some(LuaValue::NIL));
+        assert_eq!(fold("0 / 0 ~= 0 / 0"), Some(LuaValue::TRUE));
+        assert_eq!(fold("0 / 0 < 1"), Some(LuaValue::NIL));
+        assert_eq!(fold("0 / 0 >= 1"), Some(LuaValue::NIL));
+    }
+
+    #[test]
+    fn folds_strings_and_logic() {
+        assert_eq!(fold("'a' .. 'b' .. 1"), Some(LuaValue::string("ab1")));
+        assert_eq!(fold("not nil"), Some(LuaValue::TRUE));
+        assert_eq!(fold("not 0"), Some(LuaValue::NIL));
+        assert_eq!(fold("nil and x"), Some(LuaValue::NIL));
+        assert_eq!(fold("1 or x"), Some(LuaValue::int(1)));
+        assert_eq!(fold("nil or 'b'"), Some(LuaValue::string("b")));
+        assert_eq!(fold("'a' < 'b'"), Some(LuaValue::TRUE));
+    }
+
+    #[test]
+    fn leaves_runtime_dependent_and_failing_expressions_alone() {
+        assert_eq!(fold("x + 1"), None);
+        assert_eq!(fold("nil or x"), None);
+        assert_eq!(fold("'a' + 1"), None);
+        assert_eq!(fold("nil < 1"), None);
+        assert_eq!(fold("nil .. 'a'"), None);
+        assert_eq!(fold("{} == {}"), None);
+    }
+}
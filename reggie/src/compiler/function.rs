@@ -3,7 +3,8 @@ use luar_syn::{FunctionDeclaration, Return, Var};
 
 use crate::{
     compiler::{
-        compile_statement, ret::compile_ret, FunctionCompilationState, LocalScopeCompilationState,
+        compile_statement, ret::compile_ret, DebugSource, FunctionCompilationState,
+        LocalScopeCompilationState,
     },
     ids::{ArgumentRegisterID, LocalBlockID},
     machine::{CodeBlock, DataType},
@@ -15,9 +16,18 @@ use crate::{
 use super::return_traversal::return_traverse_function;
 
 pub fn compile_function(decl: &FunctionDeclaration, global_values: &mut GlobalValues) -> CodeBlock {
+    compile_function_from_source(decl, global_values, None)
+}
+
+pub fn compile_function_from_source(
+    decl: &FunctionDeclaration,
+    global_values: &mut GlobalValues,
+    debug_source: Option<DebugSource>,
+) -> CodeBlock {
     let return_count = return_traverse_function(decl);
     let mut state =
-        FunctionCompilationState::with_args(decl.args.iter().cloned(), global_values, return_count);
+        FunctionCompilationState::with_args(decl.args.iter().cloned(), global_values, return_count)
+            .with_debug_source(debug_source);
     let mut root_scope = LocalScopeCompilationState::new(&mut state);
 
     alias_arguments(&decl.args, &mut root_scope);
@@ -29,6 +39,7 @@ pub fn compile_function(decl: &FunctionDeclaration, global_values: &mut GlobalVa
     let empty_ret = Return(vec![]);
     let ret = decl.body.ret.as_ref().unwrap_or(&empty_ret);
     compile_ret(ret, &mut root_scope);
+    drop(root_scope);
 
     let debug_name = match decl.name {
         luar_syn::FunctionName::Plain(ref var) => last_ident(var).map(ToString::to_string),
@@ -38,11 +49,13 @@ pub fn compile_function(decl: &FunctionDeclaration, global_values: &mut GlobalVa
     let meta = CodeMeta {
         arg_count: ArgumentCount::Known(decl.args.len().try_into().unwrap()),
         const_strings: state.strings,
+        property_caches: state.property_caches,
         label_mappings: state.label_alloc.into_mappings(),
         return_count,
         local_count: state.reg_alloc.into_used_register_count(),
         debug_name,
         kind: FunctionKind::DeOptimized,
+        debug_info: state.debug_info,
     };
 
     CodeBlock {
@@ -77,16 +90,19 @@ pub fn compile_dyn_wrapper(
     }
 
     instructions.push(ConstC(local_block_id));
-    instructions.push(TypedCall);
 
     if let ReturnCount::Constant(return_count) = return_count {
         let return_count: u32 = return_count.try_into().unwrap();
+        instructions.push(TypedCall);
         instructions.push(ConstI(return_count as i32));
         instructions.push(StrVC);
+        instructions.push(Ret);
+    } else {
+        // Function sets the value count itself, wrapper frame is not needed anymore. Otherwise,
+        // every tail call through a dynamic value would leave a wrapper frame behind.
+        instructions.push(TailCall);
     }
 
-    instructions.push(Ret);
-
     CodeBlock {
         instructions,
         meta: CodeMeta {
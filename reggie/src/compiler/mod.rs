@@ -1,5 +1,5 @@
 use super::{
-    ids::{GlobalCellID, JmpLabel, LocalRegisterID, StringID},
+    ids::{GlobalCellID, JmpLabel, LocalRegisterID, PropertyCacheID, StringID},
     meta::LocalRegCount,
     ops::Instruction,
     GlobalValues,
@@ -7,15 +7,17 @@ use super::{
 use crate::{
     ids::ArgumentRegisterID,
     machine::DataType,
-    meta::{self, ReturnCount},
+    meta::{self, DebugInfo, LocalVariable, ReturnCount},
 };
+use luar_syn::{FunctionDeclaration, Return, SourceLines, Statement};
 use keyed_vec::KeyedVec;
-use luar_string::LuaString;
+use crate::{LuaString, PropertyCache};
 use std::{collections::HashMap, num::NonZeroU16};
 
 pub(crate) mod assignment;
 pub(crate) mod expr;
 pub(crate) mod fn_call;
+pub(crate) mod fold;
 pub mod function;
 pub mod module;
 pub(crate) mod ret;
@@ -26,6 +28,7 @@ pub(crate) mod var;
 pub(crate) use assignment::*;
 pub(crate) use expr::*;
 pub(crate) use fn_call::*;
+pub(crate) use fold::*;
 pub use function::*;
 pub use module::*;
 pub(crate) use statement::*;
@@ -293,16 +296,28 @@ impl Default for ReturnCountState {
     }
 }
 
+/// Source of the compiled module. Blocks compiled with it get [`DebugInfo`].
+#[derive(Debug, Clone, Copy)]
+pub struct DebugSource<'a> {
+    pub name: &'a str,
+    pub lines: &'a SourceLines,
+}
+
 #[derive(Debug)]
 pub struct FunctionCompilationState<'a> {
     global_values: &'a mut GlobalValues,
     reg_alloc: RegisterAllocator,
     label_alloc: LabelAllocator,
     strings: KeyedVec<StringID, LuaString>,
+    property_caches: KeyedVec<PropertyCacheID, PropertyCache>,
     instructions: Vec<Instruction>,
     arguments: ArgumentScope,
     scope_vars: Vec<LocalScope>,
     return_count: ReturnCount,
+    debug_source: Option<DebugSource<'a>>,
+    debug_info: Option<DebugInfo>,
+    /// Scopes of the locals in `debug_info`, which are still in scope
+    open_locals: Vec<(usize, usize)>,
 }
 
 impl<'a> FunctionCompilationState<'a> {
@@ -313,9 +328,13 @@ impl<'a> FunctionCompilationState<'a> {
             reg_alloc: Default::default(),
             label_alloc: Default::default(),
             strings: Default::default(),
+            property_caches: Default::default(),
             instructions: Default::default(),
             arguments: Default::default(),
             scope_vars: Default::default(),
+            debug_source: None,
+            debug_info: None,
+            open_locals: Default::default(),
         }
     }
 
@@ -330,6 +349,7 @@ impl<'a> FunctionCompilationState<'a> {
             reg_alloc: Default::default(),
             label_alloc: Default::default(),
             strings: Default::default(),
+            property_caches: Default::default(),
             instructions: Default::default(),
             arguments: ArgumentScope(
                 args.into_iter()
@@ -338,6 +358,20 @@ impl<'a> FunctionCompilationState<'a> {
                     .collect(),
             ),
             scope_vars: Default::default(),
+            debug_source: None,
+            debug_info: None,
+            open_locals: Default::default(),
+        }
+    }
+
+    pub fn with_debug_source(self, debug_source: Option<DebugSource<'a>>) -> Self {
+        Self {
+            debug_info: debug_source.map(|source| DebugInfo {
+                source_name: source.name.to_owned(),
+                ..Default::default()
+            }),
+            debug_source,
+            ..self
         }
     }
 }
@@ -367,12 +401,17 @@ impl<'a, 'b> LocalScopeCompilationState<'a, 'b> {
         self.func_state.instructions.push(instr)
     }
 
-    pub fn alloc_string(&mut self, str: impl Into<LuaString>) -> StringID {
+    pub fn alloc_string(&mut self, str: &str) -> StringID {
         let str_idx = self.strings().len();
-        self.strings().push(str.into());
+        let str = self.func_state.global_values.intern(str);
+        self.strings().push(str);
         StringID(str_idx.try_into().unwrap())
     }
 
+    pub fn alloc_property_cache(&mut self) -> PropertyCacheID {
+        self.func_state.property_caches.push(PropertyCache::default())
+    }
+
     pub fn instructions(&self) -> &[Instruction] {
         &self.func_state.instructions
     }
@@ -393,6 +432,17 @@ impl<'a, 'b> LocalScopeCompilationState<'a, 'b> {
     }
 
     pub fn define_local(&mut self, ident: String, location: LocalRegisterID) {
+        let position = self.position();
+        if let Some(debug_info) = &mut self.func_state.debug_info {
+            self.func_state
+                .open_locals
+                .push((self.scope, debug_info.locals.len()));
+            debug_info.locals.push(LocalVariable {
+                name: ident.clone(),
+                register: location,
+                scope: position..u32::MAX,
+            });
+        }
         self.func_state.scope_vars[self.scope]
             .0
             .insert(ident, location);
@@ -449,4 +499,60 @@ impl<'a, 'b> LocalScopeCompilationState<'a, 'b> {
     pub fn return_count(&self) -> ReturnCount {
         self.func_state.return_count
     }
+
+    pub fn debug_source(&self) -> Option<DebugSource<'b>> {
+        self.func_state.debug_source
+    }
+
+    /// Next instructions belong to the statement.
+    pub fn mark_statement(&mut self, statement: &Statement) {
+        let line = self
+            .debug_source()
+            .and_then(|source| source.lines.statement_line(statement));
+        self.mark_line(line);
+    }
+
+    pub fn mark_return(&mut self, ret: &Return) {
+        let line = self
+            .debug_source()
+            .and_then(|source| source.lines.return_line(ret));
+        self.mark_line(line);
+    }
+
+    pub fn mark_function_declaration(&mut self, decl: &FunctionDeclaration) {
+        let line = self
+            .debug_source()
+            .and_then(|source| source.lines.function_line(decl));
+        self.mark_line(line);
+    }
+
+    fn mark_line(&mut self, line: Option<u32>) {
+        let position = self.position();
+        if let Some(debug_info) = &mut self.func_state.debug_info
+            && let Some(line) = line
+        {
+            debug_info.add_line(position, line);
+        }
+    }
+
+    fn position(&self) -> u32 {
+        self.instructions().len().try_into().unwrap()
+    }
+}
+
+/// Locals of the scope go out of scope with it.
+impl Drop for LocalScopeCompilationState<'_, '_> {
+    fn drop(&mut self) {
+        let position = self.position();
+        let func_state = &mut *self.func_state;
+        let Some(debug_info) = &mut func_state.debug_info else {
+            return;
+        };
+        while let Some(&(scope, local)) = func_state.open_locals.last()
+            && scope >= self.scope
+        {
+            debug_info.locals[local].scope.end = position;
+            func_state.open_locals.pop();
+        }
+    }
 }
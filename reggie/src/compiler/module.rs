@@ -1,4 +1,4 @@
-use luar_syn::{Chunk, FunctionName, Return, Var};
+use luar_syn::{Chunk, FunctionName, Return, SourceLines, Var};
 
 use crate::{
     global_values::GlobalValues,
@@ -6,12 +6,14 @@ use crate::{
     machine::CodeBlock,
     meta::{ArgumentCount, CodeMeta, FunctionKind, ReturnCount},
     ops::Instruction,
+    optimizer::inline_calls,
 };
 use keyed_vec::KeyedVec;
 
 use super::{
-    compile_dyn_wrapper, compile_function, compile_statement, ret::compile_ret,
-    return_traversal::return_traverse_module, FunctionCompilationState, LocalScopeCompilationState,
+    compile_dyn_wrapper, compile_function_from_source, compile_statement, ret::compile_ret,
+    return_traversal::return_traverse_module, DebugSource, FunctionCompilationState,
+    LocalScopeCompilationState,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,9 +34,34 @@ impl std::fmt::Display for CompiledModule {
 pub fn compile_module(
     module: &luar_syn::Module,
     global_values: &mut GlobalValues,
+) -> CompiledModule {
+    compile_module_from_source(module, global_values, None)
+}
+
+/// Compiles the module along with [`DebugInfo`](crate::meta::DebugInfo) of its blocks. `source`
+/// should be the source the module was parsed from. Functions are not inlined.
+pub fn compile_module_with_debug_info(
+    module: &luar_syn::Module,
+    source: &str,
+    source_name: &str,
+    global_values: &mut GlobalValues,
+) -> CompiledModule {
+    let lines = SourceLines::new(source, module);
+    let debug_source = DebugSource {
+        name: source_name,
+        lines: &lines,
+    };
+    compile_module_from_source(module, global_values, Some(debug_source))
+}
+
+fn compile_module_from_source(
+    module: &luar_syn::Module,
+    global_values: &mut GlobalValues,
+    debug_source: Option<DebugSource>,
 ) -> CompiledModule {
     let return_count = return_traverse_module(module);
-    let mut state = FunctionCompilationState::new(global_values, return_count);
+    let mut state =
+        FunctionCompilationState::new(global_values, return_count).with_debug_source(debug_source);
     let mut root_scope = LocalScopeCompilationState::new(&mut state);
     let mut blocks = KeyedVec::new();
 
@@ -52,8 +79,9 @@ pub fn compile_module(
     let empty_ret = Return(vec![]);
     let ret = module.ret.as_ref().unwrap_or(&empty_ret);
     compile_ret(ret, &mut root_scope);
+    drop(root_scope);
 
-    CompiledModule {
+    let mut module = CompiledModule {
         blocks,
         top_level: CodeBlock {
             instructions: state.instructions,
@@ -63,11 +91,18 @@ pub fn compile_module(
                 return_count,
                 label_mappings: state.label_alloc.into_mappings(),
                 const_strings: state.strings,
+                property_caches: state.property_caches,
                 debug_name: Some("<module root>".to_owned()),
                 kind: FunctionKind::DeOptimized,
+                debug_info: state.debug_info,
             },
         },
+    };
+    // Inlined functions wouldn't report their own lines
+    if debug_source.is_none() {
+        inline_calls(&mut module);
     }
+    module
 }
 
 fn compile_function_declaration(
@@ -75,8 +110,10 @@ fn compile_function_declaration(
     decl: &luar_syn::FunctionDeclaration,
     blocks: &mut KeyedVec<LocalBlockID, CodeBlock>,
 ) {
+    root_scope.mark_function_declaration(decl);
+    let debug_source = root_scope.debug_source();
     let global_values = root_scope.global_values();
-    let func = compile_function(decl, global_values);
+    let func = compile_function_from_source(decl, global_values, debug_source);
 
     let func_to_save = if needs_wrapper(&func.meta) {
         wrap_function(func, blocks)
@@ -123,7 +160,7 @@ mod test {
     use super::compile_module;
     use crate::{
         compiler::compile_function,
-        ids::{ArgumentRegisterID, JmpLabel, LocalBlockID, StringID},
+        ids::{ArgumentRegisterID, GlobalCellID, JmpLabel, LocalBlockID, StringID},
         machine::CodeBlock,
         meta::{CodeMeta, LocalRegCount, ReturnCount},
         ops::Instruction,
@@ -265,7 +302,7 @@ mod test {
 
     test_compilation!(
         compile_simple_if,
-        "if nil then return 4 end return 5",
+        "if x then return 4 end return 5",
         CodeBlock {
             meta: CodeMeta {
                 arg_count: 0.into(),
@@ -275,7 +312,7 @@ mod test {
                 ..Default::default()
             },
             instructions: vec![
-                ConstN,
+                LdaDGl(GlobalCellID(0)),
                 NilTest,
                 JmpEQ(JmpLabel(0)),
                 ConstI(4),
@@ -291,6 +328,55 @@ mod test {
         }
     );
 
+    test_compilation!(
+        compile_if_with_constant_condition_drops_dead_branch,
+        "if nil then return 4 elseif 1 + 1 == 2 then return 5 else return 6 end",
+        CodeBlock {
+            meta: CodeMeta {
+                arg_count: 0.into(),
+                // Return count is computed before folding, so it still accounts for dead branches
+                return_count: ReturnCount::Bounded {
+                    min: 0,
+                    max: nonzero!(1u16),
+                },
+                debug_name: Some("<module root>".to_owned()),
+                ..Default::default()
+            },
+            instructions: vec![
+                ConstI(5),
+                WrapI,
+                StrRD(ArgumentRegisterID(0)),
+                ConstI(1),
+                StrVC,
+                Ret,
+                ConstI(0),
+                StrVC,
+                Ret
+            ]
+        }
+    );
+
+    test_compilation!(
+        compile_while_with_constant_condition,
+        "while nil do x = 1 end while 1 do end",
+        CodeBlock {
+            meta: CodeMeta {
+                arg_count: 0.into(),
+                return_count: 0.into(),
+                label_mappings: keyed_vec![0, 2],
+                debug_name: Some("<module root>".to_owned()),
+                ..Default::default()
+            },
+            instructions: vec![Label, Jmp(JmpLabel(0)), Label, Ret]
+        }
+    );
+
+    test_instruction_output!(
+        compile_folded_arithmetic,
+        "return 2 * 3 + 1",
+        vec![ConstI(7), WrapI, StrRD(ArgumentRegisterID(0)), Ret]
+    );
+
     #[test]
     fn correct_return_count() {
         use ReturnCount::*;
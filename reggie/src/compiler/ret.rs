@@ -2,9 +2,19 @@ use luar_syn::{Expression, Return};
 
 use crate::{ids::ArgumentRegisterID, machine::DataType, ops::Instruction};
 
-use super::{compile_expr, compile_fn_call, LocalScopeCompilationState, LocalRegisterSpan};
+use super::{
+    compile_expr, compile_fn_call, compile_tail_call, LocalRegisterSpan,
+    LocalScopeCompilationState,
+};
 
-pub fn compile_ret(Return(expressions): &Return, state: &mut LocalScopeCompilationState) {
+pub fn compile_ret(ret: &Return, state: &mut LocalScopeCompilationState) {
+    state.mark_return(ret);
+    let Return(expressions) = ret;
+    if let [Expression::FunctionCall(fn_call)] = expressions.as_slice() {
+        // Tail call returns on its own
+        compile_tail_call(fn_call, state);
+        return;
+    }
     if let Some((last, head)) = expressions.split_last() {
         compile_nonempty_return(head, last, state);
     } else if state.return_count().is_varying() {
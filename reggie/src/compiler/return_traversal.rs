@@ -7,11 +7,12 @@ use crate::meta::ReturnCount;
 use super::ReturnCountState;
 
 pub fn return_traverse_module(module: &Module) -> ReturnCount {
+    // Falling off the end of a module is an implicit empty return
     let ret = module
         .ret
         .as_ref()
         .map(return_traverse_return)
-        .unwrap_or(ReturnCountState::NotSpecified);
+        .unwrap_or(ReturnCountState::Constant(0));
 
     module
         .chunks
@@ -24,8 +25,13 @@ pub fn return_traverse_module(module: &Module) -> ReturnCount {
 }
 
 pub fn return_traverse_function(function: &FunctionDeclaration) -> ReturnCount {
-    return_traverse_block(&function.body)
-        .into_return_count()
+    let body = return_traverse_block(&function.body);
+    // Same as for modules, function without a trailing return statement returns nothing
+    let body = match function.body.ret {
+        Some(_) => body,
+        None => ReturnCountState::combine(body, ReturnCountState::Constant(0)),
+    };
+    body.into_return_count()
         .unwrap_or(ReturnCount::Constant(0))
 }
 
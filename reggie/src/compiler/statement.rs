@@ -1,13 +1,14 @@
 use luar_syn::{Block, Conditional, ConditionalTail, Statement, WhileLoop};
 
-use crate::ops::Instruction;
+use crate::{ops::Instruction, LuaValue};
 
 use super::{
-    compile_assignment, compile_expr, compile_fn_call, compile_local_decl, ret::compile_ret,
-    LocalScopeCompilationState,
+    compile_assignment, compile_expr, compile_fn_call, compile_local_decl, fold_constant,
+    ret::compile_ret, LocalScopeCompilationState,
 };
 
 pub fn compile_statement(statement: &Statement, state: &mut LocalScopeCompilationState) {
+    state.mark_statement(statement);
     match statement {
         Statement::If(conditional) => {
             compile_conditional(conditional, state);
@@ -29,6 +30,20 @@ pub fn compile_statement(statement: &Statement, state: &mut LocalScopeCompilatio
 }
 
 pub fn compile_conditional(conditional: &Conditional, state: &mut LocalScopeCompilationState) {
+    if let Some(condition) = fold_constant(&conditional.condition) {
+        // Only one of the branches can ever run, the other one is not compiled at all
+        if condition.is_truthy() {
+            compile_block(&conditional.body, state);
+        } else {
+            match conditional.tail {
+                ConditionalTail::End => {}
+                ConditionalTail::Else(ref block) => compile_block(block, state),
+                ConditionalTail::ElseIf(ref elseif) => compile_conditional(elseif, state),
+            }
+        }
+        return;
+    }
+
     compile_expr(&conditional.condition, state);
     state.push_instr(Instruction::NilTest);
 
@@ -68,18 +83,25 @@ pub fn compile_block(block: &Block, state: &mut LocalScopeCompilationState) {
         compile_statement(statement, &mut inner_scope);
     }
     if let Some(ret) = &block.ret {
-        compile_ret(ret, state);
+        compile_ret(ret, &mut inner_scope);
     }
 }
 
 pub fn compile_while_loop(while_loop: &WhileLoop, state: &mut LocalScopeCompilationState) {
+    let condition = fold_constant(&while_loop.condition);
+    if condition.as_ref().is_some_and(LuaValue::is_falsy) {
+        return;
+    }
+
     let loop_entry_lbl = state.alloc_label();
     let cont_lbl = state.alloc_label();
 
     state.push_label(loop_entry_lbl);
-    compile_expr(&while_loop.condition, state);
-    state.push_instr(Instruction::NilTest);
-    state.push_instr(Instruction::JmpEQ(cont_lbl));
+    if condition.is_none() {
+        compile_expr(&while_loop.condition, state);
+        state.push_instr(Instruction::NilTest);
+        state.push_instr(Instruction::JmpEQ(cont_lbl));
+    }
     compile_block(&while_loop.body, state);
     state.push_instr(Instruction::Jmp(loop_entry_lbl));
     state.push_label(cont_lbl);
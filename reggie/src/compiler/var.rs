@@ -47,7 +47,8 @@ fn compile_property_lookup(from: &Var, property: &Ident, state: &mut LocalScopeC
     state.push_instr(JmpEQ(is_table_lbl));
     state.push_instr(TablePropertyLookupError);
     state.push_label(is_table_lbl);
-    state.push_instr(LdaAssocAS);
+    let cache = state.alloc_property_cache();
+    state.push_instr(LdaAssocAS(cache));
 }
 
 fn compile_named_lookup(ident: &Ident, state: &mut LocalScopeCompilationState) {
@@ -0,0 +1,534 @@
+//! Coroutines, which are created with `coroutine.create(f)`, and run by `coroutine.resume(co, ...)`
+//! until the function returns or calls `coroutine.yield(...)`.
+//!
+//! Every coroutine runs on its own [`CallStack`], which is swapped with the stack of the machine
+//! while it runs, and keeps its frames and the position it yielded at while it is suspended. The
+//! values, passed to yield and resume, are the only registers carried over, as calls don't
+//! preserve the rest of them.
+//!
+//! There is no separate thread value, a coroutine is a native function. The ones made by
+//! `coroutine.create` are threads: `type` reports them as "thread", and calling them is an
+//! error. The ones made by `coroutine.wrap` are functions, which resume the coroutine when called,
+//! and can't be passed to `coroutine.resume` or `coroutine.status`.
+//!
+//! Yield returns to the resume, which runs the frames of the coroutine, so it can't be called by
+//! lua code, that runs inside of a native function, such as a callback.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    call_stack::CallStack,
+    machine::ProgramCounter,
+    meta::{LocalRegCount, ReturnCount},
+    profiler::SuspendedFrames,
+    runtime::{self, Entry, Exit, Runner},
+    EvalError, ExpectedType, LuaValue, Machine, NativeFunction, NativeFunctionKind, Profiler,
+    TypeError,
+};
+
+/// Coroutines start with a small stack, as there may be many of them.
+const COROUTINE_STACK_SIZE: usize = 4 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineStatus {
+    /// Not started yet, or yielded
+    Suspended,
+    Running,
+    /// Resumed another coroutine, and waits for it to yield
+    Normal,
+    /// Returned or failed
+    Dead,
+}
+
+impl CoroutineStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Suspended => "suspended",
+            Self::Running => "running",
+            Self::Normal => "normal",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+impl std::fmt::Display for CoroutineStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub(crate) struct Coroutine {
+    state: State,
+    /// Made by `coroutine.wrap`, and resumed by calling it
+    pub(crate) wrapped: bool,
+}
+
+enum State {
+    Created(LuaValue),
+    Suspended(Suspended),
+    Running,
+    Dead,
+}
+
+struct Suspended {
+    stack: CallStack,
+    program_counter: ProgramCounter,
+    /// Yield was tail called
+    tail: bool,
+    /// Frames on the stack, which count against the call depth limit
+    call_depth: usize,
+    /// Lets the stack be dropped without the code blocks, if the coroutine is never resumed
+    layouts: Vec<LocalRegCount>,
+    profiled: SuspendedFrames,
+    /// Block of the coroutine function, which decides the number of returned values
+    body: Option<crate::ids::BlockID>,
+}
+
+impl Coroutine {
+    pub(crate) fn new(body: LuaValue, wrapped: bool) -> Self {
+        Self {
+            state: State::Created(body),
+            wrapped,
+        }
+    }
+}
+
+impl Drop for Coroutine {
+    fn drop(&mut self) {
+        if let State::Suspended(suspended) = &mut self.state {
+            suspended.stack.clear_frames(&suspended.layouts);
+        }
+    }
+}
+
+fn status(
+    function: &NativeFunction,
+    coroutine: &RefCell<Coroutine>,
+    machine: &Machine,
+) -> CoroutineStatus {
+    match coroutine.borrow().state {
+        State::Created(_) | State::Suspended(_) => CoroutineStatus::Suspended,
+        State::Running if machine.coroutines.last() == Some(function) => CoroutineStatus::Running,
+        State::Running => CoroutineStatus::Normal,
+        State::Dead => CoroutineStatus::Dead,
+    }
+}
+
+/// Resumes the coroutine with the values in the argument registers, and runs it, until it yields or
+/// returns. Yielded or returned values are left in the argument registers. Coroutine is dead after
+/// an error.
+pub(crate) fn resume(
+    function: &NativeFunction,
+    coroutine: &RefCell<Coroutine>,
+    machine: &mut Machine,
+) -> Result<(), EvalError> {
+    let status = status(function, coroutine, machine);
+    if status != CoroutineStatus::Suspended {
+        return Err(EvalError::CannotResume(status));
+    }
+    let state = std::mem::replace(&mut coroutine.borrow_mut().state, State::Running);
+
+    let caller = machine.program_counter;
+    let caller_call_depth = machine.usage.call_depth;
+    let profiler_depth = machine.profiler.as_ref().map_or(0, Profiler::depth);
+    machine.coroutines.push(function.clone());
+    let (caller_stack, body, res) = match state {
+        State::Created(body) => {
+            let stack = CallStack::with_capacity(COROUTINE_STACK_SIZE);
+            let caller_stack = std::mem::replace(&mut machine.stack, stack);
+            let res = if let Some(block_id) = body.as_lua_function() {
+                runtime::execute_frames(machine, Entry::Call(block_id), Runner::Coroutine)
+            } else if let Some(native) = body.as_native_function() {
+                runtime::call_native(&native, machine).map(|()| Exit::Returned)
+            } else {
+                Err(EvalError::from(TypeError::IsNotCallable(body.clone())))
+            };
+            (caller_stack, body.as_lua_function(), res)
+        }
+        State::Suspended(Suspended {
+            stack,
+            program_counter,
+            tail,
+            call_depth,
+            layouts: _,
+            profiled,
+            body,
+        }) => {
+            let caller_stack = std::mem::replace(&mut machine.stack, stack);
+            machine.usage.call_depth += call_depth;
+            if let Some(profiler) = &mut machine.profiler {
+                profiler.resume(profiled);
+            }
+            let entry = Entry::Resume {
+                program_counter,
+                tail,
+            };
+            (
+                caller_stack,
+                body,
+                runtime::execute_frames(machine, entry, Runner::Coroutine),
+            )
+        }
+        State::Running | State::Dead => unreachable!("coroutine is checked to be suspended"),
+    };
+    machine.coroutines.pop();
+    let mut stack = std::mem::replace(&mut machine.stack, caller_stack);
+
+    let state = match res {
+        Ok(Exit::Yielded { tail }) => {
+            let program_counter = machine.program_counter;
+            let layouts = stack.frame_layouts(program_counter.block, &machine.code_blocks);
+            State::Suspended(Suspended {
+                stack,
+                program_counter,
+                tail,
+                call_depth: machine.usage.call_depth - caller_call_depth,
+                layouts,
+                profiled: machine
+                    .profiler
+                    .as_mut()
+                    .map(|profiler| profiler.suspend(profiler_depth))
+                    .unwrap_or_default(),
+                body,
+            })
+        }
+        Ok(Exit::Returned) => {
+            if let Some(body) = body
+                && let ReturnCount::Constant(count) = machine.code_blocks[body].meta.return_count
+            {
+                machine.value_count = count;
+            }
+            State::Dead
+        }
+        Ok(Exit::Paused | Exit::Pending { .. }) => {
+            unreachable!("only the frames of an execution are paused")
+        }
+        Err(_) => {
+            if !stack.is_empty() {
+                let last_fn = &machine.code_blocks[machine.program_counter.block];
+                stack.unwind_to(0, &last_fn.meta, &machine.code_blocks);
+            }
+            if let Some(profiler) = &mut machine.profiler {
+                profiler.unwind_to(profiler_depth);
+            }
+            State::Dead
+        }
+    };
+    machine.usage.call_depth = caller_call_depth;
+    machine.program_counter = caller;
+    coroutine.borrow_mut().state = state;
+    res.map(|_| ())
+}
+
+/// `coroutine.resume(co, ...)`, which returns true followed by the values the coroutine yielded or
+/// returned, or nil and the message, if it failed. Exceeded limits are not caught, so that
+/// scripts can't ignore them.
+pub(crate) fn resume_protected(machine: &mut Machine) -> Result<(), EvalError> {
+    let value_count = machine.value_count as usize;
+    let target = if value_count > 0 {
+        machine.argument_registers.d[0].clone()
+    } else {
+        LuaValue::NIL
+    };
+    let function = coroutine_argument(&target)?;
+    let NativeFunctionKind::Coroutine(coroutine) = &*function.0 else {
+        unreachable!("coroutine argument is checked");
+    };
+    let registers = &mut machine.argument_registers.d;
+    registers.shift_left(1, value_count);
+    machine.value_count = value_count.saturating_sub(1) as u16;
+
+    match resume(&function, coroutine, machine) {
+        Ok(()) => {
+            let registers = &mut machine.argument_registers.d;
+            registers.shift_right(1, machine.value_count as usize);
+            registers[0] = LuaValue::TRUE;
+            machine.value_count += 1;
+        }
+        Err(
+            err @ (EvalError::InstructionLimit(_)
+            | EvalError::CallDepthLimit(_)
+            | EvalError::MemoryLimit(_)),
+        ) => return Err(err),
+        Err(err) => {
+            let registers = &mut machine.argument_registers.d;
+            registers[0] = LuaValue::NIL;
+            registers[1] = LuaValue::string(err.to_string());
+            machine.value_count = 2;
+        }
+    }
+    Ok(())
+}
+
+fn coroutine_argument(value: &LuaValue) -> Result<NativeFunction, TypeError> {
+    value
+        .as_native_function()
+        .filter(NativeFunction::is_thread)
+        .ok_or_else(|| TypeError::ArgumentType {
+            position: 0,
+            expected: ExpectedType::Coroutine,
+            got: value.clone(),
+        })
+}
+
+/// `coroutine.create(f)`, which returns a thread.
+pub fn create(body: &LuaValue) -> Result<LuaValue, TypeError> {
+    function_argument(body).map(|body| LuaValue::native_function(NativeFunction::coroutine(body)))
+}
+
+/// `coroutine.wrap(f)`, which returns a function, that resumes the coroutine.
+pub fn wrap(body: &LuaValue) -> Result<LuaValue, TypeError> {
+    function_argument(body)
+        .map(|body| LuaValue::native_function(NativeFunction::wrapped_coroutine(body)))
+}
+
+fn function_argument(body: &LuaValue) -> Result<LuaValue, TypeError> {
+    if body.is_lua_function() || body.is_native_function() {
+        Ok(body.clone())
+    } else {
+        Err(TypeError::ArgumentType {
+            position: 0,
+            expected: ExpectedType::Function,
+            got: body.clone(),
+        })
+    }
+}
+
+/// `coroutine.status(co)`, which is one of "suspended", "running", "normal" and "dead".
+pub fn coroutine_status(machine: &mut Machine, args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let function = coroutine_argument(args.first().unwrap_or(&LuaValue::NIL))?;
+    let NativeFunctionKind::Coroutine(coroutine) = &*function.0 else {
+        unreachable!("coroutine argument is checked");
+    };
+    Ok(LuaValue::string(
+        status(&function, coroutine, machine).as_str(),
+    ))
+}
+
+pub fn resume_function() -> NativeFunction {
+    NativeFunction(Rc::new(NativeFunctionKind::Resume))
+}
+
+pub fn yield_function() -> NativeFunction {
+    NativeFunction(Rc::new(NativeFunctionKind::Yield))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        call_value, eval_str, CoroutineStatus, EvalError, ExpectedType, LuaError, LuaValue,
+        Machine, TypeError,
+    };
+
+    #[test]
+    fn generators_yield_values_one_by_one() {
+        let mut machine = Machine::with_stdlib();
+        let res: (i32, i32, i32, LuaValue, LuaValue) = eval_str(
+            "
+            function range(from, to)
+                local i = from
+                while i <= to do
+                    coroutine.yield(i)
+                    i = i + 1
+                end
+            end
+            local gen = coroutine.create(range)
+            local ok, first = coroutine.resume(gen, 1, 3)
+            local _, second = coroutine.resume(gen)
+            local _, third = coroutine.resume(gen)
+            local done, fourth = coroutine.resume(gen)
+            return first, second, third, done, fourth
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, (1, 2, 3, LuaValue::TRUE, LuaValue::NIL));
+    }
+
+    #[test]
+    fn values_are_passed_both_ways() {
+        let mut machine = Machine::with_stdlib();
+        let res: (i32, i32, i32, i32) = eval_str(
+            "
+            function accumulate(start)
+                local total = start
+                while 1 do
+                    local add = coroutine.yield(total, total * 2)
+                    total = total + add
+                end
+            end
+            local co = coroutine.wrap(accumulate)
+            co(10)
+            co(5)
+            local total, double = co(1)
+            return total, double, co(4), 0
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, (16, 32, 20, 0));
+    }
+
+    #[test]
+    fn created_coroutines_are_threads_and_wrapped_ones_are_functions() {
+        let mut machine = Machine::with_stdlib();
+        let res: (String, String) = eval_str(
+            "
+            function body() end
+            return type(coroutine.create(body)), type(coroutine.wrap(body))
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, ("thread".to_owned(), "function".to_owned()));
+
+        let res = eval_str::<()>("local co = coroutine.create(body) co()", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::TypeError(err)))
+                if matches!(*err, TypeError::IsNotCallable(_))
+        ));
+        for code in [
+            "coroutine.resume(coroutine.wrap(body))",
+            "coroutine.status(coroutine.wrap(body))",
+        ] {
+            let res = eval_str::<()>(code, &mut machine);
+            assert!(matches!(
+                res,
+                Err(LuaError::Eval(EvalError::TypeError(err))) if matches!(
+                    *err,
+                    TypeError::ArgumentType { expected: ExpectedType::Coroutine, .. }
+                )
+            ));
+        }
+    }
+
+    #[test]
+    fn status_follows_the_lifecycle() {
+        let mut machine = Machine::with_stdlib();
+        let res: (String, String, String, String, String) = eval_str(
+            "
+            function body()
+                local inner = coroutine.create(function_inner)
+                local _, status_of_outer = coroutine.resume(inner)
+                coroutine.yield(coroutine.status(outer), status_of_outer)
+            end
+            function function_inner()
+                coroutine.yield(coroutine.status(outer))
+            end
+            outer = coroutine.create(body)
+            local before = coroutine.status(outer)
+            local _, running, normal = coroutine.resume(outer)
+            local suspended = coroutine.status(outer)
+            coroutine.resume(outer)
+            return before, running, normal, suspended, coroutine.status(outer)
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let expected = ["suspended", "running", "normal", "suspended", "dead"];
+        assert_eq!(
+            [res.0, res.1, res.2, res.3, res.4],
+            expected.map(String::from)
+        );
+    }
+
+    #[test]
+    fn tail_called_yield_returns_the_resumed_values() {
+        let mut machine = Machine::with_stdlib();
+        let res: (i32, i32) = eval_str(
+            "
+            function pass(x)
+                return coroutine.yield(x)
+            end
+            function body(x)
+                local y = pass(x)
+                return y * 2
+            end
+            local co = coroutine.wrap(body)
+            local first = co(3)
+            return first, co(21)
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, (3, 42));
+    }
+
+    #[test]
+    fn errors_kill_the_coroutine() {
+        let mut machine = Machine::with_stdlib();
+        let res: (LuaValue, String, LuaValue, String) = eval_str(
+            "
+            function fail() local x = nil + 1 end
+            local co = coroutine.create(fail)
+            local ok, message = coroutine.resume(co)
+            local again, dead = coroutine.resume(co)
+            return ok, coroutine.status(co), again, dead
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res.0, LuaValue::NIL);
+        assert_eq!(res.1, "dead");
+        assert_eq!(res.2, LuaValue::NIL);
+        assert_eq!(
+            res.3,
+            EvalError::CannotResume(CoroutineStatus::Dead).to_string()
+        );
+
+        // Calling a coroutine directly propagates the error
+        let res = eval_str::<()>("local co = coroutine.wrap(fail) co()", &mut machine);
+        assert!(matches!(res, Err(LuaError::Eval(EvalError::TypeError(_)))));
+    }
+
+    #[test]
+    fn yields_outside_of_coroutines_and_across_native_calls_are_errors() {
+        let mut machine = Machine::with_stdlib();
+        let res = eval_str::<()>("coroutine.yield(1)", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::YieldOutsideCoroutine))
+        ));
+
+        machine.set_global(
+            "call",
+            LuaValue::reentrant_function(|machine: &mut Machine, args: &[LuaValue]| {
+                call_value::<LuaValue>(&args[0], &[], machine)
+            }),
+        );
+        let res: (LuaValue, String) = eval_str(
+            "
+            function yielder() coroutine.yield(1) end
+            function body() call(yielder) end
+            return coroutine.resume(coroutine.create(body))
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res.0, LuaValue::NIL);
+        assert_eq!(res.1, EvalError::YieldAcrossNativeCall.to_string());
+    }
+
+    #[test]
+    fn abandoned_coroutines_release_their_frames() {
+        let mut machine = Machine::with_stdlib();
+        let res: i32 = eval_str(
+            "
+            function body(t)
+                local s = 'local string'
+                local inner = t
+                coroutine.yield(1)
+            end
+            local co = coroutine.create(body)
+            coroutine.resume(co, {})
+            co = nil
+            return 1
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, 1);
+        drop(machine);
+    }
+}
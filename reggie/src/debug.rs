@@ -0,0 +1,299 @@
+//! Hooks for debuggers.
+//!
+//! A hook is installed by setting [`Machine::debug_hook`](crate::Machine::debug_hook). It is called
+//! as the machine executes, and gets a [`DebugFrame`] to inspect the frame of the running
+//! function. Lines are only known for the blocks compiled with debug info, see
+//! [`eval_str_with_debug_info`](crate::eval_str_with_debug_info). Like the profiler, hooks keep the
+//! JIT from running, as native code doesn't report what it executes.
+
+use crate::{
+    call_stack::FrameHandle,
+    ids::LocalRegisterID,
+    machine::{ArgumentRegisters, DataType, ProgramCounter},
+    meta::{CodeMeta, DebugInfo, LocalRegCount},
+    LuaValue, MaybeSend,
+};
+
+/// Every method does nothing by default.
+pub trait DebugHook: MaybeSend {
+    /// Execution reached the first instruction of a line.
+    fn line(&mut self, _frame: &mut DebugFrame, _line: u32) {}
+
+    /// A lua function was called, and its frame was just entered. Argument registers still hold
+    /// the arguments. Tail calls are reported as a return from the caller followed by a call.
+    fn call(&mut self, _frame: &mut DebugFrame) {}
+
+    /// The function is about to return, its frame is still there.
+    fn ret(&mut self, _frame: &mut DebugFrame) {}
+}
+
+/// Frame of the running function.
+pub struct DebugFrame<'a> {
+    program_counter: ProgramCounter,
+    meta: &'a CodeMeta,
+    frame: FrameHandle<'a, 'a>,
+    argument_registers: &'a ArgumentRegisters,
+    value_count: u16,
+}
+
+impl<'a> DebugFrame<'a> {
+    pub(crate) fn new(
+        program_counter: ProgramCounter,
+        meta: &'a CodeMeta,
+        frame: FrameHandle<'a, 'a>,
+        argument_registers: &'a ArgumentRegisters,
+        value_count: u16,
+    ) -> Self {
+        Self {
+            program_counter,
+            meta,
+            frame,
+            argument_registers,
+            value_count,
+        }
+    }
+
+    pub fn program_counter(&self) -> ProgramCounter {
+        self.program_counter
+    }
+
+    pub fn function_name(&self) -> Option<&str> {
+        self.meta.debug_name.as_deref()
+    }
+
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.meta.debug_info.as_ref()
+    }
+
+    /// Line of the instruction, which is about to be executed.
+    pub fn line(&self) -> Option<u32> {
+        self.debug_info()?.line_at(self.program_counter.position)
+    }
+
+    pub fn local_count(&self) -> &LocalRegCount {
+        &self.meta.local_count
+    }
+
+    /// Value of a local register. Typed registers are converted to the values they hold.
+    pub fn local(&mut self, data_type: DataType, index: u16) -> Option<LuaValue> {
+        if index >= self.meta.local_count[data_type] {
+            return None;
+        }
+        let reg = LocalRegisterID(index);
+        let value = match data_type {
+            DataType::Dynamic => self.frame.get_dyn(reg).clone(),
+            DataType::Int => LuaValue::int(*self.frame.get_int(reg)),
+            DataType::Float => LuaValue::float(*self.frame.get_float(reg)),
+            DataType::String => LuaValue::string(self.frame.get_string(reg).clone()),
+            DataType::Function => LuaValue::lua_function(*self.frame.get_function(reg)),
+            DataType::NativeFunction => match self.frame.get_native_function(reg) {
+                Some(function) => LuaValue::native_function(function.clone()),
+                None => LuaValue::NIL,
+            },
+            DataType::Table => match self.frame.get_table(reg) {
+                Some(table) => LuaValue::table(table.clone()),
+                None => LuaValue::NIL,
+            },
+        };
+        Some(value)
+    }
+
+    /// Value of a local variable, which is in scope. Requires debug info.
+    pub fn named_local(&mut self, name: &str) -> Option<LuaValue> {
+        let meta = self.meta;
+        let local = meta
+            .debug_info
+            .as_ref()?
+            .locals_at(self.program_counter.position)
+            .rev()
+            .find(|local| local.name == name)?;
+        self.local(DataType::Dynamic, local.register.0)
+    }
+
+    /// Local variables in scope, in the order of declaration, without the shadowed ones.
+    pub fn named_locals(&mut self) -> Vec<(String, LuaValue)> {
+        let meta = self.meta;
+        let Some(debug_info) = &meta.debug_info else {
+            return Vec::new();
+        };
+        let locals: Vec<_> = debug_info
+            .locals_at(self.program_counter.position)
+            .collect();
+        locals
+            .iter()
+            .enumerate()
+            .filter(|(index, local)| {
+                !locals[index + 1..]
+                    .iter()
+                    .any(|later| later.name == local.name)
+            })
+            .map(|(_, local)| {
+                let value = self
+                    .local(DataType::Dynamic, local.register.0)
+                    .unwrap_or(LuaValue::NIL);
+                (local.name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Dynamic argument registers, up to the value count. On call, they are the arguments of the
+    /// function.
+    pub fn arguments(&self) -> &[LuaValue] {
+        let registers = &self.argument_registers.d;
+        &registers[..registers.len().min(self.value_count as usize)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{DebugFrame, DebugHook};
+    use crate::{eval_str, eval_str_with_debug_info, LuaValue, Machine};
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<String>>>,
+        /// Locals are recorded on this line
+        inspected_line: u32,
+    }
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl DebugHook for Recorder {
+        fn line(&mut self, frame: &mut DebugFrame, line: u32) {
+            self.record(format!("line {line}"));
+            if line == self.inspected_line {
+                for (name, value) in frame.named_locals() {
+                    self.record(format!("{name} = {value}"));
+                }
+            }
+        }
+
+        fn call(&mut self, frame: &mut DebugFrame) {
+            let arguments = frame.arguments().len();
+            self.record(format!(
+                "call {} with {arguments}",
+                frame.function_name().unwrap_or_default()
+            ));
+        }
+
+        fn ret(&mut self, frame: &mut DebugFrame) {
+            self.record(format!("ret {}", frame.function_name().unwrap_or_default()));
+        }
+    }
+
+    fn record(source: &str, inspected_line: u32) -> (LuaValue, Vec<String>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = Machine::with_stdlib();
+        machine.debug_hook = Some(Box::new(Recorder {
+            events: Arc::clone(&events),
+            inspected_line,
+        }));
+        let res = eval_str_with_debug_info(source, "test.lua", &mut machine).unwrap();
+        let events = std::mem::take(&mut *events.lock().unwrap());
+        (res, events)
+    }
+
+    #[test]
+    fn lines_calls_and_returns_are_reported() {
+        let (res, events) = record(
+            "function add(a, b)
+                local sum = a + b
+                return sum
+            end
+            local x = 1
+            local y = add(x, 2)
+            return y",
+            0,
+        );
+        assert_eq!(res, LuaValue::int(3));
+        assert_eq!(
+            events,
+            [
+                "call <module root> with 0",
+                "line 1",
+                "line 5",
+                "line 6",
+                "call <dyn wrapper for function add> with 2",
+                "call add with 2",
+                "line 2",
+                "line 3",
+                "ret add",
+                "ret <dyn wrapper for function add>",
+                "line 7",
+                "ret <module root>",
+            ]
+        );
+    }
+
+    #[test]
+    fn loops_report_their_lines_on_every_iteration() {
+        let (_, events) = record(
+            "local i = 0
+            while i < 2 do
+                i = i + 1
+            end",
+            0,
+        );
+        let lines: Vec<_> = events
+            .iter()
+            .filter(|event| event.starts_with("line"))
+            .collect();
+        assert_eq!(
+            lines,
+            ["line 1", "line 2", "line 3", "line 2", "line 3", "line 2"]
+        );
+    }
+
+    #[test]
+    fn locals_in_scope_are_inspected_by_name() {
+        let (_, events) = record(
+            "function f(a)
+                local b = a * 2
+                if b then
+                    local b = 'inner'
+                    local c = b
+                    return c
+                end
+            end
+            f(21)",
+            6,
+        );
+        let at_line = events.iter().position(|event| event == "line 6").unwrap();
+        assert_eq!(
+            events[at_line + 1..at_line + 4],
+            ["a = 21", "b = \"inner\"", "c = \"inner\""]
+        );
+    }
+
+    #[test]
+    fn local_registers_are_read_by_type() {
+        // Values are recorded by their debug representation, as hooks may be sent between threads
+        struct Inspector(Arc<Mutex<Vec<String>>>);
+        impl DebugHook for Inspector {
+            fn ret(&mut self, frame: &mut DebugFrame) {
+                let locals = crate::DataType::Dynamic;
+                let count = frame.local_count()[locals];
+                for index in 0..count {
+                    let value = frame.local(locals, index).unwrap();
+                    self.0.lock().unwrap().push(format!("{value:?}"));
+                }
+                assert_eq!(frame.local(locals, count), None);
+                let ints = frame.local_count()[crate::DataType::Int];
+                assert_eq!(frame.local(crate::DataType::Int, ints), None);
+            }
+        }
+
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = Machine::with_stdlib();
+        machine.debug_hook = Some(Box::new(Inspector(Arc::clone(&values))));
+        eval_str::<()>("local a, b = 'x', 2", &mut machine).unwrap();
+        let expected = [LuaValue::string("x"), LuaValue::int(2)].map(|value| format!("{value:?}"));
+        assert_eq!(*values.lock().unwrap(), expected);
+    }
+}
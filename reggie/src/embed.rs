@@ -0,0 +1,204 @@
+//! Typed access to the machine for the programs, which embed lua.
+//!
+//! Values cross the boundary through [`IntoLua`] and [`FromLua`], arguments of native functions
+//! through [`FromArgs`], and the values returned from lua through [`FromReturn`]. Conversions,
+//! which don't match the type of the value, fail with a [`TypeError`].
+
+use std::marker::PhantomData;
+
+use crate::{
+    call_with_arguments, EvalError, FFIFunc, FromArgs, FromLua, FromReturn, IntoArgs, IntoLua,
+    LuaValue, Machine, MaybeSend, TypeError,
+};
+
+/// Value, which can be stored in a global. Plain values are converted with [`IntoLua`], and rust
+/// functions of up to 12 arguments become native functions. `Marker` tells the two apart, and is
+/// always inferred.
+pub trait IntoGlobal<Marker> {
+    fn into_global(self) -> LuaValue;
+}
+
+/// Marks values, which are converted with [`IntoLua`].
+pub struct ValueMarker;
+
+/// Marks rust functions, which take `Args`.
+pub struct FunctionMarker<Args>(PhantomData<Args>);
+
+impl<T: IntoLua> IntoGlobal<ValueMarker> for T {
+    fn into_global(self) -> LuaValue {
+        self.into_lua()
+    }
+}
+
+impl<'a, F, Args> IntoGlobal<FunctionMarker<Args>> for F
+where
+    F: FFIFunc<Args> + MaybeSend + 'static,
+    Args: FromArgs<'a> + 'static,
+{
+    fn into_global(self) -> LuaValue {
+        LuaValue::function(self)
+    }
+}
+
+impl Machine {
+    pub fn set_global<Marker>(&mut self, name: &str, value: impl IntoGlobal<Marker>) {
+        self.global_values.set(name, value.into_global());
+    }
+
+    /// Globals, which were never set, are nil.
+    pub fn get_global<'a, T: FromLua<'a>>(&'a self, name: &str) -> Result<T, TypeError> {
+        let value = self.global_values.get(name);
+        T::from_lua(value).map_err(|expected| TypeError::GlobalType {
+            name: name.to_owned(),
+            expected,
+            got: value.clone(),
+        })
+    }
+
+    /// Calls the function, which is stored in the global.
+    pub fn call_global<'a, T: FromReturn<'a>>(
+        &'a mut self,
+        name: &str,
+        arguments: impl IntoArgs,
+    ) -> Result<T, EvalError> {
+        let function = self.global_values.get(name).clone();
+        self.call(&function, arguments)
+    }
+
+    /// Calls a function value, the same way as [`call_value`](crate::call_value) does.
+    pub fn call<'a, T: FromReturn<'a>>(
+        &'a mut self,
+        function: &LuaValue,
+        arguments: impl IntoArgs,
+    ) -> Result<T, EvalError> {
+        self.value_count = arguments.into_args(&mut self.argument_registers);
+        call_with_arguments(function, self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        eval_str, EvalError, ExpectedType, LuaError, LuaString, LuaValue, Machine, TableRef,
+        TypeError,
+    };
+
+    #[test]
+    fn typed_globals_are_set_and_read() {
+        let mut machine = Machine::with_stdlib();
+        machine.set_global("answer", 42);
+        machine.set_global("name", "lua");
+        machine.set_global("nothing", None::<i32>);
+        let res: LuaValue = eval_str("return answer + 1, name, nothing", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(43));
+
+        eval_str::<()>("t = {} ratio = 0.5", &mut machine).unwrap();
+        assert_eq!(machine.get_global::<i32>("answer"), Ok(42));
+        assert_eq!(machine.get_global::<&str>("name"), Ok("lua"));
+        assert_eq!(machine.get_global::<f64>("ratio"), Ok(0.5));
+        assert_eq!(machine.get_global::<Option<i32>>("missing"), Ok(None));
+        assert!(machine.get_global::<TableRef>("t").is_ok());
+        assert_eq!(
+            machine.get_global::<i32>("name"),
+            Err(TypeError::GlobalType {
+                name: "name".to_owned(),
+                expected: ExpectedType::Integer,
+                got: LuaValue::string("lua"),
+            })
+        );
+    }
+
+    #[test]
+    fn closures_become_native_functions() {
+        let mut machine = Machine::with_stdlib();
+        machine.set_global("repeat_str", |s: &str, n: i32| s.repeat(n as usize));
+        machine.set_global("sum", |a: i32, b: i32, c: Option<i32>| {
+            a + b + c.unwrap_or(0)
+        });
+        machine.set_global("divmod", |a: i32, b: i32| (a / b, a % b));
+        machine.set_global("pi", || std::f64::consts::PI);
+
+        let res: LuaValue = eval_str("return repeat_str('ab', 3)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::string("ababab"));
+        let (q, r): (i32, i32) = eval_str("return divmod(17, 5)", &mut machine).unwrap();
+        assert_eq!((q, r), (3, 2));
+        let res: (i32, i32) = eval_str("return sum(1, 2), sum(1, 2, 3)", &mut machine).unwrap();
+        assert_eq!(res, (3, 6));
+        let res: f64 = eval_str("return pi()", &mut machine).unwrap();
+        assert_eq!(res, std::f64::consts::PI);
+    }
+
+    #[test]
+    fn mismatched_arguments_are_type_errors() {
+        let mut machine = Machine::with_stdlib();
+        machine.set_global("twice", |n: i32| n * 2);
+        let res = eval_str::<LuaValue>("return twice('x')", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::TypeError(err))) if *err == TypeError::ArgumentType {
+                position: 0,
+                expected: ExpectedType::Integer,
+                got: LuaValue::string("x"),
+            }
+        ));
+        let res: i32 = eval_str("return twice(2.0)", &mut machine).unwrap();
+        assert_eq!(res, 4);
+    }
+
+    #[test]
+    fn lua_functions_are_called_with_typed_arguments() {
+        let mut machine = Machine::with_stdlib();
+        eval_str::<()>(
+            "
+            function greet(greeting, name) return greeting .. ', ' .. name, name == 'world' end
+            function none() end
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let (greeting, is_world): (LuaString, bool) = machine
+            .call_global("greet", ("Hello", String::from("world")))
+            .unwrap();
+        assert_eq!(greeting, LuaString::from("Hello, world"));
+        assert!(is_world);
+
+        let res: Option<i32> = machine.call_global("none", ()).unwrap();
+        assert_eq!(res, None);
+
+        let res = machine.call_global::<i32>("greet", ("a", "b"));
+        assert!(matches!(
+            res,
+            Err(EvalError::TypeError(err)) if matches!(*err, TypeError::ReturnType { position: 0, .. })
+        ));
+
+        let res = machine.call_global::<()>("missing", (1,));
+        assert!(matches!(
+            res,
+            Err(EvalError::TypeError(err)) if matches!(*err, TypeError::IsNotCallable(_))
+        ));
+    }
+
+    #[test]
+    fn functions_of_twelve_arguments_and_returns() {
+        let mut machine = Machine::with_stdlib();
+        machine.set_global(
+            "twelve",
+            |a: i32,
+             b: i32,
+             c: i32,
+             d: i32,
+             e: i32,
+             f: i32,
+             g: i32,
+             h: i32,
+             i: i32,
+             j: i32,
+             k: i32,
+             l: i32| { (l, k, j, i, h, g, f, e, d, c, b, a) },
+        );
+        let res: (i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32) = machine
+            .call_global("twelve", (1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12))
+            .unwrap();
+        assert_eq!(res, (12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{LuaString, LuaValue};
+use crate::{CoroutineStatus, LuaString, LuaValue};
 use luar_syn::{ParseError, ParseErrorWithSourcePosition, RawParseError};
 use std::{error::Error, fmt};
 
@@ -34,6 +34,32 @@ pub enum EvalError {
     AssertionError(Option<LuaString>),
     IO(#[from] std::io::Error),
     Utf8Error,
+    InstructionLimit(u64),
+    CallDepthLimit(usize),
+    MemoryLimit(usize),
+    JsonEncode(String),
+    JsonDecode {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    /// Code, which was loaded while the machine was running, is not valid lua
+    Parse {
+        chunk: String,
+        error: Box<ParseError>,
+    },
+    ModuleNotFound(String),
+    /// Modules, which require each other, from the outermost one
+    CyclicRequire(Vec<String>),
+    YieldOutsideCoroutine,
+    YieldAcrossNativeCall,
+    CannotResume(CoroutineStatus),
+    /// Native function is pending, but the script is not run by an execution, that could wait for it
+    PendingOutsideExecution,
+    /// Code, which native code of the JIT called, panicked with the message
+    JitPanic(String),
+    /// Native function returned with a different stack or running block, than it was called with
+    CorruptedCallStack,
 }
 
 impl fmt::Display for EvalError {
@@ -44,6 +70,32 @@ impl fmt::Display for EvalError {
             Self::AssertionError(None) => write!(f, "Assertion failed"),
             Self::IO(err) => write!(f, "IO Error: {}", err),
             Self::Utf8Error => write!(f, "Operation produced invalid utf-8 sequence"),
+            Self::InstructionLimit(limit) => write!(f, "Executed more than {} instructions", limit),
+            Self::CallDepthLimit(limit) => write!(f, "Call stack is deeper than {} frames", limit),
+            Self::MemoryLimit(limit) => write!(f, "Allocated more than {} bytes", limit),
+            Self::JsonEncode(msg) => write!(f, "Cannot encode value as json: {}", msg),
+            Self::JsonDecode {
+                message,
+                line,
+                column,
+            } => write!(f, "Invalid json at line {}, column {}: {}", line, column, message),
+            Self::Parse { chunk, error } => write!(f, "Cannot parse {}: {}", chunk, error),
+            Self::ModuleNotFound(name) => write!(f, "Module {} is not found", name),
+            Self::CyclicRequire(modules) => {
+                write!(f, "Modules require each other: {}", modules.join(" -> "))
+            }
+            Self::YieldOutsideCoroutine => write!(f, "Cannot yield outside of a coroutine"),
+            Self::YieldAcrossNativeCall => {
+                write!(f, "Cannot yield across a call of a native function")
+            }
+            Self::CannotResume(status) => write!(f, "Cannot resume a {} coroutine", status),
+            Self::PendingOutsideExecution => {
+                write!(f, "Native function can only be pending in an execution")
+            }
+            Self::JitPanic(message) => write!(f, "Compiled code panicked: {}", message),
+            Self::CorruptedCallStack => {
+                write!(f, "Native function didn't leave the call stack as it found it")
+            }
         }
     }
 }
@@ -63,6 +115,16 @@ pub enum TypeError {
         expected: ExpectedType,
         got: LuaValue,
     },
+    ReturnType {
+        position: usize,
+        expected: ExpectedType,
+        got: LuaValue,
+    },
+    GlobalType {
+        name: String,
+        expected: ExpectedType,
+        got: LuaValue,
+    },
     NilAssign(LuaValue),
     NaNAssign(LuaValue),
     IsNotIndexable(LuaValue),
@@ -93,10 +155,19 @@ pub enum TypeError {
     },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExpectedType {
     Number,
+    Integer,
     String,
+    Table,
+    Function,
+    Coroutine,
+    /// Table, whose field, converted by a derived `FromLua`, is not of the expected type
+    Field {
+        name: &'static str,
+        expected: Box<ExpectedType>,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -129,7 +200,14 @@ impl fmt::Display for ExpectedType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ExpectedType::Number => "number",
+            ExpectedType::Integer => "integer",
             ExpectedType::String => "string",
+            ExpectedType::Table => "table",
+            ExpectedType::Function => "function",
+            ExpectedType::Coroutine => "coroutine",
+            ExpectedType::Field { name, expected } => {
+                return write!(f, "{} in field {}", expected, name);
+            }
         }
         .fmt(f)
     }
@@ -178,6 +256,28 @@ impl fmt::Display for TypeError {
                     position, expected, got
                 )
             }
+            Self::ReturnType {
+                position,
+                expected,
+                got,
+            } => {
+                write!(
+                    f,
+                    "Invalid return type at position {}, expected {}, got {}",
+                    position, expected, got
+                )
+            }
+            Self::GlobalType {
+                name,
+                expected,
+                got,
+            } => {
+                write!(
+                    f,
+                    "Invalid type of global {}, expected {}, got {}",
+                    name, expected, got
+                )
+            }
             Self::NilAssign(value) => {
                 write!(f, "Tried to assign value {} to a nil key in a table", value)
             }
@@ -0,0 +1,365 @@
+//! Executions run a block in slices, so that the host can interleave many scripts on one thread,
+//! and can wait for the values of native functions, which are not ready yet.
+//!
+//! Frames of the execution stay on the stack of the machine between the slices, which is why the
+//! execution borrows the machine, until it is finished or dropped. Frames of the coroutines, which
+//! the script resumes, and of the lua functions, which native functions call, run to completion
+//! within a slice, and native functions can't be pending inside of them.
+
+use std::task::Poll;
+
+use crate::{
+    ids::BlockID,
+    machine::ProgramCounter,
+    meta::ReturnCount,
+    runtime::{self, Entry, Exit, Runner},
+    EvalError, FromReturn, IntoArgs, Machine, Usage,
+};
+
+pub struct Execution<'m> {
+    machine: &'m mut Machine,
+    block: BlockID,
+    state: State,
+}
+
+enum State {
+    Created,
+    /// Slice ran out
+    Paused(ProgramCounter),
+    /// Native function waits for the host to resolve it
+    Pending {
+        program_counter: ProgramCounter,
+        tail: bool,
+    },
+    /// Values of the pending function are in the argument registers
+    Resolved {
+        program_counter: ProgramCounter,
+        tail: bool,
+    },
+    Finished,
+}
+
+impl Machine {
+    /// Starts an execution of the block, which doesn't run, until it gets a budget of instructions
+    /// with [`Execution::run_for`].
+    ///
+    /// # Panics
+    ///
+    /// If the machine is running, since the execution keeps its frames on the stack between slices.
+    pub fn start(&mut self, block_id: BlockID) -> Execution<'_> {
+        assert!(
+            self.stack.is_empty() && self.coroutines.is_empty(),
+            "execution can't be started while the machine is running"
+        );
+        Execution {
+            machine: self,
+            block: block_id,
+            state: State::Created,
+        }
+    }
+}
+
+impl Execution<'_> {
+    /// Runs the block for at least `budget` instructions, and pauses at the next loop iteration or
+    /// function call after that. Returns the values of the block, once it returns or fails, and
+    /// pending otherwise, either because the budget ran out, or because a native function is
+    /// pending, and the execution [waits](Execution::is_waiting) for it to be resolved.
+    ///
+    /// # Panics
+    ///
+    /// If the execution is already finished.
+    pub fn run_for<'a, T: FromReturn<'a>>(&'a mut self, budget: u64) -> Poll<Result<T, EvalError>> {
+        let machine = &mut *self.machine;
+        let entry = match std::mem::replace(&mut self.state, State::Finished) {
+            State::Created => {
+                machine.usage = Usage::default();
+                Entry::Call(self.block)
+            }
+            State::Paused(program_counter) => Entry::Resume {
+                program_counter,
+                tail: false,
+            },
+            state @ State::Pending { .. } => {
+                self.state = state;
+                return Poll::Pending;
+            }
+            State::Resolved {
+                program_counter,
+                tail,
+            } => Entry::Resume {
+                program_counter,
+                tail,
+            },
+            State::Finished => panic!("execution is run after it finished"),
+        };
+
+        let slice_end = machine.usage.instructions.saturating_add(budget);
+        match runtime::execute_frames(machine, entry, Runner::Execution { slice_end }) {
+            Ok(Exit::Returned) => {
+                let return_count = match machine.code_blocks[self.block].meta.return_count {
+                    ReturnCount::Constant(count) => count,
+                    _ => machine.value_count,
+                };
+                let machine = &*self.machine;
+                Poll::Ready(T::from_machine_state(machine, return_count).map_err(EvalError::from))
+            }
+            Ok(Exit::Paused) => {
+                self.state = State::Paused(machine.program_counter);
+                Poll::Pending
+            }
+            Ok(Exit::Pending { tail }) => {
+                self.state = State::Pending {
+                    program_counter: machine.program_counter,
+                    tail,
+                };
+                Poll::Pending
+            }
+            Ok(Exit::Yielded { .. }) => unreachable!("only the frames of a coroutine yield"),
+            Err(err) => {
+                unwind(machine);
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+
+    /// Resolves the pending native function with the values it returns. Script continues with the
+    /// next slice.
+    ///
+    /// # Panics
+    ///
+    /// If the execution doesn't wait for a native function.
+    pub fn resolve<A: IntoArgs>(&mut self, values: A) {
+        let State::Pending {
+            program_counter,
+            tail,
+        } = self.state
+        else {
+            panic!("execution doesn't wait for a native function");
+        };
+        self.machine.value_count = values.into_args(&mut self.machine.argument_registers);
+        self.state = State::Resolved {
+            program_counter,
+            tail,
+        };
+    }
+
+    /// A native function is pending, and the execution doesn't run, until it is resolved.
+    pub fn is_waiting(&self) -> bool {
+        matches!(self.state, State::Pending { .. })
+    }
+
+    /// The block returned or failed.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Finished)
+    }
+}
+
+impl Drop for Execution<'_> {
+    fn drop(&mut self) {
+        if let State::Paused(_) | State::Pending { .. } | State::Resolved { .. } = self.state {
+            unwind(self.machine);
+        }
+    }
+}
+
+/// Releases the frames of the execution, which failed or is abandoned.
+fn unwind(machine: &mut Machine) {
+    if !machine.stack.is_empty() {
+        let last_fn = &machine.code_blocks[machine.program_counter.block];
+        machine
+            .stack
+            .unwind_to(0, &last_fn.meta, &machine.code_blocks);
+    }
+    if let Some(profiler) = &mut machine.profiler {
+        profiler.unwind_to(0);
+    }
+    machine.usage.call_depth = 0;
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::Poll;
+
+    use crate::{eval_str, ids::BlockID, EvalError, LuaError, LuaValue, Machine, NativeFunction};
+
+    fn load(code: &str, machine: &mut Machine) -> BlockID {
+        machine.load(code).unwrap().as_lua_function().unwrap()
+    }
+
+    fn run_to_end(machine: &mut Machine, block: BlockID, budget: u64) -> (LuaValue, usize) {
+        let mut execution = machine.start(block);
+        let mut slices = 1;
+        loop {
+            match execution.run_for::<LuaValue>(budget) {
+                Poll::Ready(res) => return (res.unwrap(), slices),
+                Poll::Pending => slices += 1,
+            }
+        }
+    }
+
+    #[test]
+    fn loops_and_recursion_run_in_slices() {
+        let mut machine = Machine::with_stdlib();
+        let block = load(
+            "
+            local sum = 0
+            local i = 0
+            while i < 1000 do
+                sum = sum + i
+                i = i + 1
+            end
+            return sum
+            ",
+            &mut machine,
+        );
+        let (res, slices) = run_to_end(&mut machine, block, 100);
+        assert_eq!(res, LuaValue::int(499500));
+        assert!(slices > 10);
+        let (res, slices) = run_to_end(&mut machine, block, u64::MAX);
+        assert_eq!(res, LuaValue::int(499500));
+        assert_eq!(slices, 1);
+
+        let block = load(
+            "
+            function fib(n)
+                if n < 2 then return n end
+                return fib(n - 1) + fib(n - 2)
+            end
+            return fib(15)
+            ",
+            &mut machine,
+        );
+        let (res, slices) = run_to_end(&mut machine, block, 100);
+        assert_eq!(res, LuaValue::int(610));
+        assert!(slices > 10);
+    }
+
+    #[test]
+    fn pending_functions_wait_for_the_host() {
+        let mut machine = Machine::with_stdlib();
+        machine.set_global(
+            "fetch",
+            NativeFunction::suspending(|_: &mut Machine, args: &[LuaValue]| {
+                match args[0].as_int() {
+                    Some(0) => Poll::Ready(LuaValue::int(0)),
+                    _ => Poll::Pending,
+                }
+            }),
+        );
+        let block = load(
+            "
+            function fetch_twice(key)
+                local first = fetch(key)
+                return fetch(first)
+            end
+            return fetch(0) + fetch_twice(1)
+            ",
+            &mut machine,
+        );
+        let mut execution = machine.start(block);
+        assert!(execution.run_for::<LuaValue>(1000).is_pending());
+        assert!(execution.is_waiting());
+        // Pending function doesn't run, until it is resolved
+        assert!(execution.run_for::<LuaValue>(1000).is_pending());
+        execution.resolve((LuaValue::int(2),));
+        assert!(execution.run_for::<LuaValue>(1000).is_pending());
+        assert!(execution.is_waiting());
+        execution.resolve((LuaValue::int(40),));
+        let res = execution.run_for::<LuaValue>(1000);
+        assert!(matches!(res, Poll::Ready(Ok(value)) if value == LuaValue::int(40)));
+        assert!(execution.is_finished());
+        drop(execution);
+
+        // Ready values don't need an execution, and pending ones are errors outside of it
+        let res: LuaValue = eval_str("return fetch(0)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(0));
+        let res = eval_str::<LuaValue>("return fetch(1) + 1", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::PendingOutsideExecution))
+        ));
+    }
+
+    #[test]
+    fn errors_finish_the_execution() {
+        let mut machine = Machine::with_stdlib();
+        let block = load(
+            "
+            function fail(n)
+                if n == 0 then return nil + 1 end
+                return 1 + fail(n - 1)
+            end
+            return fail(10)
+            ",
+            &mut machine,
+        );
+        let mut execution = machine.start(block);
+        let res = loop {
+            if let Poll::Ready(res) = execution.run_for::<LuaValue>(5) {
+                break res;
+            }
+        };
+        assert!(matches!(res, Err(EvalError::TypeError(_))));
+        assert!(execution.is_finished());
+        drop(execution);
+        assert!(machine.stack.is_empty());
+        assert_eq!(machine.usage.call_depth, 0);
+    }
+
+    #[test]
+    fn dropped_executions_release_their_frames() {
+        let mut machine = Machine::with_stdlib();
+        let block = load(
+            "
+            function spin(t, s)
+                while 1 do end
+            end
+            spin({}, 'string')
+            ",
+            &mut machine,
+        );
+        let mut execution = machine.start(block);
+        assert!(execution.run_for::<()>(1000).is_pending());
+        drop(execution);
+        assert!(machine.stack.is_empty());
+
+        let res: LuaValue = eval_str("return 1 + 2", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(3));
+    }
+
+    #[test]
+    fn machines_are_interleaved() {
+        let code = "
+            local i = 0
+            while i < 100 do
+                count = count + 1
+                i = i + 1
+            end
+            return count
+        ";
+        let mut machines = [Machine::with_stdlib(), Machine::with_stdlib()];
+        let mut executions: Vec<_> = machines
+            .iter_mut()
+            .enumerate()
+            .map(|(index, machine)| {
+                machine.set_global("count", LuaValue::int(index as i32 * 1000));
+                let block = load(code, machine);
+                machine.start(block)
+            })
+            .collect();
+        let mut results = [None, None];
+        while results.iter().any(Option::is_none) {
+            for (execution, result) in executions.iter_mut().zip(&mut results) {
+                if result.is_none()
+                    && let Poll::Ready(res) = execution.run_for::<LuaValue>(50)
+                {
+                    *result = Some(res.unwrap());
+                }
+            }
+        }
+        assert_eq!(
+            results,
+            [Some(LuaValue::int(100)), Some(LuaValue::int(1100))]
+        );
+    }
+}
@@ -1,24 +1,41 @@
 use std::collections::HashMap;
 
-use crate::{LuaValue, ids::GlobalCellID};
+use crate::{
+    ids::{BlockID, GlobalCellID},
+    LuaString, LuaValue,
+};
 use keyed_vec::KeyedVec;
 
 #[derive(Debug, Clone)]
 pub struct GlobalValueCell {
     pub value: LuaValue,
     pub name: String,
+    /// Blocks, which were speculatively optimized assuming the value doesn't change
+    dependents: Vec<BlockID>,
 }
 
 impl GlobalValueCell {
     pub fn new(name: String) -> Self {
         Self {
             name,
-            value: LuaValue::Nil,
+            value: LuaValue::NIL,
+            dependents: Vec::new(),
         }
     }
 
     pub fn with_value(name: String, value: LuaValue) -> Self {
-        Self { name, value }
+        Self {
+            name,
+            value,
+            dependents: Vec::new(),
+        }
+    }
+
+    fn set(&mut self, value: LuaValue, invalidated: &mut Vec<BlockID>) {
+        if !self.dependents.is_empty() && self.value != value {
+            invalidated.append(&mut self.dependents);
+        }
+        self.value = value;
     }
 }
 
@@ -30,9 +47,23 @@ pub struct GlobalValues {
     cells: KeyedVec<GlobalCellID, GlobalValueCell>,
     mapping: HashMap<String, GlobalCellID>,
     global_nil: LuaValue,
+    /// Blocks, which depended on the cells changed since the last [`GlobalValues::take_invalidated`]
+    invalidated: Vec<BlockID>,
+    /// Constant strings of the compiled code. Equal constants share an allocation, so property
+    /// caches mostly match keys by pointer.
+    strings: HashMap<String, LuaString>,
 }
 
 impl GlobalValues {
+    pub fn intern(&mut self, str: &str) -> LuaString {
+        if let Some(interned) = self.strings.get(str) {
+            return interned.clone();
+        }
+        let interned = LuaString::from(str);
+        self.strings.insert(str.to_owned(), interned.clone());
+        interned
+    }
+
     pub fn cell_for_name<I: Into<String> + AsRef<str>>(&mut self, ident: I) -> GlobalCellID {
         let name = ident.into();
         *self
@@ -47,7 +78,7 @@ impl GlobalValues {
         match self.mapping.entry(name) {
             Occupied(entry) => {
                 let id = *entry.get();
-                self.cells[id].value = value;
+                self.cells[id].set(value, &mut self.invalidated);
                 id
             }
             Vacant(entry) => {
@@ -69,7 +100,24 @@ impl GlobalValues {
     }
 
     pub fn set_cell(&mut self, cell_id: GlobalCellID, value: LuaValue) {
-        self.cells[cell_id].value = value;
+        self.cells[cell_id].set(value, &mut self.invalidated);
+    }
+
+    /// Records, that the block assumes the current value of the cell. Once the value changes,
+    /// the block is returned by [`GlobalValues::take_invalidated`].
+    pub(crate) fn add_dependent(&mut self, cell_id: GlobalCellID, block: BlockID) {
+        let dependents = &mut self.cells[cell_id].dependents;
+        if !dependents.contains(&block) {
+            dependents.push(block);
+        }
+    }
+
+    pub(crate) fn has_invalidated(&self) -> bool {
+        !self.invalidated.is_empty()
+    }
+
+    pub(crate) fn take_invalidated(&mut self) -> Vec<BlockID> {
+        std::mem::take(&mut self.invalidated)
     }
 
     pub fn global_nil(&self) -> &LuaValue {
@@ -28,3 +28,4 @@ wrap!(BlockID, u32);
 wrap!(LocalBlockID, u16);
 wrap!(ModuleID, u32);
 wrap!(SimpleBlockID, u16);
+wrap!(PropertyCacheID, u16);
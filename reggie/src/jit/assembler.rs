@@ -0,0 +1,267 @@
+/// Just enough of an x86-64 encoder to emit the baseline code. Only the first eight general
+/// purpose registers are supported, which saves us from dealing with REX.R and REX.B.
+#[derive(Default)]
+pub(super) struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Label)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Label(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(super) enum Reg {
+    Rax = 0,
+    Rcx = 1,
+    Rdx = 2,
+    Rbx = 3,
+    Rsi = 6,
+    Rdi = 7,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(super) enum Cond {
+    Overflow = 0x0,
+    Equal = 0x4,
+    NotEqual = 0x5,
+    Less = 0xC,
+}
+
+const REX_W: u8 = 0x48;
+
+/// ModRM byte addressing `[base + disp32]`. Rsp and rbp bases need special encodings, which is
+/// why they are not in [`Reg`].
+fn modrm_disp32(reg: u8, base: Reg) -> u8 {
+    0b10_000_000 | (reg << 3) | base as u8
+}
+
+fn modrm_reg(reg: u8, rm: Reg) -> u8 {
+    0b11_000_000 | (reg << 3) | rm as u8
+}
+
+impl Assembler {
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub fn bind(&mut self, Label(label): Label) {
+        debug_assert!(self.labels[label].is_none(), "label is bound twice");
+        self.labels[label] = Some(self.code.len());
+    }
+
+    pub fn offset(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Resolves jumps to labels. Panics, if some of the labels were left unbound.
+    pub fn finish(mut self) -> Vec<u8> {
+        for (at, Label(label)) in std::mem::take(&mut self.fixups) {
+            let target = self.labels[label].expect("jump to unbound label");
+            // rel32 is counted from the end of the jump instruction, which ends with rel32
+            let rel = target as i64 - (at as i64 + 4);
+            let rel = i32::try_from(rel).expect("jump is out of rel32 range");
+            self.code[at..at + 4].copy_from_slice(&rel.to_le_bytes());
+        }
+        self.code
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.emit(&[0; 4]);
+    }
+
+    pub fn push(&mut self, reg: Reg) {
+        self.emit(&[0x50 + reg as u8]);
+    }
+
+    pub fn pop(&mut self, reg: Reg) {
+        self.emit(&[0x58 + reg as u8]);
+    }
+
+    pub fn ret(&mut self) {
+        self.emit(&[0xC3]);
+    }
+
+    pub fn call(&mut self, target: Reg) {
+        self.emit(&[0xFF, modrm_reg(2, target)]);
+    }
+
+    pub fn jmp_reg(&mut self, target: Reg) {
+        self.emit(&[0xFF, modrm_reg(4, target)]);
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.emit(&[0xE9]);
+        self.rel32(label);
+    }
+
+    pub fn jcc(&mut self, cond: Cond, label: Label) {
+        self.emit(&[0x0F, 0x80 + cond as u8]);
+        self.rel32(label);
+    }
+
+    /// `mov dst, src`
+    pub fn mov(&mut self, dst: Reg, src: Reg) {
+        self.emit(&[REX_W, 0x89, modrm_reg(src as u8, dst)]);
+    }
+
+    /// `mov dst, imm64`
+    pub fn mov_imm64(&mut self, dst: Reg, value: u64) {
+        self.emit(&[REX_W, 0xB8 + dst as u8]);
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// `mov dst32, imm32`, zeroing the upper half of the register
+    pub fn mov_imm32(&mut self, dst: Reg, value: u32) {
+        self.emit(&[0xB8 + dst as u8]);
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// `mov dst, [base + disp]`
+    pub fn load(&mut self, dst: Reg, base: Reg, disp: i32) {
+        self.emit(&[REX_W, 0x8B, modrm_disp32(dst as u8, base)]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    /// `mov dst32, [base + disp]`, zeroing the upper half of the register
+    pub fn load32(&mut self, dst: Reg, base: Reg, disp: i32) {
+        self.emit(&[0x8B, modrm_disp32(dst as u8, base)]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    /// `movzx dst32, byte [base + disp]`
+    pub fn load8(&mut self, dst: Reg, base: Reg, disp: i32) {
+        self.emit(&[0x0F, 0xB6, modrm_disp32(dst as u8, base)]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    /// `mov [base + disp], src`
+    pub fn store(&mut self, base: Reg, disp: i32, src: Reg) {
+        self.emit(&[REX_W, 0x89, modrm_disp32(src as u8, base)]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    /// `mov dword [base + disp], value`
+    pub fn store_imm32(&mut self, base: Reg, disp: i32, value: i32) {
+        self.emit(&[0xC7, modrm_disp32(0, base)]);
+        self.emit(&disp.to_le_bytes());
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// `mov byte [base + disp], value`
+    pub fn store_imm8(&mut self, base: Reg, disp: i32, value: u8) {
+        self.emit(&[0xC6, modrm_disp32(0, base)]);
+        self.emit(&disp.to_le_bytes());
+        self.emit(&[value]);
+    }
+
+    /// `shr reg, amount`
+    pub fn shr_imm8(&mut self, reg: Reg, amount: u8) {
+        self.emit(&[REX_W, 0xC1, modrm_reg(5, reg), amount]);
+    }
+
+    /// `or dst, src`
+    pub fn or(&mut self, dst: Reg, src: Reg) {
+        self.emit(&[REX_W, 0x09, modrm_reg(src as u8, dst)]);
+    }
+
+    /// `cmp lhs, rhs`
+    pub fn cmp(&mut self, lhs: Reg, rhs: Reg) {
+        self.emit(&[REX_W, 0x39, modrm_reg(rhs as u8, lhs)]);
+    }
+
+    /// `and reg32, imm32`
+    pub fn and32_imm(&mut self, reg: Reg, value: u32) {
+        self.emit(&[0x81, modrm_reg(4, reg)]);
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// `cmp reg32, imm32`
+    pub fn cmp32_imm(&mut self, reg: Reg, value: u32) {
+        self.emit(&[0x81, modrm_reg(7, reg)]);
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// `cmp lhs32, rhs32`
+    pub fn cmp32(&mut self, lhs: Reg, rhs: Reg) {
+        self.emit(&[0x39, modrm_reg(rhs as u8, lhs)]);
+    }
+
+    /// `add dst32, src32`
+    pub fn add32(&mut self, dst: Reg, src: Reg) {
+        self.emit(&[0x01, modrm_reg(src as u8, dst)]);
+    }
+
+    /// `sub dst32, src32`
+    pub fn sub32(&mut self, dst: Reg, src: Reg) {
+        self.emit(&[0x29, modrm_reg(src as u8, dst)]);
+    }
+
+    /// `imul dst32, src32`
+    pub fn imul32(&mut self, dst: Reg, src: Reg) {
+        self.emit(&[0x0F, 0xAF, modrm_reg(dst as u8, src)]);
+    }
+
+    /// `test reg32, reg32`
+    pub fn test32(&mut self, reg: Reg) {
+        self.emit(&[0x85, modrm_reg(reg as u8, reg)]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Assembler, Cond, Reg::*};
+
+    #[test]
+    fn jumps_are_relative_to_the_end_of_the_instruction() {
+        let mut asm = Assembler::default();
+        let back = asm.new_label();
+        let forward = asm.new_label();
+        asm.bind(back);
+        asm.jmp(forward);
+        asm.jcc(Cond::Equal, back);
+        asm.bind(forward);
+        asm.ret();
+        assert_eq!(
+            asm.finish(),
+            vec![
+                0xE9, 0x06, 0x00, 0x00, 0x00, // jmp forward
+                0x0F, 0x84, 0xF5, 0xFF, 0xFF, 0xFF, // je back
+                0xC3, // ret
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_registers_and_immediates() {
+        let mut asm = Assembler::default();
+        asm.mov_imm64(Rsi, 0x0102030405060708);
+        asm.store_imm32(Rax, 0x10, -1);
+        asm.load(Rdx, Rbx, 8);
+        asm.store(Rcx, -8, Rdi);
+        asm.mov(Rdi, Rbx);
+        asm.imul32(Rax, Rdi);
+        asm.call(Rax);
+        assert_eq!(
+            asm.finish(),
+            vec![
+                0x48, 0xBE, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // mov rsi, imm64
+                0xC7, 0x80, 0x10, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
+                0xFF, // mov [rax+16], -1
+                0x48, 0x8B, 0x93, 0x08, 0x00, 0x00, 0x00, // mov rdx, [rbx+8]
+                0x48, 0x89, 0xB9, 0xF8, 0xFF, 0xFF, 0xFF, // mov [rcx-8], rdi
+                0x48, 0x89, 0xDF, // mov rdi, rbx
+                0x0F, 0xAF, 0xC7, // imul eax, edi
+                0xFF, 0xD0, // call rax
+            ]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use super::{
+    assembler::{Assembler, Cond, Label, Reg, Reg::*},
+    executable_buffer::ExecutableBuffer,
+    helpers::{helper_for, Helper},
+    JitCode, JitContext, EXIT_ERROR,
+};
+use crate::{
+    ids::JmpLabel,
+    machine::{Accumulators, TestFlag},
+    ops::Instruction,
+};
+use keyed_vec::KeyedVec;
+use std::mem::offset_of;
+
+/// Call of a helper, placed after the main code, so that fast paths stay compact.
+struct SlowPath {
+    entry: Label,
+    helper: Helper,
+    operand: u64,
+    resume: Label,
+}
+
+struct Compiler<'a> {
+    asm: Assembler,
+    positions: Vec<Label>,
+    label_mappings: &'a KeyedVec<JmpLabel, u32>,
+    slow_paths: Vec<SlowPath>,
+    epilogue: Label,
+    error_exit: Label,
+}
+
+/// Native code keeps the context in rbx. It is callee-saved, so helpers leave it alone.
+const CTX: Reg = Rbx;
+
+pub(super) fn compile(
+    instructions: &[Instruction],
+    label_mappings: &KeyedVec<JmpLabel, u32>,
+) -> Option<JitCode> {
+    let mut asm = Assembler::default();
+    let positions = instructions.iter().map(|_| asm.new_label()).collect();
+    let epilogue = asm.new_label();
+    let error_exit = asm.new_label();
+    let mut compiler = Compiler {
+        asm,
+        positions,
+        label_mappings,
+        slow_paths: Vec::new(),
+        epilogue,
+        error_exit,
+    };
+
+    // Prologue. Pushing rbx also keeps the stack 16-byte aligned for helper calls.
+    compiler.asm.push(CTX);
+    compiler.asm.mov(CTX, Rdi);
+    compiler.asm.jmp_reg(Rsi);
+
+    let entries: Vec<_> = instructions
+        .iter()
+        .enumerate()
+        .map(|(position, instr)| compiler.instruction(position as u32, *instr))
+        .collect();
+    // Well-formed blocks never fall through the last instruction, but if they do, interpreter
+    // should be the one to complain.
+    compiler.exit_at(instructions.len() as u32);
+
+    compiler.slow_paths();
+    let Compiler {
+        mut asm,
+        epilogue,
+        error_exit,
+        ..
+    } = compiler;
+    asm.bind(error_exit);
+    asm.mov_imm32(Rax, EXIT_ERROR);
+    asm.bind(epilogue);
+    asm.pop(CTX);
+    asm.ret();
+
+    if entries.iter().all(Option::is_none) {
+        return None;
+    }
+    let buffer = ExecutableBuffer::new(&asm.finish()).ok()?;
+    Some(JitCode { buffer, entries })
+}
+
+impl Compiler<'_> {
+    /// Emits the instruction, returning the offset of its code, if it was compiled.
+    fn instruction(&mut self, position: u32, instr: Instruction) -> Option<u32> {
+        self.asm.bind(self.positions[position as usize]);
+        let entry = self.asm.offset() as u32;
+        match instr {
+            Instruction::Label => {}
+            Instruction::Jmp(label) => self.asm.jmp(self.target(label)),
+            Instruction::JmpEQ(label) => self.jump_on_flags(&[TestFlag::EQ], label),
+            Instruction::JmpNE(label) => self.jump_on_flags(&[TestFlag::NE], label),
+            Instruction::JmpLT(label) => self.jump_on_flags(&[TestFlag::LT], label),
+            Instruction::JmpGT(label) => self.jump_on_flags(&[TestFlag::GT], label),
+            Instruction::JmpLE(label) => self.jump_on_flags(&[TestFlag::LT, TestFlag::EQ], label),
+            Instruction::JmpGE(label) => self.jump_on_flags(&[TestFlag::GT, TestFlag::EQ], label),
+            Instruction::ConstI(value) => {
+                self.asm
+                    .load(Rax, CTX, offset_of!(JitContext, accumulators) as i32);
+                self.asm
+                    .store_imm32(Rax, offset_of!(Accumulators, i) as i32, value);
+            }
+            instr => match helper_for(instr) {
+                Some((helper, operand)) => {
+                    let slow = self.asm.new_label();
+                    if self.fast_path(instr, slow) {
+                        let resume = self.asm.new_label();
+                        self.asm.bind(resume);
+                        self.slow_paths.push(SlowPath {
+                            entry: slow,
+                            helper,
+                            operand,
+                            resume,
+                        });
+                    } else {
+                        self.asm.bind(slow);
+                        self.call_helper(helper, operand);
+                    }
+                }
+                None => {
+                    self.exit_at(position);
+                    return None;
+                }
+            },
+        }
+        Some(entry)
+    }
+
+    fn target(&self, label: JmpLabel) -> Label {
+        self.positions[self.label_mappings[label] as usize]
+    }
+
+    fn exit_at(&mut self, position: u32) {
+        self.asm.mov_imm32(Rax, position);
+        self.asm.jmp(self.epilogue);
+    }
+
+    fn jump_on_flags(&mut self, flags: &[TestFlag], label: JmpLabel) {
+        self.asm
+            .load(Rax, CTX, offset_of!(JitContext, test_flag) as i32);
+        self.asm.load8(Rax, Rax, 0);
+        for flag in flags {
+            self.asm.cmp32_imm(Rax, *flag as u32);
+            self.asm.jcc(Cond::Equal, self.target(label));
+        }
+    }
+
+    fn call_helper(&mut self, helper: Helper, operand: u64) {
+        self.asm.mov(Rdi, CTX);
+        self.asm.mov_imm64(Rsi, operand);
+        self.asm.mov_imm64(Rax, helper as usize as u64);
+        self.asm.call(Rax);
+        self.asm.test32(Rax);
+        self.asm.jcc(Cond::NotEqual, self.error_exit);
+    }
+
+    fn slow_paths(&mut self) {
+        for slow_path in std::mem::take(&mut self.slow_paths) {
+            self.asm.bind(slow_path.entry);
+            self.call_helper(slow_path.helper, slow_path.operand);
+            self.asm.jmp(slow_path.resume);
+        }
+    }
+
+    #[cfg(not(feature = "compact_value"))]
+    fn fast_path(&mut self, _instr: Instruction, _slow: Label) -> bool {
+        false
+    }
+
+    /// Emits code for the common case of the instruction, that jumps to `slow` when the case
+    /// does not apply. Returns false, if the instruction has no fast path.
+    ///
+    /// Fast paths work on the bits of compact values directly. Values, which don't own a heap
+    /// allocation, are copied and overwritten without touching reference counts, and integer
+    /// arithmetic is done in place, unless it overflows.
+    #[cfg(feature = "compact_value")]
+    fn fast_path(&mut self, instr: Instruction, slow: Label) -> bool {
+        use crate::{ids::LocalRegisterID, value::compact::bits, LuaValue};
+
+        const AD: i32 = offset_of!(Accumulators, d) as i32;
+        const AI: i32 = offset_of!(Accumulators, i) as i32;
+        let local = |LocalRegisterID(reg): LocalRegisterID| {
+            reg as i32 * std::mem::size_of::<LuaValue>() as i32
+        };
+
+        if !matches!(
+            instr,
+            Instruction::LdaLD(_)
+                | Instruction::StrLD(_)
+                | Instruction::WrapI
+                | Instruction::ConstN
+                | Instruction::DAddL(_)
+                | Instruction::DSubL(_)
+                | Instruction::DMulL(_)
+                | Instruction::EqTestLD(_)
+                | Instruction::NilTest
+                | Instruction::TestLD(_)
+        ) {
+            return false;
+        }
+
+        // rdx points to the accumulators, rcx to the dynamic locals of the frame
+        let asm = &mut self.asm;
+        asm.load(Rdx, CTX, offset_of!(JitContext, accumulators) as i32);
+        asm.load(Rcx, CTX, offset_of!(JitContext, dyn_locals) as i32);
+
+        let bail_unless_plain = |asm: &mut Assembler, value| {
+            asm.mov(Rsi, value);
+            asm.shr_imm8(Rsi, 48);
+            asm.cmp32_imm(Rsi, (bits::TABLE >> 48) as u32);
+            asm.jcc(Cond::Equal, slow);
+            asm.cmp32_imm(Rsi, (bits::NATIVE_FUNC >> 48) as u32);
+            asm.jcc(Cond::Equal, slow);
+            asm.and32_imm(Rsi, (bits::STRING_MASK >> 48) as u32);
+            asm.cmp32_imm(Rsi, (bits::STRING >> 48) as u32);
+            asm.jcc(Cond::Equal, slow);
+        };
+        let bail_unless_int = |asm: &mut Assembler, value| {
+            asm.mov(Rsi, value);
+            asm.shr_imm8(Rsi, 48);
+            asm.cmp32_imm(Rsi, (bits::INT >> 48) as u32);
+            asm.jcc(Cond::NotEqual, slow);
+        };
+        // Both operands of binary instructions end up in eax and edi
+        let int_operands = |asm: &mut Assembler, reg| {
+            asm.load(Rax, Rdx, AD);
+            bail_unless_int(asm, Rax);
+            asm.load(Rdi, Rcx, local(reg));
+            bail_unless_int(asm, Rdi);
+        };
+        let store_int_result = |asm: &mut Assembler| {
+            asm.mov_imm64(Rsi, bits::INT);
+            asm.or(Rax, Rsi);
+            asm.store(Rdx, AD, Rax);
+        };
+        // Sets test flag to EQ or NE, depending on the flags of the last comparison
+        let set_test_flag_eq = |asm: &mut Assembler| {
+            let not_equal = asm.new_label();
+            let done = asm.new_label();
+            asm.load(Rsi, CTX, offset_of!(JitContext, test_flag) as i32);
+            asm.jcc(Cond::NotEqual, not_equal);
+            asm.store_imm8(Rsi, 0, TestFlag::EQ as u8);
+            asm.jmp(done);
+            asm.bind(not_equal);
+            asm.store_imm8(Rsi, 0, TestFlag::NE as u8);
+            asm.bind(done);
+        };
+
+        match instr {
+            Instruction::LdaLD(reg) => {
+                asm.load(Rax, Rcx, local(reg));
+                bail_unless_plain(asm, Rax);
+                asm.load(Rdi, Rdx, AD);
+                bail_unless_plain(asm, Rdi);
+                asm.store(Rdx, AD, Rax);
+            }
+            Instruction::StrLD(reg) => {
+                asm.load(Rax, Rdx, AD);
+                bail_unless_plain(asm, Rax);
+                asm.load(Rdi, Rcx, local(reg));
+                bail_unless_plain(asm, Rdi);
+                asm.store(Rcx, local(reg), Rax);
+            }
+            Instruction::WrapI => {
+                asm.load(Rdi, Rdx, AD);
+                bail_unless_plain(asm, Rdi);
+                asm.load32(Rax, Rdx, AI);
+                store_int_result(asm);
+            }
+            Instruction::ConstN => {
+                asm.load(Rdi, Rdx, AD);
+                bail_unless_plain(asm, Rdi);
+                asm.mov_imm64(Rax, bits::NIL);
+                asm.store(Rdx, AD, Rax);
+            }
+            Instruction::DAddL(reg) => {
+                int_operands(asm, reg);
+                asm.add32(Rax, Rdi);
+                asm.jcc(Cond::Overflow, slow);
+                store_int_result(asm);
+            }
+            Instruction::DSubL(reg) => {
+                int_operands(asm, reg);
+                asm.sub32(Rax, Rdi);
+                asm.jcc(Cond::Overflow, slow);
+                store_int_result(asm);
+            }
+            Instruction::DMulL(reg) => {
+                int_operands(asm, reg);
+                asm.imul32(Rax, Rdi);
+                asm.jcc(Cond::Overflow, slow);
+                store_int_result(asm);
+            }
+            Instruction::EqTestLD(reg) => {
+                int_operands(asm, reg);
+                asm.cmp32(Rax, Rdi);
+                set_test_flag_eq(asm);
+            }
+            Instruction::NilTest => {
+                asm.load(Rax, Rdx, AD);
+                asm.mov_imm64(Rdi, bits::NIL);
+                asm.cmp(Rax, Rdi);
+                set_test_flag_eq(asm);
+            }
+            Instruction::TestLD(reg) => {
+                int_operands(asm, reg);
+                let less = asm.new_label();
+                let equal = asm.new_label();
+                let done = asm.new_label();
+                asm.load(Rsi, CTX, offset_of!(JitContext, test_flag) as i32);
+                asm.cmp32(Rax, Rdi);
+                asm.jcc(Cond::Equal, equal);
+                asm.jcc(Cond::Less, less);
+                asm.store_imm8(Rsi, 0, TestFlag::GT as u8);
+                asm.jmp(done);
+                asm.bind(less);
+                asm.store_imm8(Rsi, 0, TestFlag::LT as u8);
+                asm.jmp(done);
+                asm.bind(equal);
+                asm.store_imm8(Rsi, 0, TestFlag::EQ as u8);
+                asm.bind(done);
+            }
+            _ => unreachable!("instruction without a fast path"),
+        }
+        true
+    }
+}
@@ -0,0 +1,59 @@
+use std::ptr::NonNull;
+
+/// Page-aligned memory with machine code in it. The memory is writable only while the code is
+/// copied in, and is executable afterwards.
+pub(super) struct ExecutableBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl ExecutableBuffer {
+    pub fn new(code: &[u8]) -> std::io::Result<Self> {
+        let len = code.len().max(1);
+        // SAFETY: Anonymous private mapping does not alias any existing memory.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let buffer = Self {
+            ptr: NonNull::new(ptr as *mut u8).expect("mmap does not return null on success"),
+            len,
+        };
+        // SAFETY: Mapping is at least code.len() bytes long, and nobody else has seen it yet.
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.as_ptr(), buffer.ptr.as_ptr(), code.len());
+        }
+        // SAFETY: The range is exactly the mapping created above.
+        let protected = unsafe { libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) };
+        if protected != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(buffer)
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        // SAFETY: The range is exactly the mapping created in new.
+        unsafe { libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len) };
+    }
+}
+
+impl std::fmt::Debug for ExecutableBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExecutableBuffer({:p}, {} bytes)", self.ptr, self.len)
+    }
+}
@@ -0,0 +1,453 @@
+use super::JitContext;
+use crate::{
+    ids::{
+        ArgumentRegisterID, GlobalCellID, LocalBlockID, LocalRegisterID, PropertyCacheID, StringID,
+    },
+    machine::TestFlag,
+    ops::Instruction,
+    runtime::{
+        add_dyn, cmp_test_flags, div_dyn, dyn_concat, mul_dyn, neg_dyn_accumulator, register_of,
+        sub_dyn,
+    },
+    EvalError, InvalidLuaKey, LuaKey, LuaValue, TableRef, TableValue, TypeError,
+};
+
+/// Native code calls helpers with the context and the operand of the instruction. Helper returns
+/// zero on success. Otherwise, the error (or the caught panic) is stored in the context.
+pub(super) type Helper = extern "C" fn(&mut JitContext, u64) -> u32;
+
+macro_rules! register {
+    ($ctx:ident, LD, $reg:ident) => {
+        $ctx.frame.get_dyn($reg as LocalRegisterID)
+    };
+    ($ctx:ident, LI, $reg:ident) => {
+        $ctx.frame.get_int($reg as LocalRegisterID)
+    };
+    ($ctx:ident, LT, $reg:ident) => {
+        $ctx.frame.get_table($reg as LocalRegisterID)
+    };
+
+    ($ctx:ident, $rest:tt) => {
+        register_of!($ctx, $rest)
+    };
+    ($ctx:ident, $first:tt, $rest:tt) => {
+        register_of!($ctx, $first, $rest)
+    };
+}
+
+/// Every helper mirrors the interpreter's handling of the same instruction, minus the program
+/// counter bookkeeping, which native code does by itself.
+macro_rules! helpers {
+    ($(fn $name:ident($ctx:ident, $operand:pat) $body:block)*) => {
+        $(
+            extern "C" fn $name(ctx: &mut JitContext, operand: u64) -> u32 {
+                ctx.guard(operand, |$ctx, $operand| $body)
+            }
+        )*
+    };
+}
+
+fn arg(operand: u64) -> ArgumentRegisterID {
+    ArgumentRegisterID(operand as u16)
+}
+
+fn local(operand: u64) -> LocalRegisterID {
+    LocalRegisterID(operand as u16)
+}
+
+helpers! {
+    fn wrap_i(ctx, _) {
+        register!(ctx, AD) = LuaValue::int(register!(ctx, AI));
+        Ok(())
+    }
+    fn str_rd(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, RD, reg) = register!(ctx, AD).clone();
+        Ok(())
+    }
+    fn lda_rd(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = register!(ctx, RD, reg).clone();
+        Ok(())
+    }
+    fn str_ld(ctx, reg) {
+        let reg = local(reg);
+        *register!(ctx, LD, reg) = register!(ctx, AD).clone();
+        Ok(())
+    }
+    fn lda_ld(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AD) = register!(ctx, LD, reg).clone();
+        Ok(())
+    }
+    fn d_add_r(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = add_dyn(&register!(ctx, AD), &register!(ctx, RD, reg))?;
+        Ok(())
+    }
+    fn d_add_l(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AD) = add_dyn(&register!(ctx, AD), register!(ctx, LD, reg))?;
+        Ok(())
+    }
+    fn const_n(ctx, _) {
+        register!(ctx, AD) = LuaValue::NIL;
+        Ok(())
+    }
+    fn const_f(ctx, bits) {
+        register!(ctx, AF) = f64::from_bits(bits);
+        Ok(())
+    }
+    fn wrap_f(ctx, _) {
+        register!(ctx, AD) = LuaValue::float(register!(ctx, AF));
+        Ok(())
+    }
+    fn const_s(ctx, string_id) {
+        register!(ctx, AS) = ctx.block.meta.const_strings[StringID(string_id as u16)].clone();
+        Ok(())
+    }
+    fn wrap_s(ctx, _) {
+        register!(ctx, AD) = LuaValue::string(register!(ctx, AS).clone());
+        Ok(())
+    }
+    fn const_c(ctx, local_block_id) {
+        register!(ctx, AC) = ctx.code_blocks.blocks_of_module(ctx.block.module)
+            [LocalBlockID(local_block_id as u16)];
+        Ok(())
+    }
+    fn wrap_c(ctx, _) {
+        register!(ctx, AD) = LuaValue::lua_function(register!(ctx, AC));
+        Ok(())
+    }
+    fn lda_d_gl(ctx, cell_id) {
+        register!(ctx, AD) = ctx.global_values.value_of_cell(GlobalCellID(cell_id as u32)).clone();
+        Ok(())
+    }
+    fn str_d_gl(ctx, cell_id) {
+        ctx.global_values.set_cell(GlobalCellID(cell_id as u32), register!(ctx, AD).clone());
+        Ok(())
+    }
+    fn eq_test_rd(ctx, reg) {
+        let reg = arg(reg);
+        *ctx.test_flag = TestFlag::from_bool(register!(ctx, AD) == register!(ctx, RD, reg));
+        Ok(())
+    }
+    fn eq_test_ld(ctx, reg) {
+        let reg = local(reg);
+        *ctx.test_flag = TestFlag::from_bool(&mut register!(ctx, AD) == register!(ctx, LD, reg));
+        Ok(())
+    }
+    fn str_vc(ctx, _) {
+        *ctx.value_count = register!(ctx, AI).try_into().unwrap();
+        Ok(())
+    }
+    fn lda_vc(ctx, _) {
+        register!(ctx, AI) = *ctx.value_count as i32;
+        Ok(())
+    }
+    fn lda_prot(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = if *ctx.value_count > reg.0 {
+            register!(ctx, RD, reg).clone()
+        } else {
+            LuaValue::NIL
+        };
+        Ok(())
+    }
+    fn rd_shift_right(ctx, _) {
+        ctx.argument_registers
+            .d
+            .shift_right((register!(ctx, AI) as u16) as usize, *ctx.value_count as usize);
+        Ok(())
+    }
+    fn i_add_r(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AI) += register!(ctx, RI, reg);
+        Ok(())
+    }
+    fn i_add_l(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AI) += *register!(ctx, LI, reg);
+        Ok(())
+    }
+    fn str_li(ctx, reg) {
+        let reg = local(reg);
+        *register!(ctx, LI, reg) = register!(ctx, AI);
+        Ok(())
+    }
+    fn lda_li(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AI) = *register!(ctx, LI, reg);
+        Ok(())
+    }
+    fn str_ri(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, RI, reg) = register!(ctx, AI);
+        Ok(())
+    }
+    fn lda_ri(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AI) = register!(ctx, RI, reg);
+        Ok(())
+    }
+    fn nil_test(ctx, _) {
+        *ctx.test_flag = TestFlag::from_bool(register!(ctx, AD) == LuaValue::NIL);
+        Ok(())
+    }
+    fn d_sub_r(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = sub_dyn(&register!(ctx, AD), &register!(ctx, RD, reg))?;
+        Ok(())
+    }
+    fn d_sub_l(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AD) = sub_dyn(&register!(ctx, AD), register!(ctx, LD, reg))?;
+        Ok(())
+    }
+    fn new_t(ctx, _) {
+        register!(ctx, AT) = Some(TableRef::from(TableValue::new()));
+        Ok(())
+    }
+    fn str_rt(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, RT, reg) = register!(ctx, AT).clone();
+        Ok(())
+    }
+    fn lda_rt(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AT) = register!(ctx, RT, reg).clone();
+        Ok(())
+    }
+    fn lda_lt(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AT) = register!(ctx, LT, reg).clone();
+        Ok(())
+    }
+    fn str_lt(ctx, reg) {
+        let reg = local(reg);
+        *register!(ctx, LT, reg) = register!(ctx, AT).clone();
+        Ok(())
+    }
+    fn push_d(ctx, _) {
+        let table = register!(ctx, AT).as_mut().unwrap();
+        table.push(register!(ctx, AD).clone());
+        Ok(())
+    }
+    fn assoc_asd(ctx, cache) {
+        let table = register!(ctx, AT).as_mut().unwrap();
+        let cache = &ctx.block.meta.property_caches[PropertyCacheID(cache as u16)];
+        table.assoc_str_cached(&register!(ctx, AS), register!(ctx, AD).clone(), cache);
+        Ok(())
+    }
+    fn cast_t(ctx, _) {
+        *ctx.test_flag = if let Some(table) = register!(ctx, AD).as_table_ref() {
+            register!(ctx, AT) = Some(table.to_owned());
+            TestFlag::EQ
+        } else {
+            TestFlag::NE
+        };
+        Ok(())
+    }
+    fn table_property_lookup_error(ctx, _) {
+        Err(EvalError::from(TypeError::CannotAccessProperty {
+            property: register!(ctx, AS).clone(),
+            of: std::mem::replace(&mut register!(ctx, AD), LuaValue::NIL),
+        }))
+    }
+    fn table_member_lookup_error_r(ctx, reg) {
+        let reg = arg(reg);
+        Err(EvalError::from(TypeError::CannotAccessMember {
+            member: std::mem::replace(&mut register!(ctx, RD, reg), LuaValue::NIL),
+            of: std::mem::replace(&mut register!(ctx, AD), LuaValue::NIL),
+        }))
+    }
+    fn table_member_lookup_error_l(ctx, reg) {
+        let reg = local(reg);
+        Err(EvalError::from(TypeError::CannotAccessMember {
+            member: std::mem::replace(register!(ctx, LD, reg), LuaValue::NIL),
+            of: std::mem::replace(&mut register!(ctx, AD), LuaValue::NIL),
+        }))
+    }
+    fn wrap_t(ctx, _) {
+        register!(ctx, AD) = LuaValue::table(register!(ctx, AT).as_ref().unwrap().clone());
+        Ok(())
+    }
+    fn lda_assoc_as(ctx, cache) {
+        let cache = &ctx.block.meta.property_caches[PropertyCacheID(cache as u16)];
+        register!(ctx, AD) = register!(ctx, AT)
+            .as_ref()
+            .unwrap()
+            .get_str_cached(&register!(ctx, AS), cache);
+        Ok(())
+    }
+    fn lda_assoc_ad(ctx, _) {
+        register!(ctx, AD) = match LuaKey::try_from(register!(ctx, AD).clone()) {
+            Ok(key) => register!(ctx, AT).as_mut().unwrap().get(&key),
+            Err(_) => LuaValue::NIL,
+        };
+        Ok(())
+    }
+    fn d_mul_r(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = mul_dyn(&register!(ctx, AD), &register!(ctx, RD, reg))?;
+        Ok(())
+    }
+    fn d_mul_l(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AD) = mul_dyn(&register!(ctx, AD), register!(ctx, LD, reg))?;
+        Ok(())
+    }
+    fn d_div_r(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = div_dyn(&register!(ctx, AD), &register!(ctx, RD, reg))?;
+        Ok(())
+    }
+    fn d_div_l(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AD) = div_dyn(&register!(ctx, AD), register!(ctx, LD, reg))?;
+        Ok(())
+    }
+    fn assoc_rd(ctx, reg) {
+        let reg = arg(reg);
+        let value = register!(ctx, RD, reg).clone();
+        assoc(ctx, value)
+    }
+    fn assoc_ld(ctx, reg) {
+        let reg = local(reg);
+        let value = register!(ctx, LD, reg).clone();
+        assoc(ctx, value)
+    }
+    fn table_property_assign_error(ctx, _) {
+        Err(EvalError::from(TypeError::CannotAssignProperty {
+            property: register!(ctx, AS).clone(),
+            of: std::mem::replace(&mut register!(ctx, AD), LuaValue::NIL),
+        }))
+    }
+    fn table_member_assign_error_r(ctx, reg) {
+        let reg = arg(reg);
+        Err(EvalError::from(TypeError::CannotAssignMember {
+            member: std::mem::replace(&mut register!(ctx, RD, reg), LuaValue::NIL),
+            of: std::mem::replace(&mut register!(ctx, AD), LuaValue::NIL),
+        }))
+    }
+    fn table_member_assign_error_l(ctx, reg) {
+        let reg = local(reg);
+        Err(EvalError::from(TypeError::CannotAssignMember {
+            member: std::mem::replace(register!(ctx, LD, reg), LuaValue::NIL),
+            of: std::mem::replace(&mut register!(ctx, AD), LuaValue::NIL),
+        }))
+    }
+    fn neg_d(ctx, _) {
+        register!(ctx, AD) = neg_dyn_accumulator(&register!(ctx, AD))?;
+        Ok(())
+    }
+    fn test_rd(ctx, reg) {
+        let reg = arg(reg);
+        let ordering = LuaValue::partial_cmp(&register!(ctx, AD), &register!(ctx, RD, reg));
+        *ctx.test_flag = cmp_test_flags(ordering);
+        Ok(())
+    }
+    fn test_ld(ctx, reg) {
+        let reg = local(reg);
+        let lhs = &mut register!(ctx, AD);
+        let rhs = register!(ctx, LD, reg);
+        if !lhs.is_comparable_to(rhs) {
+            return Err(EvalError::from(TypeError::Ordering {
+                lhs: std::mem::replace(lhs, LuaValue::NIL),
+                rhs: std::mem::replace(rhs, LuaValue::NIL),
+                op: None,
+            }));
+        }
+        *ctx.test_flag = cmp_test_flags(LuaValue::partial_cmp(lhs, rhs));
+        Ok(())
+    }
+    fn d_concat_r(ctx, reg) {
+        let reg = arg(reg);
+        register!(ctx, AD) = dyn_concat(&register!(ctx, AD), &register!(ctx, RD, reg))?;
+        Ok(())
+    }
+    fn d_concat_l(ctx, reg) {
+        let reg = local(reg);
+        register!(ctx, AD) = dyn_concat(&register!(ctx, AD), register!(ctx, LD, reg))?;
+        Ok(())
+    }
+}
+
+fn assoc(ctx: &mut JitContext, value: LuaValue) -> Result<(), EvalError> {
+    let key = match LuaKey::try_from(register!(ctx, AD).clone()) {
+        Ok(key) => key,
+        Err(InvalidLuaKey::Nil) => return Err(EvalError::from(TypeError::NilAssign(value))),
+        Err(InvalidLuaKey::NaN) => return Err(EvalError::from(TypeError::NaNAssign(value))),
+    };
+    register!(ctx, AT).as_mut().unwrap().set(key, value);
+    Ok(())
+}
+
+/// Returns a helper implementing the instruction together with its operand, or `None` for
+/// instructions, that are either emitted natively, or left to the interpreter.
+pub(super) fn helper_for(instr: Instruction) -> Option<(Helper, u64)> {
+    use Instruction::*;
+    let helper: (Helper, u64) = match instr {
+        WrapI => (wrap_i, 0),
+        StrRD(reg) => (str_rd, reg.0.into()),
+        LdaRD(reg) => (lda_rd, reg.0.into()),
+        StrLD(reg) => (str_ld, reg.0.into()),
+        LdaLD(reg) => (lda_ld, reg.0.into()),
+        DAddR(reg) => (d_add_r, reg.0.into()),
+        DAddL(reg) => (d_add_l, reg.0.into()),
+        ConstN => (const_n, 0),
+        ConstF(value) => (const_f, value.to_bits()),
+        WrapF => (wrap_f, 0),
+        ConstS(string_id) => (const_s, string_id.0.into()),
+        WrapS => (wrap_s, 0),
+        ConstC(local_block_id) => (const_c, local_block_id.0.into()),
+        WrapC => (wrap_c, 0),
+        LdaDGl(cell_id) => (lda_d_gl, cell_id.0.into()),
+        StrDGl(cell_id) => (str_d_gl, cell_id.0.into()),
+        EqTestRD(reg) => (eq_test_rd, reg.0.into()),
+        EqTestLD(reg) => (eq_test_ld, reg.0.into()),
+        StrVC => (str_vc, 0),
+        LdaVC => (lda_vc, 0),
+        LdaProt(reg) => (lda_prot, reg.0.into()),
+        RDShiftRight => (rd_shift_right, 0),
+        IAddR(reg) => (i_add_r, reg.0.into()),
+        IAddL(reg) => (i_add_l, reg.0.into()),
+        StrLI(reg) => (str_li, reg.0.into()),
+        LdaLI(reg) => (lda_li, reg.0.into()),
+        StrRI(reg) => (str_ri, reg.0.into()),
+        LdaRI(reg) => (lda_ri, reg.0.into()),
+        NilTest => (nil_test, 0),
+        DSubR(reg) => (d_sub_r, reg.0.into()),
+        DSubL(reg) => (d_sub_l, reg.0.into()),
+        NewT => (new_t, 0),
+        StrRT(reg) => (str_rt, reg.0.into()),
+        LdaRT(reg) => (lda_rt, reg.0.into()),
+        LdaLT(reg) => (lda_lt, reg.0.into()),
+        StrLT(reg) => (str_lt, reg.0.into()),
+        PushD => (push_d, 0),
+        AssocASD(cache) => (assoc_asd, cache.0.into()),
+        CastT => (cast_t, 0),
+        TablePropertyLookupError => (table_property_lookup_error, 0),
+        TableMemberLookupErrorR(reg) => (table_member_lookup_error_r, reg.0.into()),
+        TableMemberLookupErrorL(reg) => (table_member_lookup_error_l, reg.0.into()),
+        WrapT => (wrap_t, 0),
+        LdaAssocAS(cache) => (lda_assoc_as, cache.0.into()),
+        LdaAssocAD => (lda_assoc_ad, 0),
+        DMulR(reg) => (d_mul_r, reg.0.into()),
+        DMulL(reg) => (d_mul_l, reg.0.into()),
+        DDivR(reg) => (d_div_r, reg.0.into()),
+        DDivL(reg) => (d_div_l, reg.0.into()),
+        AssocRD(reg) => (assoc_rd, reg.0.into()),
+        AssocLD(reg) => (assoc_ld, reg.0.into()),
+        TablePropertyAssignError => (table_property_assign_error, 0),
+        TableMemberAssignErrorR(reg) => (table_member_assign_error_r, reg.0.into()),
+        TableMemberAssignErrorL(reg) => (table_member_assign_error_l, reg.0.into()),
+        NegD => (neg_d, 0),
+        TestRD(reg) => (test_rd, reg.0.into()),
+        TestLD(reg) => (test_ld, reg.0.into()),
+        DConcatR(reg) => (d_concat_r, reg.0.into()),
+        DConcatL(reg) => (d_concat_l, reg.0.into()),
+        _ => return None,
+    };
+    Some(helper)
+}
@@ -0,0 +1,358 @@
+//! Baseline JIT for x86-64.
+//!
+//! Code blocks, which were called often enough, are translated into native code instruction by
+//! instruction. Jumps and integer constants are emitted directly, the rest of the supported
+//! instructions become calls of helpers, which do the same thing as the interpreter. This removes
+//! the dispatch loop, but keeps the semantics in one place.
+//!
+//! Native code never changes the current frame. Calls, returns and instructions without a helper
+//! exit native code, so that the interpreter can execute them. After that the interpreter enters
+//! native code again at the next position, so a block keeps running natively between calls.
+
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+compile_error!("jit feature is only supported on x86-64 unix targets");
+
+mod assembler;
+mod compiler;
+mod executable_buffer;
+mod helpers;
+
+use crate::{
+    call_stack::FrameHandle,
+    global_values::GlobalValues,
+    machine::{Accumulators, ArgumentRegisters, CodeBlocks, ModuleAssociatedBlock, TestFlag},
+    EvalError, LuaValue,
+};
+use executable_buffer::ExecutableBuffer;
+use std::{
+    any::Any,
+    cell::{Cell, OnceCell},
+};
+
+const DEFAULT_CALL_THRESHOLD: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitOptions {
+    /// Code block is compiled once it was called more than this many times. Zero compiles every
+    /// block before it runs for the first time.
+    pub call_threshold: u32,
+}
+
+impl Default for JitOptions {
+    fn default() -> Self {
+        Self {
+            call_threshold: DEFAULT_CALL_THRESHOLD,
+        }
+    }
+}
+
+/// Per block state of the JIT. Blocks are shared while they run, hence the cells.
+#[derive(Default)]
+pub(crate) struct JitSlot {
+    calls: Cell<u32>,
+    code: OnceCell<Option<JitCode>>,
+}
+
+impl JitSlot {
+    pub fn code(&self) -> Option<&JitCode> {
+        self.code.get().and_then(Option::as_ref)
+    }
+
+    #[cfg(test)]
+    pub fn is_compiled(&self) -> bool {
+        self.code().is_some()
+    }
+}
+
+impl std::fmt::Debug for JitSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JitSlot")
+            .field("calls", &self.calls.get())
+            .field("code", &self.code.get())
+            .finish()
+    }
+}
+
+/// Counts a call of the block, compiling it when the threshold is crossed.
+pub(crate) fn note_call(block: &ModuleAssociatedBlock, options: &JitOptions) {
+    let slot = &block.jit;
+    if slot.code.get().is_some() {
+        return;
+    }
+    let calls = slot.calls.get().saturating_add(1);
+    slot.calls.set(calls);
+    if calls > options.call_threshold {
+        let code = compiler::compile(&block.instructions, &block.meta.label_mappings);
+        let _ = slot.code.set(code);
+    }
+}
+
+/// Everything helpers need to execute an instruction on behalf of the interpreter. Native code
+/// reads `accumulators`, `test_flag` and `dyn_locals` at offsets known at compile time.
+#[repr(C)]
+pub(crate) struct JitContext<'a, 'f, 'm> {
+    pub accumulators: &'a mut Accumulators,
+    pub test_flag: &'a mut TestFlag,
+    pub argument_registers: &'a mut ArgumentRegisters,
+    pub value_count: &'a mut u16,
+    pub global_values: &'a mut GlobalValues,
+    pub code_blocks: &'a CodeBlocks,
+    pub block: &'a ModuleAssociatedBlock,
+    pub frame: &'a mut FrameHandle<'f, 'm>,
+    dyn_locals: *mut LuaValue,
+    error: Option<EvalError>,
+}
+
+impl<'a, 'f, 'm> JitContext<'a, 'f, 'm> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        accumulators: &'a mut Accumulators,
+        test_flag: &'a mut TestFlag,
+        argument_registers: &'a mut ArgumentRegisters,
+        value_count: &'a mut u16,
+        global_values: &'a mut GlobalValues,
+        code_blocks: &'a CodeBlocks,
+        block: &'a ModuleAssociatedBlock,
+        frame: &'a mut FrameHandle<'f, 'm>,
+    ) -> Self {
+        let dyn_locals = frame.dyn_locals_ptr();
+        Self {
+            accumulators,
+            test_flag,
+            argument_registers,
+            value_count,
+            global_values,
+            code_blocks,
+            block,
+            frame,
+            dyn_locals,
+            error: None,
+        }
+    }
+
+    /// Runs the body of a helper. Errors can't cross native frames, so they are parked in the
+    /// context, and returned once native code exits.
+    ///
+    /// Native frames have no unwind info either, so panics are caught as well, and returned as
+    /// errors. Catching prevents inlining of the helper body, but unwinding through native code
+    /// would be undefined behavior.
+    fn guard(
+        &mut self,
+        operand: u64,
+        body: impl FnOnce(&mut Self, u64) -> Result<(), EvalError>,
+    ) -> u32 {
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| body(self, operand)))
+            .unwrap_or_else(|panic| Err(EvalError::JitPanic(panic_message(&*panic))));
+        match res {
+            Ok(()) => 0,
+            Err(err) => {
+                self.error = Some(err);
+                1
+            }
+        }
+    }
+}
+
+/// Message of the panic, if it was made by `panic!` or a failed assert.
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panic without a message".to_string()
+    }
+}
+
+/// Returned from native code instead of a position, when a helper failed.
+pub(super) const EXIT_ERROR: u32 = u32::MAX;
+
+type NativeEntry = unsafe extern "C" fn(*mut JitContext, *const u8) -> u32;
+
+#[derive(Debug)]
+pub(crate) struct JitCode {
+    buffer: ExecutableBuffer,
+    /// Offset of native code for every instruction position, `None` for the positions which are
+    /// left to the interpreter.
+    entries: Vec<Option<u32>>,
+}
+
+impl JitCode {
+    pub fn entry(&self, position: u32) -> Option<u32> {
+        self.entries.get(position as usize).copied().flatten()
+    }
+
+    /// Runs native code from the entry, until it reaches an instruction it leaves to the
+    /// interpreter. Returns the position of that instruction.
+    ///
+    /// SAFETY: The context should belong to the block this code was compiled from, and the
+    ///         frame in the context should be the top frame of the block.
+    pub unsafe fn run(&self, entry: u32, ctx: &mut JitContext) -> Result<u32, EvalError> {
+        // SAFETY: Native code starts with the prologue, which follows NativeEntry signature.
+        let native: NativeEntry = unsafe { std::mem::transmute(self.buffer.as_ptr()) };
+        let position = unsafe { native(ctx, self.buffer.as_ptr().add(entry as usize)) };
+        if position != EXIT_ERROR {
+            return Ok(position);
+        }
+        Err(ctx.error.take().expect("helper failed without an error"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::JitContext;
+    use crate::{
+        call_stack::CallStack, eval_str, machine::ProgramCounter, EvalError, LuaError, LuaValue,
+        Machine, TypeError,
+    };
+
+    fn machine_with_threshold(call_threshold: u32) -> Machine {
+        let mut machine = Machine::with_stdlib();
+        machine.jit.call_threshold = call_threshold;
+        machine
+    }
+
+    fn is_compiled(machine: &Machine, global: &str) -> bool {
+        let block_id = machine.global_values.get(global).as_lua_function().unwrap();
+        machine.code_blocks[block_id].jit.is_compiled()
+    }
+
+    #[test]
+    fn blocks_are_compiled_after_crossing_the_threshold() {
+        let mut machine = machine_with_threshold(3);
+        eval_str::<()>("function id(a) return a end", &mut machine).unwrap();
+        for _ in 0..3 {
+            eval_str::<()>("id(1)", &mut machine).unwrap();
+        }
+        assert!(!is_compiled(&machine, "id"));
+        eval_str::<()>("id(1)", &mut machine).unwrap();
+        assert!(is_compiled(&machine, "id"));
+    }
+
+    #[test]
+    fn compiled_loops_produce_the_same_results() {
+        // Functions are declared by a separate chunk, so that they are not inlined into the calls
+        let declarations = "
+            function sum(n)
+                local total = 0
+                local i = 1
+                while i <= n do
+                    total = total + i
+                    i = i + 1
+                end
+                return total
+            end
+            function fib(n)
+                if n < 2 then return n end
+                return fib(n - 1) + fib(n - 2)
+            end
+        ";
+        let run = |machine: &mut Machine| -> (LuaValue, LuaValue) {
+            eval_str::<()>(declarations, machine).unwrap();
+            eval_str("return sum(100), fib(20)", machine).unwrap()
+        };
+        let interpreted = run(&mut machine_with_threshold(u32::MAX));
+        let mut machine = machine_with_threshold(0);
+        let compiled = run(&mut machine);
+        assert_eq!(compiled, interpreted);
+        assert_eq!(compiled, (LuaValue::int(5050), LuaValue::int(6765)));
+        assert!(is_compiled(&machine, "sum"));
+        assert!(is_compiled(&machine, "fib"));
+    }
+
+    #[test]
+    fn fast_paths_fall_back_for_values_they_do_not_handle() {
+        let source = "
+            function mix(n)
+                local acc = 0
+                local str = 'str'
+                local table = {}
+                local i = 0
+                while i < n do
+                    acc = acc + 0.5
+                    local copy = str
+                    local same = table
+                    str = copy
+                    table = same
+                    i = i + 1
+                end
+                return acc, str, table, i * 3 - n
+            end
+            return mix(10)
+        ";
+        let interpreted: (LuaValue, LuaValue, LuaValue, LuaValue) =
+            eval_str(source, &mut machine_with_threshold(u32::MAX)).unwrap();
+        let compiled: (LuaValue, LuaValue, LuaValue, LuaValue) =
+            eval_str(source, &mut machine_with_threshold(0)).unwrap();
+        assert_eq!(compiled.0, LuaValue::float(5.0));
+        assert_eq!(compiled.1, LuaValue::string("str"));
+        assert!(compiled.2.as_table_ref().is_some());
+        assert_eq!(compiled.3, LuaValue::int(20));
+        assert_eq!(
+            (compiled.0, compiled.1, compiled.3),
+            (interpreted.0, interpreted.1, interpreted.3)
+        );
+    }
+
+    #[test]
+    fn errors_in_compiled_code_unwind_the_stack() {
+        let mut machine = machine_with_threshold(0);
+        let res = eval_str::<()>(
+            "
+            function add(a, b) return a + b end
+            function outer(a) return add(a, {}) + 1 end
+            outer(1)
+            ",
+            &mut machine,
+        );
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::TypeError(ref err)))
+                if matches!(**err, TypeError::Arithmetic(_))
+        ));
+        assert!(machine.stack.is_empty());
+        let res: LuaValue = eval_str("return add(1, 2)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(3));
+    }
+
+    #[test]
+    fn panics_in_helpers_are_errors() {
+        let mut machine = machine_with_threshold(0);
+        eval_str::<()>("function id(a) return a end", &mut machine).unwrap();
+        let block_id = machine.global_values.get("id").as_lua_function().unwrap();
+        let block = &machine.code_blocks[block_id];
+        let mut stack = CallStack::default();
+        let mut frame = stack.push(
+            &block.meta,
+            ProgramCounter {
+                block: block_id,
+                position: 0,
+            },
+        );
+        let mut ctx = JitContext::new(
+            &mut machine.accumulators,
+            &mut machine.test_flag,
+            &mut machine.argument_registers,
+            &mut machine.value_count,
+            &mut machine.global_values,
+            &machine.code_blocks,
+            block,
+            &mut frame,
+        );
+
+        assert_eq!(ctx.guard(0, |_, _| Ok(())), 0);
+        assert_eq!(ctx.guard(0, |_, _| panic!("static message")), 1);
+        assert!(matches!(
+            ctx.error.take(),
+            Some(EvalError::JitPanic(message)) if message == "static message"
+        ));
+        assert_eq!(ctx.guard(7, |_, operand| panic!("operand {operand}")), 1);
+        assert!(matches!(
+            ctx.error.take(),
+            Some(EvalError::JitPanic(message)) if message == "operand 7"
+        ));
+
+        let handle = frame.release();
+        unsafe { stack.pop(handle) };
+    }
+}
@@ -4,24 +4,49 @@
 extern crate quickcheck_macros;
 
 pub mod compiler;
+pub mod coroutine;
+pub mod debug;
+pub mod embed;
+pub mod execution;
 pub(crate) mod eq_with_nan;
 pub mod global_values;
 pub(crate) mod ids;
+pub mod limits;
 pub(crate) mod machine;
 pub(crate) mod meta;
+pub mod modules;
 pub(crate) mod ops;
 pub(crate) mod optimizer;
+pub mod profiler;
 pub(crate) mod runtime;
+pub mod send;
 pub mod stdlib;
 pub mod value;
 pub(crate) mod call_stack;
+#[cfg(feature = "jit")]
+pub(crate) mod jit;
 
 use compiler::CompiledModule;
+pub use coroutine::CoroutineStatus;
 pub use global_values::GlobalValues;
 use ids::BlockID;
-pub use machine::Machine;
+pub use debug::{DebugFrame, DebugHook};
+pub use embed::IntoGlobal;
+pub use execution::Execution;
+pub use limits::{Limits, Usage};
+pub use machine::{DataType, Machine, ProgramCounter};
+#[cfg(feature = "jit")]
+pub use jit::JitOptions;
+pub use meta::{DebugInfo, LocalVariable};
+pub use modules::{FileLoader, ModuleLoader, Modules};
+pub use profiler::{ProfileReport, Profiler};
+pub use send::MaybeSend;
+#[cfg(feature = "send")]
+pub use send::SendMachine;
 use meta::ReturnCount;
 pub use value::*;
+#[cfg(feature = "derive")]
+pub use reggie_derive::{FromLua, IntoLua};
 
 pub mod error;
 pub use error::*;
@@ -34,6 +59,23 @@ pub fn eval_str<'a, T: FromReturn<'a>>(
     eval_module(&module, machine).map_err(LuaError::from)
 }
 
+/// Same as [`eval_str`], except that the blocks are compiled with [`DebugInfo`], so that debug
+/// hooks know the lines and the locals. `source_name` is usually the file name of the source.
+pub fn eval_str_with_debug_info<'a, T: FromReturn<'a>>(
+    module_str: &str,
+    source_name: &str,
+    machine: &'a mut Machine,
+) -> Result<T, LuaError> {
+    let module = luar_syn::lua_parser::module(module_str)?;
+    let compiled_module = compiler::compile_module_with_debug_info(
+        &module,
+        module_str,
+        source_name,
+        &mut machine.global_values,
+    );
+    eval_compiled_module(compiled_module, machine).map_err(LuaError::from)
+}
+
 pub fn eval_module<'a, T: FromReturn<'a>>(
     module: &luar_syn::Module,
     machine: &'a mut Machine,
@@ -66,19 +108,64 @@ pub fn call_block<'a, T: FromReturn<'a>>(
     );
     let return_count = block.meta.return_count;
 
-    if let Err(err) = runtime::execute(machine, block_id) {
-        if !machine.stack.is_empty() {
+    // Block is called either from the host, or from a native function while the machine is
+    // running, in which case the state of the caller is kept.
+    let stack_base = machine.stack.size();
+    let caller = machine.program_counter;
+    let caller_call_depth = machine.usage.call_depth;
+    let profiler_depth = machine.profiler.as_ref().map_or(0, Profiler::depth);
+    if stack_base == 0 && machine.coroutines.is_empty() {
+        machine.usage = Usage::default();
+    }
+    let res = runtime::execute(machine, block_id);
+    if let Err(err) = res {
+        if machine.stack.size() > stack_base {
             let last_fn = machine.program_counter.block;
             let last_fn = &machine.code_blocks[last_fn];
-            machine.stack.clear(&last_fn.meta, &machine.code_blocks);
+            machine
+                .stack
+                .unwind_to(stack_base, &last_fn.meta, &machine.code_blocks);
+        }
+        if let Some(profiler) = &mut machine.profiler {
+            profiler.unwind_to(profiler_depth);
         }
+        machine.usage.call_depth = caller_call_depth;
+        machine.program_counter = caller;
         return Err(err);
     }
+    machine.program_counter = caller;
     let return_count = match return_count {
         ReturnCount::Constant(count) => count,
         _ => machine.value_count,
     };
-    Ok(T::from_machine_state(machine, return_count))
+    Ok(T::from_machine_state(machine, return_count)?)
+}
+
+/// Calls a function value with the arguments, the way a script would. Native functions, which were
+/// made with [`NativeFunction::reentrant`], use it to call back into lua, while the machine is
+/// running.
+pub fn call_value<'a, T: FromReturn<'a>>(
+    function: &LuaValue,
+    arguments: &[LuaValue],
+    machine: &'a mut Machine,
+) -> Result<T, EvalError> {
+    machine.value_count = arguments.into_args(&mut machine.argument_registers);
+    call_with_arguments(function, machine)
+}
+
+/// Calls the function with the arguments, which are already in the argument registers.
+pub(crate) fn call_with_arguments<'a, T: FromReturn<'a>>(
+    function: &LuaValue,
+    machine: &'a mut Machine,
+) -> Result<T, EvalError> {
+    if let Some(block_id) = function.as_lua_function() {
+        call_block(block_id, machine)
+    } else if let Some(native) = function.as_native_function() {
+        runtime::call_native(&native, machine)?;
+        Ok(T::from_machine_state(machine, machine.value_count)?)
+    } else {
+        Err(EvalError::from(TypeError::IsNotCallable(function.clone())))
+    }
 }
 
 #[macro_export]
@@ -96,6 +183,13 @@ macro_rules! trace_execution {
 }
 
 #[test]
+#[cfg(not(feature = "compact_value"))]
 fn lua_value_is_still_16_bytes() {
     assert_eq!(std::mem::size_of::<LuaValue>(), 16);
 }
+
+#[test]
+#[cfg(feature = "compact_value")]
+fn compact_lua_value_is_8_bytes() {
+    assert_eq!(std::mem::size_of::<LuaValue>(), 8);
+}
@@ -0,0 +1,334 @@
+//! Limits on the resources a script may use, for running code which is not trusted.
+//!
+//! Limits are set with [`Machine::limits`](crate::Machine::limits), and are counted from the
+//! start of every top-level call, like [`eval_str`](crate::eval_str). Exceeding one of them stops
+//! the script with an [`EvalError`], after which the machine can be used again. Like the
+//! profiler, limits keep the JIT from running, as native code doesn't report what it executes.
+
+use crate::{EvalError, LuaKey, LuaValue, TableValue};
+
+/// Every limit is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Instructions are counted precisely, but the count is only checked at back-edges and calls,
+    /// so a script may run over the limit by the length of a block.
+    pub instructions: Option<u64>,
+    /// Frames of lua functions on the stack. Native functions and tail calls don't add frames.
+    pub call_depth: Option<usize>,
+    /// Bytes allocated for the tables and the strings made by the script. Allocations are only
+    /// added up, never subtracted, so it is a limit on the churn rather than on the live memory.
+    /// Native functions of the standard library, which build values or compile code, count what
+    /// they allocate. Allocations made by native functions of the embedder are not counted.
+    pub memory: Option<usize>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What the script used so far, since the start of the top-level call. Usage is not counted,
+/// while native code of the JIT is running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub instructions: u64,
+    pub call_depth: usize,
+    pub memory: usize,
+}
+
+/// Accounted for every new table.
+pub(crate) const TABLE_SIZE: usize = std::mem::size_of::<TableValue>();
+/// Accounted for every new entry of a table.
+pub(crate) const TABLE_ENTRY_SIZE: usize =
+    std::mem::size_of::<LuaKey>() + std::mem::size_of::<LuaValue>();
+
+/// Bytes of the tables and the strings in the value, counted the way the instructions that make
+/// them count them.
+pub(crate) fn value_size(value: &LuaValue) -> usize {
+    if let Some(str) = value.as_str() {
+        str.len()
+    } else if let Some(table) = value.as_table() {
+        let table = table.borrow();
+        let keys = table.hash_part().map(|(key, _)| match key {
+            LuaKey::String(str) => (**str).len(),
+            _ => 0,
+        });
+        let values = table
+            .array_part()
+            .iter()
+            .chain(table.hash_part().map(|(_, value)| value))
+            .map(value_size);
+        TABLE_SIZE
+            + table.entry_count() * TABLE_ENTRY_SIZE
+            + keys.sum::<usize>()
+            + values.sum::<usize>()
+    } else {
+        0
+    }
+}
+
+impl Usage {
+    pub(crate) fn count_instructions(&mut self, count: u32) {
+        self.instructions += u64::from(count);
+    }
+
+    pub(crate) fn check_instructions(&self, limits: &Limits) -> Result<(), EvalError> {
+        match limits.instructions {
+            Some(limit) if self.instructions > limit => Err(EvalError::InstructionLimit(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn enter_call(&mut self, limits: &Limits) -> Result<(), EvalError> {
+        match limits.call_depth {
+            Some(limit) if self.call_depth >= limit => Err(EvalError::CallDepthLimit(limit)),
+            _ => {
+                self.call_depth += 1;
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn leave_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    pub(crate) fn allocate(&mut self, limits: &Limits, bytes: usize) -> Result<(), EvalError> {
+        self.memory = self.memory.saturating_add(bytes);
+        match limits.memory {
+            Some(limit) if self.memory > limit => Err(EvalError::MemoryLimit(limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Limits;
+    use crate::{eval_str, EvalError, LuaError, LuaValue, Machine};
+
+    fn limited(limits: Limits) -> Machine {
+        let mut machine = Machine::with_stdlib();
+        machine.limits = limits;
+        machine
+    }
+
+    #[test]
+    fn endless_loop_runs_out_of_instructions() {
+        let mut machine = limited(Limits {
+            instructions: Some(10_000),
+            ..Default::default()
+        });
+        let res = eval_str::<()>("local i = 0 while 1 do i = i + 1 end", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::InstructionLimit(10_000)))
+        ));
+        assert!(machine.usage.instructions > 10_000);
+        assert!(machine.usage.instructions < 10_100);
+
+        let res: LuaValue = eval_str("return 1 + 2", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(3));
+        assert!(machine.usage.instructions < 100);
+    }
+
+    #[test]
+    fn instructions_are_counted_across_calls() {
+        let mut machine = Machine::with_stdlib();
+        eval_str::<()>(
+            "
+            function count(n)
+                local i = 0
+                while i < n do i = i + 1 end
+                return i
+            end
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let _: LuaValue = eval_str("return count(10)", &mut machine).unwrap();
+        let ten = machine.usage.instructions;
+        let _: LuaValue = eval_str("return count(20)", &mut machine).unwrap();
+        let twenty = machine.usage.instructions;
+        let _: LuaValue = eval_str("return count(30)", &mut machine).unwrap();
+        let thirty = machine.usage.instructions;
+        assert!(ten < twenty);
+        assert_eq!(twenty - ten, thirty - twenty);
+
+        machine.limits.instructions = Some(twenty);
+        let _: LuaValue = eval_str("return count(20)", &mut machine).unwrap();
+        assert!(eval_str::<LuaValue>("return count(30)", &mut machine).is_err());
+    }
+
+    #[test]
+    fn endless_recursion_runs_out_of_frames() {
+        let mut machine = limited(Limits {
+            call_depth: Some(100),
+            ..Default::default()
+        });
+        eval_str::<()>(
+            "
+            function depth(n)
+                if n == 0 then return 0 end
+                return 1 + depth(n - 1)
+            end
+            function forever() return 1 + forever() end
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let res: LuaValue = eval_str("return depth(40)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(40));
+        assert_eq!(machine.usage.call_depth, 0);
+
+        let res = eval_str::<()>("forever()", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::CallDepthLimit(100)))
+        ));
+
+        let res: LuaValue = eval_str("return depth(40)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(40));
+    }
+
+    #[test]
+    fn tail_calls_do_not_add_frames() {
+        let mut machine = limited(Limits {
+            call_depth: Some(10),
+            ..Default::default()
+        });
+        let res: LuaValue = eval_str(
+            "
+            function down(n)
+                if n == 0 then return 0 end
+                return down(n - 1)
+            end
+            return down(1000)
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, LuaValue::int(0));
+    }
+
+    #[test]
+    fn growing_strings_and_tables_run_out_of_memory() {
+        let mut machine = limited(Limits {
+            memory: Some(64 * 1024),
+            ..Default::default()
+        });
+        let res = eval_str::<()>("local s = 'x' while 1 do s = s .. s end", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+        ));
+
+        let res = eval_str::<()>(
+            "local t = {} local i = 1 while 1 do t[i] = i i = i + 1 end",
+            &mut machine,
+        );
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+        ));
+
+        let res = eval_str::<()>(
+            "local t = {} local i = 1 while 1 do t[1] = {} i = i + 1 end",
+            &mut machine,
+        );
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+        ));
+
+        let res: LuaValue = eval_str("local t = {1, 2, 3} return t[2]", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(2));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn decoding_large_documents_runs_out_of_memory() {
+        let mut machine = limited(Limits {
+            memory: Some(64 * 1024),
+            ..Default::default()
+        });
+        let document = format!("[{}]", vec!["\"a json string\""; 10_000].join(","));
+        machine.set_global("document", LuaValue::string(&document));
+        let res = eval_str::<()>("local decoded = json_decode(document)", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+        ));
+
+        let res: LuaValue =
+            eval_str("local t = json_decode('[1, 2]') return t[2]", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(2));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn encoding_growing_documents_runs_out_of_memory() {
+        let mut machine = limited(Limits {
+            memory: Some(64 * 1024),
+            ..Default::default()
+        });
+        let res = eval_str::<LuaValue>(
+            "local s = 'x' local i = 0 while i < 20 do s = json_encode({ s, s }) i = i + 1 end",
+            &mut machine,
+        );
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+        ));
+    }
+
+    #[test]
+    fn taking_substrings_runs_out_of_memory() {
+        let mut machine = limited(Limits {
+            memory: Some(64 * 1024),
+            ..Default::default()
+        });
+        machine.set_global("text", LuaValue::string("x".repeat(1024)));
+        let res = eval_str::<()>(
+            "local i = 0 while i < 100 do local s = strsub(text, 1) i = i + 1 end",
+            &mut machine,
+        );
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+        ));
+    }
+
+    #[test]
+    fn compiling_code_runs_out_of_memory() {
+        let mut machine = limited(Limits {
+            memory: Some(64 * 1024),
+            ..Default::default()
+        });
+        for function in ["load", "dostring"] {
+            let res = eval_str::<()>(
+                &format!("local i = 0 while i < 10000 do {function}('return 1') i = i + 1 end"),
+                &mut machine,
+            );
+            assert!(matches!(
+                res,
+                Err(LuaError::Eval(EvalError::MemoryLimit(65536)))
+            ));
+        }
+    }
+
+    #[test]
+    fn overwriting_entries_allocates_nothing() {
+        let mut machine = Machine::with_stdlib();
+        eval_str::<()>(
+            "local t = {} local i = 1 while i < 100 do t.x = i t[1] = i i = i + 1 end",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(
+            machine.usage.memory,
+            super::TABLE_SIZE + 2 * super::TABLE_ENTRY_SIZE
+        );
+    }
+}
@@ -1,13 +1,13 @@
 use enum_map::Enum;
 
 use crate::{
-    call_stack::CallStack, compiler::CompiledModule, global_values::GlobalValues, ids::{BlockID, LocalBlockID, ModuleID}, meta::CodeMeta, ops::Instruction, stdlib::define_stdlib, LuaString, LuaValue, TableRef
+    call_stack::CallStack, compiler::CompiledModule, debug::DebugHook, global_values::GlobalValues, ids::{BlockID, LocalBlockID, ModuleID}, limits::{Limits, Usage}, meta::CodeMeta, modules::Modules, ops::Instruction, optimizer::SpeculationSlot, profiler::Profiler, stdlib::define_stdlib, LuaString, LuaValue, NativeFunction, TableRef
 };
 use keyed_vec::{keyed_vec, KeyedVec};
 
 // const ARG_REG_COUNT: usize = 16;
 // TODO: Implement ExtR in order to make argument register more likely to be in cache(?)
-const ARG_REG_COUNT: usize = 32;
+pub const ARG_REG_COUNT: usize = 32;
 
 // pub const OPTIMIZE: bool = true;
 
@@ -43,7 +43,70 @@ pub struct ArgumentRegisters {
     pub i: [i32; ARG_REG_COUNT],
     pub s: [LuaString; ARG_REG_COUNT],
     pub t: [Option<TableRef>; ARG_REG_COUNT],
-    pub d: [LuaValue; ARG_REG_COUNT],
+    pub d: DynArgumentRegisters,
+}
+
+/// Dynamic argument registers are the ones every call and return goes through, so their count
+/// is not limited. First `ARG_REG_COUNT` of them are always there, the rest are spilled into the
+/// same buffer on the first write, which keeps the values contiguous for native functions.
+pub struct DynArgumentRegisters(Vec<LuaValue>);
+
+impl DynArgumentRegisters {
+    fn new() -> Self {
+        Self(vec![LuaValue::NIL; ARG_REG_COUNT])
+    }
+
+    /// Moves `value_count` values `by` registers to the left, dropping the first `by` of them.
+    pub fn shift_left(&mut self, by: usize, value_count: usize) {
+        let live = value_count.max(ARG_REG_COUNT);
+        if self.0.len() < live {
+            self.0.resize(live, LuaValue::NIL);
+        }
+        self.0[..live].rotate_left(by.min(live));
+    }
+
+    /// Moves `value_count` values `by` registers to the right. Registers past the moved values
+    /// are left in unspecified state.
+    pub fn shift_right(&mut self, by: usize, value_count: usize) {
+        let live = (value_count + by).max(ARG_REG_COUNT);
+        if self.0.len() < live {
+            self.0.resize(live, LuaValue::NIL);
+        }
+        self.0[..live].rotate_right(by);
+    }
+}
+
+impl std::ops::Deref for DynArgumentRegisters {
+    type Target = [LuaValue];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::Index<usize> for DynArgumentRegisters {
+    type Output = LuaValue;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl std::ops::Index<std::ops::RangeTo<usize>> for DynArgumentRegisters {
+    type Output = [LuaValue];
+
+    fn index(&self, index: std::ops::RangeTo<usize>) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl std::ops::IndexMut<usize> for DynArgumentRegisters {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if index >= self.0.len() {
+            self.0.resize(index + 1, LuaValue::NIL);
+        }
+        &mut self.0[index]
+    }
 }
 
 pub struct Accumulators {
@@ -52,6 +115,7 @@ pub struct Accumulators {
     pub s: LuaString,
     pub c: BlockID,
     pub t: Option<TableRef>,
+    pub u: Option<NativeFunction>,
     pub d: LuaValue,
 }
 
@@ -145,6 +209,17 @@ pub struct ModuleAssociatedBlock {
     pub module: ModuleID,
     pub meta: CodeMeta,
     pub instructions: Vec<Instruction>,
+    pub(crate) speculation: SpeculationSlot,
+    #[cfg(feature = "jit")]
+    pub(crate) jit: crate::jit::JitSlot,
+}
+
+impl ModuleAssociatedBlock {
+    /// Instructions to execute. Those are speculatively optimized ones, while the globals they
+    /// depend on keep their values, and the original ones otherwise.
+    pub(crate) fn code(&self) -> &[Instruction] {
+        self.speculation.instructions().unwrap_or(&self.instructions)
+    }
 }
 
 struct ModuleBlocks {
@@ -178,6 +253,9 @@ impl CodeBlocks {
             module,
             instructions: code_block.instructions,
             meta: code_block.meta,
+            speculation: Default::default(),
+            #[cfg(feature = "jit")]
+            jit: Default::default(),
         })
     }
 
@@ -219,14 +297,30 @@ impl CodeBlocks {
 
 pub struct Machine {
     pub accumulators: Accumulators,
-    pub program_counter: ProgramCounter,
+    /// Running frames rely on the program counter, the code blocks and the stack, so native
+    /// functions, which get the whole machine, can't change them
+    pub(crate) program_counter: ProgramCounter,
     pub value_count: u16,
     pub test_flag: TestFlag,
     pub type_test_result: TypeTestResult,
     pub argument_registers: ArgumentRegisters,
     pub global_values: GlobalValues,
-    pub code_blocks: CodeBlocks,
-    pub stack: CallStack,
+    pub(crate) code_blocks: CodeBlocks,
+    pub(crate) stack: CallStack,
+    /// Counts executed calls and instructions, when set
+    pub profiler: Option<Profiler>,
+    /// Is called on lines, calls and returns, when set
+    pub debug_hook: Option<Box<dyn DebugHook>>,
+    /// Stop scripts, which run for too long or use too much memory
+    pub limits: Limits,
+    /// Counted against the limits, reset on every top-level call
+    pub usage: Usage,
+    /// Loads and keeps the modules for `require` and `dofile`
+    pub modules: Modules,
+    /// Coroutines, which are running, from the outermost one
+    pub(crate) coroutines: Vec<NativeFunction>,
+    #[cfg(feature = "jit")]
+    pub jit: crate::JitOptions,
 }
 
 impl Machine {
@@ -245,6 +339,7 @@ impl Machine {
                 s: LuaString::default(),
                 c: dummy_block_id,
                 t: None,
+                u: None,
                 d: LuaValue::NIL,
             },
             program_counter: ProgramCounter {
@@ -259,11 +354,19 @@ impl Machine {
                 i: [0; ARG_REG_COUNT],
                 s: [(); ARG_REG_COUNT].map(|_| LuaString::default()),
                 t: [(); ARG_REG_COUNT].map(|_| None),
-                d: [(); ARG_REG_COUNT].map(|_| LuaValue::NIL),
+                d: DynArgumentRegisters::new(),
             },
             global_values: GlobalValues::default(),
             code_blocks: CodeBlocks::default(),
             stack: CallStack::default(),
+            profiler: None,
+            debug_hook: None,
+            limits: Limits::default(),
+            usage: Usage::default(),
+            modules: Modules::default(),
+            coroutines: Vec::new(),
+            #[cfg(feature = "jit")]
+            jit: Default::default(),
         }
     }
 
@@ -272,4 +375,22 @@ impl Machine {
         define_stdlib(&mut machine.global_values);
         machine
     }
+
+    pub fn code_blocks(&self) -> &CodeBlocks {
+        &self.code_blocks
+    }
+
+    /// Adds the compiled module, and returns its top level block, see [`CodeBlocks::add_module`].
+    pub fn add_module(&mut self, module: CompiledModule) -> BlockID {
+        self.code_blocks.add_module(module)
+    }
+
+    pub fn add_top_level_block(&mut self, code_block: CodeBlock) -> BlockID {
+        self.code_blocks.add_top_level_block(code_block)
+    }
+
+    /// Checks if the machine has frames on the stack, which is the case while it runs a script.
+    pub fn is_running(&self) -> bool {
+        !self.stack.is_empty()
+    }
 }
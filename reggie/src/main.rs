@@ -1,15 +1,17 @@
 use itertools::Itertools;
-use reggie::{eval_str, LuaValue, Machine};
+use reggie::{eval_str, eval_str_with_debug_info, FileLoader, LuaValue, Machine, Modules, Profiler};
 use std::error::Error;
 
-fn repl() -> Result<(), Box<dyn Error>> {
+mod cli_debugger;
+use cli_debugger::CliDebugger;
+
+fn repl(machine: &mut Machine) -> Result<(), Box<dyn Error>> {
     use std::io::{BufRead, Write};
 
-    let mut machine = Machine::with_stdlib();
     print!(">>> ");
     std::io::stdout().flush()?;
     for line in std::io::stdin().lock().lines() {
-        let res = eval_str::<&[LuaValue]>(&line?, &mut machine);
+        let res = eval_str::<&[LuaValue]>(&line?, machine);
         match res {
             Ok(values) if values.len() > 0 => println!("{}", values.iter().join("\t")),
             Ok(_) => {},
@@ -21,20 +23,84 @@ fn repl() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn eval_file(filename: &str) -> Result<(), Box<dyn Error>> {
+fn eval_file(filename: &str, machine: &mut Machine) -> Result<(), Box<dyn Error>> {
     use std::io::Read;
 
     let mut file = std::fs::File::open(filename)?;
     let mut buffer = String::new();
     file.read_to_string(&mut buffer)?;
-    eval_str::<()>(&buffer, &mut Machine::with_stdlib())?;
+    if machine.debug_hook.is_some() {
+        eval_str_with_debug_info::<()>(&buffer, filename, machine)?;
+    } else {
+        eval_str::<()>(&buffer, machine)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Options {
+    filename: Option<String>,
+    /// Print the profile report to stderr, once the program ends
+    profile: bool,
+    /// File to write folded stacks of the profile to
+    folded: Option<String>,
+    /// Run the script under the interactive debugger
+    debug: bool,
+}
+
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    const USAGE: &str = "usage: reggie [--profile] [--folded <output>] [script]\n       reggie --debug <script>";
+
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => options.profile = true,
+            "--folded" => options.folded = Some(args.next().ok_or(USAGE)?),
+            "--debug" => options.debug = true,
+            _ if arg.starts_with("--") => return Err(USAGE.into()),
+            _ if options.filename.is_none() => options.filename = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    if options.debug && options.filename.is_none() {
+        return Err(USAGE.into());
+    }
+    Ok(options)
+}
+
+fn write_profile(options: &Options, machine: &Machine) -> Result<(), Box<dyn Error>> {
+    let Some(profiler) = &machine.profiler else {
+        return Ok(());
+    };
+    let report = profiler.report(machine.code_blocks());
+    if options.profile {
+        eprint!("{report}");
+    }
+    if let Some(folded) = &options.folded {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(folded)?);
+        report.write_folded(&mut file)?;
+    }
     Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    if let Some(filename) = std::env::args().skip(1).next() {
-        eval_file(&filename)
-    } else {
-        repl()
+    let options = parse_args()?;
+    let mut machine = Machine::with_stdlib();
+    // Scripts, run from the command line, are trusted with the file system
+    machine.modules = Modules::new(FileLoader::default());
+    if options.profile || options.folded.is_some() {
+        machine.profiler = Some(Profiler::default());
+    }
+    if options.debug {
+        machine.debug_hook = Some(Box::new(CliDebugger::new()));
     }
+
+    let res = if let Some(filename) = &options.filename {
+        eval_file(filename, &mut machine)
+    } else {
+        repl(&mut machine)
+    };
+    write_profile(&options, &machine)?;
+    res
 }
@@ -1,10 +1,10 @@
 use crate::{
-    ids::{BlockID, JmpLabel, StringID},
-    machine::DataType, LuaString,
+    ids::{BlockID, JmpLabel, LocalRegisterID, PropertyCacheID, StringID},
+    machine::DataType, LuaString, PropertyCache,
 };
 use enum_map::EnumMap;
 use keyed_vec::KeyedVec;
-use std::num::NonZeroU16;
+use std::{num::NonZeroU16, ops::Range};
 
 pub type LocalRegCount = EnumMap<DataType, u16>;
 
@@ -181,7 +181,65 @@ pub struct CodeMeta {
     pub return_count: ReturnCount,
     pub label_mappings: KeyedVec<JmpLabel, u32>,
     pub const_strings: KeyedVec<StringID, LuaString>,
+    pub property_caches: KeyedVec<PropertyCacheID, PropertyCache>,
     pub debug_name: Option<String>,
     pub kind: FunctionKind,
+    /// Only blocks compiled along with their source have it
+    pub debug_info: Option<DebugInfo>,
     // pub global_deps:
 }
+
+/// Maps instructions of a block back to the source.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DebugInfo {
+    /// Name of the source, usually its file name
+    pub source_name: String,
+    /// Position of the first instruction of every line, along with the 1-based line. Ordered by
+    /// the position.
+    pub lines: Vec<(u32, u32)>,
+    /// Ordered by the position, at which the local is declared
+    pub locals: Vec<LocalVariable>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVariable {
+    pub name: String,
+    /// Locals are always dynamic
+    pub register: LocalRegisterID,
+    /// Positions of the instructions, at which the local is in scope
+    pub scope: Range<u32>,
+}
+
+impl DebugInfo {
+    /// Line of the instruction at the position.
+    pub fn line_at(&self, position: u32) -> Option<u32> {
+        let index = self
+            .lines
+            .partition_point(|(line_start, _)| *line_start <= position);
+        index.checked_sub(1).map(|index| self.lines[index].1)
+    }
+
+    /// Line, which starts at the position.
+    pub fn line_starting_at(&self, position: u32) -> Option<u32> {
+        self.lines
+            .binary_search_by_key(&position, |(line_start, _)| *line_start)
+            .ok()
+            .map(|index| self.lines[index].1)
+    }
+
+    /// Locals, which are in scope at the position. Locals declared later shadow the earlier ones
+    /// with the same name.
+    pub fn locals_at(&self, position: u32) -> impl DoubleEndedIterator<Item = &LocalVariable> {
+        self.locals
+            .iter()
+            .filter(move |local| local.scope.contains(&position))
+    }
+
+    pub(crate) fn add_line(&mut self, position: u32, line: u32) {
+        match self.lines.last_mut() {
+            Some((_, last_line)) if *last_line == line => {}
+            Some((last_position, last_line)) if *last_position == position => *last_line = line,
+            _ => self.lines.push((position, line)),
+        }
+    }
+}
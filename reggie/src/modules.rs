@@ -0,0 +1,427 @@
+//! Loading of code from scripts, with `dofile(path)` and `require(name)` for other files, and
+//! `dostring(code)` and `load(code)` for strings.
+//!
+//! `dofile` runs the file every time it is called, while `require` runs a module once per machine
+//! and keeps what it returned, so the following calls return the same value. A module, which
+//! returns nothing, is stored as true. Sources are found by the [`ModuleLoader`] of
+//! [`Machine::modules`](crate::Machine::modules). By default it finds nothing, so that scripts
+//! cannot read the file system unless the embedder allows it with a [`FileLoader`]:
+//!
+//! ```
+//! # use reggie::{FileLoader, Machine, Modules};
+//! let mut machine = Machine::with_stdlib();
+//! machine.modules = Modules::new(FileLoader::default());
+//! ```
+//!
+//! `dofile` and `dostring` return every value the code returned. Code, which is not valid lua, is
+//! reported as [`EvalError::Parse`] at the call, except by `load`, which returns nil and the
+//! message of the error, the way it does in lua.
+
+use std::{collections::HashMap, io, path::Path};
+
+use crate::{
+    call_block, compiler, ids::BlockID, EvalError, ExpectedType, FromReturn, LuaValue, Machine,
+    MaybeSend, MultiValue, TypeError,
+};
+
+/// Finds the sources of the modules.
+pub trait ModuleLoader: MaybeSend {
+    /// Source of the module, which is required under `name`, or `None` if there is no such
+    /// module.
+    fn find(&self, name: &str) -> Result<Option<String>, io::Error>;
+
+    /// Source of the file, which is run by `dofile`.
+    fn read_file(&self, path: &str) -> Result<String, io::Error>;
+}
+
+/// Searches for the modules in the file system, and reads any file for `dofile`. Every `?` of a template on the search path is
+/// replaced with the name of the module, where dots are replaced with slashes, and the first file,
+/// which exists, is the module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLoader {
+    pub search_path: Vec<String>,
+}
+
+impl FileLoader {
+    pub fn new(search_path: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            search_path: search_path.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Searches the working directory.
+impl Default for FileLoader {
+    fn default() -> Self {
+        Self::new(["?.lua", "?/init.lua"])
+    }
+}
+
+impl ModuleLoader for FileLoader {
+    fn find(&self, name: &str) -> Result<Option<String>, io::Error> {
+        let name = name.replace('.', "/");
+        for template in &self.search_path {
+            let path = template.replace('?', &name);
+            if Path::new(&path).is_file() {
+                return std::fs::read_to_string(path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_file(&self, path: &str) -> Result<String, io::Error> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Modules and files, served from memory by their names and paths.
+impl ModuleLoader for HashMap<String, String> {
+    fn find(&self, name: &str) -> Result<Option<String>, io::Error> {
+        Ok(self.get(name).cloned())
+    }
+
+    fn read_file(&self, path: &str) -> Result<String, io::Error> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_owned()))
+    }
+}
+
+pub struct Modules {
+    pub loader: Box<dyn ModuleLoader>,
+    loaded: HashMap<String, LuaValue>,
+    /// Modules, which are being run, from the outermost one
+    loading: Vec<String>,
+}
+
+impl Modules {
+    pub fn new(loader: impl ModuleLoader + 'static) -> Self {
+        Self {
+            loader: Box::new(loader),
+            loaded: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    /// Value, returned by the module, if it was already loaded.
+    pub fn get(&self, name: &str) -> Option<&LuaValue> {
+        self.loaded.get(name)
+    }
+
+    /// Forgets the module, so that it is run again by the next `require`.
+    pub fn unload(&mut self, name: &str) -> Option<LuaValue> {
+        self.loaded.remove(name)
+    }
+}
+
+/// Finds no modules and no files.
+impl Default for Modules {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
+
+/// Parses and compiles `source` as a new module of the machine. `chunk` names the source in parse
+/// errors.
+pub(crate) fn compile_chunk(
+    machine: &mut Machine,
+    source: &str,
+    chunk: &str,
+) -> Result<BlockID, EvalError> {
+    let module = luar_syn::lua_parser::module(source).map_err(|err| EvalError::Parse {
+        chunk: chunk.to_owned(),
+        error: Box::new(err.into()),
+    })?;
+    let compiled_module = compiler::compile_module(&module, &mut machine.global_values);
+    Ok(machine.code_blocks.add_module(compiled_module))
+}
+
+/// Names the code in parse errors by its first line, the way lua does.
+fn string_chunk_name(code: &str) -> String {
+    const MAX_LEN: usize = 40;
+    let line = code.trim_start().lines().next().unwrap_or_default();
+    match line.char_indices().nth(MAX_LEN) {
+        Some((end, _)) => format!("[string \"{}...\"]", &line[..end]),
+        None if line.len() < code.trim().len() => format!("[string \"{}...\"]", line),
+        None => format!("[string \"{}\"]", line),
+    }
+}
+
+impl Machine {
+    /// Compiles the code into a function, which runs it when called.
+    pub fn load(&mut self, code: &str) -> Result<LuaValue, EvalError> {
+        let block = compile_chunk(self, code, &string_chunk_name(code))?;
+        Ok(LuaValue::lua_function(block))
+    }
+
+    /// Compiles and runs the code.
+    pub fn dostring<'a, T: FromReturn<'a>>(&'a mut self, code: &str) -> Result<T, EvalError> {
+        let block = compile_chunk(self, code, &string_chunk_name(code))?;
+        call_block(block, self)
+    }
+
+    /// Runs the file, read by the loader of the modules.
+    pub fn dofile<'a, T: FromReturn<'a>>(&'a mut self, path: &str) -> Result<T, EvalError> {
+        let source = self.modules.loader.read_file(path)?;
+        let block = compile_chunk(self, &source, path)?;
+        call_block(block, self)
+    }
+
+    /// Runs the module, unless it was already loaded, and returns the value it returned.
+    pub fn require(&mut self, name: &str) -> Result<LuaValue, EvalError> {
+        if let Some(value) = self.modules.get(name) {
+            return Ok(value.clone());
+        }
+        if self.modules.loading.iter().any(|loading| loading == name) {
+            let mut cycle = self.modules.loading.clone();
+            cycle.push(name.to_owned());
+            return Err(EvalError::CyclicRequire(cycle));
+        }
+        let source = self
+            .modules
+            .loader
+            .find(name)?
+            .ok_or_else(|| EvalError::ModuleNotFound(name.to_owned()))?;
+
+        self.modules.loading.push(name.to_owned());
+        let res = compile_chunk(self, &source, name)
+            .and_then(|block| call_block::<LuaValue>(block, self));
+        self.modules.loading.pop();
+
+        let value = match res? {
+            value if value.is_nil() => LuaValue::TRUE,
+            value => value,
+        };
+        self.modules.loaded.insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+}
+
+fn string_argument(args: &[LuaValue]) -> Result<String, EvalError> {
+    let arg = args.first().cloned().unwrap_or(LuaValue::NIL);
+    match arg.as_str() {
+        Some(str) => Ok(str.to_owned()),
+        None => Err(EvalError::from(TypeError::ArgumentType {
+            position: 0,
+            expected: ExpectedType::String,
+            got: arg,
+        })),
+    }
+}
+
+pub fn dofile(machine: &mut Machine, args: &[LuaValue]) -> Result<MultiValue, EvalError> {
+    let path = string_argument(args)?;
+    machine.dofile(&path)
+}
+
+pub fn require(machine: &mut Machine, args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let name = string_argument(args)?;
+    machine.require(&name)
+}
+
+/// Code, compiled by a script, is counted against the memory limit by the length of its source.
+fn count_code(machine: &mut Machine, code: &str) -> Result<(), EvalError> {
+    machine.usage.allocate(&machine.limits, code.len())
+}
+
+pub fn dostring(machine: &mut Machine, args: &[LuaValue]) -> Result<MultiValue, EvalError> {
+    let code = string_argument(args)?;
+    count_code(machine, &code)?;
+    machine.dostring(&code)
+}
+
+/// Returns the function, or nil and the message, if the code is not valid lua.
+pub fn load(machine: &mut Machine, args: &[LuaValue]) -> Result<MultiValue, EvalError> {
+    let code = string_argument(args)?;
+    count_code(machine, &code)?;
+    match machine.load(&code) {
+        Ok(function) => Ok(MultiValue(vec![function])),
+        Err(err @ EvalError::Parse { .. }) => Ok(MultiValue(vec![
+            LuaValue::NIL,
+            LuaValue::string(err.to_string()),
+        ])),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        eval_str, EvalError, FileLoader, LuaError, LuaValue, Machine, ModuleLoader, Modules,
+    };
+
+    fn machine_with_modules(modules: &[(&str, &str)]) -> Machine {
+        let mut machine = Machine::with_stdlib();
+        let modules: HashMap<String, String> = modules
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect();
+        machine.modules = Modules::new(modules);
+        machine
+    }
+
+    #[test]
+    fn required_modules_are_run_once() {
+        let mut machine = machine_with_modules(&[
+            ("counter", "loads = loads + 1 return { value = 42 }"),
+            ("empty", "local unused = 1"),
+        ]);
+        let res: (i32, i32, LuaValue) = eval_str(
+            "
+            loads = 0
+            local first = require('counter')
+            local second = require('counter')
+            return first.value, loads, require('empty')
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, (42, 1, LuaValue::TRUE));
+        assert!(machine.modules.get("counter").is_some());
+    }
+
+    #[test]
+    fn dofile_runs_the_file_every_time() {
+        let mut machine = machine_with_modules(&[
+            ("lib/inc.lua", "runs = runs + 1 return runs"),
+            ("lib/pair.lua", "return 'first', 'second'"),
+        ]);
+        let res: i32 = eval_str(
+            "runs = 0 dofile('lib/inc.lua') return dofile('lib/inc.lua')",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, 2);
+        let res: (String, String) = eval_str("return dofile('lib/pair.lua')", &mut machine).unwrap();
+        assert_eq!(res, ("first".to_owned(), "second".to_owned()));
+        assert!(matches!(
+            machine.dofile::<()>("missing.lua"),
+            Err(EvalError::IO(_))
+        ));
+    }
+
+    #[test]
+    fn file_loader_searches_the_templates_in_order() {
+        let dir = std::env::temp_dir().join(format!("reggie-modules-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("util/strings")).unwrap();
+        std::fs::write(dir.join("util/strings/init.lua"), "return 'init'").unwrap();
+        std::fs::write(dir.join("util/strings.lua"), "return 'file'").unwrap();
+        let dir_str = dir.to_str().unwrap();
+
+        let loader = FileLoader::new([format!("{dir_str}/?.lua"), format!("{dir_str}/?/init.lua")]);
+        assert_eq!(
+            loader.find("util.strings").unwrap().as_deref(),
+            Some("return 'file'")
+        );
+        let loader = FileLoader::new([format!("{dir_str}/?/init.lua")]);
+        assert_eq!(
+            loader.find("util.strings").unwrap().as_deref(),
+            Some("return 'init'")
+        );
+        assert_eq!(loader.find("util.missing").unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn default_machine_cannot_read_files() {
+        let path = std::env::temp_dir().join(format!("reggie-dofile-{}.lua", std::process::id()));
+        std::fs::write(&path, "return 42").unwrap();
+        let path_str = path.to_str().unwrap();
+
+        let mut machine = Machine::with_stdlib();
+        assert!(matches!(
+            machine.dofile::<()>(path_str),
+            Err(EvalError::IO(err)) if err.kind() == std::io::ErrorKind::NotFound
+        ));
+        assert!(matches!(
+            machine.require("reggie"),
+            Err(EvalError::ModuleNotFound(_))
+        ));
+
+        machine.modules = Modules::new(FileLoader::default());
+        assert_eq!(machine.dofile::<i32>(path_str).unwrap(), 42);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cyclic_requires_are_errors() {
+        let mut machine =
+            machine_with_modules(&[("a", "return require('b')"), ("b", "return require('a')")]);
+        let res = machine.require("a");
+        assert!(matches!(
+            res,
+            Err(EvalError::CyclicRequire(cycle)) if cycle == ["a", "b", "a"]
+        ));
+        // Failed modules are not cached, and the machine can load them again
+        assert!(machine.modules.get("a").is_none());
+        assert!(matches!(
+            machine.require("b"),
+            Err(EvalError::CyclicRequire(cycle)) if cycle == ["b", "a", "b"]
+        ));
+    }
+
+    #[test]
+    fn strings_are_run_and_loaded_as_functions() {
+        let mut machine = Machine::with_stdlib();
+        let res: (i32, i32, i32, i32) = eval_str(
+            "
+            counter = 0
+            local increment = load('counter = counter + 1 return counter')
+            increment()
+            increment()
+            local twice = dostring('counter = counter * 2 return counter')
+            return increment(), twice, dostring('return 1, 2')
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, (5, 4, 1, 2));
+        let res: i32 = eval_str(
+            "
+            dostring('function square(x) return x * x end')
+            return square(7)
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, 49);
+    }
+
+    #[test]
+    fn invalid_strings_are_runtime_errors() {
+        let mut machine = Machine::with_stdlib();
+        let (function, message): (LuaValue, String) =
+            eval_str("return load('return +')", &mut machine).unwrap();
+        assert_eq!(function, LuaValue::NIL);
+        assert!(message.contains("[string \"return +\"]"), "{message}");
+        let res = eval_str::<LuaValue>("return dostring('return +')", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::Parse { chunk, .. })) if chunk == "[string \"return +\"]"
+        ));
+        let res = machine.dostring::<LuaValue>("x = = 1\nreturn x");
+        assert!(matches!(
+            res,
+            Err(EvalError::Parse { chunk, .. }) if chunk == "[string \"x = = 1...\"]"
+        ));
+        // Machine is still usable after the errors
+        assert_eq!(machine.dostring::<i32>("return 42").unwrap(), 42);
+    }
+
+    #[test]
+    fn missing_and_invalid_modules_are_errors() {
+        let mut machine = machine_with_modules(&[("broken", "local = 1")]);
+        let res = eval_str::<LuaValue>("return require('missing')", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::ModuleNotFound(name))) if name == "missing"
+        ));
+        let res = eval_str::<LuaValue>("return require('broken')", &mut machine);
+        assert!(matches!(
+            res,
+            Err(LuaError::Eval(EvalError::Parse { chunk, .. })) if chunk == "broken"
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 use super::ids::{
-    ArgumentRegisterID, GlobalCellID, JmpLabel, LocalBlockID, LocalRegisterID, StringID,
+    ArgumentRegisterID, GlobalCellID, JmpLabel, LocalBlockID, LocalRegisterID, PropertyCacheID,
+    StringID,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -152,11 +153,11 @@ pub enum Instruction {
     AssocRD(ArgumentRegisterID),
     AssocLD(LocalRegisterID),
     // assoc_ASD
-    AssocASD,
+    AssocASD(PropertyCacheID),
 
     // lda_assoc
     LdaAssocAD,
-    LdaAssocAS,
+    LdaAssocAS(PropertyCacheID),
 
     // push_D
     PushD,
@@ -171,6 +172,12 @@ pub enum Instruction {
     TypedCall,
     // D_call
     DCall,
+    // native_call
+    NativeCall,
+    // tail_call
+    TailCall,
+    // D_tail_call
+    DTailCall,
     // ret
     Ret,
 
@@ -432,6 +439,9 @@ impl std::fmt::Display for Instruction {
             Instruction::Call => write!(f, "call"),
             Instruction::TypedCall => write!(f, "typed_call"),
             Instruction::DCall => write!(f, "D_call"),
+            Instruction::NativeCall => write!(f, "native_call"),
+            Instruction::TailCall => write!(f, "tail_call"),
+            Instruction::DTailCall => write!(f, "D_tail_call"),
             Instruction::Ret => write!(f, "ret"),
             Instruction::EqTestRF(reg) => write!(f, "eq_test RF{}", reg.0),
             Instruction::EqTestRS(reg) => write!(f, "eq_test RS{}", reg.0),
@@ -498,9 +508,9 @@ impl std::fmt::Display for Instruction {
             Instruction::AssocLD(reg) => write!(f, "assoc LD{}", reg.0),
             Instruction::NewT => write!(f, "new_T"),
             Instruction::PushD => write!(f, "push_D"),
-            Instruction::AssocASD => write!(f, "assoc AS D"),
+            Instruction::AssocASD(cache) => write!(f, "assoc AS D ic{}", cache.0),
             Instruction::LdaAssocAD => write!(f, "lda_assoc AD"),
-            Instruction::LdaAssocAS => write!(f, "lda_assoc AS"),
+            Instruction::LdaAssocAS(cache) => write!(f, "lda_assoc AS ic{}", cache.0),
             Instruction::TablePropertyLookupError => write!(f, "error table_property_lookup"),
             Instruction::TableMemberLookupErrorR(reg) => {
                 write!(f, "error table_member_lookup RD{}", reg.0)
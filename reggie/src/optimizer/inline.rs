@@ -0,0 +1,651 @@
+use std::collections::HashMap;
+
+use crate::{
+    compiler::CompiledModule,
+    ids::{ArgumentRegisterID, GlobalCellID, JmpLabel, LocalBlockID, LocalRegisterID},
+    machine::{CodeBlock, DataType},
+    meta::{ArgumentCount, LocalRegCount, ReturnCount},
+    ops::Instruction,
+};
+
+/// Functions longer than that are not inlined, the call overhead is negligible for them.
+const MAX_INLINED_INSTRUCTIONS: usize = 64;
+
+/// Substitutes calls of small module level functions with their bodies.
+///
+/// Function is inlined, if its global is assigned only by its declaration in this module, and its
+/// body doesn't call anything, which also rules out recursion. Global can still be reassigned by
+/// other modules, so every inlined body is guarded by a comparison of the global with the declared
+/// function, and the original call is made when they differ.
+///
+/// Inlined body behaves like a call through the dynamic wrapper: missing arguments are set to nil,
+/// and return values are left in argument registers along with the value count.
+pub fn inline_calls(module: &mut CompiledModule) {
+    let callees = find_inlinable_functions(module);
+    if callees.is_empty() {
+        return;
+    }
+    for (_, block) in &mut module.blocks {
+        inline_into(block, &callees);
+    }
+    inline_into(&mut module.top_level, &callees);
+}
+
+#[derive(Debug)]
+struct Callee {
+    /// Function value, which is stored in the global. It is the dynamic wrapper, if the function
+    /// needs one.
+    function: LocalBlockID,
+    body: CodeBlock,
+}
+
+fn find_inlinable_functions(module: &CompiledModule) -> HashMap<GlobalCellID, Callee> {
+    let mut global_stores: HashMap<GlobalCellID, usize> = HashMap::new();
+    for block in module.blocks.slice().iter().chain([&module.top_level]) {
+        for instr in &block.instructions {
+            if let Some(cell) = global_store(*instr) {
+                *global_stores.entry(cell).or_default() += 1;
+            }
+        }
+    }
+
+    module
+        .top_level
+        .instructions
+        .windows(3)
+        .filter_map(|window| match *window {
+            [Instruction::ConstC(function), Instruction::WrapC, Instruction::StrDGl(cell)] => {
+                Some((cell, function))
+            }
+            _ => None,
+        })
+        .filter(|(cell, _)| global_stores[cell] == 1)
+        .filter_map(|(cell, function)| {
+            let body = &module.blocks[unwrap_dyn_wrapper(module, function)];
+            is_inlinable(body).then(|| {
+                let body = body.clone();
+                (cell, Callee { function, body })
+            })
+        })
+        .collect()
+}
+
+fn global_store(instr: Instruction) -> Option<GlobalCellID> {
+    use Instruction::*;
+    match instr {
+        StrFGl(cell) | StrIGl(cell) | StrSGl(cell) | StrTGl(cell) | StrCGl(cell) | StrUGl(cell)
+        | StrDGl(cell) => Some(cell),
+        _ => None,
+    }
+}
+
+/// Dynamic wrapper sets up the arguments, and calls the function right after.
+fn unwrap_dyn_wrapper(module: &CompiledModule, function: LocalBlockID) -> LocalBlockID {
+    let block = &module.blocks[function];
+    let ArgumentCount::Known(arg_count) = block.meta.arg_count else {
+        return function;
+    };
+    let setup_len = arg_count as usize * 2;
+    match block.instructions.get(setup_len..setup_len + 2) {
+        Some(&[Instruction::ConstC(wrapped), Instruction::TypedCall | Instruction::TailCall]) => {
+            wrapped
+        }
+        _ => function,
+    }
+}
+
+fn is_inlinable(body: &CodeBlock) -> bool {
+    use Instruction::*;
+    body.instructions.len() <= MAX_INLINED_INSTRUCTIONS
+        && matches!(body.meta.arg_count, ArgumentCount::Known(_))
+        && !body
+            .instructions
+            .iter()
+            .any(|instr| {
+                matches!(
+                    instr,
+                    Call | TypedCall | DCall | NativeCall | TailCall | DTailCall
+                )
+            })
+}
+
+/// Locals, which arguments are moved into at the start of the body, if arguments are not used
+/// anywhere else.
+fn aliased_arguments(body: &CodeBlock, arg_count: u16) -> Option<Vec<LocalRegisterID>> {
+    let (prologue, rest) = body.instructions.split_at_checked(arg_count as usize * 2)?;
+    let locals = prologue
+        .chunks(2)
+        .zip(0..)
+        .map(|(pair, arg)| match *pair {
+            [Instruction::LdaRD(ArgumentRegisterID(reg)), Instruction::StrLD(local)]
+                if reg == arg =>
+            {
+                Some(local)
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!rest.iter().copied().any(reads_arguments)).then_some(locals)
+}
+
+fn reads_arguments(instr: Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instr,
+        LdaRF(_)
+            | LdaRS(_)
+            | LdaRI(_)
+            | LdaRT(_)
+            | LdaRC(_)
+            | LdaRU(_)
+            | LdaRD(_)
+            | LdaProt(_)
+            | LdaVC
+            | RFShiftRight
+            | RIShiftRight
+            | RSShiftRight
+            | RTShiftRight
+            | RCShiftRight
+            | RUShiftRight
+            | RDShiftRight
+            | FAddR(_)
+            | FMulR(_)
+            | FSubR(_)
+            | FDivR(_)
+            | IAddR(_)
+            | IMulR(_)
+            | ISubR(_)
+            | IDivR(_)
+            | DAddR(_)
+            | DMulR(_)
+            | DSubR(_)
+            | DDivR(_)
+            | SConcatR(_)
+            | SConcatL(_)
+            | DConcatR(_)
+            | AssocRD(_)
+            | EqTestRF(_)
+            | EqTestRS(_)
+            | EqTestRI(_)
+            | EqTestRT(_)
+            | EqTestRC(_)
+            | EqTestRU(_)
+            | EqTestRD(_)
+            | TestRF(_)
+            | TestRS(_)
+            | TestRI(_)
+            | TestRT(_)
+            | TestRC(_)
+            | TestRU(_)
+            | TestRD(_)
+            | TableMemberLookupErrorR(_)
+            | TableMemberAssignErrorR(_)
+    )
+}
+
+fn call_site(window: &[Instruction]) -> Option<(GlobalCellID, Instruction)> {
+    match *window {
+        [Instruction::LdaDGl(cell), call @ (Instruction::DCall | Instruction::DTailCall)] => {
+            Some((cell, call))
+        }
+        _ => None,
+    }
+}
+
+fn inline_into(block: &mut CodeBlock, callees: &HashMap<GlobalCellID, Callee>) {
+    let has_call_sites = block
+        .instructions
+        .windows(2)
+        .filter_map(call_site)
+        .any(|(cell, _)| callees.contains_key(&cell));
+    if !has_call_sites {
+        return;
+    }
+
+    let mut inliner = Inliner::new(block);
+    let instructions = std::mem::take(&mut block.instructions);
+    let labels_at: HashMap<u32, JmpLabel> = block
+        .meta
+        .label_mappings
+        .iter()
+        .map(|(label, &position)| (position, label))
+        .collect();
+
+    let mut position = 0;
+    while position < instructions.len() {
+        if let Some((cell, call)) = instructions.get(position..position + 2).and_then(call_site)
+            && let Some(callee) = callees.get(&cell)
+        {
+            inliner.inline_call(block, cell, call, callee);
+            position += 2;
+            continue;
+        }
+        if let Some(&label) = labels_at.get(&(position as u32)) {
+            block.meta.label_mappings[label] = inliner.position();
+        }
+        inliner.out.push(instructions[position]);
+        position += 1;
+    }
+
+    block.instructions = inliner.out;
+    block.meta.local_count = inliner.local_count;
+}
+
+struct Inliner {
+    out: Vec<Instruction>,
+    /// Dynamic register, which holds the declared function during the guard check
+    guard: LocalRegisterID,
+    /// Inlined bodies get registers after the registers of the block. Each call site reuses them.
+    locals_start: LocalRegCount,
+    local_count: LocalRegCount,
+}
+
+impl Inliner {
+    fn new(block: &CodeBlock) -> Self {
+        let mut locals_start = block.meta.local_count;
+        let guard = LocalRegisterID(locals_start[DataType::Dynamic]);
+        locals_start[DataType::Dynamic] += 1;
+        Self {
+            out: Vec::with_capacity(block.instructions.len()),
+            guard,
+            locals_start,
+            local_count: locals_start,
+        }
+    }
+
+    fn position(&self) -> u32 {
+        self.out.len().try_into().unwrap()
+    }
+
+    fn inline_call(
+        &mut self,
+        block: &mut CodeBlock,
+        cell: GlobalCellID,
+        call: Instruction,
+        callee: &Callee,
+    ) {
+        use Instruction::*;
+
+        let slow_lbl = block.meta.label_mappings.push(0);
+        let cont_lbl = block.meta.label_mappings.push(0);
+
+        self.out.extend([
+            ConstC(callee.function),
+            WrapC,
+            StrLD(self.guard),
+            LdaDGl(cell),
+            EqTestLD(self.guard),
+            JmpNE(slow_lbl),
+        ]);
+        // Marks the end of the guard, so that it can be skipped, when the global is speculated
+        // to keep its value.
+        block.meta.label_mappings.push(self.position());
+
+        let body = &callee.body;
+        let ArgumentCount::Known(arg_count) = body.meta.arg_count else {
+            unreachable!("only functions with known argument count are inlined")
+        };
+        let relocation = Relocation {
+            locals: self.locals_start,
+            labels: block.meta.label_mappings.len().try_into().unwrap(),
+            strings: block.meta.const_strings.len().try_into().unwrap(),
+            caches: block.meta.property_caches.len().try_into().unwrap(),
+        };
+        for _ in body.meta.label_mappings.keys() {
+            block.meta.label_mappings.push(0);
+        }
+        for string in body.meta.const_strings.slice() {
+            block.meta.const_strings.push(string.clone());
+        }
+        for cache in body.meta.property_caches.slice() {
+            block.meta.property_caches.push(cache.clone());
+        }
+        for (ty, count) in body.meta.local_count {
+            let count = self.locals_start[ty] + count;
+            self.local_count[ty] = std::cmp::max(self.local_count[ty], count);
+        }
+
+        // Functions start by moving arguments into locals. When nothing else reads arguments,
+        // the locals are set up directly, instead of going through argument registers.
+        let aliased = aliased_arguments(body, arg_count);
+        let skipped = match aliased {
+            Some(ref locals) => {
+                for (arg, &local) in (0..arg_count).zip(locals) {
+                    self.out.extend([
+                        LdaProt(ArgumentRegisterID(arg)),
+                        StrLD(relocation.local(local, DataType::Dynamic)),
+                    ]);
+                }
+                locals.len() * 2
+            }
+            None => {
+                for arg in 0..arg_count {
+                    self.out.extend([
+                        LdaProt(ArgumentRegisterID(arg)),
+                        StrRD(ArgumentRegisterID(arg)),
+                    ]);
+                }
+                0
+            }
+        };
+
+        let done_lbl = block.meta.label_mappings.push(0);
+        let labels_at: HashMap<u32, JmpLabel> = body
+            .meta
+            .label_mappings
+            .iter()
+            .map(|(label, &position)| (position, relocation.label(label)))
+            .collect();
+        for (position, &instr) in body.instructions.iter().enumerate().skip(skipped) {
+            if let Some(&label) = labels_at.get(&(position as u32)) {
+                block.meta.label_mappings[label] = self.position();
+            }
+            let instr = match instr {
+                Ret => Jmp(done_lbl),
+                instr => relocation.apply(instr),
+            };
+            self.out.push(instr);
+        }
+        block.meta.label_mappings[done_lbl] = self.position();
+        self.out.push(Label);
+
+        if let ReturnCount::Constant(count) = body.meta.return_count {
+            // Set by the dynamic wrapper, function itself doesn't do it
+            self.out.extend([ConstI(count as i32), StrVC]);
+        }
+        match call {
+            DCall => self.out.push(Jmp(cont_lbl)),
+            DTailCall => self.out.push(Ret),
+            _ => unreachable!("call site is either a call or a tail call"),
+        }
+
+        block.meta.label_mappings[slow_lbl] = self.position();
+        self.out.extend([Label, call]);
+        block.meta.label_mappings[cont_lbl] = self.position();
+        self.out.push(Label);
+    }
+}
+
+/// Offsets of everything in the inlined body, which is numbered per code block.
+struct Relocation {
+    locals: LocalRegCount,
+    labels: u16,
+    strings: u16,
+    caches: u16,
+}
+
+impl Relocation {
+    fn label(&self, JmpLabel(label): JmpLabel) -> JmpLabel {
+        JmpLabel(self.labels + label)
+    }
+
+    fn local(&self, LocalRegisterID(reg): LocalRegisterID, ty: DataType) -> LocalRegisterID {
+        LocalRegisterID(self.locals[ty] + reg)
+    }
+
+    fn apply(&self, instr: Instruction) -> Instruction {
+        use crate::{
+            ids::{PropertyCacheID, StringID},
+            machine::DataType::*,
+        };
+        use Instruction::*;
+
+        match instr {
+            LdaLF(reg) => LdaLF(self.local(reg, Float)),
+            LdaLS(reg) => LdaLS(self.local(reg, String)),
+            LdaLI(reg) => LdaLI(self.local(reg, Int)),
+            LdaLT(reg) => LdaLT(self.local(reg, Table)),
+            LdaLC(reg) => LdaLC(self.local(reg, Function)),
+            LdaLU(reg) => LdaLU(self.local(reg, NativeFunction)),
+            LdaLD(reg) => LdaLD(self.local(reg, Dynamic)),
+            StrLF(reg) => StrLF(self.local(reg, Float)),
+            StrLS(reg) => StrLS(self.local(reg, String)),
+            StrLI(reg) => StrLI(self.local(reg, Int)),
+            StrLT(reg) => StrLT(self.local(reg, Table)),
+            StrLC(reg) => StrLC(self.local(reg, Function)),
+            StrLU(reg) => StrLU(self.local(reg, NativeFunction)),
+            StrLD(reg) => StrLD(self.local(reg, Dynamic)),
+            FAddL(reg) => FAddL(self.local(reg, Float)),
+            FMulL(reg) => FMulL(self.local(reg, Float)),
+            FSubL(reg) => FSubL(self.local(reg, Float)),
+            FDivL(reg) => FDivL(self.local(reg, Float)),
+            IAddL(reg) => IAddL(self.local(reg, Int)),
+            IMulL(reg) => IMulL(self.local(reg, Int)),
+            ISubL(reg) => ISubL(self.local(reg, Int)),
+            IDivL(reg) => IDivL(self.local(reg, Int)),
+            DAddL(reg) => DAddL(self.local(reg, Dynamic)),
+            DMulL(reg) => DMulL(self.local(reg, Dynamic)),
+            DSubL(reg) => DSubL(self.local(reg, Dynamic)),
+            DDivL(reg) => DDivL(self.local(reg, Dynamic)),
+            DConcatL(reg) => DConcatL(self.local(reg, Dynamic)),
+            AssocLD(reg) => AssocLD(self.local(reg, Dynamic)),
+            EqTestLF(reg) => EqTestLF(self.local(reg, Float)),
+            EqTestLS(reg) => EqTestLS(self.local(reg, String)),
+            EqTestLI(reg) => EqTestLI(self.local(reg, Int)),
+            EqTestLT(reg) => EqTestLT(self.local(reg, Table)),
+            EqTestLC(reg) => EqTestLC(self.local(reg, Function)),
+            EqTestLU(reg) => EqTestLU(self.local(reg, NativeFunction)),
+            EqTestLD(reg) => EqTestLD(self.local(reg, Dynamic)),
+            TestLF(reg) => TestLF(self.local(reg, Float)),
+            TestLS(reg) => TestLS(self.local(reg, String)),
+            TestLI(reg) => TestLI(self.local(reg, Int)),
+            TestLT(reg) => TestLT(self.local(reg, Table)),
+            TestLC(reg) => TestLC(self.local(reg, Function)),
+            TestLU(reg) => TestLU(self.local(reg, NativeFunction)),
+            TestLD(reg) => TestLD(self.local(reg, Dynamic)),
+            TableMemberLookupErrorL(reg) => TableMemberLookupErrorL(self.local(reg, Dynamic)),
+            TableMemberAssignErrorL(reg) => TableMemberAssignErrorL(self.local(reg, Dynamic)),
+
+            Jmp(label) => Jmp(self.label(label)),
+            JmpLT(label) => JmpLT(self.label(label)),
+            JmpGT(label) => JmpGT(self.label(label)),
+            JmpEQ(label) => JmpEQ(self.label(label)),
+            JmpNE(label) => JmpNE(self.label(label)),
+            JmpLE(label) => JmpLE(self.label(label)),
+            JmpGE(label) => JmpGE(self.label(label)),
+            JmpN(label) => JmpN(self.label(label)),
+            JmpF(label) => JmpF(self.label(label)),
+            JmpI(label) => JmpI(self.label(label)),
+            JmpC(label) => JmpC(self.label(label)),
+            JmpT(label) => JmpT(self.label(label)),
+            JmpU(label) => JmpU(self.label(label)),
+
+            ConstS(StringID(id)) => ConstS(StringID(self.strings + id)),
+            AssocASD(PropertyCacheID(id)) => AssocASD(PropertyCacheID(self.caches + id)),
+            LdaAssocAS(PropertyCacheID(id)) => LdaAssocAS(PropertyCacheID(self.caches + id)),
+
+            Call | TypedCall | DCall | NativeCall | TailCall | DTailCall | Ret => {
+                unreachable!("{instr} can't be relocated into another block")
+            }
+
+            LdaRF(_)
+            | LdaRS(_)
+            | LdaRI(_)
+            | LdaRT(_)
+            | LdaRC(_)
+            | LdaRU(_)
+            | LdaRD(_)
+            | StrRF(_)
+            | StrRS(_)
+            | StrRI(_)
+            | StrRT(_)
+            | StrRC(_)
+            | StrRU(_)
+            | StrRD(_)
+            | LdaFGl(_)
+            | LdaIGl(_)
+            | LdaSGl(_)
+            | LdaTGl(_)
+            | LdaCGl(_)
+            | LdaUGl(_)
+            | LdaDGl(_)
+            | StrFGl(_)
+            | StrIGl(_)
+            | StrSGl(_)
+            | StrTGl(_)
+            | StrCGl(_)
+            | StrUGl(_)
+            | StrDGl(_)
+            | LdaDynGl
+            | StrDynGl
+            | LdaProt(_)
+            | RFShiftRight
+            | RIShiftRight
+            | RSShiftRight
+            | RTShiftRight
+            | RCShiftRight
+            | RUShiftRight
+            | RDShiftRight
+            | FAddR(_)
+            | FMulR(_)
+            | FSubR(_)
+            | FDivR(_)
+            | NegF
+            | IAddR(_)
+            | IMulR(_)
+            | ISubR(_)
+            | IDivR(_)
+            | NegI
+            | DAddR(_)
+            | DMulR(_)
+            | DSubR(_)
+            | DDivR(_)
+            | NegD
+            | SConcatR(_)
+            | SConcatL(_)
+            | DConcatR(_)
+            | IToS
+            | FToS
+            | DToS
+            | AssocRD(_)
+            | LdaAssocAD
+            | PushD
+            | StrVC
+            | LdaVC
+            | EqTestRF(_)
+            | EqTestRS(_)
+            | EqTestRI(_)
+            | EqTestRT(_)
+            | EqTestRC(_)
+            | EqTestRU(_)
+            | EqTestRD(_)
+            | TestRF(_)
+            | TestRS(_)
+            | TestRI(_)
+            | TestRT(_)
+            | TestRC(_)
+            | TestRU(_)
+            | TestRD(_)
+            | TypeTest
+            | NilTest
+            | ConstF(_)
+            | ConstI(_)
+            | ConstN
+            | ConstC(_)
+            | NewT
+            | WrapF
+            | WrapI
+            | WrapS
+            | WrapC
+            | WrapT
+            | WrapU
+            | CastF
+            | CastI
+            | CastS
+            | CastC
+            | CastT
+            | CastU
+            | Label
+            | TablePropertyLookupError
+            | TableMemberLookupErrorR(_)
+            | TablePropertyAssignError
+            | TableMemberAssignErrorR(_) => instr,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compiler::compile_module, eval_str, ops::Instruction, GlobalValues, LuaValue, Machine,
+    };
+
+    fn top_level_instructions(module: &str) -> Vec<Instruction> {
+        let module = luar_syn::lua_parser::module(module).unwrap();
+        compile_module(&module, &mut GlobalValues::default())
+            .top_level
+            .instructions
+    }
+
+    fn inlines_call(module: &str) -> bool {
+        top_level_instructions(module)
+            .windows(2)
+            .any(|window| matches!(window, [Instruction::LdaDGl(_), Instruction::EqTestLD(_)]))
+    }
+
+    #[test]
+    fn small_functions_are_inlined() {
+        let module = "
+            function add(a, b) return a + b end
+            return add(1, 2), add('3', 4)
+        ";
+        assert!(inlines_call(module));
+        let res: (LuaValue, LuaValue) = eval_str(module, &mut Machine::with_stdlib()).unwrap();
+        assert_eq!(res, (LuaValue::int(3), LuaValue::float(7.0)));
+    }
+
+    #[test]
+    fn inlined_calls_keep_argument_and_return_semantics() {
+        let module = "
+            function second(a, b) return b end
+            function pair() local x = 1 return x, x + 1 end
+            function nothing() end
+            local a, b = pair()
+            return second(1), second(1, 2, 3), a, b, nothing()
+        ";
+        assert!(inlines_call(module));
+        let mut machine = Machine::with_stdlib();
+        let res: &[LuaValue] = eval_str(module, &mut machine).unwrap();
+        assert_eq!(
+            res,
+            [
+                LuaValue::NIL,
+                LuaValue::int(2),
+                LuaValue::int(1),
+                LuaValue::int(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_calling_and_reassigned_functions_are_not_inlined() {
+        assert!(!inlines_call(
+            "function f(n) if n == 0 then return 0 end return f(n - 1) end return f(3)"
+        ));
+        assert!(!inlines_call(
+            "function f() return 1 end function g() return f() end return g()"
+        ));
+        assert!(!inlines_call(
+            "function f() return 1 end f = print return f()"
+        ));
+    }
+
+    #[test]
+    fn guard_falls_back_to_call_when_global_is_replaced() {
+        let mut machine = Machine::with_stdlib();
+        eval_str::<()>(
+            "
+            function op(a, b) return a + b end
+            function apply() return op(2, 3) end
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let res: LuaValue = eval_str("return apply()", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(5));
+
+        eval_str::<()>("function op(a, b) return a * b end", &mut machine).unwrap();
+        let res: LuaValue = eval_str("return apply()", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(6));
+    }
+}
@@ -12,6 +12,11 @@ use crate::{
 };
 use keyed_vec::KeyedVec;
 
+mod inline;
+pub(crate) use inline::*;
+mod speculate;
+pub(crate) use speculate::*;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum BlockExit {
     End,
@@ -0,0 +1,331 @@
+use std::cell::{Cell, OnceCell};
+
+use crate::{
+    global_values::GlobalValues,
+    ids::{ArgumentRegisterID, BlockID, GlobalCellID, JmpLabel, LocalBlockID, ModuleID},
+    machine::{CodeBlock, CodeBlocks, ModuleAssociatedBlock},
+    meta::{ArgumentCount, CodeMeta, FunctionKind},
+    ops::Instruction,
+    LuaValue,
+};
+
+/// Per block state of speculation. Blocks are shared while they run, hence the cells.
+#[derive(Debug, Default)]
+pub(crate) struct SpeculationSlot {
+    attempted: Cell<bool>,
+    optimized: OnceCell<CodeBlock>,
+    deoptimized: Cell<bool>,
+}
+
+impl SpeculationSlot {
+    pub fn instructions(&self) -> Option<&[Instruction]> {
+        if self.deoptimized.get() {
+            return None;
+        }
+        self.optimized
+            .get()
+            .map(|optimized| optimized.instructions.as_slice())
+    }
+
+    #[cfg(test)]
+    pub fn kind(&self) -> FunctionKind {
+        match self.optimized.get() {
+            Some(optimized) if !self.deoptimized.get() => optimized.meta.kind,
+            _ => FunctionKind::DeOptimized,
+        }
+    }
+}
+
+/// Speculates on the globals, which the block uses, when it is called for the first time.
+pub(crate) fn note_call(
+    code_blocks: &CodeBlocks,
+    block_id: BlockID,
+    global_values: &mut GlobalValues,
+) {
+    let slot = &code_blocks[block_id].speculation;
+    if slot.attempted.replace(true) {
+        return;
+    }
+    if let Some((optimized, dependencies)) = speculate(code_blocks, block_id, global_values) {
+        for cell in dependencies {
+            global_values.add_dependent(cell, block_id);
+        }
+        let _ = slot.optimized.set(optimized);
+    }
+}
+
+/// Switches blocks, whose assumptions were broken by global stores, back to the original
+/// instructions. Such blocks are not speculated on again.
+pub(crate) fn deoptimize_invalidated(code_blocks: &CodeBlocks, global_values: &mut GlobalValues) {
+    for block_id in global_values.take_invalidated() {
+        code_blocks[block_id].speculation.deoptimized.set(true);
+    }
+}
+
+/// Produces a version of the block, which assumes that the globals it calls keep their current
+/// values:
+///
+/// * guards of inlined calls are skipped,
+/// * calls of lua functions are made directly, bypassing the dynamic wrapper, when enough
+///   arguments are passed,
+/// * native functions, unless they are tail called, are called without checking the type of the
+///   called value.
+///
+/// Speculated instructions are at the same positions as the original ones, and the frame layout
+/// is the same. So a frame can switch to the original instructions at any point, including the
+/// frames, which are suspended in a call. That is how the block is deoptimized once any of the
+/// returned globals changes. Native code of the JIT is compiled from the original instructions,
+/// so it is not entered while the speculated ones are in use.
+fn speculate(
+    code_blocks: &CodeBlocks,
+    block_id: BlockID,
+    global_values: &GlobalValues,
+) -> Option<(CodeBlock, Vec<GlobalCellID>)> {
+    use Instruction::*;
+
+    let block = &code_blocks[block_id];
+    let module_blocks = code_blocks.blocks_of_module(block.module);
+    let function_in_cell = |cell| global_values.value_of_cell(cell).as_lua_function();
+
+    let mut instructions = block.instructions.clone();
+    let mut dependencies = Vec::new();
+    for position in 0..instructions.len() {
+        match block.instructions[position..] {
+            [ConstC(function), WrapC, StrLD(guard), LdaDGl(cell), EqTestLD(tested), JmpNE(_), ..]
+                if guard == tested
+                    && function_in_cell(cell) == Some(module_blocks[function])
+                    && let Some(fast_path) = label_at(&block.meta, position + 6) =>
+            {
+                instructions[position] = Jmp(fast_path);
+                dependencies.push(cell);
+            }
+            [ConstI(arg_count), StrVC, LdaDGl(cell), call @ (DCall | DTailCall), ..]
+                if let Some(function) = function_in_cell(cell)
+                    && let Some(callee) =
+                        direct_callee(code_blocks, block.module, function, arg_count, call) =>
+            {
+                instructions[position + 2] = ConstC(callee);
+                instructions[position + 3] = if call == DCall { TypedCall } else { TailCall };
+                dependencies.push(cell);
+            }
+            [LdaDGl(cell), DCall, ..]
+                if global_values.value_of_cell(cell).as_native_function().is_some() =>
+            {
+                instructions[position] = LdaUGl(cell);
+                instructions[position + 1] = NativeCall;
+                dependencies.push(cell);
+            }
+            _ => {}
+        }
+    }
+
+    if dependencies.is_empty() {
+        return None;
+    }
+    let optimized = CodeBlock {
+        meta: CodeMeta {
+            kind: FunctionKind::GlobalsOptimized {
+                deopt_original: block_id,
+            },
+            ..block.meta.clone()
+        },
+        instructions,
+    };
+    Some((optimized, dependencies))
+}
+
+fn label_at(meta: &CodeMeta, position: usize) -> Option<JmpLabel> {
+    meta.label_mappings
+        .iter()
+        .find(|(_, label_position)| **label_position as usize == position)
+        .map(|(label, _)| label)
+}
+
+/// Block to call with a typed call instead of calling the function value. It should be in the
+/// same module as the caller, so that the caller can load it with `ConstC`.
+fn direct_callee(
+    code_blocks: &CodeBlocks,
+    module: ModuleID,
+    function: BlockID,
+    arg_count: i32,
+    call: Instruction,
+) -> Option<LocalBlockID> {
+    let callee = &code_blocks[function];
+    if callee.module != module {
+        return None;
+    }
+    if let ArgumentCount::Known(param_count) = callee.meta.arg_count
+        && let Some((wrapped, wrapper_call)) = dyn_wrapper_target(callee, param_count)
+    {
+        // Wrapper sets missing arguments to nil, which the wrapped function relies on
+        let has_arguments = arg_count >= i32::from(param_count);
+        // Functions with a constant return count set the value count only when they return to a
+        // frame. Tail call can replace the frame, which returns to the caller of the machine.
+        let sets_value_count = call == Instruction::DCall || wrapper_call == Instruction::TailCall;
+        return (has_arguments && sets_value_count).then_some(wrapped);
+    }
+    code_blocks
+        .blocks_of_module(module)
+        .iter()
+        .find(|(_, block_id)| **block_id == function)
+        .map(|(local, _)| local)
+}
+
+/// Dynamic wrapper copies each argument onto itself, setting it to nil if it is missing, and
+/// calls the wrapped function right after.
+fn dyn_wrapper_target(
+    block: &ModuleAssociatedBlock,
+    param_count: u16,
+) -> Option<(LocalBlockID, Instruction)> {
+    let setup_len = param_count as usize * 2;
+    let (setup, rest) = block.instructions.split_at_checked(setup_len)?;
+    let copies_arguments = setup.chunks(2).zip(0..).all(|(pair, arg)| {
+        pair == [
+            Instruction::LdaProt(ArgumentRegisterID(arg)),
+            Instruction::StrRD(ArgumentRegisterID(arg)),
+        ]
+    });
+    match rest {
+        [Instruction::ConstC(wrapped), call @ (Instruction::TypedCall | Instruction::TailCall), ..]
+            if copies_arguments =>
+        {
+            Some((*wrapped, *call))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::dyn_wrapper_target;
+    use crate::{
+        eval_str,
+        meta::{ArgumentCount, FunctionKind},
+        LuaValue, Machine,
+    };
+
+    /// Kind of the function body, which is called through the global
+    fn kind_of(machine: &Machine, global: &str) -> FunctionKind {
+        let function = machine.global_values.get(global).as_lua_function().unwrap();
+        let block = &machine.code_blocks[function];
+        let body = match block.meta.arg_count {
+            ArgumentCount::Known(param_count) => dyn_wrapper_target(block, param_count)
+                .map(|(wrapped, _)| machine.code_blocks.blocks_of_module(block.module)[wrapped]),
+            ArgumentCount::Unknown => None,
+        };
+        machine.code_blocks[body.unwrap_or(function)]
+            .speculation
+            .kind()
+    }
+
+    #[test]
+    fn calls_of_stable_globals_are_speculated() {
+        let mut machine = Machine::with_stdlib();
+        let res: LuaValue = eval_str(
+            "
+            function add(a, b) return a + b end
+            function twice(a) return add(a, a) end
+            function count()
+                return twice(1) + twice(2)
+            end
+            return count()
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, LuaValue::int(6));
+        assert!(matches!(
+            kind_of(&machine, "count"),
+            FunctionKind::GlobalsOptimized { .. }
+        ));
+        assert_eq!(kind_of(&machine, "add"), FunctionKind::DeOptimized);
+    }
+
+    #[test]
+    fn changing_a_global_deoptimizes_dependent_blocks() {
+        let mut machine = Machine::with_stdlib();
+        eval_str::<()>(
+            "
+            function one() return 1 end
+            function ten() return 10 end
+            function calls_one() return one() end
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let res: LuaValue = eval_str("return calls_one()", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(1));
+        assert!(matches!(
+            kind_of(&machine, "calls_one"),
+            FunctionKind::GlobalsOptimized { .. }
+        ));
+
+        let ten = machine.global_values.get("ten").clone();
+        machine.global_values.set("one", ten);
+        let res: LuaValue = eval_str("return calls_one()", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::int(10));
+        assert_eq!(kind_of(&machine, "calls_one"), FunctionKind::DeOptimized);
+    }
+
+    #[test]
+    fn changing_a_native_global_deoptimizes_dependent_blocks() {
+        let mut machine = Machine::with_stdlib();
+        eval_str::<()>(
+            "function round(x) local rounded = floor(x + 0.5) return rounded end",
+            &mut machine,
+        )
+        .unwrap();
+        let res: LuaValue = eval_str("return round(2.7)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::float(3.0));
+        assert!(matches!(
+            kind_of(&machine, "round"),
+            FunctionKind::GlobalsOptimized { .. }
+        ));
+
+        eval_str::<()>("floor = type", &mut machine).unwrap();
+        let res: LuaValue = eval_str("return round(2.7)", &mut machine).unwrap();
+        assert_eq!(res, LuaValue::string("number"));
+        assert_eq!(kind_of(&machine, "round"), FunctionKind::DeOptimized);
+    }
+
+    #[test]
+    fn suspended_frames_continue_in_deoptimized_code() {
+        let mut machine = Machine::with_stdlib();
+        let res: LuaValue = eval_str(
+            "
+            function one() return 1 end
+            function ten() return 10 end
+            function replace_one() one = ten end
+            function run()
+                local before = one()
+                replace_one()
+                return before + one()
+            end
+            return run()
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, LuaValue::int(11));
+        assert_eq!(kind_of(&machine, "run"), FunctionKind::DeOptimized);
+    }
+
+    #[test]
+    fn missing_arguments_are_still_nil() {
+        let mut machine = Machine::with_stdlib();
+        let res: (LuaValue, LuaValue) = eval_str(
+            "
+            function pair(a, b)
+                local i = 0
+                while i < 10 do i = i + 1 end
+                return a, b
+            end
+            function call() return pair(1) end
+            return call()
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, (LuaValue::int(1), LuaValue::NIL));
+    }
+}
@@ -0,0 +1,402 @@
+//! Counting profiler.
+//!
+//! Every executed instruction and every call of a code block is counted, and the time is measured
+//! on calls and returns, so there is no sampling error. The cost is that the machine runs slower
+//! while it is profiled, and the JIT is not used, as native code doesn't report what it executes.
+//!
+//! Profiling is enabled by setting [`Machine::profiler`](crate::Machine::profiler). Calls of
+//! native functions are accounted to the calling block, and inlined functions to the block they
+//! were inlined into.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{ids::BlockID, machine::CodeBlocks};
+
+/// How many instructions are listed in [`ProfileReport::hot_instructions`].
+const HOT_INSTRUCTIONS: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct Profiler {
+    /// Indexed by [`BlockID`]
+    blocks: Vec<BlockProfile>,
+    frames: Vec<ActiveFrame>,
+    /// Blocks of `frames`, which is the key of `stacks`
+    path: Vec<BlockID>,
+    /// Instructions executed by the innermost block of a stack
+    stacks: HashMap<Vec<BlockID>, u64>,
+}
+
+#[derive(Debug, Default)]
+struct BlockProfile {
+    calls: u64,
+    /// Execution counts of the instructions, indexed by position
+    positions: Vec<u64>,
+    total_time: Duration,
+    self_time: Duration,
+    /// Frames of the block on the stack. Total time is only accounted for the outermost one, so
+    /// that recursive calls are not counted twice.
+    active: u32,
+}
+
+/// Frames of a suspended coroutine. Their time keeps running, so the time a coroutine was
+/// suspended for is accounted to its functions.
+#[derive(Debug, Default)]
+pub(crate) struct SuspendedFrames {
+    frames: Vec<ActiveFrame>,
+    path: Vec<BlockID>,
+}
+
+#[derive(Debug)]
+struct ActiveFrame {
+    block: BlockID,
+    started: Instant,
+    callee_time: Duration,
+    instructions: u64,
+}
+
+impl Profiler {
+    pub(crate) fn enter(&mut self, block: BlockID, instruction_count: usize) {
+        let index = block.0 as usize;
+        if self.blocks.len() <= index {
+            self.blocks.resize_with(index + 1, Default::default);
+        }
+        let profile = &mut self.blocks[index];
+        profile.calls += 1;
+        profile.active += 1;
+        profile.positions.resize(instruction_count, 0);
+
+        self.frames.push(ActiveFrame {
+            block,
+            started: Instant::now(),
+            callee_time: Duration::ZERO,
+            instructions: 0,
+        });
+        self.path.push(block);
+    }
+
+    pub(crate) fn leave(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let elapsed = frame.started.elapsed();
+        let profile = &mut self.blocks[frame.block.0 as usize];
+        profile.self_time += elapsed.saturating_sub(frame.callee_time);
+        profile.active -= 1;
+        if profile.active == 0 {
+            profile.total_time += elapsed;
+        }
+        if let Some(caller) = self.frames.last_mut() {
+            caller.callee_time += elapsed;
+        }
+
+        if frame.instructions > 0 {
+            match self.stacks.get_mut(self.path.as_slice()) {
+                Some(count) => *count += frame.instructions,
+                None => {
+                    self.stacks.insert(self.path.clone(), frame.instructions);
+                }
+            }
+        }
+        self.path.pop();
+    }
+
+    /// Leaves the frames above `depth`, which were left on the stack by an error.
+    pub(crate) fn unwind_to(&mut self, depth: usize) {
+        while self.frames.len() > depth {
+            self.leave();
+        }
+    }
+
+    /// Takes the frames above `depth` out, while the coroutine they belong to is suspended.
+    pub(crate) fn suspend(&mut self, depth: usize) -> SuspendedFrames {
+        let depth = depth.min(self.frames.len());
+        SuspendedFrames {
+            frames: self.frames.split_off(depth),
+            path: self.path.split_off(depth),
+        }
+    }
+
+    /// Puts the frames of a resumed coroutine on top of the ones of the code, that resumed it.
+    pub(crate) fn resume(&mut self, suspended: SuspendedFrames) {
+        self.frames.extend(suspended.frames);
+        self.path.extend(suspended.path);
+    }
+
+    /// Number of the frames entered and not yet left.
+    pub(crate) fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub(crate) fn instruction(&mut self, block: BlockID, position: u32) {
+        self.blocks[block.0 as usize].positions[position as usize] += 1;
+        if let Some(frame) = self.frames.last_mut() {
+            frame.instructions += 1;
+        }
+    }
+
+    /// Summarizes what was executed so far. Blocks are named after the functions they were
+    /// compiled from, using the code blocks of the profiled machine.
+    pub fn report(&self, code_blocks: &CodeBlocks) -> ProfileReport {
+        let name_of = |block: BlockID| {
+            code_blocks[block]
+                .meta
+                .debug_name
+                .clone()
+                .unwrap_or_else(|| format!("<block {}>", block.0))
+        };
+
+        let profiled = || {
+            self.blocks
+                .iter()
+                .zip(0..)
+                .map(|(profile, id)| (BlockID(id), profile))
+                .filter(|(_, profile)| profile.calls > 0)
+        };
+
+        let mut functions: Vec<_> = profiled()
+            .map(|(block, profile)| FunctionProfile {
+                name: name_of(block),
+                calls: profile.calls,
+                instructions: profile.positions.iter().sum(),
+                total_time: profile.total_time,
+                self_time: profile.self_time,
+            })
+            .collect();
+        functions.sort_by(|a, b| {
+            b.self_time
+                .cmp(&a.self_time)
+                .then(b.instructions.cmp(&a.instructions))
+        });
+
+        let mut hot_instructions: Vec<_> = profiled()
+            .flat_map(|(block, profile)| {
+                profile
+                    .positions
+                    .iter()
+                    .zip(0..)
+                    .filter(|(count, _)| **count > 0)
+                    .map(move |(&count, position)| (block, position, count))
+            })
+            .collect();
+        hot_instructions.sort_by_key(|&(_, _, count)| std::cmp::Reverse(count));
+        hot_instructions.truncate(HOT_INSTRUCTIONS);
+        let hot_instructions = hot_instructions
+            .into_iter()
+            .map(|(block, position, count)| InstructionProfile {
+                function: name_of(block),
+                position,
+                instruction: code_blocks[block].instructions[position as usize].to_string(),
+                count,
+            })
+            .collect();
+
+        let mut folded_stacks: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(path, &instructions)| {
+                let frames = path.iter().map(|&block| name_of(block)).collect::<Vec<_>>();
+                (frames.join(";"), instructions)
+            })
+            .collect();
+        folded_stacks.sort();
+
+        ProfileReport {
+            functions,
+            hot_instructions,
+            folded_stacks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    /// Sorted by the time spent in the function itself, the most expensive first
+    pub functions: Vec<FunctionProfile>,
+    /// The most executed instructions, the most executed first
+    pub hot_instructions: Vec<InstructionProfile>,
+    /// Call stacks, joined with `;`, with the number of instructions executed by the innermost
+    /// function of the stack
+    pub folded_stacks: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub instructions: u64,
+    /// Time from the call until the return, including the callees
+    pub total_time: Duration,
+    /// Total time without the time spent in the callees
+    pub self_time: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionProfile {
+    pub function: String,
+    pub position: u32,
+    pub instruction: String,
+    pub count: u64,
+}
+
+impl ProfileReport {
+    /// Writes the stacks in the folded format, which is read by flamegraph tools.
+    pub fn write_folded(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for (stack, instructions) in &self.folded_stacks {
+            writeln!(out, "{stack} {instructions}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>14} {:>12} {:>12}  function",
+            "calls", "instructions", "self ms", "total ms"
+        )?;
+        for function in &self.functions {
+            writeln!(
+                f,
+                "{:>10} {:>14} {:>12.3} {:>12.3}  {}",
+                function.calls,
+                function.instructions,
+                function.self_time.as_secs_f64() * 1000.0,
+                function.total_time.as_secs_f64() * 1000.0,
+                function.name
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:>14}  instruction", "executed")?;
+        for instr in &self.hot_instructions {
+            writeln!(
+                f,
+                "{:>14}  {}:{} {}",
+                instr.count, instr.function, instr.position, instr.instruction
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Profiler;
+    use crate::{eval_str, LuaValue, Machine};
+
+    fn profiled_machine() -> Machine {
+        let mut machine = Machine::with_stdlib();
+        machine.profiler = Some(Profiler::default());
+        machine
+    }
+
+    #[test]
+    fn calls_and_instructions_are_counted() {
+        let mut machine = profiled_machine();
+        eval_str::<()>(
+            "
+            function count(n)
+                local i = 0
+                while i < n do i = i + 1 end
+                return i
+            end
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        let res: LuaValue = eval_str(
+            "
+            local total = 0
+            local i = 0
+            while i < 10 do
+                total = total + count(i)
+                i = i + 1
+            end
+            return total
+            ",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, LuaValue::int(45));
+
+        let report = machine
+            .profiler
+            .as_ref()
+            .unwrap()
+            .report(&machine.code_blocks);
+        let count = report
+            .functions
+            .iter()
+            .find(|function| function.name == "count")
+            .unwrap();
+        assert_eq!(count.calls, 10);
+        assert!(count.instructions > 45);
+        assert!(count.total_time >= count.self_time);
+
+        let hottest = &report.hot_instructions[0];
+        assert_eq!(hottest.function, "count");
+        assert!(hottest.count >= 45);
+        assert_eq!(
+            report
+                .folded_stacks
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<u64>(),
+            {
+                report
+                    .functions
+                    .iter()
+                    .map(|function| function.instructions)
+                    .sum::<u64>()
+            }
+        );
+    }
+
+    #[test]
+    fn folded_stacks_follow_calls() {
+        let mut machine = profiled_machine();
+        eval_str::<()>(
+            "
+            function inner() return floor(1.5) end
+            function outer() inner() inner() end
+            outer()
+            ",
+            &mut machine,
+        )
+        .unwrap();
+
+        let report = machine
+            .profiler
+            .as_ref()
+            .unwrap()
+            .report(&machine.code_blocks);
+        let mut folded = Vec::new();
+        report.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        let inner_stack = folded
+            .lines()
+            .find(|line| line.rsplit_once(' ').unwrap().0.ends_with(";inner"))
+            .unwrap();
+        assert!(inner_stack.starts_with("<module root>;"));
+        assert!(inner_stack.contains(";outer;"));
+    }
+
+    #[test]
+    fn errors_close_the_frames() {
+        let mut machine = profiled_machine();
+        eval_str::<()>("function fail() return 1 + {} end", &mut machine).unwrap();
+        assert!(eval_str::<()>("fail()", &mut machine).is_err());
+        let profiler = machine.profiler.as_ref().unwrap();
+        assert!(profiler.frames.is_empty());
+        assert!(profiler.path.is_empty());
+        let report = profiler.report(&machine.code_blocks);
+        assert!(report
+            .folded_stacks
+            .iter()
+            .any(|(stack, _)| stack.ends_with(";fail")));
+    }
+}
@@ -2,11 +2,19 @@ use super::{
     ids::{ArgumentRegisterID, LocalRegisterID},
     machine::{Machine, ProgramCounter, TestFlag},
     ops::Instruction,
-    ArithmeticError, EvalError, InvalidLuaKey, LuaKey, LuaValue, NativeFunction, TableRef,
-    TableValue, TypeError,
+    ArithmeticError, EvalError, InvalidLuaKey, LuaKey, LuaValue, NativeFunction,
+    NativeFunctionKind, TableRef, TableValue, TypeError,
 };
-use crate::{ids::BlockID, trace_execution, ArithmeticOperator};
-use std::cmp::Ordering;
+use crate::{
+    coroutine,
+    debug::DebugFrame,
+    ids::BlockID,
+    limits::{TABLE_ENTRY_SIZE, TABLE_SIZE},
+    meta::ReturnCount,
+    optimizer::{deoptimize_invalidated, note_call},
+    trace_execution, ArithmeticOperator, SuspendingFunctionCallable,
+};
+use std::{cmp::Ordering, task::Poll};
 
 macro_rules! register_of {
     ($machine:expr, AD) => {
@@ -27,6 +35,9 @@ macro_rules! register_of {
     ($machine:expr, AT) => {
         $machine.accumulators.t
     };
+    ($machine:expr, AU) => {
+        $machine.accumulators.u
+    };
 
     ($machine:expr, RD, $reg:ident) => {
         $machine.argument_registers.d[($reg as ArgumentRegisterID).0 as usize]
@@ -62,21 +73,135 @@ macro_rules! register_of {
     };
 }
 
+#[cfg(feature = "jit")]
+pub(crate) use register_of;
+
+/// How the execution of the frames stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Exit {
+    /// The entry frame returned.
+    Returned,
+    /// `coroutine.yield` was called, and the frames are left on the stack. `tail` is set, when
+    /// yield was tail called, so that the yielding function returns, once it is resumed.
+    Yielded { tail: bool },
+    /// Slice of the execution ran out, and the frames are left on the stack.
+    Paused,
+    /// Native function is pending, and the execution waits for its values, like it does for the
+    /// values of a yield.
+    Pending { tail: bool },
+}
+
+/// What runs the frames, which decides how they can be suspended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Runner {
+    /// Host or a native function, which waits for the frames to return.
+    Call,
+    /// Resume of a coroutine, which the frames can yield to.
+    Coroutine,
+    /// [`Execution`](crate::Execution), which pauses the frames, once the count of executed
+    /// instructions reaches the end of the slice, and waits for the pending native functions.
+    Execution { slice_end: u64 },
+}
+
+/// Where the execution starts.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Entry {
+    /// Calls the block on top of the frames on the stack.
+    Call(BlockID),
+    /// Continues the frames of a suspended coroutine or execution, which are the only ones on the
+    /// stack.
+    Resume {
+        program_counter: ProgramCounter,
+        tail: bool,
+    },
+}
+
+/// Calls the block, and runs until it returns.
 pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), EvalError> {
-    machine.program_counter = ProgramCounter {
-        block: block_id,
-        position: 0,
-    };
+    let exit = execute_frames(machine, Entry::Call(block_id), Runner::Call)?;
+    debug_assert_eq!(exit, Exit::Returned);
+    Ok(())
+}
 
-    let mut block = &machine.code_blocks[machine.program_counter.block];
-    let mut position = &mut machine.program_counter.position;
-    let mut frame = machine.stack.push(
-        &block.meta,
-        ProgramCounter {
-            block: block_id,
-            position: 0,
-        },
-    );
+/// Runs the frames from the entry, until the entry frame returns, or the frames are suspended.
+/// Only the frames of a coroutine can yield, and only the frames of an execution can pause or wait
+/// for a native function.
+pub(crate) fn execute_frames(
+    machine: &mut Machine,
+    entry: Entry,
+    runner: Runner,
+) -> Result<Exit, EvalError> {
+    // Native functions may call back into the machine, so there may be frames below the entry one
+    let stack_base;
+    let mut block;
+    let mut position;
+    let mut frame;
+    let mut code;
+    match entry {
+        Entry::Call(block_id) => {
+            machine.program_counter = ProgramCounter {
+                block: block_id,
+                position: 0,
+            };
+
+            machine.usage.enter_call(&machine.limits)?;
+            stack_base = machine.stack.size();
+            block = &machine.code_blocks[machine.program_counter.block];
+            position = &mut machine.program_counter.position;
+            frame = machine.stack.push(
+                &block.meta,
+                ProgramCounter {
+                    block: block_id,
+                    position: 0,
+                },
+            );
+            #[cfg(feature = "jit")]
+            crate::jit::note_call(block, &machine.jit);
+            deoptimize_invalidated(&machine.code_blocks, &mut machine.global_values);
+            note_call(&machine.code_blocks, block_id, &mut machine.global_values);
+            code = block.code();
+            if let Some(profiler) = &mut machine.profiler {
+                profiler.enter(block_id, code.len());
+            }
+        }
+        Entry::Resume {
+            program_counter, ..
+        } => {
+            machine.program_counter = program_counter;
+            stack_base = 0;
+            block = &machine.code_blocks[machine.program_counter.block];
+            position = &mut machine.program_counter.position;
+            // SAFETY: The frames were suspended in this block, and its frame is the top one.
+            frame = unsafe { machine.stack.restore(&block.meta) };
+            // Globals may have changed, while the frames were suspended
+            deoptimize_invalidated(&machine.code_blocks, &mut machine.global_values);
+            code = block.code();
+        }
+    }
+    // Position of the first instruction executed after the last jump or call in the current frame
+    let mut run_start = *position;
+
+    /// Calls the debug hook with the current frame.
+    macro_rules! debug_hook {
+        ($event:ident $(, $arg:expr)*) => {{
+            if let Some(hook) = &mut machine.debug_hook {
+                let mut debug_frame = DebugFrame::new(
+                    ProgramCounter {
+                        block: machine.program_counter.block,
+                        position: *position,
+                    },
+                    &block.meta,
+                    frame.reborrow(),
+                    &machine.argument_registers,
+                    machine.value_count,
+                );
+                hook.$event(&mut debug_frame $(, $arg)*);
+            }
+        }};
+    }
+    if let Entry::Call(_) = entry {
+        debug_hook!(call);
+    }
 
     macro_rules! register {
         (LD, $reg:ident) => {
@@ -97,33 +222,262 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
         };
     }
 
+    /// Counts the instructions executed since the start of the run, including the current one.
+    macro_rules! count_run {
+        () => {
+            machine.usage.count_instructions(*position + 1 - run_start)
+        };
+    }
+
+    /// Execution is paused at back-edges and at the starts of functions, once its slice runs out,
+    /// so that neither loops nor recursion run past it for long.
+    macro_rules! pause_if_exhausted {
+        () => {{
+            if let Runner::Execution { slice_end } = runner
+                && machine.usage.instructions >= slice_end
+            {
+                return Ok(Exit::Paused);
+            }
+        }};
+    }
+
+    /// Instruction limit is checked at back-edges, so that every loop is interrupted.
+    macro_rules! jump {
+        ($label:expr) => {{
+            let target = block.meta.label_mappings[$label];
+            count_run!();
+            let is_back_edge = target <= *position;
+            *position = target;
+            run_start = target;
+            if is_back_edge {
+                machine.usage.check_instructions(&machine.limits)?;
+                pause_if_exhausted!();
+            }
+        }};
+    }
+
+    macro_rules! ret {
+        () => {{
+            count_run!();
+            debug_hook!(ret);
+            if let Some(profiler) = &mut machine.profiler {
+                profiler.leave();
+            }
+            machine.program_counter = frame.return_addr();
+            position = &mut machine.program_counter.position;
+
+            let release_handle = frame.release();
+            unsafe { machine.stack.pop(release_handle) };
+            machine.usage.leave_call();
+            if machine.stack.size() == stack_base {
+                return Ok(Exit::Returned);
+            }
+            block = &machine.code_blocks[machine.program_counter.block];
+            // SAFETY: We keep track of the stack frames, and guarantee
+            //         first-come first-serve ordering of stack frames.
+            frame = unsafe { machine.stack.restore(&block.meta) };
+            code = block.code();
+            run_start = *position;
+
+            trace_execution!(
+                "ret back to {:?} {}",
+                frame.return_addr.block,
+                block
+                    .meta
+                    .debug_name
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            );
+        }};
+    }
+
+    /// Replaces the frame of the current function with the frame of the callee. Callee returns
+    /// straight to the caller of the current function, so tail recursion runs in constant stack.
+    macro_rules! tail_call {
+        ($block_id:expr) => {{
+            let block_id = $block_id;
+            count_run!();
+            machine.usage.check_instructions(&machine.limits)?;
+            debug_hook!(ret);
+            let return_addr = frame.return_addr();
+            let release_handle = frame.release();
+            // SAFETY: Released frame is the top one, and it belongs to the current block.
+            unsafe { machine.stack.pop(release_handle) };
+            let new_block = &machine.code_blocks[block_id];
+            frame = machine.stack.push(&new_block.meta, return_addr);
+            block = new_block;
+            *position = 0;
+            run_start = 0;
+            machine.program_counter.block = block_id;
+            #[cfg(feature = "jit")]
+            crate::jit::note_call(block, &machine.jit);
+            note_call(&machine.code_blocks, block_id, &mut machine.global_values);
+            code = block.code();
+            if let Some(profiler) = &mut machine.profiler {
+                profiler.leave();
+                profiler.enter(block_id, code.len());
+            }
+            debug_hook!(call);
+            pause_if_exhausted!();
+        }};
+    }
+
+    /// Native function gets the whole machine, so the state of the current frame is read anew
+    /// after the call, since nested calls may have moved the stack and added code blocks. Tail
+    /// calls return from the frame right away, and don't read the code again.
+    macro_rules! reload_frame {
+        (tail = true) => {{
+            block = &machine.code_blocks[machine.program_counter.block];
+            // SAFETY: Stack and the current block were checked with `CallerFrame` to be the same,
+            //         as they were before the call.
+            frame = unsafe { machine.stack.restore(&block.meta) };
+            position = &mut machine.program_counter.position;
+        }};
+        (tail = false) => {{
+            reload_frame!(tail = true);
+            code = block.code();
+        }};
+    }
+
+    macro_rules! call_reentrant {
+        ($function:expr, tail = $tail:tt) => {{
+            let caller = CallerFrame::of(machine);
+            let res = call_native(&$function, machine);
+            caller.check(machine)?;
+            res?;
+            reload_frame!(tail = $tail);
+        }};
+    }
+
+    /// Pending function suspends the execution, which continues after the call, once the host
+    /// resolves it with the values to return. Anywhere else, pending is an error.
+    macro_rules! call_suspending {
+        ($callable:expr, tail = $tail:tt) => {{
+            let caller = CallerFrame::of(machine);
+            let poll = call_suspending($callable.as_ref(), machine);
+            caller.check(machine)?;
+            let poll = poll?;
+            reload_frame!(tail = $tail);
+            if poll.is_pending() {
+                let Runner::Execution { .. } = runner else {
+                    return Err(EvalError::PendingOutsideExecution);
+                };
+                count_run!();
+                *position += 1;
+                return Ok(Exit::Pending { tail: $tail });
+            }
+        }};
+    }
+
+    /// Switches to the original instructions, if a global store broke the assumptions of the
+    /// current block. Other blocks switch, once their frames are returned to.
+    macro_rules! deoptimize_invalidated {
+        () => {{
+            if machine.global_values.has_invalidated() {
+                deoptimize_invalidated(&machine.code_blocks, &mut machine.global_values);
+                code = block.code();
+            }
+        }};
+    }
+
+    // Function, which tail called yield or a pending function, returns the values it was resumed
+    // with
+    if let Entry::Resume { tail: true, .. } = entry {
+        run_start = *position + 1;
+        ret!();
+    }
+
+    /// Suspends the coroutine, leaving its frames on the stack. Yield can only return to the
+    /// resume, which runs the frames of the coroutine, so it can't cross native calls.
+    macro_rules! yield_coroutine {
+        (tail = $tail:expr) => {{
+            if runner != Runner::Coroutine {
+                return Err(if machine.coroutines.is_empty() {
+                    EvalError::YieldOutsideCoroutine
+                } else {
+                    EvalError::YieldAcrossNativeCall
+                });
+            }
+            count_run!();
+            *position += 1;
+            return Ok(Exit::Yielded { tail: $tail });
+        }};
+    }
+
+    /// Calls the native function, and continues after the call, unless it suspended the
+    /// execution.
+    macro_rules! call_native_function {
+        ($function:expr) => {{
+            match &*$function.0 {
+                NativeFunctionKind::Plain(dyn_fn) => {
+                    machine.value_count =
+                        dyn_fn.call(&mut machine.argument_registers, machine.value_count)?;
+                }
+                NativeFunctionKind::Reentrant(_)
+                | NativeFunctionKind::Resume
+                | NativeFunctionKind::Coroutine(_) => {
+                    call_reentrant!($function, tail = false);
+                    deoptimize_invalidated!();
+                }
+                NativeFunctionKind::Suspending(callable) => {
+                    call_suspending!(callable, tail = false);
+                    deoptimize_invalidated!();
+                }
+                NativeFunctionKind::Yield => yield_coroutine!(tail = false),
+            }
+            *position += 1;
+            run_start = *position;
+        }};
+    }
+
     loop {
-        let instr = block.instructions[*position as usize];
+        // Profiler, debug hook, limits and slices of executions see every instruction, so native
+        // code is not entered for them. Native code is compiled from the original instructions,
+        // and speculated ones may rely on registers, that it doesn't set.
+        #[cfg(feature = "jit")]
+        if machine.profiler.is_none()
+            && machine.debug_hook.is_none()
+            && machine.limits.is_unlimited()
+            && !matches!(runner, Runner::Execution { .. })
+            && block.speculation.instructions().is_none()
+            && let Some(native) = block.jit.code()
+            && let Some(entry) = native.entry(*position)
+        {
+            let mut ctx = crate::jit::JitContext::new(
+                &mut machine.accumulators,
+                &mut machine.test_flag,
+                &mut machine.argument_registers,
+                &mut machine.value_count,
+                &mut machine.global_values,
+                &machine.code_blocks,
+                block,
+                &mut frame,
+            );
+            // SAFETY: The context is made of the current block and its frame.
+            *position = unsafe { native.run(entry, &mut ctx) }?;
+            run_start = *position;
+            deoptimize_invalidated!();
+        }
+        if let Some(profiler) = &mut machine.profiler {
+            profiler.instruction(machine.program_counter.block, *position);
+        }
+        if machine.debug_hook.is_some()
+            && let Some(debug_info) = &block.meta.debug_info
+            && let Some(line) = debug_info.line_starting_at(*position)
+        {
+            debug_hook!(line, line);
+        }
+        let instr = code[*position as usize];
         match instr {
             Instruction::Ret => {
-                machine.program_counter = frame.return_addr();
-                position = &mut machine.program_counter.position;
-
-                let release_handle = frame.release();
-                unsafe { machine.stack.pop(release_handle) };
-                if machine.stack.is_empty() {
-                    return Ok(());
+                let return_count = block.meta.return_count;
+                ret!();
+                // Callers, which call the function directly instead of its dynamic wrapper, rely
+                // on the value count being set like the wrapper does.
+                if let ReturnCount::Constant(count) = return_count {
+                    machine.value_count = count;
                 }
-                block = &machine.code_blocks[machine.program_counter.block];
-                // SAFETY: We keep track of the stack frames, and guarantee
-                //         first-come first-serve ordering of stack frames.
-                frame = unsafe { machine.stack.restore(&block.meta) };
-
-                trace_execution!(
-                    "ret back to {:?} {}",
-                    frame.return_addr.block,
-                    block
-                        .meta
-                        .debug_name
-                        .as_ref()
-                        .map(String::as_str)
-                        .unwrap_or_default()
-                );
             }
             Instruction::ConstI(value) => {
                 register!(AI) = value;
@@ -189,6 +543,10 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 register!(AD) = machine.global_values.value_of_cell(cell_id).clone();
                 *position += 1;
             }
+            Instruction::LdaUGl(cell_id) => {
+                register!(AU) = machine.global_values.value_of_cell(cell_id).as_native_function();
+                *position += 1;
+            }
             Instruction::EqTestRD(reg) => {
                 machine.test_flag = TestFlag::from_bool(register!(AD) == register!(RD, reg));
                 *position += 1;
@@ -198,7 +556,7 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 *position += 1;
             }
             Instruction::Jmp(jmp_label) => {
-                *position = block.meta.label_mappings[jmp_label];
+                jump!(jmp_label);
             }
             Instruction::Label => {
                 /* nop */
@@ -206,48 +564,49 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
             }
             Instruction::JmpEQ(jmp_label) => {
                 if let TestFlag::EQ = machine.test_flag {
-                    *position = block.meta.label_mappings[jmp_label];
+                    jump!(jmp_label);
                 } else {
                     *position += 1;
                 }
             }
             Instruction::JmpNE(jmp_label) => {
                 if let TestFlag::NE = machine.test_flag {
-                    *position = block.meta.label_mappings[jmp_label];
+                    jump!(jmp_label);
                 } else {
                     *position += 1;
                 }
             }
             Instruction::JmpLT(jmp_label) => {
                 if let TestFlag::LT = machine.test_flag {
-                    *position = block.meta.label_mappings[jmp_label];
+                    jump!(jmp_label);
                 } else {
                     *position += 1;
                 }
             }
             Instruction::JmpGT(jmp_label) => {
                 if let TestFlag::GT = machine.test_flag {
-                    *position = block.meta.label_mappings[jmp_label];
+                    jump!(jmp_label);
                 } else {
                     *position += 1;
                 }
             }
             Instruction::JmpLE(jmp_label) => {
                 if machine.test_flag == TestFlag::LT || machine.test_flag == TestFlag::EQ {
-                    *position = block.meta.label_mappings[jmp_label];
+                    jump!(jmp_label);
                 } else {
                     *position += 1;
                 }
             }
             Instruction::JmpGE(jmp_label) => {
                 if machine.test_flag == TestFlag::GT || machine.test_flag == TestFlag::EQ {
-                    *position = block.meta.label_mappings[jmp_label];
+                    jump!(jmp_label);
                 } else {
                     *position += 1;
                 }
             }
             Instruction::StrDGl(cell) => {
                 machine.global_values.set_cell(cell, register!(AD).clone());
+                deoptimize_invalidated!();
                 *position += 1;
             }
             Instruction::StrVC => {
@@ -255,6 +614,8 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 *position += 1;
             }
             Instruction::DCall => {
+                count_run!();
+                machine.usage.check_instructions(&machine.limits)?;
                 if let Some(block_id) = register!(AD).as_lua_function() {
                     let new_block = &machine.code_blocks[block_id];
                     trace_execution!(
@@ -266,6 +627,7 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                             .map(String::as_str)
                             .unwrap_or_default()
                     );
+                    machine.usage.enter_call(&machine.limits)?;
                     frame = machine.stack.push(
                         &new_block.meta,
                         ProgramCounter {
@@ -275,12 +637,20 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                     );
                     block = new_block;
                     *position = 0;
+                    run_start = 0;
                     machine.program_counter.block = block_id;
-                } else if let Some(NativeFunction(dyn_fn)) = register!(AD).as_native_function() {
-                    trace_execution!("d_call into native function {:p}", dyn_fn as *const _);
-                    dyn_fn.call(&mut machine.argument_registers, machine.value_count)?;
-                    machine.value_count = dyn_fn.return_count();
-                    *position += 1;
+                    #[cfg(feature = "jit")]
+                    crate::jit::note_call(block, &machine.jit);
+                    note_call(&machine.code_blocks, block_id, &mut machine.global_values);
+                    code = block.code();
+                    if let Some(profiler) = &mut machine.profiler {
+                        profiler.enter(block_id, code.len());
+                    }
+                    debug_hook!(call);
+                    pause_if_exhausted!();
+                } else if let Some(function) = register!(AD).as_native_function() {
+                    trace_execution!("d_call into native function {:?}", function);
+                    call_native_function!(function);
                 } else {
                     trace_execution!("d_call {_val}");
                     return Err(EvalError::from(TypeError::IsNotCallable(
@@ -288,6 +658,56 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                     )));
                 }
             }
+            Instruction::NativeCall => {
+                count_run!();
+                machine.usage.check_instructions(&machine.limits)?;
+                let function = register!(AU)
+                    .clone()
+                    .expect("native_call expects a native function in AU");
+                trace_execution!("native_call into {:?}", function);
+                call_native_function!(function);
+            }
+            Instruction::TailCall => {
+                trace_execution!(
+                    "tail_call into {:?} {}",
+                    register!(AC),
+                    machine.code_blocks[register!(AC)]
+                        .meta
+                        .debug_name
+                        .as_ref()
+                        .map(String::as_str)
+                        .unwrap_or_default()
+                );
+                tail_call!(register!(AC));
+            }
+            Instruction::DTailCall => {
+                if let Some(block_id) = register!(AD).as_lua_function() {
+                    trace_execution!("d_tail_call into {block_id:?}");
+                    tail_call!(block_id);
+                } else if let Some(function) = register!(AD).as_native_function() {
+                    trace_execution!("d_tail_call into native function {:?}", function);
+                    match &*function.0 {
+                        NativeFunctionKind::Plain(dyn_fn) => {
+                            machine.value_count = dyn_fn
+                                .call(&mut machine.argument_registers, machine.value_count)?;
+                        }
+                        NativeFunctionKind::Reentrant(_)
+                        | NativeFunctionKind::Resume
+                        | NativeFunctionKind::Coroutine(_) => {
+                            call_reentrant!(function, tail = true)
+                        }
+                        NativeFunctionKind::Suspending(callable) => {
+                            call_suspending!(callable, tail = true)
+                        }
+                        NativeFunctionKind::Yield => yield_coroutine!(tail = true),
+                    }
+                    ret!();
+                } else {
+                    return Err(EvalError::from(TypeError::IsNotCallable(
+                        register!(AD).clone(),
+                    )));
+                }
+            }
             Instruction::LdaProt(reg) => {
                 register!(AD) = if machine.value_count > reg.0 {
                     register!(RD, reg).clone()
@@ -297,6 +717,9 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 *position += 1;
             }
             Instruction::TypedCall => {
+                count_run!();
+                machine.usage.check_instructions(&machine.limits)?;
+                machine.usage.enter_call(&machine.limits)?;
                 let new_block = &machine.code_blocks[register!(AC)];
                 trace_execution!(
                     "typed_call into {:?} {}",
@@ -317,13 +740,23 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 );
                 block = new_block;
                 *position = 0;
+                run_start = 0;
                 machine.program_counter.block = register!(AC);
+                #[cfg(feature = "jit")]
+                crate::jit::note_call(block, &machine.jit);
+                note_call(&machine.code_blocks, register!(AC), &mut machine.global_values);
+                code = block.code();
+                if let Some(profiler) = &mut machine.profiler {
+                    profiler.enter(register!(AC), code.len());
+                }
+                debug_hook!(call);
+                pause_if_exhausted!();
             }
             Instruction::RDShiftRight => {
                 machine
                     .argument_registers
                     .d
-                    .rotate_right((register!(AI) as u16) as usize);
+                    .shift_right((register!(AI) as u16) as usize, machine.value_count as usize);
                 *position += 1;
             }
             Instruction::LdaVC => {
@@ -367,6 +800,7 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 *position += 1;
             }
             Instruction::NewT => {
+                machine.usage.allocate(&machine.limits, TABLE_SIZE)?;
                 register!(AT) = Some(TableRef::from(TableValue::new()));
                 *position += 1;
             }
@@ -389,11 +823,16 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
             Instruction::PushD => {
                 let table = register!(AT).as_mut().unwrap();
                 table.push(register!(AD).clone());
+                machine.usage.allocate(&machine.limits, TABLE_ENTRY_SIZE)?;
                 *position += 1;
             }
-            Instruction::AssocASD => {
+            Instruction::AssocASD(cache) => {
                 let table = register!(AT).as_mut().unwrap();
-                table.assoc_str(register!(AS).clone(), register!(AD).clone());
+                let cache = &block.meta.property_caches[cache];
+                let entry_count = table.entry_count();
+                table.assoc_str_cached(&register!(AS), register!(AD).clone(), cache);
+                let new_entries = table.entry_count() - entry_count;
+                machine.usage.allocate(&machine.limits, new_entries * TABLE_ENTRY_SIZE)?;
                 *position += 1;
             }
             Instruction::CastT => {
@@ -427,13 +866,14 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                 register!(AD) = LuaValue::table(register!(AT).as_ref().unwrap().clone());
                 *position += 1;
             }
-            Instruction::LdaAssocAS => {
+            Instruction::LdaAssocAS(cache) => {
+                let cache = &block.meta.property_caches[cache];
                 register!(AD) = machine
                     .accumulators
                     .t
-                    .as_mut()
+                    .as_ref()
                     .unwrap()
-                    .get_str_assoc(register!(AS).clone());
+                    .get_str_cached(&register!(AS), cache);
                 *position += 1;
             }
             Instruction::LdaAssocAD => {
@@ -474,7 +914,11 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                         return Err(EvalError::from(TypeError::NaNAssign(value)))
                     }
                 };
-                register!(AT).as_mut().unwrap().set(key, value);
+                let table = register!(AT).as_mut().unwrap();
+                let entry_count = table.entry_count();
+                table.set(key, value);
+                let new_entries = table.entry_count() - entry_count;
+                machine.usage.allocate(&machine.limits, new_entries * TABLE_ENTRY_SIZE)?;
                 *position += 1;
             }
             Instruction::AssocLD(reg) => {
@@ -488,7 +932,11 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
                         return Err(EvalError::from(TypeError::NaNAssign(value)))
                     }
                 };
-                register!(AT).as_mut().unwrap().set(key, value);
+                let table = register!(AT).as_mut().unwrap();
+                let entry_count = table.entry_count();
+                table.set(key, value);
+                let new_entries = table.entry_count() - entry_count;
+                machine.usage.allocate(&machine.limits, new_entries * TABLE_ENTRY_SIZE)?;
                 *position += 1;
             }
             Instruction::TablePropertyAssignError => {
@@ -534,10 +982,14 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
             }
             Instruction::DConcatR(reg) => {
                 register!(AD) = dyn_concat(&register!(AD), &register!(RD, reg))?;
+                let len = register!(AD).as_str().map_or(0, |str| str.len());
+                machine.usage.allocate(&machine.limits, len)?;
                 *position += 1;
             }
             Instruction::DConcatL(reg) => {
                 register!(AD) = dyn_concat(&register!(AD), &register!(LD, reg))?;
+                let len = register!(AD).as_str().map_or(0, |str| str.len());
+                machine.usage.allocate(&machine.limits, len)?;
                 *position += 1;
             }
 
@@ -562,7 +1014,6 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
             Instruction::LdaSGl(_) => todo!(),
             Instruction::LdaTGl(_) => todo!(),
             Instruction::LdaCGl(_) => todo!(),
-            Instruction::LdaUGl(_) => todo!(),
             Instruction::StrFGl(_) => todo!(),
             Instruction::StrIGl(_) => todo!(),
             Instruction::StrSGl(_) => todo!(),
@@ -640,7 +1091,75 @@ pub(crate) fn execute(machine: &mut Machine, block_id: BlockID) -> Result<(), Ev
     }
 }
 
-fn cmp_test_flags(ordering: Option<Ordering>) -> TestFlag {
+/// State of the frame, which calls a native function, that gets the whole machine. The frame is
+/// read anew from the stack after the call, so the call must leave the same stack and block.
+struct CallerFrame {
+    block: BlockID,
+    stack_size: usize,
+}
+
+impl CallerFrame {
+    fn of(machine: &Machine) -> Self {
+        Self {
+            block: machine.program_counter.block,
+            stack_size: machine.stack.size(),
+        }
+    }
+
+    fn check(self, machine: &Machine) -> Result<(), EvalError> {
+        if machine.program_counter.block != self.block || machine.stack.size() != self.stack_size {
+            return Err(EvalError::CorruptedCallStack);
+        }
+        Ok(())
+    }
+}
+
+/// Calls a native function with the arguments in the dynamic argument registers, and sets the
+/// value count to the count of its returns.
+pub(crate) fn call_native(function: &NativeFunction, machine: &mut Machine) -> Result<(), EvalError> {
+    match &*function.0 {
+        NativeFunctionKind::Plain(callable) => {
+            machine.value_count =
+                callable.call(&mut machine.argument_registers, machine.value_count)?;
+        }
+        NativeFunctionKind::Reentrant(callable) => {
+            let arguments = machine.argument_registers.d[..machine.value_count as usize].to_vec();
+            machine.value_count = callable.call(machine, &arguments)?;
+        }
+        NativeFunctionKind::Suspending(callable) => {
+            if call_suspending(callable.as_ref(), machine)?.is_pending() {
+                return Err(EvalError::PendingOutsideExecution);
+            }
+        }
+        NativeFunctionKind::Resume => coroutine::resume_protected(machine)?,
+        NativeFunctionKind::Coroutine(coroutine) if coroutine.borrow().wrapped => {
+            coroutine::resume(function, coroutine, machine)?
+        }
+        NativeFunctionKind::Coroutine(_) => {
+            let thread = LuaValue::native_function(function.clone());
+            return Err(EvalError::from(TypeError::IsNotCallable(thread)));
+        }
+        // Yields, which get here, are not called by the frames of a coroutine
+        NativeFunctionKind::Yield if machine.coroutines.is_empty() => {
+            return Err(EvalError::YieldOutsideCoroutine)
+        }
+        NativeFunctionKind::Yield => return Err(EvalError::YieldAcrossNativeCall),
+    }
+    Ok(())
+}
+
+/// Calls the native function, which returns no values, if it is pending.
+fn call_suspending(
+    callable: &dyn SuspendingFunctionCallable,
+    machine: &mut Machine,
+) -> Result<Poll<()>, EvalError> {
+    let arguments = machine.argument_registers.d[..machine.value_count as usize].to_vec();
+    Ok(callable.call(machine, &arguments)?.map(|value_count| {
+        machine.value_count = value_count;
+    }))
+}
+
+pub(crate) fn cmp_test_flags(ordering: Option<Ordering>) -> TestFlag {
     match ordering {
         Some(Ordering::Equal) => TestFlag::EQ,
         Some(Ordering::Less) => TestFlag::LT,
@@ -649,15 +1168,18 @@ fn cmp_test_flags(ordering: Option<Ordering>) -> TestFlag {
     }
 }
 
-// All arithmetic on integers can overflow. It probably should overflow into the float. Right now
-// it does signed 32-bit wrapping. I'm okay with that, adherence to the lua spec, might not be.
-fn neg_dyn_accumulator(accumulator: &LuaValue) -> Result<LuaValue, EvalError> {
+// Integers are an optimization of lua numbers, which are all floats in the spec. When integer
+// arithmetic overflows, the result is computed as a float instead.
+pub(crate) fn neg_dyn_accumulator(accumulator: &LuaValue) -> Result<LuaValue, EvalError> {
     if let Some(int) = accumulator.as_int() {
-        Ok(LuaValue::int(-int))
+        Ok(int
+            .checked_neg()
+            .map(LuaValue::int)
+            .unwrap_or_else(|| LuaValue::float(-(int as f64))))
     } else if let Some(float) = accumulator.as_float() {
         Ok(LuaValue::float(-float))
     } else if let Some(str) = accumulator.as_str() {
-        match str.parse::<f64>() {
+        match str.trim_ascii().parse::<f64>() {
             Ok(value) => {
                 Ok(LuaValue::float(-value))
             }
@@ -672,10 +1194,13 @@ fn neg_dyn_accumulator(accumulator: &LuaValue) -> Result<LuaValue, EvalError> {
     }
 }
 
-fn sub_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
+pub(crate) fn sub_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     if let Some(lhs_int) = lhs.as_int() {
         if let Some(rhs_int) = rhs.as_int() {
-            return Ok(LuaValue::int(lhs_int - rhs_int));
+            return Ok(lhs_int
+                .checked_sub(rhs_int)
+                .map(LuaValue::int)
+                .unwrap_or_else(|| LuaValue::float(lhs_int as f64 - rhs_int as f64)));
         } else if let Some(rhs_float) = rhs.coerce_to_f64() {
             return Ok(LuaValue::float(lhs_int as f64 - rhs_float));
         }
@@ -690,10 +1215,13 @@ fn sub_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     }))
 }
 
-fn add_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
+pub(crate) fn add_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     if let Some(lhs_int) = lhs.as_int() {
         if let Some(rhs_int) = rhs.as_int() {
-            return Ok(LuaValue::int(lhs_int + rhs_int));
+            return Ok(lhs_int
+                .checked_add(rhs_int)
+                .map(LuaValue::int)
+                .unwrap_or_else(|| LuaValue::float(lhs_int as f64 + rhs_int as f64)));
         } else if let Some(rhs_float) = rhs.coerce_to_f64() {
             return Ok(LuaValue::float(lhs_int as f64 + rhs_float));
         }
@@ -708,7 +1236,7 @@ fn add_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     }))
 }
 
-fn div_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
+pub(crate) fn div_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     if let (Some(lhs), Some(rhs)) = (lhs.coerce_to_f64(), rhs.coerce_to_f64()) {
         Ok(LuaValue::float(lhs / rhs))
     } else {
@@ -720,10 +1248,13 @@ fn div_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     }
 }
 
-fn mul_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
+pub(crate) fn mul_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     if let Some(lhs_int) = lhs.as_int() {
         if let Some(rhs_int) = rhs.as_int() {
-            return Ok(LuaValue::int(lhs_int * rhs_int));
+            return Ok(lhs_int
+                .checked_mul(rhs_int)
+                .map(LuaValue::int)
+                .unwrap_or_else(|| LuaValue::float(lhs_int as f64 * rhs_int as f64)));
         } else if let Some(rhs_float) = rhs.coerce_to_f64() {
             return Ok(LuaValue::float(lhs_int as f64 * rhs_float));
         }
@@ -739,7 +1270,7 @@ fn mul_dyn(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
 }
 
 
-fn dyn_concat(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
+pub(crate) fn dyn_concat(lhs: &LuaValue, rhs: &LuaValue) -> Result<LuaValue, TypeError> {
     macro_rules! dyn_concat_of {
         ($(($left_pat:ident, $right_pat:ident),)*) => {
             $(if let Some(lhs) = LuaValue::$left_pat(lhs) && let Some(rhs) = LuaValue::$right_pat(rhs) {
@@ -1640,4 +2171,38 @@ mod test {
             assert_eq!(register_of!(machine, AD), LuaValue::string("69.28842"))
         }
     }
+
+    test_instructions_with_locals! {
+        name: integer_overflow_produces_float,
+        code: [
+            ConstI(i32::MAX),
+            WrapI,
+            StrLD(LocalRegisterID(0)),
+            DAddL(LocalRegisterID(0)),
+            Ret
+        ],
+        locals: reg_count! { D: 1 },
+        post_condition: |machine: Machine| {
+            assert_eq!(register_of!(machine, AD), LuaValue::float(2.0 * i32::MAX as f64))
+        }
+    }
+
+    #[test]
+    fn tail_recursion_does_not_grow_the_stack() {
+        let mut machine = Machine::with_stdlib();
+        let capacity = machine.stack.capacity();
+        let res: LuaValue = crate::eval_str(
+            "function count(left, acc)
+                if left == 0 then
+                    return acc
+                end
+                return count(left - 1, acc + 1)
+            end
+            return count(20000, 0)",
+            &mut machine,
+        )
+        .unwrap();
+        assert_eq!(res, 20000);
+        assert_eq!(machine.stack.capacity(), capacity);
+    }
 }
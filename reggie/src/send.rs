@@ -0,0 +1,145 @@
+//! Machines, which move between threads.
+//!
+//! Values count their references without atomics, so neither they nor the machine are `Send`. A
+//! machine can still be moved between threads as a whole, as long as none of its values are left
+//! behind. [`SendMachine`] can't check that, so its users promise it. With the `send` feature, the
+//! native functions, debug hooks and module loaders, which the machine keeps, must be `Send` for
+//! it to be sound.
+
+/// `Send`, when the `send` feature is enabled, and implemented by every type otherwise.
+#[cfg(feature = "send")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "send")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// `Send`, when the `send` feature is enabled, and implemented by every type otherwise.
+#[cfg(not(feature = "send"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "send"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+#[cfg(feature = "send")]
+pub use send_machine::SendMachine;
+
+#[cfg(feature = "send")]
+mod send_machine {
+    use crate::Machine;
+
+    /// Machine, which owns all of its values, so that it can be sent to another thread. Machine is
+    /// only lent to closures, which can't capture values from the outside, nor return values out,
+    /// as values are not `Send`. Closures can still stash values somewhere else, like in a thread
+    /// local, which is why lending the machine is unsafe.
+    pub struct SendMachine {
+        machine: Machine,
+    }
+
+    // SAFETY: Callers of `new`, `with_stdlib` and `with` promise, that values of the machine are
+    //         only reachable through the machine, so their refcounts are never touched by two
+    //         threads at once. Everything else the machine keeps is `Send`.
+    unsafe impl Send for SendMachine {}
+
+    impl SendMachine {
+        /// # Safety
+        ///
+        /// The machine can be sent to another thread right away, so it must only be used as
+        /// described by [`SendMachine::with`].
+        pub unsafe fn new() -> Self {
+            Self {
+                machine: Machine::new(),
+            }
+        }
+
+        /// # Safety
+        ///
+        /// Same as for [`SendMachine::new`].
+        pub unsafe fn with_stdlib() -> Self {
+            Self {
+                machine: Machine::with_stdlib(),
+            }
+        }
+
+        /// Lends the machine to the closure, which can set it up, or run scripts with it.
+        ///
+        /// # Safety
+        ///
+        /// No value of the machine may outlive the closure outside of the machine. The closure
+        /// must not store them in thread locals, statics, or anything else that stays on the
+        /// current thread, neither directly, nor through the native functions it calls.
+        pub unsafe fn with<R: Send>(&mut self, f: impl FnOnce(&mut Machine) -> R + Send) -> R {
+            f(&mut self.machine)
+        }
+
+        /// Takes the machine out, which then stays on the current thread.
+        pub fn into_inner(self) -> Machine {
+            self.machine
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "send")]
+mod test {
+    use std::thread;
+
+    use crate::{eval_str, LuaValue, Machine, SendMachine};
+
+    const fn assert_send<T: Send>() {}
+    const _: () = assert_send::<SendMachine>();
+
+    #[test]
+    fn machines_keep_their_values_across_threads() {
+        // SAFETY: Closures below only run scripts, which don't stash values anywhere.
+        let mut machine = unsafe { SendMachine::with_stdlib() };
+        unsafe {
+            machine.with(|machine| {
+                machine.set_global("double", |x: i32| x * 2);
+                eval_str::<()>("items = {} count = 0", machine).unwrap();
+            })
+        };
+        for _ in 0..3 {
+            machine = thread::spawn(move || {
+                unsafe {
+                    machine.with(|machine| {
+                        eval_str::<()>("count = count + 1 items[count] = double(count)", machine)
+                            .unwrap()
+                    })
+                };
+                machine
+            })
+            .join()
+            .unwrap();
+        }
+        let res = unsafe {
+            machine
+                .with(|machine| eval_str::<(i32, i32)>("return count, items[3]", machine).unwrap())
+        };
+        assert_eq!(res, (3, 6));
+
+        let machine: Machine = machine.into_inner();
+        assert_eq!(
+            machine.get_global::<LuaValue>("count").unwrap(),
+            LuaValue::int(3)
+        );
+    }
+
+    #[test]
+    fn machines_run_on_a_pool_of_threads() {
+        let workers: Vec<_> = (0..4)
+            .map(|index| {
+                // SAFETY: Closures below only run scripts, which don't stash values anywhere.
+                let mut machine = unsafe { SendMachine::with_stdlib() };
+                unsafe { machine.with(|machine| machine.set_global("index", index)) };
+                thread::spawn(move || unsafe {
+                    machine.with(|machine| {
+                        eval_str::<i32>("local s = '' return index * 10", machine).unwrap()
+                    })
+                })
+            })
+            .collect();
+        let res: Vec<i32> = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect();
+        assert_eq!(res, [0, 10, 20, 30]);
+    }
+}
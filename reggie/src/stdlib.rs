@@ -3,7 +3,10 @@ use std::{
     io::{self, Write}, rc::Rc,
 };
 
-use crate::{lmatch, trace_execution, EvalError, ExpectedType, GlobalValues, LuaValue, TypeError};
+use crate::{
+    coroutine, limits, lmatch, modules, trace_execution, EvalError, ExpectedType, GlobalValues,
+    LuaKey, LuaValue, Machine, NativeFunction, TableRef, TableValue, TypeError,
+};
 
 pub fn assert(value: LuaValue, message: LuaValue) -> Result<(), EvalError> {
     trace_execution!("assert({:?}, {:?})", value, message);
@@ -33,7 +36,7 @@ pub fn floor(value: &LuaValue) -> Result<LuaValue, TypeError> {
     } else if let Some(float) = value.as_float() {
         Ok(LuaValue::float(float.floor()))
     } else if let Some(string) = value.as_string() {
-        match string.as_ref().parse::<f64>() {
+        match string.as_ref().trim_ascii().parse::<f64>() {
             Ok(float) => Ok(LuaValue::float(float.floor())),
             Err(_) => Err(TypeError::ArgumentType {
                 position: 0,
@@ -86,7 +89,11 @@ pub fn lua_type(value: &LuaValue) -> LuaValue {
         float _ => LuaValue::string("number"),
         string _ => LuaValue::string("string"),
         table _ => LuaValue::string("table"),
-        native_function _ => LuaValue::string("function"),
+        native_function function => if function.is_thread() {
+            LuaValue::string("thread")
+        } else {
+            LuaValue::string("function")
+        },
         lua_function _ => LuaValue::string("function"),
     }
 }
@@ -150,6 +157,17 @@ pub fn strsub(value: &LuaValue, from: &LuaValue, to: &LuaValue) -> Result<LuaVal
     return Ok(LuaValue::string(&str[from..to]));
 }
 
+/// Substrings are new strings, so they are counted against the memory limit.
+fn counted_strsub(machine: &mut Machine, args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let [value, from, to] =
+        [0, 1, 2].map(|position| args.get(position).cloned().unwrap_or(LuaValue::NIL));
+    let substring = strsub(&value, &from, &to)?;
+    machine
+        .usage
+        .allocate(&machine.limits, limits::value_size(&substring))?;
+    Ok(substring)
+}
+
 pub fn print_stdout(args: &[LuaValue]) -> Result<(), EvalError> {
     print(&mut std::io::stdout(), args)
 }
@@ -178,14 +196,78 @@ pub fn print(writer: &mut impl Write, args: &[LuaValue]) -> Result<(), EvalError
     Ok(())
 }
 
+/// Tables with only the array part become arrays and all the other tables become objects, see
+/// [`crate::value::ser`]. NaNs and infinities become nulls, as json has no way to represent them.
+#[cfg(feature = "json")]
+pub fn json_encode(machine: &mut Machine, args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let value = args.first().cloned().unwrap_or(LuaValue::NIL);
+    let encoded =
+        serde_json::to_string(&value).map_err(|err| EvalError::JsonEncode(err.to_string()))?;
+    machine.usage.allocate(&machine.limits, encoded.len())?;
+    Ok(LuaValue::string(encoded))
+}
+
+/// Arrays are decoded into the array part of a table, and objects into tables with string keys.
+/// There are no booleans in lua, so `true` becomes 1 and `false` becomes nil.
+#[cfg(feature = "json")]
+pub fn json_decode(machine: &mut Machine, args: &[LuaValue]) -> Result<LuaValue, EvalError> {
+    let value = args.first().cloned().unwrap_or(LuaValue::NIL);
+    let str = value
+        .coerce_to_string()
+        .ok_or_else(|| TypeError::ArgumentType {
+            position: 0,
+            expected: ExpectedType::String,
+            got: value.clone(),
+        })?;
+    let decoded = serde_json::from_str(&str).map_err(|err| {
+        let (line, column) = (err.line(), err.column());
+        // Position is reported in the fields, so it is left out of the message
+        let message = err.to_string();
+        let message = message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .unwrap_or(&message);
+        EvalError::JsonDecode {
+            message: message.to_owned(),
+            line,
+            column,
+        }
+    })?;
+    let size = limits::value_size(&decoded);
+    machine.usage.allocate(&machine.limits, size)?;
+    Ok(decoded)
+}
+
+fn coroutine_library() -> LuaValue {
+    let mut library = TableValue::new();
+    let mut set = |name: &str, function: NativeFunction| {
+        library.set(LuaKey::string(name), LuaValue::native_function(function));
+    };
+    set("create", NativeFunction::new(coroutine::create));
+    set("wrap", NativeFunction::new(coroutine::wrap));
+    set("resume", coroutine::resume_function());
+    set("yield", coroutine::yield_function());
+    set("status", NativeFunction::reentrant(coroutine::coroutine_status));
+    LuaValue::table(TableRef::from(library))
+}
+
 pub fn define_stdlib(global_values: &mut GlobalValues) {
     global_values.set("assert", LuaValue::function(assert));
     global_values.set("floor", LuaValue::function(floor));
     global_values.set("random", LuaValue::function(random));
     global_values.set("type", LuaValue::function(lua_type));
     global_values.set("strlen", LuaValue::function(strlen));
-    global_values.set("strsub", LuaValue::function(strsub));
+    global_values.set("strsub", LuaValue::reentrant_function(counted_strsub));
     global_values.set("print", LuaValue::function(print_stdout));
+    global_values.set("dofile", LuaValue::reentrant_function(modules::dofile));
+    global_values.set("require", LuaValue::reentrant_function(modules::require));
+    global_values.set("dostring", LuaValue::reentrant_function(modules::dostring));
+    global_values.set("load", LuaValue::reentrant_function(modules::load));
+    global_values.set("coroutine", coroutine_library());
+    #[cfg(feature = "json")]
+    {
+        global_values.set("json_encode", LuaValue::reentrant_function(json_encode));
+        global_values.set("json_decode", LuaValue::reentrant_function(json_decode));
+    }
 }
 
 #[cfg(test)]
@@ -1,8 +1,8 @@
 use std::{cell::{Ref, RefCell}, fmt, ptr::NonNull, rc::Rc};
 
-use crate::{eq_with_nan::eq_with_nan, ids::BlockID, LuaValue, NativeFunction, NativeFunctionKind, TableRef, TableValue};
+use crate::{eq_with_nan::eq_with_nan, ids::BlockID, LuaValue, MaybeSend, NativeFunction, NativeFunctionKind, TableRef, TableValue};
 
-use super::{lua_format, string::{CompactString, SharedStringPtr}, FFIFunc, FromArgs, LuaString, UnownedTableRef};
+use super::{lua_format, string::{CompactString, SharedStringPtr}, FFIFunc, FromArgs, LuaString, ReentrantFunctionCallable, UnownedTableRef};
 
 /// Here's the anatomy of the packed value:
 /// ```text
@@ -27,6 +27,7 @@ use super::{lua_format, string::{CompactString, SharedStringPtr}, FFIFunc, FromA
 ///   v v           v v   v
 /// 0b0_00000000000_0_000_000000000000000000000000000000000000000000000000
 /// ```
+#[repr(transparent)]
 pub struct CompactLuaValue(u64);
 
 // We can be able to pack pointers into 48 bits on:
@@ -97,6 +98,18 @@ macro_rules! pick {
     }
 }
 
+/// Bit patterns, which native code uses to recognize values without calling back into rust.
+#[cfg(feature = "jit")]
+pub(crate) mod bits {
+    pub(crate) const INT: u64 = super::INT_BITPATTERN;
+    pub(crate) const NIL: u64 = super::NIL_BITPATTERN;
+    pub(crate) const TABLE: u64 = super::TABLE_BITPATTERN;
+    pub(crate) const NATIVE_FUNC: u64 = super::NATIVE_FUNC_BITPATTERN;
+    pub(crate) const STRING: u64 = super::STRING_BITPATTERN;
+    /// Strings use the type tag for their own purposes, so only these bits tell them apart
+    pub(crate) const STRING_MASK: u64 = bitmask!(sign, exponent, snan);
+}
+
 static GLOBAL_NIL: &'static CompactLuaValue = &CompactLuaValue::NIL;
 
 impl CompactLuaValue {
@@ -281,12 +294,20 @@ impl CompactLuaValue {
 
     pub fn function<'a, F, Args>(func: F) -> Self
     where
-        F: FFIFunc<Args> + 'static,
+        F: FFIFunc<Args> + MaybeSend + 'static,
         Args: FromArgs<'a> + 'static,
     {
         Self::native_function(NativeFunction::new(func))
     }
 
+    /// Native function, which can call back into lua, see [`NativeFunction::reentrant`].
+    pub fn reentrant_function<F>(func: F) -> Self
+    where
+        F: ReentrantFunctionCallable + 'static,
+    {
+        Self::native_function(NativeFunction::reentrant(func))
+    }
+
     fn as_native_function_ptr(&self) -> Option<NonNull<NativeFunctionKind>> {
         if self.is_native_function() {
             Some(unsafe { self.decode_pointer().cast() })
@@ -336,7 +357,7 @@ impl CompactLuaValue {
         } else if let Some(float) = self.as_float() {
             Some(float)
         } else if let Some(str) = self.as_str() {
-            str.parse().ok()
+            str.trim_ascii().parse().ok()
         } else {
             None
         }
@@ -348,7 +369,7 @@ impl CompactLuaValue {
         } else if let Some(float) = self.as_float() {
             Some(float as i32)
         } else if let Some(str) = self.as_str() {
-            str.parse().ok()
+            str.trim_ascii().parse().ok()
         } else {
             None
         }
@@ -376,6 +397,14 @@ impl CompactLuaValue {
         }
     }
 
+    fn number_as_string(&self) -> Option<String> {
+        if let Some(int) = self.as_int() {
+            Some(int.to_string())
+        } else {
+            self.as_float().map(|float| float.to_string())
+        }
+    }
+
     pub const TRUE: Self = Self::int(1);
     pub const FALSE: Self = Self::NIL;
 
@@ -415,10 +444,13 @@ impl CompactLuaValue {
         }
     }
 
+    pub fn is_comparable(&self) -> bool {
+        self.is_int() || self.is_float() || self.is_string()
+    }
+
+    /// Numbers are coerced to strings when compared to strings
     pub fn is_comparable_to(&self, other: &Self) -> bool {
-        let self_is_numeric = self.is_int() || self.is_float();
-        let other_is_numeric = other.is_int() || other.is_float();
-        self_is_numeric && other_is_numeric || self.is_string() && other.is_string()
+        self.is_comparable() && other.is_comparable()
     }
 }
 
@@ -608,9 +640,16 @@ impl PartialOrd for CompactLuaValue {
             if let Some(rhs_int) = other.as_int() {
                 return lhs_float.partial_cmp(&(rhs_int as f64));
             }
-        } else if let Some(lhs_str) = self.as_str() && let Some(rhs_str) = self.as_str() {
+        } else if let Some(lhs_str) = self.as_str() && let Some(rhs_str) = other.as_str() {
             return lhs_str.partial_cmp(rhs_str);
-        } 
+        }
+
+        // TODO: Compare numbers with strings without allocating an intermediate string
+        if let Some(lhs_str) = self.as_str() && let Some(rhs_num) = other.number_as_string() {
+            return lhs_str.partial_cmp(rhs_num.as_str());
+        } else if let Some(lhs_num) = self.number_as_string() && let Some(rhs_str) = other.as_str() {
+            return lhs_num.as_str().partial_cmp(rhs_str);
+        }
 
         return None;
     }
@@ -735,6 +774,39 @@ mod tests {
         assert_eq!(Rc::strong_count(&table_ref.0), 1);
     }
 
+    #[test]
+    fn owned_table_ref_takes_its_own_refcount() {
+        let table_ref = TableRef::new();
+        let value = CompactLuaValue::table(table_ref.clone());
+        assert_eq!(Rc::strong_count(&table_ref.0), 2);
+
+        let owned = value.as_table_ref().unwrap().to_owned();
+        assert_eq!(Rc::strong_count(&table_ref.0), 3);
+
+        drop(owned);
+        assert_eq!(Rc::strong_count(&table_ref.0), 2);
+        drop(value);
+        assert_eq!(Rc::strong_count(&table_ref.0), 1);
+    }
+
+    #[test]
+    fn strings_are_ordered_by_contents() {
+        let a = CompactLuaValue::string("a");
+        let b = CompactLuaValue::string("b");
+        assert!(a < b);
+        assert!(b > a);
+        assert!(b >= a);
+    }
+
+    #[test]
+    fn numbers_are_compared_to_strings_as_strings() {
+        let ten = CompactLuaValue::int(10);
+        let nine = CompactLuaValue::string("9");
+        assert!(ten.is_comparable_to(&nine));
+        assert!(ten < nine);
+        assert!(nine > ten);
+    }
+
     #[cfg(feature = "quickcheck")]
     #[quickcheck]
     fn strings_are_stored_properly(str: String) {
@@ -0,0 +1,486 @@
+//! Deserialization of lua values with serde, and of rust values out of lua values.
+//!
+//! [`from_value`] reads any deserializable type out of a lua value, like the one returned by a
+//! script. Tables are read as sequences of their array part, or as maps of all of their entries,
+//! whichever the type asks for. Integers are also read from floats, which hold an integer, and
+//! bools from the truthiness of any value. Enums are read from the names of their variants, or
+//! from tables with a single entry, the variant name mapped to the variant's content.
+//! Recursive tables are an error.
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+        SeqAccess, Unexpected, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+
+use super::lmatch;
+use crate::{LuaKey, LuaString, LuaValue, TableRef, TableValue};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{0}")]
+pub struct DeserializeError(String);
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+pub fn from_value<T: DeserializeOwned>(value: &LuaValue) -> Result<T, DeserializeError> {
+    T::deserialize(ValueDeserializer::new(value.clone()))
+}
+
+impl<'de> Deserialize<'de> for LuaValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for TableValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TableVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = LuaValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a lua value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(LuaValue::from_bool(v))
+    }
+
+    /// Integers out of the range of lua integers become floats.
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(match i32::try_from(v) {
+            Ok(int) => LuaValue::int(int),
+            Err(_) => LuaValue::float(v as f64),
+        })
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(match i32::try_from(v) {
+            Ok(int) => LuaValue::int(int),
+            Err(_) => LuaValue::float(v as f64),
+        })
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(LuaValue::float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(LuaValue::string(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(LuaValue::NIL)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(LuaValue::NIL)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        LuaValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        TableVisitor
+            .visit_seq(seq)
+            .map(|table| LuaValue::table(TableRef::from(table)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        TableVisitor
+            .visit_map(map)
+            .map(|table| LuaValue::table(TableRef::from(table)))
+    }
+}
+
+struct TableVisitor;
+
+impl<'de> Visitor<'de> for TableVisitor {
+    type Value = TableValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence or a map")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut table = TableValue::new();
+        while let Some(element) = seq.next_element::<LuaValue>()? {
+            table.push(element);
+        }
+        Ok(table)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut table = TableValue::new();
+        while let Some((key, value)) = map.next_entry::<LuaValue, LuaValue>()? {
+            let key = LuaKey::try_from(key.clone()).map_err(|_| {
+                de::Error::custom(format_args!("{} can't be a key of a table", key))
+            })?;
+            table.set(key, value);
+        }
+        Ok(table)
+    }
+}
+
+/// Reads rust values out of a lua value.
+pub struct ValueDeserializer {
+    value: LuaValue,
+    /// Tables, which are being deserialized, from the outermost one
+    visiting: Rc<RefCell<Vec<*const TableValue>>>,
+}
+
+impl ValueDeserializer {
+    pub fn new(value: LuaValue) -> Self {
+        Self {
+            value,
+            visiting: Rc::default(),
+        }
+    }
+
+    fn nested(&self, value: LuaValue) -> Self {
+        Self {
+            value,
+            visiting: Rc::clone(&self.visiting),
+        }
+    }
+
+    /// Runs the visit with the table marked as being visited.
+    fn visit_table<T>(
+        &self,
+        table: &TableRef,
+        visit: impl FnOnce() -> Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        let ptr = table.as_ptr();
+        if self.visiting.borrow().contains(&ptr) {
+            return Err(de::Error::custom("recursive tables can't be deserialized"));
+        }
+        self.visiting.borrow_mut().push(ptr);
+        let res = visit();
+        self.visiting.borrow_mut().pop();
+        res
+    }
+
+    fn visit_array<'de, V: Visitor<'de>>(
+        &self,
+        table: &TableRef,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        let elements: Vec<_> = table.borrow().array_part().to_vec();
+        self.visit_table(table, || {
+            visitor.visit_seq(Seq {
+                elements: elements.into_iter(),
+                deserializer: self,
+            })
+        })
+    }
+
+    fn visit_entries<'de, V: Visitor<'de>>(
+        &self,
+        table: &TableRef,
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError> {
+        let table_value = table.borrow();
+        let array = table_value
+            .array_part()
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.is_nil())
+            .map(|(index, value)| (LuaValue::int(index as i32 + 1), value.clone()));
+        let hash = table_value
+            .hash_part()
+            .map(|(key, value)| (LuaValue::from(key.clone()), value.clone()));
+        let entries: Vec<_> = array.chain(hash).collect();
+        drop(table_value);
+        self.visit_table(table, || {
+            visitor.visit_map(Map {
+                entries: entries.into_iter(),
+                value: None,
+                deserializer: self,
+            })
+        })
+    }
+
+    fn unexpected(&self) -> Unexpected<'_> {
+        if self.value.is_nil() {
+            Unexpected::Unit
+        } else if let Some(int) = self.value.as_int() {
+            Unexpected::Signed(int.into())
+        } else if let Some(float) = self.value.as_float() {
+            Unexpected::Float(float)
+        } else if let Some(str) = self.value.as_str() {
+            Unexpected::Str(str)
+        } else if self.value.is_table() {
+            Unexpected::Map
+        } else {
+            Unexpected::Other("function")
+        }
+    }
+}
+
+/// Integers are read from floats, which hold an integer.
+macro_rules! deserialize_integer {
+    ($($method: ident),+) => {
+        $(fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            match self.value.as_float() {
+                Some(float) if float.fract() == 0.0 && float.abs() < 2f64.powi(63) => {
+                    visitor.visit_i64(float as i64)
+                }
+                _ => self.deserialize_any(visitor),
+            }
+        })+
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer {
+    type Error = DeserializeError;
+
+    /// Tables without a hash part are read as sequences, others as maps.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        lmatch! { &self.value;
+            nil => visitor.visit_unit(),
+            int int => visitor.visit_i32(int),
+            float float => visitor.visit_f64(float),
+            string ref str => visitor.visit_str(str),
+            table table => {
+                if table.borrow().hash_part().next().is_none() {
+                    self.visit_array(&table, visitor)
+                } else {
+                    self.visit_entries(&table, visitor)
+                }
+            },
+            native_function _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            lua_function _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_bool(self.value.is_truthy())
+    }
+
+    deserialize_integer!(
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64
+    );
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.value.is_nil() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Only the array part is read, the hash part is left out.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value.as_table() {
+            Some(table) => self.visit_array(&table, visitor),
+            None => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value.as_table() {
+            Some(table) => self.visit_entries(&table, visitor),
+            None => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if let Some(variant) = self.value.as_str() {
+            return visitor.visit_enum(variant.into_deserializer());
+        }
+        let single_entry = self.value.as_table().and_then(|table| {
+            let table_value = table.borrow();
+            let mut entries = table_value.hash_part();
+            match (
+                table_value.array_part().is_empty(),
+                entries.next(),
+                entries.next(),
+            ) {
+                (true, Some((LuaKey::String(variant), value)), None) => {
+                    Some((table.clone(), variant.clone(), value.clone()))
+                }
+                _ => None,
+            }
+        });
+        match single_entry {
+            Some((table, variant, value)) => self.visit_table(&table, || {
+                visitor.visit_enum(Enum {
+                    variant,
+                    deserializer: self.nested(value),
+                })
+            }),
+            None => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        f32 f64 char str string bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+struct Seq<'a> {
+    elements: std::vec::IntoIter<LuaValue>,
+    deserializer: &'a ValueDeserializer,
+}
+
+impl<'de> SeqAccess<'de> for Seq<'_> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.elements
+            .next()
+            .map(|element| seed.deserialize(self.deserializer.nested(element)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct Map<'a> {
+    entries: std::vec::IntoIter<(LuaValue, LuaValue)>,
+    /// Value of the last key
+    value: Option<LuaValue>,
+    deserializer: &'a ValueDeserializer,
+}
+
+impl<'de> MapAccess<'de> for Map<'_> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.value = Some(value);
+        seed.deserialize(self.deserializer.nested(key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self.value.take().expect("value is only read after its key");
+        seed.deserialize(self.deserializer.nested(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct Enum {
+    variant: LuaString,
+    deserializer: ValueDeserializer,
+}
+
+impl<'de> EnumAccess<'de> for Enum {
+    type Error = DeserializeError;
+    type Variant = ValueDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant: &str = self.variant.as_ref();
+        let variant = seed.deserialize(variant.into_deserializer())?;
+        Ok((variant, self.deserializer))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer {
+    type Error = DeserializeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+}
@@ -12,8 +12,16 @@ pub use table::*;
 pub mod key;
 pub use key::*;
 
+#[cfg(feature = "serde")]
+pub mod ser;
+
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "serde")]
+pub use de::{from_value, DeserializeError, ValueDeserializer};
+
 #[cfg(feature = "compact_value")]
-mod compact;
+pub(crate) mod compact;
 #[cfg(feature = "compact_value")]
 mod string;
 
@@ -1,6 +1,9 @@
-use std::{hash::Hash, ops::Deref, rc::Rc};
+use std::{cell::RefCell, hash::Hash, rc::Rc};
 
-use crate::{FFIFunc, FromArgs, NativeFunctionCallable, NativeFunctionWrapper};
+use crate::{
+    coroutine::Coroutine, FFIFunc, FromArgs, LuaValue, MaybeSend, NativeFunctionCallable,
+    NativeFunctionWrapper, ReentrantFunctionCallable, SuspendingFunctionCallable,
+};
 
 #[derive(Clone, Debug)]
 pub struct NativeFunction(pub(crate) Rc<NativeFunctionKind>);
@@ -24,35 +27,97 @@ where
     T: NativeFunctionCallable + 'static,
 {
     fn from(func: T) -> Self {
-        Self(Rc::new(NativeFunctionKind(Box::new(func))))
+        Self(Rc::new(NativeFunctionKind::Plain(Box::new(func))))
     }
 }
 
 impl NativeFunction {
     pub fn new<'a, F, Args>(func: F) -> Self
     where
-        F: FFIFunc<Args> + 'static,
+        F: FFIFunc<Args> + MaybeSend + 'static,
         Args: FromArgs<'a> + 'static,
     {
-        Self(Rc::new(NativeFunctionKind(Box::new(
+        Self(Rc::new(NativeFunctionKind::Plain(Box::new(
             NativeFunctionWrapper::new(func),
         ))))
     }
-}
 
-pub(crate) struct NativeFunctionKind(Box<dyn NativeFunctionCallable>);
+    /// Function, which gets the machine it is called by, so that it can call lua functions with
+    /// [`call_value`](crate::call_value).
+    pub fn reentrant<F>(func: F) -> Self
+    where
+        F: ReentrantFunctionCallable + 'static,
+    {
+        Self(Rc::new(NativeFunctionKind::Reentrant(Box::new(func))))
+    }
 
-impl Deref for NativeFunctionKind {
-    type Target = dyn NativeFunctionCallable;
+    /// Reentrant function, which may return [`Poll::Pending`](std::task::Poll::Pending) to
+    /// suspend the [`Execution`](crate::Execution) running the script, until the host resolves it.
+    pub fn suspending<F>(func: F) -> Self
+    where
+        F: SuspendingFunctionCallable + 'static,
+    {
+        Self(Rc::new(NativeFunctionKind::Suspending(Box::new(func))))
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &*self.0
+    /// Coroutine, which runs the body, when it is resumed for the first time, see
+    /// [`coroutine`](crate::coroutine).
+    pub fn coroutine(body: LuaValue) -> Self {
+        Self(Rc::new(NativeFunctionKind::Coroutine(RefCell::new(
+            Coroutine::new(body, false),
+        ))))
     }
+
+    /// Coroutine, which is resumed by calling it, the way `coroutine.wrap` makes them.
+    pub fn wrapped_coroutine(body: LuaValue) -> Self {
+        Self(Rc::new(NativeFunctionKind::Coroutine(RefCell::new(
+            Coroutine::new(body, true),
+        ))))
+    }
+
+    pub fn is_reentrant(&self) -> bool {
+        matches!(
+            *self.0,
+            NativeFunctionKind::Reentrant(_)
+                | NativeFunctionKind::Suspending(_)
+                | NativeFunctionKind::Resume
+                | NativeFunctionKind::Coroutine(_)
+        )
+    }
+
+    pub fn is_coroutine(&self) -> bool {
+        matches!(*self.0, NativeFunctionKind::Coroutine(_))
+    }
+
+    /// Coroutine, which is resumed by `coroutine.resume`, and can't be called.
+    pub fn is_thread(&self) -> bool {
+        match &*self.0 {
+            NativeFunctionKind::Coroutine(coroutine) => !coroutine.borrow().wrapped,
+            _ => false,
+        }
+    }
+}
+
+pub(crate) enum NativeFunctionKind {
+    Plain(Box<dyn NativeFunctionCallable>),
+    Reentrant(Box<dyn ReentrantFunctionCallable>),
+    Suspending(Box<dyn SuspendingFunctionCallable>),
+    /// `coroutine.resume`, which returns as many values, as the coroutine yielded
+    Resume,
+    /// `coroutine.yield`, which suspends the execution, instead of being called
+    Yield,
+    Coroutine(RefCell<Coroutine>),
 }
 
 impl std::fmt::Debug for NativeFunctionKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let ptr = self.0.as_ref() as *const _;
-        write!(f, "Dyn {:p}", ptr)
+        match self {
+            Self::Plain(func) => write!(f, "Dyn {:p}", func.as_ref() as *const _),
+            Self::Reentrant(func) => write!(f, "Reentrant {:p}", func.as_ref() as *const _),
+            Self::Suspending(func) => write!(f, "Suspending {:p}", func.as_ref() as *const _),
+            Self::Resume => write!(f, "Resume"),
+            Self::Yield => write!(f, "Yield"),
+            Self::Coroutine(coroutine) => write!(f, "Coroutine {:p}", coroutine.as_ptr()),
+        }
     }
 }
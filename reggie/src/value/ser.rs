@@ -0,0 +1,114 @@
+//! Serialization of lua values with serde.
+//!
+//! Nil becomes a unit, numbers and strings become themselves. Tables, which only have the array
+//! part, become sequences, all the other tables become maps, with the keys of the array part
+//! being integers from 1 up. Functions can't be serialized, and neither can recursive tables.
+
+use std::cell::RefCell;
+
+use serde::{
+    ser::{Error, SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+
+use super::lmatch;
+use crate::{LuaKey, LuaValue, TableValue};
+
+impl Serialize for LuaValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value {
+            value: self,
+            visiting: &RefCell::default(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for TableValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let visiting = RefCell::new(vec![self as *const TableValue]);
+        Table {
+            table: self,
+            visiting: &visiting,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Tables, which are being serialized, from the outermost one.
+type Visiting = RefCell<Vec<*const TableValue>>;
+
+struct Value<'a> {
+    value: &'a LuaValue,
+    visiting: &'a Visiting,
+}
+
+struct Table<'a> {
+    table: &'a TableValue,
+    visiting: &'a Visiting,
+}
+
+impl Serialize for Value<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        lmatch! { self.value;
+            nil => serializer.serialize_unit(),
+            int int => serializer.serialize_i32(int),
+            float float => serializer.serialize_f64(float),
+            string ref str => serializer.serialize_str(str),
+            table table => {
+                let ptr = table.as_ptr();
+                if self.visiting.borrow().contains(&ptr) {
+                    return Err(S::Error::custom("recursive tables can't be serialized"));
+                }
+                self.visiting.borrow_mut().push(ptr);
+                let res = Table {
+                    table: &table.borrow(),
+                    visiting: self.visiting,
+                }
+                .serialize(serializer);
+                self.visiting.borrow_mut().pop();
+                res
+            },
+            native_function _ => Err(S::Error::custom("functions can't be serialized")),
+            lua_function _ => Err(S::Error::custom("functions can't be serialized")),
+        }
+    }
+}
+
+impl Serialize for Table<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = |value| Value {
+            value,
+            visiting: self.visiting,
+        };
+        let array = self.table.array_part();
+        if self.table.hash_part().next().is_none() {
+            let mut seq = serializer.serialize_seq(Some(array.len()))?;
+            for element in array {
+                seq.serialize_element(&value(element))?;
+            }
+            return seq.end();
+        }
+
+        let mut map = serializer.serialize_map(None)?;
+        for (index, element) in array.iter().enumerate() {
+            if !element.is_nil() {
+                map.serialize_entry(&(index + 1), &value(element))?;
+            }
+        }
+        for (key, element) in self.table.hash_part() {
+            match key {
+                LuaKey::Int(int) => map.serialize_key(int)?,
+                LuaKey::Float(float) => map.serialize_key(&float.into_inner())?,
+                LuaKey::String(str) => map.serialize_key(str.as_ref() as &str)?,
+                LuaKey::Table(_) | LuaKey::NativeFunction(_) | LuaKey::Function(_) => {
+                    return Err(S::Error::custom(
+                        "only numbers and strings can be serialized as keys",
+                    ))
+                }
+            }
+            map.serialize_value(&value(element))?;
+        }
+        map.end()
+    }
+}
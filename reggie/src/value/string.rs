@@ -108,6 +108,12 @@ impl CompactString {
         unsafe { self.0.len() }
     }
 
+    /// Checks if both strings point to the same allocation. Equal strings can still be allocated
+    /// separately, so `false` doesn't mean they differ.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
     // SAFETY: Make sure the pointer is valid
     pub(crate) unsafe fn retain(ptr: SharedStringPtr) -> Self {
         unsafe { ptr.retain() };
@@ -138,27 +144,11 @@ impl AsRef<str> for CompactString {
     }
 }
 
-#[repr(transparent)]
-struct UnsafeGlobalAllocation(StringHeader);
-unsafe impl Sync for UnsafeGlobalAllocation {}
-
-static EMPTY_STRING_ALLOCATION: UnsafeGlobalAllocation = UnsafeGlobalAllocation(StringHeader {
-    len: 0,
-    // There is a race condition here. Even though LuaString is not Sync, one could create two
-    // empty strings in two threads at the same time, and both would try to change the count.
-    //
-    // This would be fixed by interning and/or small string optimization.
-    // TODO: SSO
-    refcount: 1,
-    _unused: PhantomData,
-});
-
 impl Default for CompactString {
     fn default() -> Self {
-        let ptr = &EMPTY_STRING_ALLOCATION.0 as *const StringHeader as *mut _;
-        let ptr = unsafe { NonNull::new_unchecked(ptr) };
-        let ptr = SharedStringPtr(ptr);
-        Self(ptr)
+        // Empty strings are not shared, since refcount of a static allocation cannot be changed.
+        // TODO: SSO
+        Self::new("")
     }
 }
 
@@ -262,3 +252,21 @@ macro_rules! compact_format {
 }
 
 pub(crate) use compact_format;
+
+#[cfg(test)]
+mod tests {
+    use super::CompactString;
+
+    #[test]
+    fn default_strings_are_independently_refcounted() {
+        let empty = CompactString::default();
+        let copy = empty.clone();
+        assert_eq!(empty.refcount(), 2);
+        drop(copy);
+        drop(empty);
+
+        let other = CompactString::default();
+        assert_eq!(other.refcount(), 1);
+        assert_eq!(other.as_ref(), "");
+    }
+}
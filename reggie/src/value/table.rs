@@ -1,12 +1,38 @@
 use crate::{LuaKey, LuaValue};
-use std::{borrow::Borrow, cell::{RefCell, RefMut}, collections::HashMap, hash::Hash, ops::Deref, ptr::NonNull, rc::Rc};
+use indexmap::IndexMap;
+use std::{cell::{Cell, RefCell, RefMut}, hash::Hash, ptr::NonNull, rc::Rc};
 
 use super::LuaString;
 
+/// Hash part keeps keys in the order of insertion, and keys are never removed from it (assigning
+/// nil keeps the key). So the slot of a key never changes, and tables filled in the same order,
+/// like the ones made by the same constructor, have the same keys in the same slots.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct TableValue {
     array: Vec<LuaValue>,
-    hash: HashMap<LuaKey, LuaValue>,
+    hash: IndexMap<LuaKey, LuaValue>,
+}
+
+/// Inline cache of a property access instruction. Remembers the slot the property was found in
+/// last time. The slot is only a guess, it is checked against the key, so the cache can be
+/// shared by any number of tables and never has to be invalidated. Constant strings are interned,
+/// so the key check is usually a pointer comparison.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PropertyCache {
+    slot: Cell<u32>,
+}
+
+impl PropertyCache {
+    fn slot(&self) -> usize {
+        self.slot.get() as usize
+    }
+
+    fn remember(&self, slot: usize) {
+        // Slots past u32 are just never cached
+        if let Ok(slot) = slot.try_into() {
+            self.slot.set(slot);
+        }
+    }
 }
 
 fn is_usize_like_float(float: f64) -> bool {
@@ -87,6 +113,21 @@ impl TableValue {
         };
     }
 
+    /// Values of the keys from 1 up, which may include nils.
+    pub fn array_part(&self) -> &[LuaValue] {
+        &self.array
+    }
+
+    /// Entries of the other keys in the order of insertion, without the ones holding nil.
+    pub fn hash_part(&self) -> impl Iterator<Item = (&LuaKey, &LuaValue)> {
+        self.hash.iter().filter(|(_, value)| !value.is_nil())
+    }
+
+    /// Number of entries, including the ones holding nil.
+    pub fn entry_count(&self) -> usize {
+        self.array.len() + self.hash.len()
+    }
+
     pub fn total_eq(&self, other: &TableValue) -> bool {
         iter_eq_by(&self.array, &other.array, LuaValue::total_eq)
             && self
@@ -113,6 +154,36 @@ impl TableValue {
             .cloned()
             .unwrap_or_default()
     }
+
+    /// Same as [`TableValue::get_str_assoc`], but skips hashing if the property is in the slot
+    /// remembered by the cache.
+    pub fn get_str_cached(&self, str: &LuaString, cache: &PropertyCache) -> LuaValue {
+        if let Some((LuaKey::String(key), value)) = self.hash.get_index(cache.slot())
+            && (key.ptr_eq(str) || key == str)
+        {
+            return value.clone();
+        }
+        match self.hash.get_full(&LuaKey::String(str.clone())) {
+            Some((slot, _, value)) => {
+                cache.remember(slot);
+                value.clone()
+            }
+            None => LuaValue::NIL,
+        }
+    }
+
+    /// Same as [`TableValue::assoc_str`], but skips hashing if the property is in the slot
+    /// remembered by the cache.
+    pub fn assoc_str_cached(&mut self, str: &LuaString, value: LuaValue, cache: &PropertyCache) {
+        if let Some((LuaKey::String(key), slot_value)) = self.hash.get_index_mut(cache.slot())
+            && (key.ptr_eq(str) || key == str)
+        {
+            *slot_value = value;
+            return;
+        }
+        let (slot, _) = self.hash.insert_full(LuaKey::String(str.clone()), value);
+        cache.remember(slot);
+    }
 }
 
 #[repr(transparent)]
@@ -125,9 +196,13 @@ impl UnownedTableRef<'_> {
     }
 
     pub fn to_owned(&self) -> TableRef {
-        TableRef(
-            unsafe { Rc::from_raw(self.0 as * const _) }
-        )
+        // SAFETY: The pointer came from an Rc, that is kept alive by the value this ref borrows
+        //         from. The new owner gets its own strong count.
+        unsafe {
+            let ptr = self.0 as *const RefCell<TableValue>;
+            Rc::increment_strong_count(ptr);
+            TableRef(Rc::from_raw(ptr))
+        }
     }
 
     pub fn borrow(&self) -> std::cell::Ref<'_, TableValue> {
@@ -152,6 +227,14 @@ impl TableRef {
         RefCell::borrow(&self.0).is_empty()
     }
 
+    pub fn entry_count(&self) -> usize {
+        RefCell::borrow(&self.0).entry_count()
+    }
+
+    pub fn borrow(&self) -> std::cell::Ref<'_, TableValue> {
+        RefCell::borrow(&self.0)
+    }
+
     pub fn unwrap_or_clone(self) -> TableValue {
         Rc::try_unwrap(self.0)
             .unwrap_or_else(|rc| (*rc).clone())
@@ -170,8 +253,16 @@ impl TableRef {
         self.0.borrow_mut().get_str_assoc(str)
     }
 
+    pub fn get_str_cached(&self, str: &LuaString, cache: &PropertyCache) -> LuaValue {
+        RefCell::borrow(&self.0).get_str_cached(str, cache)
+    }
+
+    pub fn assoc_str_cached(&mut self, str: &LuaString, value: LuaValue, cache: &PropertyCache) {
+        self.0.borrow_mut().assoc_str_cached(str, value, cache)
+    }
+
     pub fn get(&self, member: &LuaKey) -> LuaValue {
-        self.0.borrow().get(member).clone()
+        RefCell::borrow(&self.0).get(member).clone()
     }
 
     pub fn set(&mut self, member: LuaKey, value: LuaValue) {
@@ -221,12 +312,15 @@ impl quickcheck::Arbitrary for TableValue {
     fn arbitrary(g: &mut quickcheck::Gen) -> Self {
         Self {
             array: quickcheck::Arbitrary::arbitrary(g),
-            hash: quickcheck::Arbitrary::arbitrary(g),
+            hash: std::collections::HashMap::<LuaKey, LuaValue>::arbitrary(g)
+                .into_iter()
+                .collect(),
         }
     }
     fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
         let array = self.array.clone();
         let hash = self.hash.clone();
+        let hash_entries: Vec<(LuaKey, LuaValue)> = self.hash.clone().into_iter().collect();
         Box::new(
             self.array
                 .shrink()
@@ -234,9 +328,9 @@ impl quickcheck::Arbitrary for TableValue {
                     array,
                     hash: hash.clone(),
                 })
-                .chain(self.hash.shrink().map(move |hash| Self {
+                .chain(hash_entries.shrink().map(move |hash| Self {
                     array: array.clone(),
-                    hash,
+                    hash: hash.into_iter().collect(),
                 })),
         )
     }
@@ -256,3 +350,60 @@ impl quickcheck::Arbitrary for TableRef {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{PropertyCache, TableValue};
+    use crate::{GlobalValues, LuaString, LuaValue};
+
+    fn table(properties: &[(&str, i32)]) -> TableValue {
+        let mut table = TableValue::new();
+        for (property, value) in properties {
+            table.assoc_str(*property, LuaValue::int(*value));
+        }
+        table
+    }
+
+    #[test]
+    fn cached_property_lookup_is_shared_by_tables_of_the_same_layout() {
+        let cache = PropertyCache::default();
+        let y = LuaString::from("y");
+        let first = table(&[("x", 1), ("y", 2)]);
+        let second = table(&[("x", 3), ("y", 4)]);
+        assert_eq!(first.get_str_cached(&y, &cache), LuaValue::int(2));
+        assert_eq!(cache.slot(), 1);
+        assert_eq!(second.get_str_cached(&y, &cache), LuaValue::int(4));
+        assert_eq!(cache.slot(), 1);
+    }
+
+    #[test]
+    fn cached_property_lookup_falls_back_to_hashing_on_different_layout() {
+        let cache = PropertyCache::default();
+        let y = LuaString::from("y");
+        let same_layout = table(&[("x", 3), ("y", 4)]);
+        let mut other_layout = table(&[("y", 1), ("x", 2)]);
+        assert_eq!(same_layout.get_str_cached(&y, &cache), LuaValue::int(4));
+        assert_eq!(other_layout.get_str_cached(&y, &cache), LuaValue::int(1));
+        assert_eq!(cache.slot(), 0);
+
+        let z = LuaString::from("z");
+        assert_eq!(other_layout.get_str_cached(&z, &cache), LuaValue::NIL);
+        other_layout.assoc_str_cached(&z, LuaValue::int(5), &cache);
+        assert_eq!(cache.slot(), 2);
+        assert_eq!(other_layout.get_str_assoc("z"), LuaValue::int(5));
+    }
+    #[test]
+    fn interned_constants_share_the_key_of_the_table() {
+        let mut global_values = GlobalValues::default();
+        let cache = PropertyCache::default();
+        let mut table = TableValue::new();
+        table.assoc_str(global_values.intern("description"), LuaValue::int(1));
+
+        let name = global_values.intern("description");
+        let separately_allocated = LuaString::from("description");
+        assert!(name.ptr_eq(&global_values.intern("description")));
+        assert!(!name.ptr_eq(&separately_allocated));
+        assert_eq!(table.get_str_cached(&name, &cache), LuaValue::int(1));
+        assert_eq!(table.get_str_cached(&separately_allocated, &cache), LuaValue::int(1));
+    }
+}
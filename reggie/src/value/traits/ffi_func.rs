@@ -30,5 +30,14 @@ ffi_func_impl! {
     [A0];
     [A0, A1];
     [A0, A1, A2];
+    [A0, A1, A2, A3];
+    [A0, A1, A2, A3, A4];
+    [A0, A1, A2, A3, A4, A5];
+    [A0, A1, A2, A3, A4, A5, A6];
+    [A0, A1, A2, A3, A4, A5, A6, A7];
+    [A0, A1, A2, A3, A4, A5, A6, A7, A8];
+    [A0, A1, A2, A3, A4, A5, A6, A7, A8, A9];
+    [A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10];
+    [A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11];
 }
 
@@ -1,93 +1,57 @@
-use crate::{machine::ArgumentRegisters, FromArgsPart, LuaValue};
+use crate::{machine::ArgumentRegisters, FromArgsPart, LuaValue, TypeError};
 
-pub trait FromArgs<'a> {
-    fn from_args(argument_registers: &'a ArgumentRegisters, arg_count: u16) -> Self;
+/// Arguments of a native function. Missing arguments are converted from nil, extra ones are
+/// ignored.
+pub trait FromArgs<'a>: Sized {
+    fn from_args(
+        argument_registers: &'a ArgumentRegisters,
+        arg_count: u16,
+    ) -> Result<Self, TypeError>;
 }
 
 impl<'a> FromArgs<'a> for () {
-    fn from_args(_: &'a ArgumentRegisters, _: u16) -> Self {
-        ()
+    fn from_args(_: &'a ArgumentRegisters, _: u16) -> Result<Self, TypeError> {
+        Ok(())
     }
 }
 
 impl<'a> FromArgs<'a> for (&'a [LuaValue],) {
-    fn from_args(argument_registers: &'a ArgumentRegisters, arg_count: u16) -> Self {
-        (&argument_registers.d[..arg_count as usize],)
+    fn from_args(
+        argument_registers: &'a ArgumentRegisters,
+        arg_count: u16,
+    ) -> Result<Self, TypeError> {
+        Ok((&argument_registers.d[..arg_count as usize],))
     }
 }
 
-macro_rules! match_arm {
-    ($argument_registers: expr, $type: ident-) => {
-        $type::from_absent_argument()
-    };
-    ($argument_registers: expr, $type: ident+) => {
-        $type::from_argument($argument_registers)
-    };
-}
-
 macro_rules! impl_from_args_tuple {
-    ($($pos: expr => ($generic: ident, [$($gen: ident $present: tt)+])),+$(,)?) => {
+    ($($pos: literal => $generic: ident),+) => {
         impl<'a, $($generic,)+> FromArgs<'a> for ($($generic,)+)
         where
             $($generic: FromArgsPart<'a, $pos>),+
         {
-            fn from_args(argument_registers: &'a ArgumentRegisters, arg_count: u16) -> Self {
-                match arg_count {
-                    $($pos => ($(match_arm!{ argument_registers, $gen $present },)+),)+
-                    _ => ($($generic::from_argument(argument_registers),)+)
-                }
+            fn from_args(argument_registers: &'a ArgumentRegisters, arg_count: u16) -> Result<Self, TypeError> {
+                Ok(($(
+                    if $pos < arg_count {
+                        <$generic as FromArgsPart<'a, $pos>>::from_argument(argument_registers)?
+                    } else {
+                        <$generic as FromArgsPart<'a, $pos>>::from_absent_argument()?
+                    },
+                )+))
             }
         }
     };
 }
 
-impl_from_args_tuple! {
-    0 => (A, [A-])
-}
-impl_from_args_tuple! {
-    0 => (A, [A-B-]),
-    1 => (B, [A+B-]),
-}
-impl_from_args_tuple! {
-    0 => (A, [A-B-C-]),
-    1 => (B, [A+B-C-]),
-    2 => (C, [A+B+C-]),
-}
-impl_from_args_tuple! {
-    0 => (A, [A-B-C-D-]),
-    1 => (B, [A+B-C-D-]),
-    2 => (C, [A+B+C-D-]),
-    3 => (D, [A+B+C+D-]),
-}
-
-// impl<'a, T> FromArgs<'a> for (T,)
-// where
-//     T: FromArgsPart<'a, 0>
-// {
-//     fn from_args(argument_registers: &'a ArgumentRegisters, arg_count: u16) -> Self {
-//         // if argumn
-//     }
-// }
-
-// impl FromArgs for (LuaValue,) {
-//     fn from_args(argument_registers: &mut ArgumentRegisters, argument_count: u16) -> Self {
-//         if argument_count > 0 {
-//             (argument_registers.d[0].clone(),)
-//         } else {
-//             (LuaValue::Nil,)
-//         }
-//     }
-// }
-
-// impl FromArgs for (LuaValue, LuaValue) {
-//     fn from_args(argument_registers: &mut ArgumentRegisters, argument_count: u16) -> Self {
-//         match argument_count {
-//             0 => (LuaValue::Nil, LuaValue::Nil),
-//             1 => (argument_registers.d[0].clone(), LuaValue::Nil),
-//             _ => (
-//                 argument_registers.d[0].clone(),
-//                 argument_registers.d[1].clone(),
-//             ),
-//         }
-//     }
-// }
+impl_from_args_tuple!(0 => A);
+impl_from_args_tuple!(0 => A, 1 => B);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K);
+impl_from_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K, 11 => L);
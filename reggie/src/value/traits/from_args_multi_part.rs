@@ -1,27 +1,26 @@
-use crate::{machine::ArgumentRegisters, LuaValue};
+use crate::{machine::ArgumentRegisters, FromLua, LuaValue, TypeError};
 
-pub trait FromArgsPart<'a, const N: u16> {
-    fn from_argument(argument_registers: &'a ArgumentRegisters) -> Self;
-    fn from_absent_argument() -> Self;
+pub trait FromArgsPart<'a, const N: u16>: Sized {
+    fn from_argument(argument_registers: &'a ArgumentRegisters) -> Result<Self, TypeError>;
+    fn from_absent_argument() -> Result<Self, TypeError>;
 }
 
-impl<'a, const N: u16> FromArgsPart<'a, N> for LuaValue {
-    fn from_argument(argument_registers: &'a ArgumentRegisters) -> Self {
-        argument_registers.d[N as usize].clone()
+impl<'a, const N: u16, T: FromLua<'a>> FromArgsPart<'a, N> for T {
+    fn from_argument(argument_registers: &'a ArgumentRegisters) -> Result<Self, TypeError> {
+        from_argument_value::<N, T>(&argument_registers.d[N as usize])
     }
 
-    fn from_absent_argument() -> Self {
-        LuaValue::NIL
+    fn from_absent_argument() -> Result<Self, TypeError> {
+        from_argument_value::<N, T>(LuaValue::nil_ref())
     }
 }
 
-impl<'a, const N: u16> FromArgsPart<'a, N> for &'a LuaValue {
-    fn from_argument(argument_registers: &'a ArgumentRegisters) -> Self {
-        &argument_registers.d[N as usize]
-    }
-
-    fn from_absent_argument() -> Self {
-        LuaValue::nil_ref()
-    }
+fn from_argument_value<'a, const N: u16, T: FromLua<'a>>(
+    value: &'a LuaValue,
+) -> Result<T, TypeError> {
+    T::from_lua(value).map_err(|expected| TypeError::ArgumentType {
+        position: N as usize,
+        expected,
+        got: value.clone(),
+    })
 }
-
@@ -0,0 +1,200 @@
+use crate::{ExpectedType, LuaKey, LuaString, LuaValue, NativeFunction, TableRef};
+
+/// Conversion of a single lua value, which is passed into rust as an argument, a return value or
+/// a global. Absent values are converted from nil. Numbers are not coerced from strings, and the
+/// other way around.
+pub trait FromLua<'a>: Sized {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType>;
+}
+
+impl<'a> FromLua<'a> for LuaValue {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        Ok(value.clone())
+    }
+}
+
+impl<'a> FromLua<'a> for &'a LuaValue {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        Ok(value)
+    }
+}
+
+/// Truthiness of the value, never fails.
+impl<'a> FromLua<'a> for bool {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        Ok(value.is_truthy())
+    }
+}
+
+/// Floats are accepted, if they hold an integer in the range of the type.
+impl<'a> FromLua<'a> for i32 {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        if let Some(int) = value.as_int() {
+            return Ok(int);
+        }
+        match value.as_float() {
+            Some(float)
+                if float.fract() == 0.0
+                    && float >= f64::from(i32::MIN)
+                    && float <= f64::from(i32::MAX) =>
+            {
+                Ok(float as i32)
+            }
+            _ => Err(ExpectedType::Integer),
+        }
+    }
+}
+
+/// Floats are accepted, if they hold an integer in the range of the type.
+impl<'a> FromLua<'a> for i64 {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        if let Some(int) = value.as_int() {
+            return Ok(i64::from(int));
+        }
+        match value.as_float() {
+            // i64::MAX is not representable as a float, hence the exclusive bound
+            Some(float)
+                if float.fract() == 0.0 && float >= -(2f64.powi(63)) && float < 2f64.powi(63) =>
+            {
+                Ok(float as i64)
+            }
+            _ => Err(ExpectedType::Integer),
+        }
+    }
+}
+
+impl<'a> FromLua<'a> for f64 {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        value.number_as_f64().ok_or(ExpectedType::Number)
+    }
+}
+
+impl<'a> FromLua<'a> for &'a str {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        value.as_str().ok_or(ExpectedType::String)
+    }
+}
+
+impl<'a> FromLua<'a> for String {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        value
+            .as_str()
+            .map(ToOwned::to_owned)
+            .ok_or(ExpectedType::String)
+    }
+}
+
+impl<'a> FromLua<'a> for LuaString {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        value.as_string().ok_or(ExpectedType::String)
+    }
+}
+
+impl<'a> FromLua<'a> for TableRef {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        value.as_table().ok_or(ExpectedType::Table)
+    }
+}
+
+impl<'a> FromLua<'a> for NativeFunction {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        value.as_native_function().ok_or(ExpectedType::Function)
+    }
+}
+
+/// Array part of a table, up to the first nil. Elements can't borrow from the table, so they
+/// have to be converted into owned values.
+impl<'a, T> FromLua<'a> for Vec<T>
+where
+    T: for<'b> FromLua<'b>,
+{
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        let table = value.as_table().ok_or(ExpectedType::Table)?;
+        let mut elements = Vec::new();
+        for index in 1.. {
+            let element = table.get(&LuaKey::Int(index));
+            if element.is_nil() {
+                break;
+            }
+            elements.push(T::from_lua(&element)?);
+        }
+        Ok(elements)
+    }
+}
+
+/// Nil is converted into `None`, anything else has to convert into `T`.
+impl<'a, T: FromLua<'a>> FromLua<'a> for Option<T> {
+    fn from_lua(value: &'a LuaValue) -> Result<Self, ExpectedType> {
+        if value.is_nil() {
+            Ok(None)
+        } else {
+            T::from_lua(value).map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FromLua;
+    use crate::{ExpectedType, LuaKey, LuaString, LuaValue, TableRef};
+
+    #[test]
+    fn integral_floats_convert_into_integers() {
+        assert_eq!(i32::from_lua(&LuaValue::float(3.0)), Ok(3));
+        assert_eq!(i64::from_lua(&LuaValue::int(-3)), Ok(-3));
+        assert_eq!(i64::from_lua(&LuaValue::float(1e12)), Ok(1_000_000_000_000));
+        assert_eq!(
+            i32::from_lua(&LuaValue::float(3.5)),
+            Err(ExpectedType::Integer)
+        );
+        assert_eq!(
+            i32::from_lua(&LuaValue::float(1e12)),
+            Err(ExpectedType::Integer)
+        );
+        assert_eq!(
+            i32::from_lua(&LuaValue::string("3")),
+            Err(ExpectedType::Integer)
+        );
+        assert_eq!(f64::from_lua(&LuaValue::int(3)), Ok(3.0));
+    }
+
+    #[test]
+    fn strings_are_borrowed_or_copied() {
+        let value = LuaValue::string("hello");
+        assert_eq!(<&str>::from_lua(&value), Ok("hello"));
+        assert_eq!(String::from_lua(&value), Ok("hello".to_owned()));
+        assert_eq!(LuaString::from_lua(&value), Ok(LuaString::from("hello")));
+        assert_eq!(
+            <&str>::from_lua(&LuaValue::int(1)),
+            Err(ExpectedType::String)
+        );
+    }
+
+    #[test]
+    fn nil_is_none_and_anything_is_a_bool() {
+        assert_eq!(Option::<i32>::from_lua(&LuaValue::NIL), Ok(None));
+        assert_eq!(Option::<i32>::from_lua(&LuaValue::int(1)), Ok(Some(1)));
+        assert_eq!(
+            Option::<i32>::from_lua(&LuaValue::string("x")),
+            Err(ExpectedType::Integer)
+        );
+        assert_eq!(bool::from_lua(&LuaValue::NIL), Ok(false));
+        assert_eq!(bool::from_lua(&LuaValue::int(0)), Ok(true));
+    }
+
+    #[test]
+    fn sequences_convert_into_vectors() {
+        let mut table = TableRef::new();
+        table.push(LuaValue::int(1));
+        table.push(LuaValue::int(2));
+        table.set(LuaKey::Int(4), LuaValue::int(4));
+        table.assoc_str("x", LuaValue::int(0));
+        let value = LuaValue::table(table);
+        assert_eq!(Vec::<i32>::from_lua(&value), Ok(vec![1, 2]));
+        assert_eq!(Vec::<String>::from_lua(&value), Err(ExpectedType::String));
+        assert_eq!(
+            Vec::<i32>::from_lua(&LuaValue::NIL),
+            Err(ExpectedType::Table)
+        );
+    }
+}
@@ -1,36 +1,26 @@
-use crate::{Machine, LuaValue};
+use crate::{FromLua, LuaValue, Machine, TypeError};
 
-pub trait FromMultiReturnPart<'a, const N: u16> {
-    fn from_multi_return(machine: &'a Machine) -> Self;
-    fn from_absent_value(machine: &'a Machine) -> Self;
+pub trait FromMultiReturnPart<'a, const N: u16>: Sized {
+    fn from_multi_return(machine: &'a Machine) -> Result<Self, TypeError>;
+    fn from_absent_value(machine: &'a Machine) -> Result<Self, TypeError>;
 }
 
-impl<'a, const N: u16> FromMultiReturnPart<'a, N> for LuaValue {
-    fn from_multi_return(machine: &'a Machine) -> Self {
-        machine.argument_registers.d[N as usize].clone()
+impl<'a, const N: u16, T: FromLua<'a>> FromMultiReturnPart<'a, N> for T {
+    fn from_multi_return(machine: &'a Machine) -> Result<Self, TypeError> {
+        from_return_value::<N, T>(&machine.argument_registers.d[N as usize])
     }
 
-    fn from_absent_value(_: &'a Machine) -> Self {
-        LuaValue::NIL
+    fn from_absent_value(machine: &'a Machine) -> Result<Self, TypeError> {
+        from_return_value::<N, T>(machine.global_values.global_nil())
     }
 }
 
-impl<'a, const N: u16> FromMultiReturnPart<'a, N> for &'a LuaValue {
-    fn from_multi_return(machine: &'a Machine) -> Self {
-        &machine.argument_registers.d[N as usize]
-    }
-
-    fn from_absent_value(machine: &'a Machine) -> Self {
-        machine.global_values.global_nil()
-    }
-}
-
-impl<'a, const N: u16> FromMultiReturnPart<'a, N> for bool {
-    fn from_multi_return(machine: &'a Machine) -> Self {
-        machine.argument_registers.d[N as usize].is_truthy()
-    }
-
-    fn from_absent_value(_: &'a Machine) -> Self {
-        false
-    }
+fn from_return_value<'a, const N: u16, T: FromLua<'a>>(
+    value: &'a LuaValue,
+) -> Result<T, TypeError> {
+    T::from_lua(value).map_err(|expected| TypeError::ReturnType {
+        position: N as usize,
+        expected,
+        got: value.clone(),
+    })
 }
@@ -1,22 +1,21 @@
-use crate::{Machine, sized_value::SizedValue, FromMultiReturnPart, LuaValue, TryFromReturn};
+use crate::{
+    sized_value::SizedValue, FromMultiReturnPart, LuaValue, Machine, MultiValue, TypeError,
+};
 
+/// Values returned into rust. Missing values are converted from nil, extra ones are ignored.
 pub trait FromReturn<'a>
 where
     Self: Sized,
 {
-    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Self;
+    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Result<Self, TypeError>;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Strict<T>(pub T);
 
-// pub struct TryStrict<T>(pub T);
-
-// pub struct CannotCollectReturn;
-
 impl<'a> FromReturn<'a> for () {
-    fn from_machine_state(_: &'a Machine, _: u16) -> Self {
-        ()
+    fn from_machine_state(_: &'a Machine, _: u16) -> Result<Self, TypeError> {
+        Ok(())
     }
 }
 
@@ -24,7 +23,7 @@ impl<'a, T> FromReturn<'a> for Strict<T>
 where
     T: FromReturn<'a> + SizedValue,
 {
-    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Self {
+    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Result<Self, TypeError> {
         assert_eq!(
             return_count, 
             T::COUNT, 
@@ -33,13 +32,7 @@ where
             std::any::type_name::<T>(), 
             T::COUNT
         );
-        Strict(T::from_machine_state(machine, return_count))
-    }
-}
-
-impl<'a, T> FromReturn<'a> for Result<T, T::Error> where T: TryFromReturn<'a> {
-    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Self {
-        T::try_from_machine_state(machine, return_count)
+        T::from_machine_state(machine, return_count).map(Strict)
     }
 }
 
@@ -47,7 +40,7 @@ impl<'a, T> FromReturn<'a> for T
 where 
     T: FromMultiReturnPart<'a, 0> 
 {
-    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Self {
+    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Result<Self, TypeError> {
         if return_count == 0 {
             T::from_absent_value(machine)
         } else {
@@ -56,52 +49,48 @@ where
     }
 }
 
-macro_rules! match_arm {
-    ($machine: expr, $type: ident-) => {
-        $type::from_absent_value($machine)
-    };
-    ($machine: expr, $type: ident+) => {
-        $type::from_multi_return($machine)
-    };
-}
-
 macro_rules! impl_from_lua_return_tuple {
-    ($($pos: expr => ($generic: ident, [$($gen: ident $present: tt)+])),+$(,)?) => {
+    ($($pos: literal => $generic: ident),+) => {
         impl<'a, $($generic,)+> FromReturn<'a> for ($($generic,)+)
         where
             $($generic: FromMultiReturnPart<'a, $pos>),+
         {
-            fn from_machine_state(machine: &'a Machine, return_count: u16) -> Self {
-                match return_count {
-                    $($pos => ($(match_arm!{ machine, $gen $present },)+),)+
-                    _ => ($($generic::from_multi_return(machine),)+)
-                }
+            fn from_machine_state(machine: &'a Machine, return_count: u16) -> Result<Self, TypeError> {
+                Ok(($(
+                    if $pos < return_count {
+                        <$generic as FromMultiReturnPart<'a, $pos>>::from_multi_return(machine)?
+                    } else {
+                        <$generic as FromMultiReturnPart<'a, $pos>>::from_absent_value(machine)?
+                    },
+                )+))
             }
         }
     };
 }
 
-impl_from_lua_return_tuple! { 
-    0 => (A, [A-])
-}
-impl_from_lua_return_tuple! { 
-    0 => (A, [A-B-]),
-    1 => (B, [A+B-]),
-}
-impl_from_lua_return_tuple! { 
-    0 => (A, [A-B-C-]),
-    1 => (B, [A+B-C-]),
-    2 => (C, [A+B+C-]),
-}
-impl_from_lua_return_tuple! { 
-    0 => (A, [A-B-C-D-]),
-    1 => (B, [A+B-C-D-]),
-    2 => (C, [A+B+C-D-]),
-    3 => (D, [A+B+C+D-]),
-}
+impl_from_lua_return_tuple!(0 => A);
+impl_from_lua_return_tuple!(0 => A, 1 => B);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K);
+impl_from_lua_return_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K, 11 => L);
 
 impl<'a> FromReturn<'a> for &'a [LuaValue] {
-    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Self {
-        &machine.argument_registers.d[..(return_count as usize)]
+    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Result<Self, TypeError> {
+        Ok(&machine.argument_registers.d[..(return_count as usize)])
+    }
+}
+
+impl<'a> FromReturn<'a> for MultiValue {
+    fn from_machine_state(machine: &'a Machine, return_count: u16) -> Result<Self, TypeError> {
+        Ok(MultiValue(
+            machine.argument_registers.d[..(return_count as usize)].to_vec(),
+        ))
     }
 }
@@ -0,0 +1,50 @@
+use crate::{machine::ArgumentRegisters, IntoLua, LuaValue};
+
+/// Arguments of a call from rust into lua. Writes the arguments into the registers, and returns
+/// their count.
+pub trait IntoArgs {
+    fn into_args(self, argument_registers: &mut ArgumentRegisters) -> u16;
+}
+
+impl IntoArgs for () {
+    fn into_args(self, _: &mut ArgumentRegisters) -> u16 {
+        0
+    }
+}
+
+impl IntoArgs for &[LuaValue] {
+    fn into_args(self, argument_registers: &mut ArgumentRegisters) -> u16 {
+        for (index, argument) in self.iter().enumerate() {
+            argument_registers.d[index] = argument.clone();
+        }
+        self.len()
+            .try_into()
+            .expect("argument count fits into the value count")
+    }
+}
+
+macro_rules! impl_into_args_tuple {
+    ($($pos: literal => $generic: ident),+) => {
+        impl<$($generic: IntoLua,)+> IntoArgs for ($($generic,)+) {
+            #[allow(non_snake_case)]
+            fn into_args(self, argument_registers: &mut ArgumentRegisters) -> u16 {
+                let ($($generic,)+) = self;
+                $(argument_registers.d[$pos] = $generic.into_lua();)+
+                [$($pos),+].len() as u16
+            }
+        }
+    };
+}
+
+impl_into_args_tuple!(0 => A);
+impl_into_args_tuple!(0 => A, 1 => B);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K);
+impl_into_args_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K, 11 => L);
@@ -0,0 +1,101 @@
+use crate::{LuaString, LuaValue, NativeFunction, TableRef, TableValue};
+
+/// Conversion of a rust value into a single lua value, which is passed into lua as an argument, a
+/// return value or a global.
+pub trait IntoLua {
+    fn into_lua(self) -> LuaValue;
+}
+
+impl IntoLua for LuaValue {
+    fn into_lua(self) -> LuaValue {
+        self
+    }
+}
+
+impl IntoLua for &LuaValue {
+    fn into_lua(self) -> LuaValue {
+        self.clone()
+    }
+}
+
+impl IntoLua for bool {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::from_bool(self)
+    }
+}
+
+impl IntoLua for i32 {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::int(self)
+    }
+}
+
+/// Integers out of the range of lua integers become floats.
+impl IntoLua for i64 {
+    fn into_lua(self) -> LuaValue {
+        match i32::try_from(self) {
+            Ok(int) => LuaValue::int(int),
+            Err(_) => LuaValue::float(self as f64),
+        }
+    }
+}
+
+impl IntoLua for f64 {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::float(self)
+    }
+}
+
+impl IntoLua for &str {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::string(self)
+    }
+}
+
+impl IntoLua for String {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::string(self)
+    }
+}
+
+impl IntoLua for LuaString {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::string(self)
+    }
+}
+
+impl IntoLua for TableRef {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::table(self)
+    }
+}
+
+impl IntoLua for TableValue {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::table(TableRef::from(self))
+    }
+}
+
+impl IntoLua for NativeFunction {
+    fn into_lua(self) -> LuaValue {
+        LuaValue::native_function(self)
+    }
+}
+
+/// Elements go into the array part of a new table.
+impl<T: IntoLua> IntoLua for Vec<T> {
+    fn into_lua(self) -> LuaValue {
+        let mut table = TableRef::new();
+        for element in self {
+            table.push(element.into_lua());
+        }
+        LuaValue::table(table)
+    }
+}
+
+/// `None` becomes nil.
+impl<T: IntoLua> IntoLua for Option<T> {
+    fn into_lua(self) -> LuaValue {
+        self.map_or(LuaValue::NIL, T::into_lua)
+    }
+}
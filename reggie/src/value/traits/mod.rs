@@ -7,15 +7,21 @@ pub use sized_value::*;
 pub mod from_multi_return_part;
 pub use from_multi_return_part::*;
 
-pub mod try_from_return;
-pub use try_from_return::*;
+pub mod from_lua;
+pub use from_lua::*;
 
-pub mod try_from_multi_return_part;
-pub use try_from_multi_return_part::*;
+pub mod into_lua;
+pub use into_lua::*;
 
 pub mod native_function_callable;
 pub use native_function_callable::*;
 
+pub mod reentrant_function_callable;
+pub use reentrant_function_callable::*;
+
+pub mod suspending_function_callable;
+pub use suspending_function_callable::*;
+
 pub mod ffi_func;
 pub use ffi_func::*;
 
@@ -27,3 +33,6 @@ pub use from_args::*;
 
 pub mod from_args_multi_part;
 pub use from_args_multi_part::*;
+
+pub mod into_args;
+pub use into_args::*;
@@ -1,24 +1,27 @@
 use std::marker::PhantomData;
 
-use crate::{machine::ArgumentRegisters, EvalError, FFIFunc, FromArgs, ReturnRepresentable};
+use crate::{
+    machine::ArgumentRegisters, EvalError, FFIFunc, FromArgs, MaybeSend, ReturnRepresentable,
+};
 
-pub trait NativeFunctionCallable {
+pub trait NativeFunctionCallable: MaybeSend {
+    /// Calls the function with `value_count` arguments, and returns the count of its returns.
     fn call(
         &self,
         argument_registers: &mut ArgumentRegisters,
         value_count: u16,
-    ) -> Result<(), EvalError>;
-    fn return_count(&self) -> u16;
+    ) -> Result<u16, EvalError>;
 }
 
 pub struct NativeFunctionWrapper<F, Args> {
     func: F,
-    _args: PhantomData<Args>,
+    // Arguments are only passed to the function, so they don't decide whether it is Send
+    _args: PhantomData<fn(Args)>,
 }
 
 impl<'a, F, Args> NativeFunctionCallable for NativeFunctionWrapper<F, Args>
 where
-    F: FFIFunc<Args>,
+    F: FFIFunc<Args> + MaybeSend,
     Args: FromArgs<'a>,
     F::Output: 'static,
 {
@@ -26,7 +29,7 @@ where
         &self,
         argument_registers: &mut ArgumentRegisters,
         value_count: u16,
-    ) -> Result<(), EvalError> {
+    ) -> Result<u16, EvalError> {
         // SAFETY: Look, I'm dumb. I can't figure out for the life of me the lifetimes here.
         //         The idea is, that result of Args::from_args is valid for the lifetime
         //         that &mut ArgumentRegisters is valid for (which is the duration of this call).
@@ -45,14 +48,10 @@ where
             unsafe { &mut *(argument_registers as *mut ArgumentRegisters) },
             // argument_registers,
             value_count,
-        );
+        )?;
         let res = self.func.call(args);
         res.to_lua_return(argument_registers)
     }
-
-    fn return_count(&self) -> u16 {
-        F::Output::return_count()
-    }
 }
 
 impl<'a, F, Args> NativeFunctionWrapper<F, Args>
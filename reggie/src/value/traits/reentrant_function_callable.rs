@@ -0,0 +1,18 @@
+use crate::{EvalError, LuaValue, Machine, MaybeSend, ReturnRepresentable};
+
+/// Calling convention of native functions, which call back into lua. Function gets the machine,
+/// that called it, instead of the argument registers. Arguments are copied out of the registers
+/// before the call, as nested calls overwrite them. Returns the count of values it returned.
+pub trait ReentrantFunctionCallable: MaybeSend {
+    fn call(&self, machine: &mut Machine, arguments: &[LuaValue]) -> Result<u16, EvalError>;
+}
+
+impl<F, Ret> ReentrantFunctionCallable for F
+where
+    F: Fn(&mut Machine, &[LuaValue]) -> Ret + MaybeSend,
+    Ret: ReturnRepresentable,
+{
+    fn call(&self, machine: &mut Machine, arguments: &[LuaValue]) -> Result<u16, EvalError> {
+        self(machine, arguments).to_lua_return(&mut machine.argument_registers)
+    }
+}
@@ -1,55 +1,80 @@
 use crate::{
     machine::ArgumentRegisters,
     signature::{ArgumentType, FunctionSignatureList},
-    EvalError, LuaValue, TypeError,
+    EvalError, IntoLua, LuaValue, TypeError,
 };
 
 pub trait ReturnRepresentable {
     fn returns() -> FunctionSignatureList;
-    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<(), EvalError>;
-    fn return_count() -> u16;
+    /// Writes the values into the argument registers, and returns how many of them there are.
+    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<u16, EvalError>;
 }
 
+/// Any number of values, returned by a native function, which doesn't know their count in
+/// advance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultiValue(pub Vec<LuaValue>);
+
 impl ReturnRepresentable for () {
     fn returns() -> FunctionSignatureList {
         FunctionSignatureList::Finite(vec![])
     }
-    fn to_lua_return(self, _: &mut ArgumentRegisters) -> Result<(), EvalError> {
-        Ok(())
-    }
-
-    fn return_count() -> u16 {
-        0
+    fn to_lua_return(self, _: &mut ArgumentRegisters) -> Result<u16, EvalError> {
+        Ok(0)
     }
 }
 
-impl ReturnRepresentable for LuaValue {
+impl<T: IntoLua> ReturnRepresentable for T {
     fn returns() -> FunctionSignatureList {
         FunctionSignatureList::Finite(vec![ArgumentType::Dynamic])
     }
-    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<(), EvalError> {
-        argument_registers.d[0] = self;
-        Ok(())
-    }
-    fn return_count() -> u16 {
-        1
+    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<u16, EvalError> {
+        argument_registers.d[0] = self.into_lua();
+        Ok(1)
     }
 }
 
-impl ReturnRepresentable for (LuaValue, LuaValue) {
+impl ReturnRepresentable for MultiValue {
     fn returns() -> FunctionSignatureList {
-        FunctionSignatureList::Finite(vec![ArgumentType::Dynamic, ArgumentType::Dynamic])
-    }
-    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<(), EvalError> {
-        argument_registers.d[0] = self.0;
-        argument_registers.d[1] = self.1;
-        Ok(())
+        FunctionSignatureList::Unspecified
     }
-    fn return_count() -> u16 {
-        2
+    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<u16, EvalError> {
+        let count = self.0.len() as u16;
+        for (pos, value) in self.0.into_iter().enumerate() {
+            argument_registers.d[pos] = value;
+        }
+        Ok(count)
     }
 }
 
+macro_rules! impl_return_representable_tuple {
+    ($($pos: literal => $generic: ident),+) => {
+        impl<$($generic: IntoLua,)+> ReturnRepresentable for ($($generic,)+) {
+            fn returns() -> FunctionSignatureList {
+                FunctionSignatureList::Finite(vec![ArgumentType::Dynamic; [$($pos),+].len()])
+            }
+            #[allow(non_snake_case)]
+            fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<u16, EvalError> {
+                let ($($generic,)+) = self;
+                $(argument_registers.d[$pos] = $generic.into_lua();)+
+                Ok([$($pos),+].len() as u16)
+            }
+        }
+    };
+}
+
+impl_return_representable_tuple!(0 => A, 1 => B);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K);
+impl_return_representable_tuple!(0 => A, 1 => B, 2 => C, 3 => D, 4 => E, 5 => F, 6 => G, 7 => H, 8 => I, 9 => J, 10 => K, 11 => L);
+
 impl<T> ReturnRepresentable for Result<T, EvalError>
 where
     T: ReturnRepresentable,
@@ -57,12 +82,9 @@ where
     fn returns() -> FunctionSignatureList {
         T::returns()
     }
-    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<(), EvalError> {
+    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<u16, EvalError> {
         self.and_then(|value| value.to_lua_return(argument_registers))
     }
-    fn return_count() -> u16 {
-        T::return_count()
-    }
 }
 
 impl<T> ReturnRepresentable for Result<T, TypeError>
@@ -72,11 +94,8 @@ where
     fn returns() -> FunctionSignatureList {
         T::returns()
     }
-    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<(), EvalError> {
+    fn to_lua_return(self, argument_registers: &mut ArgumentRegisters) -> Result<u16, EvalError> {
         self.map_err(EvalError::from)
             .and_then(|value| value.to_lua_return(argument_registers))
     }
-    fn return_count() -> u16 {
-        T::return_count()
-    }
 }
@@ -1,4 +1,4 @@
-use crate::LuaValue;
+use crate::{LuaString, LuaValue, NativeFunction, TableRef};
 
 pub trait SizedValue {
     const COUNT: u16;
@@ -21,19 +21,37 @@ impl SizedValue for () {
     const COUNT: u16 = 0;
 }
 
-impl SizedValue for LuaValue {
-    const COUNT: u16 = 1;
+macro_rules! impl_single_value {
+    ($($type: ty),+) => {
+        $(impl SizedValue for $type {
+            const COUNT: u16 = 1;
+        })+
+    };
 }
 
-impl<'a, T: SizedValue> SizedValue for &'a T {
-    const COUNT: u16 = T::COUNT;
+impl_single_value!(LuaValue, bool, i32, i64, f64, String, LuaString, TableRef, NativeFunction);
+
+impl<T> SizedValue for Option<T> {
+    const COUNT: u16 = 1;
 }
 
-impl SizedValue for bool {
+impl<T> SizedValue for Vec<T> {
     const COUNT: u16 = 1;
 }
 
+impl<'a, T: SizedValue> SizedValue for &'a T {
+    const COUNT: u16 = T::COUNT;
+}
+
 impl_return_size_tuple! { A }
 impl_return_size_tuple! { A B }
 impl_return_size_tuple! { A B C }
 impl_return_size_tuple! { A B C D }
+impl_return_size_tuple! { A B C D E }
+impl_return_size_tuple! { A B C D E F }
+impl_return_size_tuple! { A B C D E F G }
+impl_return_size_tuple! { A B C D E F G H }
+impl_return_size_tuple! { A B C D E F G H I }
+impl_return_size_tuple! { A B C D E F G H I J }
+impl_return_size_tuple! { A B C D E F G H I J K }
+impl_return_size_tuple! { A B C D E F G H I J K L }
@@ -0,0 +1,26 @@
+use std::task::Poll;
+
+use crate::{EvalError, LuaValue, Machine, MaybeSend, ReturnRepresentable};
+
+/// Calling convention of native functions, which may not have their result ready. Pending function
+/// suspends the [`Execution`](crate::Execution), that called it, until the host resolves it with
+/// the values to return. Ready function returns the count of its values.
+pub trait SuspendingFunctionCallable: MaybeSend {
+    fn call(&self, machine: &mut Machine, arguments: &[LuaValue]) -> Result<Poll<u16>, EvalError>;
+}
+
+impl<F, Ret> SuspendingFunctionCallable for F
+where
+    F: Fn(&mut Machine, &[LuaValue]) -> Poll<Ret> + MaybeSend,
+    Ret: ReturnRepresentable,
+{
+    fn call(&self, machine: &mut Machine, arguments: &[LuaValue]) -> Result<Poll<u16>, EvalError> {
+        match self(machine, arguments) {
+            Poll::Ready(ret) => ret
+                .to_lua_return(&mut machine.argument_registers)
+                .map(Poll::Ready),
+            Poll::Pending => Ok(Poll::Pending),
+        }
+    }
+
+}
@@ -2,9 +2,9 @@ use std::{cmp::Ordering, rc::Rc};
 
 use luar_string::{lua_format, LuaString};
 
-use crate::{eq_with_nan::eq_with_nan, ids::BlockID};
+use crate::{eq_with_nan::eq_with_nan, ids::BlockID, MaybeSend};
 
-use super::{FFIFunc, FromArgs, NativeFunction, TableRef};
+use super::{FFIFunc, FromArgs, NativeFunction, ReentrantFunctionCallable, TableRef};
 
 #[derive(Debug, Clone)]
 pub enum WideLuaValue {
@@ -99,12 +99,20 @@ impl WideLuaValue {
 
     pub fn function<'a, F, Args>(func: F) -> Self
     where
-        F: FFIFunc<Args> + 'static,
+        F: FFIFunc<Args> + MaybeSend + 'static,
         Args: FromArgs<'a> + 'static,
     {
         Self::NativeFunction(NativeFunction::new(func))
     }
 
+    /// Native function, which can call back into lua, see [`NativeFunction::reentrant`].
+    pub fn reentrant_function<F>(func: F) -> Self
+    where
+        F: ReentrantFunctionCallable + 'static,
+    {
+        Self::NativeFunction(NativeFunction::reentrant(func))
+    }
+
     pub fn int<T>(int: T) -> Self
     where
         T: TryInto<i32>,
@@ -117,7 +125,7 @@ impl WideLuaValue {
         match self {
             Self::Int(int) => Some(*int as f64),
             Self::Float(float) => Some(*float),
-            Self::String(str) => str.parse().ok(),
+            Self::String(str) => str.trim_ascii().parse().ok(),
             _ => None,
         }
     }
@@ -126,7 +134,7 @@ impl WideLuaValue {
         match self {
             Self::Int(int) => Some(*int),
             Self::Float(float) => Some(*float as i32),
-            Self::String(str) => str.parse().ok(),
+            Self::String(str) => str.trim_ascii().parse().ok(),
             _ => None,
         }
     }
@@ -135,7 +143,7 @@ impl WideLuaValue {
         match self {
             Self::Int(int) => Some(*int as usize),
             Self::Float(float) => Some(*float as usize),
-            Self::String(str) => str.parse().ok(),
+            Self::String(str) => str.trim_ascii().parse().ok(),
             _ => None,
         }
     }
@@ -0,0 +1,210 @@
+//! Derives `reggie::FromLua` and `reggie::IntoLua`, which convert structs to and from lua tables.
+//!
+//! Named fields are stored under their names as string keys, raw identifiers without the `r#`
+//! prefix, and the fields of tuple structs in the array part of the table. Fields are converted
+//! with their own `FromLua` and `IntoLua`, so `Option<T>` fields are nil when `None`, `Vec<T>`
+//! fields are sequences and nested structs are nested tables. A field, which fails to convert, is
+//! named in the error. Enums, which only have unit variants, are converted to and from the names
+//! of the variants.
+//!
+//! Arguments and returns of native functions are built on these two traits, so a derived type
+//! can be taken and returned by a native function as is.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{ext::IdentExt, parse_macro_input, Data, DataEnum, DeriveInput, Fields, Index};
+
+#[proc_macro_derive(FromLua)]
+pub fn derive_from_lua(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_lua(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(IntoLua)]
+pub fn derive_into_lua(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_into_lua(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_from_lua(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    reject_generics(input)?;
+    let name = &input.ident;
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let construct = match &data.fields {
+                Fields::Named(fields) => {
+                    let fields = fields.named.iter().map(|field| {
+                        let ident = field.ident.as_ref().expect("named fields have names");
+                        let key = ident.unraw().to_string();
+                        let value = quote! { table.get(&::reggie::LuaKey::string(#key)) };
+                        let field = convert_field(&key, value);
+                        quote! { #ident: #field }
+                    });
+                    quote! { Self { #(#fields,)* } }
+                }
+                Fields::Unnamed(fields) => {
+                    let fields = (1..=fields.unnamed.len()).map(|index| {
+                        let name = index.to_string();
+                        let index = index as i32;
+                        convert_field(&name, quote! { table.get(&::reggie::LuaKey::Int(#index)) })
+                    });
+                    quote! { Self(#(#fields,)*) }
+                }
+                Fields::Unit => quote! { Self },
+            };
+            if data.fields.is_empty() {
+                quote! {
+                    value
+                        .as_table()
+                        .map(|_| #construct)
+                        .ok_or(::reggie::ExpectedType::Table)
+                }
+            } else {
+                quote! {
+                    let table = value.as_table().ok_or(::reggie::ExpectedType::Table)?;
+                    Ok(#construct)
+                }
+            }
+        }
+        Data::Enum(data) => {
+            let variants = unit_variants(data)?;
+            let names = variants.iter().map(|variant| variant.unraw().to_string());
+            quote! {
+                match value.as_str() {
+                    #(Some(#names) => Ok(Self::#variants),)*
+                    _ => Err(::reggie::ExpectedType::String),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "FromLua can't be derived for unions",
+            ));
+        }
+    };
+    Ok(quote! {
+        impl<'lua> ::reggie::FromLua<'lua> for #name {
+            fn from_lua(
+                value: &'lua ::reggie::LuaValue,
+            ) -> ::std::result::Result<Self, ::reggie::ExpectedType> {
+                #body
+            }
+        }
+
+        impl ::reggie::SizedValue for #name {
+            const COUNT: u16 = 1;
+        }
+    })
+}
+
+fn expand_into_lua(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    reject_generics(input)?;
+    let name = &input.ident;
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let entries: Vec<_> = match &data.fields {
+                Fields::Named(fields) => fields
+                    .named
+                    .iter()
+                    .map(|field| {
+                        let ident = field.ident.as_ref().expect("named fields have names");
+                        let key = ident.unraw().to_string();
+                        (
+                            quote! { ::reggie::LuaKey::string(#key) },
+                            quote! { self.#ident },
+                        )
+                    })
+                    .collect(),
+                Fields::Unnamed(fields) => (0..fields.unnamed.len())
+                    .map(|index| {
+                        let key = index as i32 + 1;
+                        let index = Index::from(index);
+                        (
+                            quote! { ::reggie::LuaKey::Int(#key) },
+                            quote! { self.#index },
+                        )
+                    })
+                    .collect(),
+                Fields::Unit => Vec::new(),
+            };
+            // Nil fields are left out, as they would take up space in the table
+            let entries = entries.into_iter().map(|(key, field)| {
+                quote! {
+                    let value = ::reggie::IntoLua::into_lua(#field);
+                    if !value.is_nil() {
+                        table.set(#key, value);
+                    }
+                }
+            });
+            quote! {
+                let mut table = ::reggie::TableValue::new();
+                #(#entries)*
+                ::reggie::LuaValue::table(::reggie::TableRef::from(table))
+            }
+        }
+        Data::Enum(data) => {
+            let variants = unit_variants(data)?;
+            let names = variants.iter().map(|variant| variant.unraw().to_string());
+            quote! {
+                match self {
+                    #(Self::#variants => ::reggie::LuaValue::string(#names),)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "IntoLua can't be derived for unions",
+            ));
+        }
+    };
+    Ok(quote! {
+        impl ::reggie::IntoLua for #name {
+            fn into_lua(self) -> ::reggie::LuaValue {
+                #body
+            }
+        }
+    })
+}
+
+/// Converts the value of a field, naming the field in the error, since the value of a mismatched
+/// field is not reported.
+fn convert_field(name: &str, value: TokenStream2) -> TokenStream2 {
+    quote! {
+        ::reggie::FromLua::from_lua(&#value).map_err(|expected| ::reggie::ExpectedType::Field {
+            name: #name,
+            expected: ::std::boxed::Box::new(expected),
+        })?
+    }
+}
+
+/// Fields of a generic type would need bounds on every parameter, which is not worth it yet.
+fn reject_generics(input: &DeriveInput) -> syn::Result<()> {
+    if input.generics.params.is_empty() {
+        Ok(())
+    } else {
+        Err(syn::Error::new_spanned(
+            &input.generics,
+            "lua conversions can't be derived for generic types",
+        ))
+    }
+}
+
+fn unit_variants(data: &DataEnum) -> syn::Result<Vec<&syn::Ident>> {
+    data.variants
+        .iter()
+        .map(|variant| match variant.fields {
+            Fields::Unit => Ok(&variant.ident),
+            _ => Err(syn::Error::new_spanned(
+                variant,
+                "lua conversions can only be derived for enums with unit variants",
+            )),
+        })
+        .collect()
+}
@@ -38,6 +38,28 @@ struct SharedString {
     data: str,
 }
 
+impl LuaString {
+    /// Checks if both strings point to the same allocation. Short strings
+    /// are stored inline, so they are compared by contents. Equal strings
+    /// can still be allocated separately, so `false` doesn't mean they differ.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        let len = self.len;
+        if len != other.len {
+            return false;
+        }
+        // SAFETY: Strings of the same length use the same variant of the
+        //         storage, and inline data is zero padded.
+        unsafe {
+            if len <= INLINE_BUFFER_SIZE as u32 {
+                self.ptr_or_inline_data.inline_data == other.ptr_or_inline_data.inline_data
+            } else {
+                self.ptr_or_inline_data.heap_allocation
+                    == other.ptr_or_inline_data.heap_allocation
+            }
+        }
+    }
+}
+
 impl Default for LuaString {
     fn default() -> Self {
         Self {
@@ -37,19 +37,23 @@ impl ToTokenStream for Expression {
             String(literal) => Box::new(literal.to_tokens()),
             Number(literal) => Box::new(literal.to_tokens()),
             Variable(var) => var.to_tokens(),
-            BinaryOperator { lhs, op, rhs } => Box::new(
-                iter::once(Token::OpenRoundBracket)
-                    .chain(lhs.to_tokens())
-                    .chain(op.to_tokens())
-                    .chain(rhs.to_tokens())
-                    .chain(iter::once(Token::CloseRoundBracket)),
-            ),
-            UnaryOperator { op, exp } => Box::new(
-                iter::once(Token::OpenRoundBracket)
-                    .chain(op.to_tokens())
-                    .chain(exp.to_tokens())
-                    .chain(iter::once(Token::CloseRoundBracket)),
-            ),
+            BinaryOperator { lhs, op, rhs } => {
+                let (lhs_precedence, rhs_precedence) = if op.is_right_associative() {
+                    (ATOM_PRECEDENCE, op.precedence())
+                } else {
+                    (op.precedence(), op.precedence() + 1)
+                };
+                Box::new(
+                    operand_tokens(*lhs, lhs_precedence, false)
+                        .chain(op.to_tokens())
+                        .chain(operand_tokens(*rhs, rhs_precedence, true)),
+                )
+            }
+            UnaryOperator { op, exp } => Box::new(op.to_tokens().chain(operand_tokens(
+                *exp,
+                self::UnaryOperator::PRECEDENCE + 1,
+                true,
+            ))),
             TableConstructor(constructor) => constructor.to_tokens(),
             FunctionCall(func) => func.to_tokens(),
         }
@@ -58,6 +62,37 @@ impl ToTokenStream for Expression {
 
 fmt_tokens!(Expression);
 
+/// Precedence of everything that is not an operator application, i.e. things that never need to
+/// be parenthesized.
+const ATOM_PRECEDENCE: u8 = 7;
+
+impl Expression {
+    /// How tightly does expression bind, as understood by the parser. See [`BinaryOperator::precedence`]
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Expression::BinaryOperator { op, .. } => op.precedence(),
+            Expression::UnaryOperator { .. } => self::UnaryOperator::PRECEDENCE,
+            _ => ATOM_PRECEDENCE,
+        }
+    }
+}
+
+/// Emits operand of an operator, wrapping it in parens only when the parser would otherwise
+/// associate it differently. Prefix operators can start any operand to the right of an operator
+/// without the need for parens, hence `allow_unary`.
+fn operand_tokens(operand: Expression, min_precedence: u8, allow_unary: bool) -> DynTokens {
+    let is_unary = matches!(operand, Expression::UnaryOperator { .. });
+    if operand.precedence() >= min_precedence || (allow_unary && is_unary) {
+        operand.to_tokens()
+    } else {
+        Box::new(
+            iter::once(Token::OpenRoundBracket)
+                .chain(operand.to_tokens())
+                .chain(iter::once(Token::CloseRoundBracket)),
+        )
+    }
+}
+
 #[cfg(feature = "quickcheck")]
 use quickcheck::{empty_shrinker, Arbitrary, Gen};
 #[cfg(feature = "quickcheck")]
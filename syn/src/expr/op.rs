@@ -22,7 +22,7 @@ pub enum BinaryOperator {
     // Precedence level 4
     Mul,
     Div,
-    // Precedence level 5
+    // Precedence level 6 (level 5 is occupied by unary operators)
     Exp,
 }
 
@@ -32,6 +32,28 @@ pub enum UnaryOperator {
     Not,
 }
 
+impl BinaryOperator {
+    pub fn precedence(self) -> u8 {
+        use BinaryOperator::*;
+        match self {
+            And | Or => 0,
+            Less | Greater | LessOrEquals | GreaterOrEquals | NotEquals | Equals => 1,
+            Concat => 2,
+            Plus | Minus => 3,
+            Mul | Div => 4,
+            Exp => 6,
+        }
+    }
+
+    pub fn is_right_associative(self) -> bool {
+        self == BinaryOperator::Exp
+    }
+}
+
+impl UnaryOperator {
+    pub const PRECEDENCE: u8 = 5;
+}
+
 impl ToTokenStream for BinaryOperator {
     type Tokens = iter::Once<Token>;
     fn to_tokens(self) -> Self::Tokens {
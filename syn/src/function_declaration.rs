@@ -1,12 +1,6 @@
-use std::{
-    fmt::Display,
-    iter::{Chain, Flatten, Once},
-};
+use std::iter::{Chain, Flatten, Once};
 
-use luar_lex::{
-    format::{format_single_token, format_tokens, FormattingStyle, IndentationChange},
-    DynTokens, Ident, ToTokenStream, Token,
-};
+use luar_lex::{fmt_tokens, DynTokens, Ident, ToTokenStream, Token};
 
 use crate::flat_intersperse::FlatIntersperseExt;
 
@@ -73,20 +67,7 @@ impl ToTokenStream for FunctionName {
     }
 }
 
-impl Display for FunctionDeclaration {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self { name, args, body } = self.clone();
-        format_tokens(&mut header_tokens(name, args), f)?;
-
-        let mut indent = 0;
-        let mut current_format = FormattingStyle::Indent(IndentationChange::Increase);
-        for token in body.to_tokens() {
-            format_single_token(token, &mut indent, &mut current_format, f)?;
-        }
-        format_single_token(Token::End, &mut indent, &mut current_format, f)?;
-        Ok(())
-    }
-}
+fmt_tokens!(FunctionDeclaration);
 
 #[cfg(feature = "quickcheck")]
 use quickcheck::{Arbitrary, Gen};
@@ -238,9 +219,9 @@ mod test {
             indoc! {"
                 function foo.bar:baz(self, x, y)
                     if self.condition then
-                        return (x - y)
+                        return x - y
                     end
-                    return (x + y)
+                    return x + y
                 end"}
         )
     }
@@ -34,6 +34,17 @@ pub use ret::*;
 pub mod module;
 pub use module::*;
 
+pub mod recovery;
+pub use recovery::*;
+
+pub mod lines;
+pub use lines::*;
+
+#[cfg(feature = "quickcheck")]
+pub mod well_formed;
+#[cfg(feature = "quickcheck")]
+pub use well_formed::*;
+
 #[derive(Debug, PartialEq, Clone)]
 enum VarLeftover {
     Nothing,
@@ -97,6 +108,20 @@ pub struct ParseErrorWithSourcePosition {
     end: Option<SourcePosition>,
 }
 
+impl ParseErrorWithSourcePosition {
+    pub fn expected(&self) -> &ExpectedSet {
+        &self.expected
+    }
+
+    pub fn start(&self) -> Option<SourcePosition> {
+        self.start
+    }
+
+    pub fn end(&self) -> Option<SourcePosition> {
+        self.end
+    }
+}
+
 pub(crate) fn enrich_error(source: &str, raw_error: RawParseError) -> ParseErrorWithSourcePosition {
     let RawParseError { expected, location } = raw_error;
     match location {
@@ -149,6 +174,13 @@ pub mod lua_parser {
     forward!(repeat_loop, super::RepeatLoop);
     forward!(while_loop, super::WhileLoop);
     forward!(module, super::Module);
+
+    /// Unlike [`module`], does not stop at the first syntax error. See [`super::RecoveredModule`]
+    pub fn module_recovering(input: &str) -> super::RecoveredModule {
+        use logos::Logos;
+        let tokens: crate::TokenStream = luar_lex::Token::lexer(input).spanned().collect();
+        crate::recovery::recover_module(&tokens, input)
+    }
 }
 
 pub mod unspanned_lua_token_parser {
@@ -572,6 +604,25 @@ peg::parser! {
                 Module { chunks, ret }
             }
 
+        #[no_eof]
+        pub rule chunk_at(start: usize) -> (Chunk, usize)
+            = ##seek(start) chunk:chunk() end:position!() { (chunk, end) }
+
+        #[no_eof]
+        pub rule statement_at(start: usize) -> (Statement, usize)
+            = ##seek(start) statement:statement() end:position!() { (statement, end) }
+
+        #[no_eof]
+        pub rule expression_at(start: usize) -> (Expression, usize)
+            = ##seek(start) expression:expression() end:position!() { (expression, end) }
+
+        #[no_eof]
+        pub rule ret_at(start: usize) -> (Return, usize)
+            = ##seek(start) ret:ret() end:position!() { (ret, end) }
+
+        pub rule eof_at(start: usize)
+            = ##seek(start)
+
         rule chunk() -> Chunk
             = statement:statement() { Chunk::Statement(statement) }
             / decl:function_declaration() { Chunk::FnDecl(decl) }
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use logos::Logos;
+use luar_lex::Token;
+
+use crate::{
+    lua_token_parser, Block, Chunk, Conditional, ConditionalTail, FunctionDeclaration, Module,
+    Return, Statement, TokenSpan, TokenStream,
+};
+
+/// Lines of the statements, returns and function declarations of a parsed module.
+///
+/// Syntax tree doesn't keep track of where its nodes came from, so the lines are recovered by
+/// parsing the source once more, statement by statement, alongside the tree. Nodes are identified
+/// by their address, so the lines are only valid for the very module they were computed for, and
+/// only as long as it is not modified.
+#[derive(Debug, Clone, Default)]
+pub struct SourceLines {
+    statements: HashMap<usize, u32>,
+    returns: HashMap<usize, u32>,
+    functions: HashMap<usize, u32>,
+}
+
+impl SourceLines {
+    /// Lines are 1-based. If the module was not parsed from `source`, lines are only found for
+    /// the statements, which come before the first mismatch.
+    pub fn new(source: &str, module: &Module) -> Self {
+        let tokens: TokenStream = Token::lexer(source).spanned().collect();
+        let mut walker = Walker {
+            tokens: &tokens,
+            line_starts: std::iter::once(0)
+                .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+                .collect(),
+            lines: SourceLines::default(),
+        };
+        walker.module(module);
+        walker.lines
+    }
+
+    pub fn statement_line(&self, statement: &Statement) -> Option<u32> {
+        self.statements.get(&address_of(statement)).copied()
+    }
+
+    pub fn return_line(&self, ret: &Return) -> Option<u32> {
+        self.returns.get(&address_of(ret)).copied()
+    }
+
+    pub fn function_line(&self, decl: &FunctionDeclaration) -> Option<u32> {
+        self.functions.get(&address_of(decl)).copied()
+    }
+}
+
+fn address_of<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+struct Walker<'a> {
+    tokens: &'a TokenStream,
+    /// Byte offsets, at which the lines of the source start
+    line_starts: Vec<usize>,
+    lines: SourceLines,
+}
+
+/// Every method takes the position of the first token of the node, and returns the position right
+/// after it, if the source matches the node.
+impl<'a> Walker<'a> {
+    fn module(&mut self, module: &Module) -> Option<()> {
+        let mut pos = 0;
+        for chunk in &module.chunks {
+            let (_, end) = lua_token_parser::chunk_at(self.tokens, pos).ok()?;
+            match chunk {
+                Chunk::Statement(statement) => self.statement(statement, pos)?,
+                Chunk::FnDecl(decl) => self.function_declaration(decl, pos)?,
+            };
+            pos = end;
+        }
+        if let Some(ret) = &module.ret {
+            self.ret(ret, pos)?;
+        }
+        Some(())
+    }
+
+    fn function_declaration(&mut self, decl: &FunctionDeclaration, pos: usize) -> Option<usize> {
+        let line = self.line_of(pos)?;
+        self.lines.functions.insert(address_of(decl), line);
+        // Arguments are plain identifiers, so the first closing bracket ends them
+        let args_end = self.tokens.as_slice()[pos..]
+            .iter()
+            .position(|(token, _)| *token == Token::CloseRoundBracket)?;
+        let end = self.block(&decl.body, pos + args_end + 1)?;
+        Some(end + 1)
+    }
+
+    fn block(&mut self, block: &Block, mut pos: usize) -> Option<usize> {
+        for statement in &block.statements {
+            pos = self.statement(statement, pos)?;
+        }
+        if let Some(ret) = &block.ret {
+            pos = self.ret(ret, pos)?;
+        }
+        Some(pos)
+    }
+
+    fn statement(&mut self, statement: &Statement, pos: usize) -> Option<usize> {
+        let (_, end) = lua_token_parser::statement_at(self.tokens, pos).ok()?;
+        let line = self.line_of(pos)?;
+        self.lines.statements.insert(address_of(statement), line);
+        match statement {
+            Statement::While(while_loop) => {
+                let (_, condition_end) =
+                    lua_token_parser::expression_at(self.tokens, pos + 1).ok()?;
+                self.block(&while_loop.body, condition_end + 1)?;
+            }
+            Statement::Repeat(repeat_loop) => {
+                self.block(&repeat_loop.body, pos + 1)?;
+            }
+            Statement::If(conditional) => {
+                self.conditional(conditional, pos)?;
+            }
+            Statement::Assignment(_)
+            | Statement::LocalDeclaration(_)
+            | Statement::FunctionCall(_) => {}
+        }
+        Some(end)
+    }
+
+    /// Starts at either `if` or `elseif`.
+    fn conditional(&mut self, conditional: &Conditional, pos: usize) -> Option<usize> {
+        let (_, condition_end) = lua_token_parser::expression_at(self.tokens, pos + 1).ok()?;
+        let body_end = self.block(&conditional.body, condition_end + 1)?;
+        match &conditional.tail {
+            ConditionalTail::End => Some(body_end + 1),
+            ConditionalTail::Else(body) => Some(self.block(body, body_end + 1)? + 1),
+            ConditionalTail::ElseIf(conditional) => self.conditional(conditional, body_end),
+        }
+    }
+
+    fn ret(&mut self, ret: &Return, pos: usize) -> Option<usize> {
+        let (_, end) = lua_token_parser::ret_at(self.tokens, pos).ok()?;
+        let line = self.line_of(pos)?;
+        self.lines.returns.insert(address_of(ret), line);
+        Some(end)
+    }
+
+    fn line_of(&self, pos: usize) -> Option<u32> {
+        let (_, TokenSpan::SourceByteSpan { start, .. }) = self.tokens.as_slice().get(pos)? else {
+            return None;
+        };
+        let line = self
+            .line_starts
+            .partition_point(|line_start| line_start <= start);
+        line.try_into().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SourceLines;
+    use crate::{lua_parser, Chunk, ConditionalTail, Statement};
+
+    #[test]
+    fn statements_of_nested_blocks_get_their_lines() {
+        let source = "local a = 1
+function f(x)
+    while x do
+        x = nil
+    end
+    return x
+end
+if a then a = 2
+elseif f(a) then
+    a = 3
+else repeat
+    a = 4
+until a end
+return a";
+        let module = lua_parser::module(source).unwrap();
+        let lines = SourceLines::new(source, &module);
+
+        let Chunk::Statement(decl) = &module.chunks[0] else {
+            panic!("declaration is a statement")
+        };
+        assert_eq!(lines.statement_line(decl), Some(1));
+
+        let Chunk::FnDecl(function) = &module.chunks[1] else {
+            panic!("function declaration")
+        };
+        assert_eq!(lines.function_line(function), Some(2));
+        let Statement::While(while_loop) = &function.body.statements[0] else {
+            panic!("while loop")
+        };
+        assert_eq!(lines.statement_line(&function.body.statements[0]), Some(3));
+        assert_eq!(
+            lines.statement_line(&while_loop.body.statements[0]),
+            Some(4)
+        );
+        assert_eq!(
+            lines.return_line(function.body.ret.as_ref().unwrap()),
+            Some(6)
+        );
+
+        let Chunk::Statement(conditional @ Statement::If(if_statement)) = &module.chunks[2] else {
+            panic!("conditional")
+        };
+        assert_eq!(lines.statement_line(conditional), Some(8));
+        assert_eq!(
+            lines.statement_line(&if_statement.body.statements[0]),
+            Some(8)
+        );
+        let ConditionalTail::ElseIf(else_if) = &if_statement.tail else {
+            panic!("elseif")
+        };
+        assert_eq!(lines.statement_line(&else_if.body.statements[0]), Some(10));
+        let ConditionalTail::Else(else_body) = &else_if.tail else {
+            panic!("else")
+        };
+        let Statement::Repeat(repeat_loop) = &else_body.statements[0] else {
+            panic!("repeat loop")
+        };
+        assert_eq!(lines.statement_line(&else_body.statements[0]), Some(11));
+        assert_eq!(
+            lines.statement_line(&repeat_loop.body.statements[0]),
+            Some(12)
+        );
+
+        assert_eq!(lines.return_line(module.ret.as_ref().unwrap()), Some(14));
+    }
+
+    #[test]
+    fn lines_are_not_found_for_other_modules() {
+        let source = "a = 1";
+        let module = lua_parser::module(source).unwrap();
+        let lines = SourceLines::new(source, &module);
+        let copy = module.clone();
+        assert_eq!(
+            lines.statement_line(copy.chunks[0].as_statement_ref().unwrap()),
+            None
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use luar_lex::Token;
+
+use crate::{
+    enrich_error, lua_token_parser, Chunk, Module, ParseErrorWithSourcePosition, RawParseError,
+    Return, TokenSpan, TokenStream,
+};
+
+/// A run of tokens that the recovering parser had to skip in order to get back on track after a
+/// syntax error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorNode {
+    pub tokens: Vec<Token>,
+    pub span: TokenSpan,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartialChunk {
+    Chunk(Chunk),
+    Error(ErrorNode),
+}
+
+/// Same as [`Module`], except that chunks which failed to parse are kept around as error nodes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PartialModule {
+    pub chunks: Vec<PartialChunk>,
+    pub ret: Option<Return>,
+}
+
+impl PartialModule {
+    pub fn has_errors(&self) -> bool {
+        self.chunks
+            .iter()
+            .any(|chunk| matches!(chunk, PartialChunk::Error(_)))
+    }
+
+    /// Drops all of the error nodes, leaving only chunks that were parsed successfully.
+    pub fn into_module(self) -> Module {
+        Module {
+            chunks: self
+                .chunks
+                .into_iter()
+                .filter_map(|chunk| match chunk {
+                    PartialChunk::Chunk(chunk) => Some(chunk),
+                    PartialChunk::Error(_) => None,
+                })
+                .collect(),
+            ret: self.ret,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RecoveredModule {
+    pub module: PartialModule,
+    /// Every syntax error encountered, in the order of appearance in the source
+    pub errors: Vec<ParseErrorWithSourcePosition>,
+}
+
+/// Parses module chunk by chunk. Whenever a chunk fails to parse, the error is recorded,
+/// tokens up to the next statement boundary are skipped, and parsing continues from there.
+///
+/// Statement boundaries are `;`, the `end`/`until` closing the broken statement, or one of the
+/// keywords that start a new statement (`local`, `function`, `if`, `while`, `repeat`, `return`)
+/// on the same nesting level as the broken statement.
+pub(crate) fn recover_module(tokens: &TokenStream, source: &str) -> RecoveredModule {
+    let stream = tokens.as_slice();
+    let mut module = PartialModule::default();
+    let mut errors = Vec::new();
+    let mut pos = 0;
+
+    while pos < stream.len() {
+        if module.ret.is_some() {
+            // Nothing is allowed after the module's return. Treat the rest as a single error
+            let error = lua_token_parser::eof_at(tokens, pos)
+                .expect_err("there are tokens left in the stream");
+            errors.push(enrich_error(source, error));
+            module
+                .chunks
+                .push(PartialChunk::Error(ErrorNode::new(&stream[pos..])));
+            break;
+        }
+
+        let error = match lua_token_parser::chunk_at(tokens, pos) {
+            Ok((chunk, end)) => {
+                module.chunks.push(PartialChunk::Chunk(chunk));
+                pos = end;
+                continue;
+            }
+            Err(error) if stream[pos].0 == Token::Return => {
+                match lua_token_parser::ret_at(tokens, pos) {
+                    Ok((ret, end)) => {
+                        module.ret = Some(ret);
+                        pos = end;
+                        continue;
+                    }
+                    Err(ret_error) => furthest(error, ret_error, stream),
+                }
+            }
+            Err(error) => error,
+        };
+
+        let resume_at = resynchronize(stream, pos, token_index(stream, &error));
+        errors.push(enrich_error(source, error));
+        module
+            .chunks
+            .push(PartialChunk::Error(ErrorNode::new(&stream[pos..resume_at])));
+        pos = resume_at;
+    }
+
+    RecoveredModule { module, errors }
+}
+
+impl ErrorNode {
+    fn new(skipped: &[(Token, TokenSpan)]) -> Self {
+        let span = match (skipped.first(), skipped.last()) {
+            (
+                Some((_, TokenSpan::SourceByteSpan { start, .. })),
+                Some((_, TokenSpan::SourceByteSpan { end, .. })),
+            ) => TokenSpan::SourceByteSpan {
+                start: *start,
+                end: *end,
+            },
+            (Some((_, span)), _) => *span,
+            (None, _) => TokenSpan::Unknown,
+        };
+        Self {
+            tokens: skipped.iter().map(|(token, _)| token.clone()).collect(),
+            span,
+        }
+    }
+}
+
+fn token_index(stream: &[(Token, TokenSpan)], error: &RawParseError) -> usize {
+    stream
+        .iter()
+        .position(|(_, span)| *span == error.location)
+        .unwrap_or(stream.len())
+}
+
+fn furthest(
+    lhs: RawParseError,
+    rhs: RawParseError,
+    stream: &[(Token, TokenSpan)],
+) -> RawParseError {
+    if token_index(stream, &rhs) > token_index(stream, &lhs) {
+        rhs
+    } else {
+        lhs
+    }
+}
+
+fn starts_statement(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Local | Token::Function | Token::If | Token::While | Token::Repeat | Token::Return
+    )
+}
+
+fn opens_block(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Function | Token::If | Token::While | Token::Repeat
+    )
+}
+
+fn closes_block(token: &Token) -> bool {
+    matches!(token, Token::End | Token::Until)
+}
+
+/// Returns the index of a token from which parsing should resume after the statement starting
+/// at `start` failed to parse at `error_at`. Always skips at least one token.
+fn resynchronize(stream: &[(Token, TokenSpan)], start: usize, error_at: usize) -> usize {
+    let error_at = error_at.clamp(start, stream.len());
+    let mut depth = stream[start..error_at]
+        .iter()
+        .fold(0usize, |depth, (token, _)| {
+            if opens_block(token) {
+                depth + 1
+            } else if closes_block(token) {
+                depth.saturating_sub(1)
+            } else {
+                depth
+            }
+        });
+
+    for (idx, (token, _)) in stream.iter().enumerate().skip(error_at) {
+        if depth == 0 && idx > start && starts_statement(token) {
+            return idx;
+        }
+        if opens_block(token) {
+            depth += 1;
+        } else if closes_block(token) {
+            if depth <= 1 {
+                return idx + 1;
+            }
+            depth -= 1;
+        } else if depth == 0 && *token == Token::Semicolon {
+            return idx + 1;
+        }
+    }
+    stream.len()
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use luar_lex::Token;
+
+    use crate::{lua_parser, PartialChunk, SourcePosition};
+
+    fn error_rows(source: &str) -> Vec<Option<usize>> {
+        lua_parser::module_recovering(source)
+            .errors
+            .iter()
+            .map(|error| error.start().map(|position| position.row))
+            .collect()
+    }
+
+    #[test]
+    fn valid_module_is_parsed_the_same_as_without_recovery() {
+        let source = indoc! {"
+            function fib(n)
+                if n < 2 then
+                    return n
+                end
+                return fib(n - 1) + fib(n - 2)
+            end
+            local a, b = 1, 2
+            while a < 10 do a = a + b end
+            return fib(a)
+        "};
+        let recovered = lua_parser::module_recovering(source);
+        assert!(recovered.errors.is_empty());
+        assert!(!recovered.module.has_errors());
+        assert_eq!(
+            recovered.module.into_module(),
+            lua_parser::module(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_every_broken_statement() {
+        let source = indoc! {"
+            local a = 1
+            local = 2
+            local c = 3
+            if then
+                c = 4
+            end
+            d = )
+            return a
+        "};
+        let recovered = lua_parser::module_recovering(source);
+        assert_eq!(error_rows(source), vec![Some(1), Some(3), Some(6)]);
+
+        let module = recovered.module.into_module();
+        assert_eq!(module.chunks.len(), 2);
+        assert_eq!(
+            module,
+            lua_parser::module("local a = 1 local c = 3 return a").unwrap()
+        );
+    }
+
+    #[test]
+    fn resynchronizes_at_the_end_of_broken_function() {
+        let source = indoc! {"
+            function broken()
+                if a then
+                    b = = 3
+                end
+                while 1 do end
+            end
+            function fine()
+                return 42
+            end
+        "};
+        let recovered = lua_parser::module_recovering(source);
+        assert_eq!(recovered.errors.len(), 1);
+        assert_eq!(
+            recovered.errors[0].start(),
+            Some(SourcePosition { row: 2, col: 12 })
+        );
+        assert!(matches!(
+            &recovered.module.chunks[..],
+            [PartialChunk::Error(_), PartialChunk::Chunk(_)]
+        ));
+    }
+
+    #[test]
+    fn skips_stray_end() {
+        let source = "a = 1 end b = 2";
+        let recovered = lua_parser::module_recovering(source);
+        assert_eq!(recovered.errors.len(), 1);
+        match &recovered.module.chunks[..] {
+            [PartialChunk::Chunk(_), PartialChunk::Error(node), PartialChunk::Chunk(_)] => {
+                assert_eq!(node.tokens, vec![Token::End])
+            }
+            chunks => panic!("Unexpected chunks {:?}", chunks),
+        }
+    }
+
+    #[test]
+    fn resynchronizes_at_semicolon() {
+        let source = "a = ; b = 2";
+        let recovered = lua_parser::module_recovering(source);
+        assert_eq!(recovered.errors.len(), 1);
+        assert_eq!(
+            recovered.module.into_module(),
+            lua_parser::module("b = 2").unwrap()
+        );
+    }
+
+    #[test]
+    fn everything_after_return_is_a_single_error() {
+        let source = indoc! {"
+            return 1
+            a = 2
+            b = = 3
+        "};
+        let recovered = lua_parser::module_recovering(source);
+        assert_eq!(error_rows(source), vec![Some(1)]);
+        assert!(recovered.module.ret.is_some());
+    }
+
+    #[test]
+    fn unterminated_block_swallows_the_rest_of_the_file() {
+        let source = indoc! {"
+            a = 1
+            while a do
+                a = nil
+        "};
+        let recovered = lua_parser::module_recovering(source);
+        assert_eq!(recovered.errors.len(), 1);
+        assert!(matches!(
+            &recovered.module.chunks[..],
+            [PartialChunk::Chunk(_), PartialChunk::Error(_)]
+        ));
+    }
+}
@@ -26,8 +26,8 @@ impl std::fmt::Display for TokenSpan {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SourcePosition {
-    col: usize,
-    row: usize,
+    pub col: usize,
+    pub row: usize,
 }
 
 // Source always in utf-8
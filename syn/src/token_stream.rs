@@ -40,6 +40,22 @@ pub trait ToTokenStreamExt: ToTokenStream {
 
 impl<T: ToTokenStream> ToTokenStreamExt for T {}
 
+impl TokenStream {
+    pub(crate) fn as_slice(&self) -> &[(Token, TokenSpan)] {
+        &self.0
+    }
+
+    /// Jumps straight to the `target` token. Used by the grammar (via `##seek`) to start parsing
+    /// from the middle of the stream without re-matching everything before it.
+    pub(crate) fn seek(&self, _pos: usize, target: usize) -> peg::RuleResult<()> {
+        if target <= self.0.len() {
+            peg::RuleResult::Matched(target, ())
+        } else {
+            peg::RuleResult::Failed
+        }
+    }
+}
+
 impl Parse for TokenStream {
     type PositionRepr = TokenSpan;
 
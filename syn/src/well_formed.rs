@@ -0,0 +1,502 @@
+use std::fmt;
+
+use luar_lex::{Ident, NumberLiteral, StringLiteral};
+use non_empty::NonEmptyVec;
+use quickcheck::{Arbitrary, Gen};
+use test_util::QUICKCHECK_RECURSIVE_DEPTH;
+
+use crate::{
+    Assignment, BinaryOperator, Block, Chunk, Conditional, ConditionalTail, Declaration,
+    Expression, FunctionCall, FunctionCallArgs, FunctionDeclaration, FunctionName, Module,
+    RepeatLoop, Return, Statement, TableConstructor, UnaryOperator, Var, WhileLoop,
+};
+
+/// Unlike `Module::arbitrary`, which produces random (albeit syntactically valid) trees,
+/// this one resembles an actual program. Functions are called with the number of arguments they
+/// are declared with, methods are declared on tables that exist, locals are referenced only in
+/// the scopes they are declared in, and so on.
+///
+/// `Debug` output is the source code of the module, to make counterexamples readable.
+#[derive(Clone, PartialEq)]
+pub struct WellFormedModule(pub Module);
+
+impl fmt::Debug for WellFormedModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "WellFormedModule:")?;
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Arbitrary for WellFormedModule {
+    fn arbitrary(g: &mut Gen) -> Self {
+        WellFormedModule(ModuleGenerator::new(g).module())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let module = self.0.clone();
+        let flattened = (0..module.chunks.len()).filter_map(move |idx| flatten_chunk(&module, idx));
+        Box::new(flattened.chain(self.0.shrink()).map(WellFormedModule))
+    }
+}
+
+/// Replaces function declaration or a compound statement with the statements of its body.
+/// Quickly gets rid of nesting, which the structural shrinking of `Module` does not do.
+fn flatten_chunk(module: &Module, idx: usize) -> Option<Module> {
+    let body = match &module.chunks[idx] {
+        Chunk::FnDecl(decl) => &decl.body,
+        Chunk::Statement(Statement::While(while_loop)) => &while_loop.body,
+        Chunk::Statement(Statement::Repeat(repeat_loop)) => &repeat_loop.body,
+        Chunk::Statement(Statement::If(conditional)) => &conditional.body,
+        _ => return None,
+    };
+    let statements: Vec<_> = body
+        .statements
+        .iter()
+        .cloned()
+        .map(Chunk::Statement)
+        .collect();
+    let mut module = module.clone();
+    module.chunks.splice(idx..=idx, statements);
+    Some(module)
+}
+
+const LOCAL_NAMES: [&str; 8] = ["a", "b", "i", "n", "acc", "tmp", "value", "count"];
+const FIELD_NAMES: [&str; 6] = ["x", "y", "name", "size", "next", "data"];
+const STRING_CHARS: [char; 16] = [
+    'a', 'b', 'z', 'A', 'Z', '0', '9', ' ', '_', '.', '!', '\n', '\t', '"', '\'', '\\',
+];
+
+fn choose<T: Clone>(g: &mut Gen, options: &[T]) -> T {
+    options[usize::arbitrary(g) % options.len()].clone()
+}
+
+struct Table {
+    name: Ident,
+    fields: Vec<Ident>,
+    /// Methods along with their arity (not counting `self`)
+    methods: Vec<(Ident, usize)>,
+}
+
+struct ModuleGenerator<'a> {
+    g: &'a mut Gen,
+    /// How many more levels of nested blocks are allowed
+    depth: usize,
+    name_counter: usize,
+    globals: Vec<Ident>,
+    functions: Vec<(Ident, usize)>,
+    tables: Vec<Table>,
+    scopes: Vec<Vec<Ident>>,
+    in_method: Option<usize>,
+}
+
+impl<'a> ModuleGenerator<'a> {
+    fn new(g: &'a mut Gen) -> Self {
+        Self {
+            depth: *QUICKCHECK_RECURSIVE_DEPTH,
+            g,
+            name_counter: 0,
+            globals: Vec::new(),
+            functions: Vec::new(),
+            tables: Vec::new(),
+            scopes: vec![Vec::new()],
+            in_method: None,
+        }
+    }
+
+    fn pick(&mut self, options: usize) -> usize {
+        usize::arbitrary(self.g) % options
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.pick(100) < percent
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> Ident {
+        self.name_counter += 1;
+        Ident::new(format!("{}{}", prefix, self.name_counter))
+    }
+
+    fn module(mut self) -> Module {
+        let chunk_count = self.pick(self.g.size().max(1)) + 1;
+        let mut chunks = Vec::with_capacity(chunk_count);
+        for _ in 0..chunk_count {
+            match self.pick(5) {
+                0 => chunks.push(Chunk::FnDecl(self.function())),
+                1 => chunks.extend(self.table_with_methods()),
+                _ => chunks.push(Chunk::Statement(self.statement())),
+            }
+        }
+        let ret = if self.chance(30) {
+            Some(self.ret())
+        } else {
+            None
+        };
+        Module { chunks, ret }
+    }
+
+    fn function(&mut self) -> FunctionDeclaration {
+        let name = self.fresh_name("fn");
+        let arity = self.pick(4);
+        // Registered before the body is generated, so that function can be recursive
+        self.functions.push((name.clone(), arity));
+        self.function_with_body(FunctionName::Plain(Var::Named(name)), arity, None)
+    }
+
+    fn function_with_body(
+        &mut self,
+        name: FunctionName,
+        arity: usize,
+        method_of: Option<usize>,
+    ) -> FunctionDeclaration {
+        let args: Vec<_> = (0..arity).map(|_| self.fresh_name("arg")).collect();
+        let mut scope = args.clone();
+        if method_of.is_some() {
+            scope.push(Ident::new("self"));
+        }
+        // Functions do not capture locals of the enclosing scope
+        let outer_scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        let outer_method = std::mem::replace(&mut self.in_method, method_of);
+        let body = self.block();
+        self.scopes = outer_scopes;
+        self.in_method = outer_method;
+        FunctionDeclaration { name, args, body }
+    }
+
+    fn table_with_methods(&mut self) -> Vec<Chunk> {
+        let name = self.fresh_name("obj");
+        let field_count = self.pick(FIELD_NAMES.len()) + 1;
+        let fields: Vec<_> = FIELD_NAMES[..field_count]
+            .iter()
+            .copied()
+            .map(Ident::new)
+            .collect();
+        let constructor = TableConstructor {
+            lfield: vec![],
+            ffield: fields
+                .iter()
+                .map(|field| (field.clone(), self.expression(1)))
+                .collect(),
+        };
+        let mut chunks = vec![Chunk::Statement(Statement::Assignment(Assignment {
+            names: NonEmptyVec::of_single(Var::Named(name.clone())),
+            values: NonEmptyVec::of_single(Expression::TableConstructor(constructor)),
+        }))];
+        self.globals.push(name.clone());
+        self.tables.push(Table {
+            name: name.clone(),
+            fields,
+            methods: vec![],
+        });
+        let table_idx = self.tables.len() - 1;
+
+        for _ in 0..self.pick(3) + 1 {
+            let method = self.fresh_name("method");
+            let arity = self.pick(3);
+            self.tables[table_idx].methods.push((method.clone(), arity));
+            let decl = if self.chance(75) {
+                let name = FunctionName::Method(Var::Named(name.clone()), method);
+                self.function_with_body(name, arity, Some(table_idx))
+            } else {
+                // Plain function stored in the table: `function obj.method(arg) ... end`
+                let var = Var::PropertyAccess {
+                    from: Box::new(Var::Named(name.clone())),
+                    property: method,
+                };
+                self.function_with_body(FunctionName::Plain(var), arity + 1, None)
+            };
+            chunks.push(Chunk::FnDecl(decl));
+        }
+        chunks
+    }
+
+    fn block(&mut self) -> Block {
+        self.depth = self.depth.saturating_sub(1);
+        self.scopes.push(Vec::new());
+        let statement_count = self.pick(4);
+        let statements = (0..statement_count).map(|_| self.statement()).collect();
+        let ret = if self.chance(25) {
+            Some(self.ret())
+        } else {
+            None
+        };
+        self.scopes.pop();
+        self.depth += 1;
+        Block { statements, ret }
+    }
+
+    fn ret(&mut self) -> Return {
+        let count = self.pick(3);
+        Return((0..count).map(|_| self.expression(2)).collect())
+    }
+
+    fn statement(&mut self) -> Statement {
+        let kinds = if self.depth > 0 { 8 } else { 4 };
+        match self.pick(kinds) {
+            0 => Statement::LocalDeclaration(self.local_declaration()),
+            1 => Statement::Assignment(self.assignment()),
+            2 => Statement::FunctionCall(self.function_call(2)),
+            3 => Statement::Assignment(self.field_assignment()),
+            4 | 5 => Statement::If(self.conditional(2)),
+            6 => Statement::While(WhileLoop {
+                condition: self.expression(2),
+                body: self.block(),
+            }),
+            7 => Statement::Repeat(RepeatLoop {
+                body: self.block(),
+                condition: self.expression(2),
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    fn local_declaration(&mut self) -> Declaration {
+        let name_count = self.pick(3) + 1;
+        let names: Vec<_> = (0..name_count)
+            .map(|_| {
+                let name = LOCAL_NAMES[self.pick(LOCAL_NAMES.len())];
+                Ident::new(name)
+            })
+            .collect();
+        let value_count = self.pick(name_count + 1);
+        let initial_values = (0..value_count).map(|_| self.expression(2)).collect();
+        // Values are evaluated before the names are in scope
+        self.scopes
+            .last_mut()
+            .expect("There is always at least one scope")
+            .extend(names.iter().cloned());
+        Declaration {
+            names: NonEmptyVec::new(names),
+            initial_values,
+        }
+    }
+
+    fn assignment(&mut self) -> Assignment {
+        let target_count = self.pick(2) + 1;
+        let names: Vec<_> = (0..target_count)
+            .map(|_| self.assignment_target())
+            .collect();
+        let value_count = self.pick(target_count + 1) + 1;
+        let values: Vec<_> = (0..value_count).map(|_| self.expression(2)).collect();
+        Assignment {
+            names: NonEmptyVec::new(names),
+            values: NonEmptyVec::new(values),
+        }
+    }
+
+    fn assignment_target(&mut self) -> Var {
+        let locals: Vec<_> = self.scopes.iter().flatten().cloned().collect();
+        match self.pick(3) {
+            0 if !locals.is_empty() => Var::Named(locals[self.pick(locals.len())].clone()),
+            1 if !self.globals.is_empty() => Var::Named(choose(self.g, &self.globals)),
+            _ => {
+                let name = self.fresh_name("global");
+                self.globals.push(name.clone());
+                Var::Named(name)
+            }
+        }
+    }
+
+    fn field_assignment(&mut self) -> Assignment {
+        let target = match self.readable_field() {
+            Some(field) => field,
+            None => return self.assignment(),
+        };
+        let target = if self.chance(30) {
+            let Var::PropertyAccess { from, .. } = target else {
+                unreachable!("readable_field always returns property access")
+            };
+            Var::MemberLookup {
+                from,
+                value: Box::new(self.expression(1)),
+            }
+        } else {
+            target
+        };
+        Assignment {
+            names: NonEmptyVec::of_single(target),
+            values: NonEmptyVec::of_single(self.expression(2)),
+        }
+    }
+
+    fn conditional(&mut self, max_tail: usize) -> Conditional {
+        let condition = self.expression(2);
+        let body = self.block();
+        let tail = match self.pick(3) {
+            0 if max_tail > 0 => ConditionalTail::ElseIf(Box::new(self.conditional(max_tail - 1))),
+            1 => ConditionalTail::Else(self.block()),
+            _ => ConditionalTail::End,
+        };
+        Conditional {
+            condition,
+            body,
+            tail,
+        }
+    }
+
+    fn function_call(&mut self, depth: usize) -> FunctionCall {
+        let with_methods: Vec<_> = (0..self.tables.len())
+            .filter(|&idx| !self.tables[idx].methods.is_empty())
+            .collect();
+        match self.pick(3) {
+            0 if !self.functions.is_empty() => {
+                let (name, arity) = choose(self.g, &self.functions);
+                FunctionCall::Function {
+                    func: Var::Named(name),
+                    args: self.call_args(arity, depth),
+                }
+            }
+            1 if !with_methods.is_empty() => {
+                let table = &self.tables[choose(self.g, &with_methods)];
+                let func = Var::Named(table.name.clone());
+                let (method, arity) = choose(self.g, &table.methods);
+                FunctionCall::Method {
+                    func,
+                    method,
+                    args: self.call_args(arity, depth),
+                }
+            }
+            _ => {
+                let arg_count = self.pick(4);
+                FunctionCall::Function {
+                    func: Var::Named(Ident::new("print")),
+                    args: self.call_args(arg_count, depth),
+                }
+            }
+        }
+    }
+
+    fn call_args(&mut self, arity: usize, depth: usize) -> FunctionCallArgs {
+        if arity == 1 && self.chance(20) {
+            FunctionCallArgs::Table(self.table_constructor(depth.saturating_sub(1)))
+        } else {
+            FunctionCallArgs::Arglist(
+                (0..arity)
+                    .map(|_| self.expression(depth.saturating_sub(1)))
+                    .collect(),
+            )
+        }
+    }
+
+    fn table_constructor(&mut self, depth: usize) -> TableConstructor {
+        let lfield = (0..self.pick(4)).map(|_| self.expression(depth)).collect();
+        let field_count = self.pick(3);
+        let ffield = FIELD_NAMES[..field_count]
+            .iter()
+            .map(|&field| (Ident::new(field), self.expression(depth)))
+            .collect();
+        TableConstructor { lfield, ffield }
+    }
+
+    fn readable_field(&mut self) -> Option<Var> {
+        let own_table = self.in_method.map(|idx| (Ident::new("self"), idx));
+        let candidates: Vec<_> = (0..self.tables.len())
+            .map(|idx| (self.tables[idx].name.clone(), idx))
+            .chain(own_table)
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let (from, table_idx) = candidates[self.pick(candidates.len())].clone();
+        let property = choose(self.g, &self.tables[table_idx].fields);
+        Some(Var::PropertyAccess {
+            from: Box::new(Var::Named(from)),
+            property,
+        })
+    }
+
+    fn readable_var(&mut self) -> Option<Var> {
+        let locals: Vec<_> = self.scopes.iter().flatten().cloned().collect();
+        match self.pick(3) {
+            0 if !locals.is_empty() => Some(Var::Named(locals[self.pick(locals.len())].clone())),
+            1 if !self.globals.is_empty() => Some(Var::Named(choose(self.g, &self.globals))),
+            _ => self.readable_field(),
+        }
+    }
+
+    fn expression(&mut self, depth: usize) -> Expression {
+        if depth == 0 {
+            return self.leaf_expression();
+        }
+        match self.pick(8) {
+            0 | 1 => self.leaf_expression(),
+            2 | 3 => Expression::BinaryOperator {
+                lhs: Box::new(self.expression(depth - 1)),
+                op: BinaryOperator::arbitrary(self.g),
+                rhs: Box::new(self.expression(depth - 1)),
+            },
+            4 => Expression::UnaryOperator {
+                op: UnaryOperator::arbitrary(self.g),
+                exp: Box::new(self.expression(depth - 1)),
+            },
+            5 => Expression::TableConstructor(self.table_constructor(depth - 1)),
+            6 => Expression::FunctionCall(self.function_call(depth - 1)),
+            7 => match self.readable_var() {
+                Some(from) => Expression::Variable(Var::MemberLookup {
+                    from: Box::new(from),
+                    value: Box::new(self.expression(depth - 1)),
+                }),
+                None => self.leaf_expression(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn leaf_expression(&mut self) -> Expression {
+        match self.pick(5) {
+            0 => Expression::Nil,
+            1 => Expression::Number(self.number()),
+            2 => Expression::String(self.string()),
+            _ => self
+                .readable_var()
+                .map(Expression::Variable)
+                .unwrap_or_else(|| Expression::Number(self.number())),
+        }
+    }
+
+    /// Numbers that survive a trip through their textual representation: integers and quarters
+    fn number(&mut self) -> NumberLiteral {
+        let whole = self.pick(1000) as f64;
+        let fraction = if self.chance(25) {
+            self.pick(4) as f64 / 4.0
+        } else {
+            0.0
+        };
+        NumberLiteral(whole + fraction)
+    }
+
+    fn string(&mut self) -> StringLiteral {
+        let len = self.pick(8);
+        StringLiteral(
+            (0..len)
+                .map(|_| STRING_CHARS[self.pick(STRING_CHARS.len())])
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::Arbitrary;
+
+    use super::WellFormedModule;
+    use crate::lua_parser;
+
+    #[quickcheck]
+    fn well_formed_module_round_trips_through_source_text(module: WellFormedModule) {
+        let WellFormedModule(module) = module;
+        let source = module.to_string();
+        let parsed = lua_parser::module(&source)
+            .unwrap_or_else(|err| panic!("Failed to parse:\n{}\n{:?}", source, err));
+        assert_eq!(parsed, module, "Source:\n{}", source);
+    }
+
+    #[quickcheck]
+    fn shrinks_to_smaller_modules(module: WellFormedModule) {
+        for shrunk in module.shrink().take(20) {
+            let WellFormedModule(shrunk) = shrunk;
+            assert_ne!(shrunk, module.0);
+            let source = shrunk.to_string();
+            assert_eq!(lua_parser::module(&source).ok(), Some(shrunk));
+        }
+    }
+}
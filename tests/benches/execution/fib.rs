@@ -38,8 +38,8 @@ macro_rules! fib_bench {
                 let mut machine = ::reggie::Machine::new();
                 let compiled_module =
                     ::reggie::compiler::compile_module(&module, &mut machine.global_values);
-                let top_level_block = machine.code_blocks.add_module(compiled_module);
-                machine.global_values.set("N", ::reggie::LuaValue::Int(*i));
+                let top_level_block = machine.add_module(compiled_module);
+                machine.global_values.set("N", ::reggie::LuaValue::int(*i));
 
                 b.iter(|| {
                     ::reggie::call_block::<()>(top_level_block, &mut machine).unwrap();
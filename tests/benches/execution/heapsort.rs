@@ -31,7 +31,7 @@ fn random_mlua_tbl(lua: &mlua::Lua, size: usize) -> mlua::Table {
 fn random_reggie_tbl(size: usize) -> reggie::TableValue {
     let mut table = reggie::TableValue::new();
     for _ in 0..size {
-        table.push(reggie::LuaValue::Float(random()))
+        table.push(reggie::LuaValue::float(random()))
     }
     table
 }
@@ -83,14 +83,14 @@ fn bench_reggie(b: &mut Bencher, i: &usize) {
             let mut machine = reggie::Machine::with_stdlib();
             let compiled_module =
                 reggie::compiler::compile_module(&module, &mut machine.global_values);
-            let block = machine.code_blocks.add_module(compiled_module);
+            let block = machine.add_module(compiled_module);
             machine.global_values.set(
                 "TABLE",
-                reggie::LuaValue::Table(reggie::TableRef::from(random_reggie_tbl(*i))),
+                reggie::LuaValue::table(reggie::TableRef::from(random_reggie_tbl(*i))),
             );
             machine
                 .global_values
-                .set("COUNT", reggie::LuaValue::Int(*i as i32));
+                .set("COUNT", reggie::LuaValue::int(*i as i32));
             (block, machine)
         },
         |(block, mut machine)| {
@@ -1,6 +1,7 @@
 use criterion::criterion_group;
 
 mod heapsort;
+mod property_access;
 mod fib;
 mod string_packing;
 
@@ -12,5 +13,6 @@ criterion_group!(
     fib_tailrec::bench,
     fib_loop::bench,
     heapsort::bench,
-    string_packing::bench
+    string_packing::bench,
+    property_access::bench
 );
@@ -0,0 +1,94 @@
+use criterion::{Bencher, BenchmarkId, Criterion};
+use luar_syn::lua_parser;
+
+static BENCH_FILE: &str = include_str!("../lua_benches/property_access.lua");
+
+/// Objects of mixed layouts miss the property caches half of the time, which makes them a
+/// baseline for the objects of the same layout.
+#[derive(Debug, Clone, Copy)]
+struct Input {
+    count: i32,
+    mixed_layouts: bool,
+}
+
+fn bench_ast_opt(b: &mut Bencher, input: &Input) {
+    use ast_vm::lang::LuaValue;
+
+    let mut context = ast_vm::stdlib::std_context();
+    let module = lua_parser::module(BENCH_FILE).unwrap();
+    let module = ast_vm::opt::compile_module(module, &mut context.globals);
+    context.set("COUNT", LuaValue::number(input.count));
+    if input.mixed_layouts {
+        context.set("MIXED_LAYOUTS", LuaValue::number(1));
+    }
+
+    b.iter(|| {
+        ast_vm::opt::eval_module(&module, &mut context).unwrap();
+    });
+}
+
+fn bench_reggie(b: &mut Bencher, input: &Input) {
+    use reggie::LuaValue;
+
+    let module = lua_parser::module(BENCH_FILE).unwrap();
+    let mut machine = reggie::Machine::new();
+    let compiled_module = reggie::compiler::compile_module(&module, &mut machine.global_values);
+    let top_level_block = machine.add_module(compiled_module);
+    machine
+        .global_values
+        .set("COUNT", LuaValue::int(input.count));
+    if input.mixed_layouts {
+        machine.global_values.set("MIXED_LAYOUTS", LuaValue::int(1));
+    }
+
+    b.iter(|| {
+        reggie::call_block::<()>(top_level_block, &mut machine).unwrap();
+    });
+}
+
+fn bench_lua(b: &mut Bencher, input: &Input) {
+    let lua = mlua::Lua::new();
+    lua.globals().set("COUNT", input.count).unwrap();
+    lua.globals()
+        .set("MIXED_LAYOUTS", input.mixed_layouts)
+        .unwrap();
+    let routine = lua.load(BENCH_FILE).into_function().unwrap();
+
+    b.iter(|| routine.call::<(), ()>(()));
+}
+
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("property_access.lua");
+
+    let inputs = [16, 64, 256, 1024, 4096];
+    let len = inputs.len();
+    let sample_count = 100 / len;
+
+    for (idx, count) in inputs.into_iter().enumerate() {
+        group.sample_size((len - idx) * sample_count);
+
+        for mixed_layouts in [false, true] {
+            let input = Input {
+                count,
+                mixed_layouts,
+            };
+            let layouts = if mixed_layouts { "mixed" } else { "same" };
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("AST optimized, {layouts} layouts"), count),
+                &input,
+                bench_ast_opt,
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("Reggie baseline, {layouts} layouts"), count),
+                &input,
+                bench_reggie,
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("Lua 5.4, {layouts} layouts"), count),
+                &input,
+                bench_lua,
+            );
+        }
+    }
+}
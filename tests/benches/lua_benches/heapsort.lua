@@ -0,0 +1,43 @@
+floor = floor or math.floor
+
+function less(ra, i, j)
+    return ra[i] < ra[j]
+end
+
+function swap(ra, i, j)
+    local tmp = ra[i]
+    ra[i] = ra[j]
+    ra[j] = tmp
+end
+
+function sift(ra, root, last)
+    local child = root * 2
+    while child <= last do
+        if (child < last) and less(ra, child, child + 1) then
+            child = child + 1
+        end
+        if less(ra, root, child) then
+            swap(ra, root, child)
+            root = child
+            child = root * 2
+        else
+            return
+        end
+    end
+end
+
+function heapsort(n, ra)
+    local root = floor(n / 2)
+    while root >= 1 do
+        sift(ra, root, n)
+        root = root - 1
+    end
+    local last = n
+    while last > 1 do
+        swap(ra, 1, last)
+        last = last - 1
+        sift(ra, 1, last)
+    end
+end
+
+heapsort(COUNT, TABLE)
@@ -0,0 +1,35 @@
+-- Sums the fields of COUNT objects a couple of times over. With MIXED_LAYOUTS, every other
+-- object has its fields in a different order, so property lookups can't be served by a cache
+-- of the slot the field was found in the last time.
+local objects = {}
+local flip = nil
+local i = 1
+while i <= COUNT do
+    if flip then
+        objects[i] = { y = i, x = i, z = 1 }
+    else
+        objects[i] = { x = i, y = i, z = 1 }
+    end
+    if MIXED_LAYOUTS then
+        if flip then
+            flip = nil
+        else
+            flip = 1
+        end
+    end
+    i = i + 1
+end
+
+local sum = 0
+local pass = 0
+while pass < 10 do
+    i = 1
+    while i <= COUNT do
+        local object = objects[i]
+        object.z = object.z + 1
+        sum = sum + object.x + object.y + object.z
+        i = i + 1
+    end
+    pass = pass + 1
+end
+return sum
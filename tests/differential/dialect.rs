@@ -0,0 +1,198 @@
+//! Reference implementation is Lua 5.4, which differs from this dialect in a couple of places on
+//! purpose. Booleans of the dialect are `1` and `nil`, numbers are compared to strings as
+//! strings, strings cannot be indexed, and `and` has the same precedence as `or`. Programs, which
+//! are run by the reference implementation, have the operators which produce booleans replaced
+//! with calls to functions of the prelude, that behave like the dialect.
+
+use luar_lex::Ident;
+use luar_syn::{
+    BinaryOperator, Block, Chunk, Conditional, ConditionalTail, Expression, FunctionCall,
+    FunctionCallArgs, FunctionName, Module, Statement, TableConstructor, UnaryOperator, Var,
+};
+
+/// Numbers are converted to strings the way rust formats `f64`: without exponents, and with the
+/// shortest number of digits that round trip.
+pub const PRELUDE: &str = r#"
+local function dialect_tostring(num)
+    if num ~= num then
+        return "NaN"
+    elseif num == math.huge then
+        return "inf"
+    elseif num == -math.huge then
+        return "-inf"
+    elseif num == 0 then
+        return 1 / num < 0 and "-0" or "0"
+    elseif math.tointeger(num) then
+        return tostring(math.tointeger(num))
+    end
+    for precision = 1, 17 do
+        local str = string.format("%." .. precision .. "g", num)
+        if tonumber(str) == num then
+            local exponent = string.match(str, "e([-+]%d+)$")
+            if not exponent then
+                return str
+            end
+            local decimals = math.max(precision - 1 - tonumber(exponent), 0)
+            return string.format("%." .. decimals .. "f", num)
+        end
+    end
+end
+
+local function coerce(lhs, rhs)
+    if type(lhs) == "number" and type(rhs) == "string" then
+        return dialect_tostring(lhs), rhs
+    elseif type(lhs) == "string" and type(rhs) == "number" then
+        return lhs, dialect_tostring(rhs)
+    end
+    return lhs, rhs
+end
+
+function __less(lhs, rhs) lhs, rhs = coerce(lhs, rhs) return lhs < rhs and 1 or nil end
+function __greater(lhs, rhs) lhs, rhs = coerce(lhs, rhs) return lhs > rhs and 1 or nil end
+function __less_or_equals(lhs, rhs) lhs, rhs = coerce(lhs, rhs) return lhs <= rhs and 1 or nil end
+function __greater_or_equals(lhs, rhs) lhs, rhs = coerce(lhs, rhs) return lhs >= rhs and 1 or nil end
+function __equals(lhs, rhs) return lhs == rhs and 1 or nil end
+function __not_equals(lhs, rhs) return lhs ~= rhs and 1 or nil end
+function __not(value) return not value and 1 or nil end
+function __group(value) return value end
+
+-- Strings do not have methods
+getmetatable("").__index = function() error("attempt to index a string value") end
+"#;
+
+/// Replaces comparisons and `not` with calls to the functions of [`PRELUDE`], and groups `or`
+/// operands of `and`, which are printed without parens.
+pub fn emulate_in_reference(module: &mut Module) {
+    for chunk in &mut module.chunks {
+        match chunk {
+            Chunk::FnDecl(decl) => {
+                match &mut decl.name {
+                    FunctionName::Plain(var) | FunctionName::Method(var, _) => var_operators(var),
+                }
+                block_operators(&mut decl.body);
+            }
+            Chunk::Statement(statement) => statement_operators(statement),
+        }
+    }
+    if let Some(ret) = &mut module.ret {
+        ret.0.iter_mut().for_each(expression_operators);
+    }
+}
+
+fn block_operators(block: &mut Block) {
+    block.statements.iter_mut().for_each(statement_operators);
+    if let Some(ret) = &mut block.ret {
+        ret.0.iter_mut().for_each(expression_operators);
+    }
+}
+
+fn statement_operators(statement: &mut Statement) {
+    match statement {
+        Statement::Assignment(assignment) => {
+            assignment.names.iter_mut().for_each(var_operators);
+            assignment.values.iter_mut().for_each(expression_operators);
+        }
+        Statement::LocalDeclaration(decl) => decl
+            .initial_values
+            .iter_mut()
+            .for_each(expression_operators),
+        Statement::FunctionCall(call) => call_operators(call),
+        Statement::While(while_loop) => {
+            expression_operators(&mut while_loop.condition);
+            block_operators(&mut while_loop.body);
+        }
+        Statement::Repeat(repeat_loop) => {
+            block_operators(&mut repeat_loop.body);
+            expression_operators(&mut repeat_loop.condition);
+        }
+        Statement::If(conditional) => conditional_operators(conditional),
+    }
+}
+
+fn conditional_operators(conditional: &mut Conditional) {
+    expression_operators(&mut conditional.condition);
+    block_operators(&mut conditional.body);
+    match &mut conditional.tail {
+        ConditionalTail::End => {}
+        ConditionalTail::Else(block) => block_operators(block),
+        ConditionalTail::ElseIf(conditional) => conditional_operators(conditional),
+    }
+}
+
+fn var_operators(var: &mut Var) {
+    match var {
+        Var::Named(_) => {}
+        Var::PropertyAccess { from, .. } => var_operators(from),
+        Var::MemberLookup { from, value } => {
+            var_operators(from);
+            expression_operators(value);
+        }
+    }
+}
+
+fn call_operators(call: &mut FunctionCall) {
+    let (FunctionCall::Function { func, args } | FunctionCall::Method { func, args, .. }) = call;
+    var_operators(func);
+    match args {
+        FunctionCallArgs::Table(table) => table_operators(table),
+        FunctionCallArgs::Arglist(args) => args.iter_mut().for_each(expression_operators),
+    }
+}
+
+fn table_operators(table: &mut TableConstructor) {
+    table.lfield.iter_mut().for_each(expression_operators);
+    for (_, value) in &mut table.ffield {
+        expression_operators(value);
+    }
+}
+
+fn expression_operators(expression: &mut Expression) {
+    match expression {
+        Expression::Nil | Expression::String(_) | Expression::Number(_) => {}
+        Expression::Variable(var) => var_operators(var),
+        Expression::TableConstructor(table) => table_operators(table),
+        Expression::FunctionCall(call) => call_operators(call),
+        Expression::UnaryOperator { op, exp } => {
+            expression_operators(exp);
+            if *op == UnaryOperator::Not {
+                let exp = std::mem::replace(exp.as_mut(), Expression::Nil);
+                *expression = prelude_call("__not", vec![exp]);
+            }
+        }
+        Expression::BinaryOperator { lhs, op, rhs } => {
+            expression_operators(lhs);
+            expression_operators(rhs);
+            let or_lhs = matches!(
+                lhs.as_ref(),
+                Expression::BinaryOperator {
+                    op: BinaryOperator::Or,
+                    ..
+                }
+            );
+            if *op == BinaryOperator::And && or_lhs {
+                let or = std::mem::replace(lhs.as_mut(), Expression::Nil);
+                **lhs = prelude_call("__group", vec![or]);
+                return;
+            }
+            let function = match op {
+                BinaryOperator::Less => "__less",
+                BinaryOperator::Greater => "__greater",
+                BinaryOperator::LessOrEquals => "__less_or_equals",
+                BinaryOperator::GreaterOrEquals => "__greater_or_equals",
+                BinaryOperator::Equals => "__equals",
+                BinaryOperator::NotEquals => "__not_equals",
+                _ => return,
+            };
+            let lhs = std::mem::replace(lhs.as_mut(), Expression::Nil);
+            let rhs = std::mem::replace(rhs.as_mut(), Expression::Nil);
+            *expression = prelude_call(function, vec![lhs, rhs]);
+        }
+    }
+}
+
+fn prelude_call(function: &str, args: Vec<Expression>) -> Expression {
+    Expression::FunctionCall(FunctionCall::Function {
+        func: Var::Named(Ident::new(function)),
+        args: FunctionCallArgs::Arglist(args),
+    })
+}
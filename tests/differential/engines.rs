@@ -0,0 +1,149 @@
+use std::{cell::RefCell, rc::Rc};
+
+use luar_syn::Module;
+
+use crate::{
+    dialect,
+    outcome::{ErrorKind, Outcome, Value},
+};
+
+/// Arguments of `print` calls, collected in place of writing them to stdout
+type Printed = Rc<RefCell<Vec<Vec<Value>>>>;
+
+fn outcome(printed: Printed, result: Result<Vec<Value>, ErrorKind>) -> Outcome {
+    Outcome {
+        printed: printed.take(),
+        result,
+    }
+}
+
+pub fn ast_vm(module: &Module) -> Outcome {
+    use ast_vm::stdlib;
+
+    let printed = Printed::default();
+    let mut context = stdlib::std_context();
+    context.set("print", ast_vm_print(Rc::clone(&printed)));
+    let result = ast_vm::eval_module(module, &mut context);
+    outcome(printed, ast_vm_result(result))
+}
+
+pub fn ast_vm_opt(module: &Module) -> Outcome {
+    use ast_vm::{opt, stdlib};
+
+    let printed = Printed::default();
+    let mut context = stdlib::std_context();
+    context.set("print", ast_vm_print(Rc::clone(&printed)));
+    let compiled = opt::compile_module(module.clone(), &mut context.globals);
+    let result = opt::eval_module(&compiled, &mut context);
+    outcome(printed, ast_vm_result(result))
+}
+
+fn ast_vm_print(printed: Printed) -> ast_vm::lang::LuaValue {
+    ast_vm::lang::LuaValue::function(move |_, args| {
+        printed
+            .borrow_mut()
+            .push(args.iter().map(ast_vm_value).collect());
+        Ok(ast_vm::lang::ReturnValue::EMPTY)
+    })
+}
+
+fn ast_vm_result(
+    result: Result<ast_vm::lang::ReturnValue, ast_vm::EvalError>,
+) -> Result<Vec<Value>, ErrorKind> {
+    result
+        .map(|values| {
+            values
+                .into_iter()
+                .map(|value| ast_vm_value(&value))
+                .collect()
+        })
+        .map_err(|err| ErrorKind::from(&err))
+}
+
+fn ast_vm_value(value: &ast_vm::lang::LuaValue) -> Value {
+    use ast_vm::lang::LuaValue;
+    match value {
+        LuaValue::Nil => Value::Nil,
+        LuaValue::Number(num) => Value::Number(num.as_f64()),
+        LuaValue::String(str) => Value::String(AsRef::<str>::as_ref(str).to_string()),
+        LuaValue::Table(_) => Value::Table,
+        LuaValue::Function(_) | LuaValue::NativeFunction(_) => Value::Function,
+    }
+}
+
+pub fn reggie(module: &Module) -> Outcome {
+    use reggie::{LuaValue, Machine};
+
+    let printed = Printed::default();
+    let mut machine = Machine::with_stdlib();
+    let recorder = Rc::clone(&printed);
+    machine.global_values.set(
+        "print",
+        LuaValue::function(move |args: &[LuaValue]| {
+            recorder
+                .borrow_mut()
+                .push(args.iter().map(reggie_value).collect());
+        }),
+    );
+    let result = reggie::eval_module::<&[LuaValue]>(module, &mut machine)
+        .map(|values| values.iter().map(reggie_value).collect())
+        .map_err(|err| ErrorKind::from(&err));
+    outcome(printed, result)
+}
+
+fn reggie_value(value: &reggie::LuaValue) -> Value {
+    if value.is_nil() {
+        Value::Nil
+    } else if let Some(int) = value.as_int() {
+        Value::Number(int.into())
+    } else if let Some(float) = value.as_float() {
+        Value::Number(float)
+    } else if let Some(str) = value.as_str() {
+        Value::String(str.to_string())
+    } else if value.is_table() {
+        Value::Table
+    } else if value.is_function() {
+        Value::Function
+    } else {
+        unreachable!("unknown reggie value {value:?}")
+    }
+}
+
+/// Reference implementation
+pub fn lua(module: &Module) -> Outcome {
+    use mlua::{Lua, MultiValue};
+
+    let lua = Lua::new();
+    let recorded = Printed::default();
+    let recorder = Rc::clone(&recorded);
+    let print = lua
+        .create_function(move |_, args: MultiValue| {
+            recorder
+                .borrow_mut()
+                .push(args.iter().map(lua_value).collect());
+            Ok(())
+        })
+        .unwrap();
+    lua.globals().set("print", print).unwrap();
+    lua.load(dialect::PRELUDE).exec().unwrap();
+    let result = lua
+        .load(&module.to_string())
+        .eval::<MultiValue>()
+        .map(|values| values.iter().map(lua_value).collect())
+        .map_err(|err| ErrorKind::from_lua_message(&err.to_string()));
+    outcome(recorded, result)
+}
+
+fn lua_value(value: &mlua::Value) -> Value {
+    use mlua::Value as LuaValue;
+    match value {
+        LuaValue::Nil | LuaValue::Boolean(false) => Value::Nil,
+        LuaValue::Boolean(true) => Value::Number(1.0),
+        LuaValue::Integer(int) => Value::Number(*int as f64),
+        LuaValue::Number(num) => Value::Number(*num),
+        LuaValue::String(str) => Value::String(str.to_string_lossy().into_owned()),
+        LuaValue::Table(_) => Value::Table,
+        LuaValue::Function(_) => Value::Function,
+        other => unreachable!("generated programs do not produce {other:?}"),
+    }
+}
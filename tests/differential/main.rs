@@ -0,0 +1,143 @@
+//! Runs generated programs on every engine and checks that they behave the same way.
+//! Counterexamples are shrunk by quickcheck, and printed as lua source.
+//!
+//! ```sh
+//! QUICKCHECK_TESTS=10000 QUICKCHECK_GENERATOR_SIZE=50 cargo test -p luar_tests --test differential
+//! ```
+
+use std::{
+    any::Any,
+    env,
+    panic::{self, catch_unwind, resume_unwind, AssertUnwindSafe},
+    str::FromStr,
+    sync::Once,
+    thread,
+};
+
+use luar_syn::Module;
+use quickcheck::{Gen, QuickCheck, TestResult};
+
+mod dialect;
+mod engines;
+mod outcome;
+mod program;
+
+use outcome::Outcome;
+use program::Program;
+
+/// Tree walking interpreters recurse on the native stack, so deeply nested (but perfectly
+/// terminating) programs need more of it than the test thread has.
+const ENGINE_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+type Engine = (&'static str, fn(&Module) -> Outcome);
+
+const IN_TREE_ENGINES: &[Engine] = &[
+    ("ast_vm", engines::ast_vm),
+    ("ast_vm::opt", engines::ast_vm_opt),
+    ("reggie", engines::reggie),
+];
+
+/// Runs the module on every engine, that implements all of the features the module uses.
+/// Engines bail out with a `todo!` on missing features, and are left out of the results then.
+fn run(module: Module, engines: &'static [Engine]) -> Vec<(&'static str, Outcome)> {
+    silence_unimplemented_panics();
+    thread::Builder::new()
+        .stack_size(ENGINE_STACK_SIZE)
+        .spawn(move || {
+            engines
+                .iter()
+                .filter_map(|(name, engine)| {
+                    match catch_unwind(AssertUnwindSafe(|| engine(&module))) {
+                        Ok(outcome) => Some((*name, outcome)),
+                        Err(panic) if is_unimplemented(&*panic) => None,
+                        Err(panic) => resume_unwind(panic),
+                    }
+                })
+                .collect()
+        })
+        .unwrap()
+        .join()
+        .unwrap_or_else(|panic| resume_unwind(panic))
+}
+
+/// Checks that all of the engines that managed to run the program agree with the first one
+fn assert_agree(outcomes: Vec<(&'static str, Outcome)>) -> TestResult {
+    let Some(((expected_name, expected), rest)) = outcomes.split_first() else {
+        return TestResult::discard();
+    };
+    if rest.is_empty() {
+        return TestResult::discard();
+    }
+    for (name, outcome) in rest {
+        assert_eq!(expected, outcome, "{expected_name} and {name} diverged");
+    }
+    TestResult::passed()
+}
+
+/// Programs that run into missing features are common, and their panic messages would bury
+/// the actual counterexample.
+fn silence_unimplemented_panics() {
+    static SILENCE: Once = Once::new();
+    SILENCE.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !is_unimplemented(info.payload()) {
+                default_hook(info)
+            }
+        }));
+    });
+}
+
+fn is_unimplemented(panic: &(dyn Any + Send)) -> bool {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied());
+    message.is_some_and(|message| message.starts_with("not yet implemented"))
+}
+
+/// Programs of the default quickcheck size have thousands of lines, and shrinking a
+/// counterexample of that size takes minutes. Both are overridden by the usual environment
+/// variables.
+const GENERATOR_SIZE: usize = 10;
+const TESTS: u64 = 30;
+
+fn check(property: fn(Program) -> TestResult) {
+    let size = env_or("QUICKCHECK_GENERATOR_SIZE", GENERATOR_SIZE);
+    let tests = env_or("QUICKCHECK_TESTS", TESTS);
+    QuickCheck::new()
+        .gen(Gen::new(size))
+        .tests(tests)
+        .quickcheck(property)
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[test]
+fn in_tree_engines_agree() {
+    fn property(program: Program) -> TestResult {
+        if program.has_unspecified_behavior() || program.has_repeat_loops() {
+            return TestResult::discard();
+        }
+        assert_agree(run(program.module(), IN_TREE_ENGINES))
+    }
+    check(property)
+}
+
+#[test]
+fn engines_agree_with_reference_lua() {
+    fn property(program: Program) -> TestResult {
+        if program.has_unspecified_behavior() || program.has_repeat_loops() {
+            return TestResult::discard();
+        }
+        let mut outcomes = run(program.reference_module(), &[("lua", engines::lua)]);
+        outcomes.extend(run(program.module(), IN_TREE_ENGINES));
+        assert_agree(outcomes)
+    }
+    check(property)
+}
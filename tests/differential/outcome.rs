@@ -0,0 +1,210 @@
+use std::fmt;
+
+/// Engine independent representation of a lua value. Tables and functions are only compared by
+/// their type, since their identities are not observable across engines.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Number(f64),
+    String(String),
+    Table,
+    Function,
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) | (Self::Table, Self::Table) => true,
+            (Self::Function, Self::Function) => true,
+            (Self::Number(lhs), Self::Number(rhs)) => lhs == rhs || lhs.is_nan() && rhs.is_nan(),
+            (Self::String(lhs), Self::String(rhs)) => {
+                lhs == rhs || normalize_numbers(lhs) == normalize_numbers(rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Numbers are converted to strings differently by every engine: reference Lua keeps `.0` of
+/// integral floats, prints 14 significant digits and `nan` with a sign. Every number in a string
+/// is replaced with a canonical representation, so that only the values of numbers are compared.
+fn normalize_numbers(str: &str) -> String {
+    let mut normalized = String::with_capacity(str.len());
+    let mut rest = str;
+    while let Some(start) = rest.find(|char: char| char.is_ascii_digit() || char == 'n' || char == 'N')
+    {
+        normalized.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.get(..3).is_some_and(|word| word.eq_ignore_ascii_case("nan")) {
+            if normalized.ends_with('-') {
+                normalized.pop();
+            }
+            normalized.push_str("nan");
+            rest = &rest[3..];
+        } else if let Some(len) = number_len(rest) {
+            let number: f64 = rest[..len].parse().unwrap();
+            normalized.push_str(&format!("{number:.13e}"));
+            rest = &rest[len..];
+        } else {
+            normalized.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+    normalized.push_str(rest);
+    normalized
+}
+
+/// Length of the decimal number at the start of the string
+fn number_len(str: &str) -> Option<usize> {
+    let bytes = str.as_bytes();
+    let digits = |from: usize| {
+        from + bytes[from..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count()
+    };
+    let mut len = digits(0);
+    if len == 0 {
+        return None;
+    }
+    if bytes.get(len) == Some(&b'.') && bytes.get(len + 1).is_some_and(u8::is_ascii_digit) {
+        len = digits(len + 1);
+    }
+    if let Some(b'e' | b'E') = bytes.get(len) {
+        let sign = usize::from(matches!(bytes.get(len + 1), Some(b'+' | b'-')));
+        let exponent_end = digits(len + 1 + sign);
+        if exponent_end > len + 1 + sign {
+            len = exponent_end;
+        }
+    }
+    Some(len)
+}
+
+/// Error messages are different for every engine, only the kind of an error is compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Arithmetic,
+    Concat,
+    Comparison,
+    Call,
+    Index,
+    Assertion,
+    Other,
+}
+
+/// reggie has its own copy of the error types, which mirrors the one of `luar_error`, with the
+/// variants only reggie has listed after the types.
+macro_rules! error_kind_from {
+    (
+        $eval_error: ty, $error_crate: ident,
+        type_errors: [$($type_error: pat => $type_error_kind: expr),* $(,)?],
+        eval_errors: [$($eval_error_pat: pat => $eval_error_kind: expr),* $(,)?] $(,)?
+    ) => {
+        impl From<&$eval_error> for ErrorKind {
+            fn from(err: &$eval_error) -> Self {
+                use $error_crate::{EvalError, TypeError};
+                match err {
+                    EvalError::TypeError(err) => match err.as_ref() {
+                        TypeError::Arithmetic(_) => ErrorKind::Arithmetic,
+                        TypeError::StringConcat { .. } => ErrorKind::Concat,
+                        TypeError::Ordering { .. } => ErrorKind::Comparison,
+                        TypeError::IsNotCallable(_) => ErrorKind::Call,
+                        TypeError::NilAssign(_)
+                        | TypeError::NaNAssign(_)
+                        | TypeError::IsNotIndexable(_)
+                        | TypeError::CannotAccessProperty { .. }
+                        | TypeError::CannotAssignProperty { .. }
+                        | TypeError::CannotAccessMember { .. }
+                        | TypeError::CannotAssignMember { .. } => ErrorKind::Index,
+                        TypeError::ArgumentType { .. } => ErrorKind::Other,
+                        $($type_error => $type_error_kind,)*
+                    },
+                    EvalError::AssertionError(_) => ErrorKind::Assertion,
+                    EvalError::IO(_)
+                    | EvalError::Utf8Error
+                    | EvalError::JsonEncode(_)
+                    | EvalError::JsonDecode { .. } => ErrorKind::Other,
+                    $($eval_error_pat => $eval_error_kind,)*
+                }
+            }
+        }
+    };
+}
+
+error_kind_from!(
+    ast_vm::EvalError,
+    luar_error,
+    type_errors: [],
+    eval_errors: [],
+);
+error_kind_from!(
+    reggie::EvalError,
+    reggie,
+    type_errors: [
+        // Conversions of values returned to rust, and of globals read by rust
+        TypeError::ReturnType { .. } | TypeError::GlobalType { .. } => ErrorKind::Other,
+    ],
+    eval_errors: [
+        // Execution limits
+        EvalError::InstructionLimit(_)
+        | EvalError::CallDepthLimit(_)
+        | EvalError::MemoryLimit(_) => ErrorKind::Other,
+        EvalError::Parse { .. }
+        | EvalError::ModuleNotFound(_)
+        | EvalError::CyclicRequire(_) => ErrorKind::Other,
+        EvalError::YieldOutsideCoroutine
+        | EvalError::YieldAcrossNativeCall
+        | EvalError::CannotResume(_)
+        | EvalError::PendingOutsideExecution => ErrorKind::Other,
+        // Bug of the engine or of a native function, rather than an error of the program
+        EvalError::JitPanic(_) | EvalError::CorruptedCallStack => ErrorKind::Other,
+    ],
+);
+
+impl ErrorKind {
+    /// Reference implementation reports errors only as messages
+    pub fn from_lua_message(message: &str) -> Self {
+        // Failed string to number coercions are reported by string metamethods
+        let string_arithmetic = ["add", "sub", "mul", "div", "mod", "pow", "unm", "idiv"]
+            .iter()
+            .any(|op| message.contains(&format!("attempt to {op} a ")));
+        if message.contains("attempt to perform arithmetic") || string_arithmetic {
+            ErrorKind::Arithmetic
+        } else if message.contains("attempt to concatenate") {
+            ErrorKind::Concat
+        } else if message.contains("attempt to compare") {
+            ErrorKind::Comparison
+        } else if message.contains("attempt to call") {
+            ErrorKind::Call
+        } else if message.contains("attempt to index")
+            || message.contains("index is nil")
+            || message.contains("index is NaN")
+        {
+            ErrorKind::Index
+        } else if message.contains("assertion failed") {
+            ErrorKind::Assertion
+        } else {
+            ErrorKind::Other
+        }
+    }
+}
+
+/// Everything observable about a single run of a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    /// Arguments of every `print` call, in order
+    pub printed: Vec<Vec<Value>>,
+    pub result: Result<Vec<Value>, ErrorKind>,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Number(num) => write!(f, "{}", num),
+            Value::String(str) => write!(f, "{:?}", str),
+            Value::Table => write!(f, "table"),
+            Value::Function => write!(f, "function"),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+
+use luar_syn::{
+    lua_parser, BinaryOperator, Block, Chunk, Conditional, ConditionalTail, Expression, Module,
+    Statement, Var, WellFormedModule,
+};
+use quickcheck::{Arbitrary, Gen};
+
+use crate::dialect;
+
+/// How many loop iterations and function calls a program is allowed to make, in total.
+const FUEL: usize = 200;
+
+/// Generated program, which is guaranteed to terminate. Every loop iteration and every function
+/// call burns a unit of a global `__fuel` counter, and loops exit (and functions return early)
+/// once it is exhausted. Since the counter is an ordinary global, every engine runs out of it at
+/// exactly the same point of the program.
+#[derive(Clone)]
+pub struct Program(pub WellFormedModule);
+
+impl Program {
+    pub fn module(&self) -> Module {
+        let mut module = self.0 .0.clone();
+        for chunk in &mut module.chunks {
+            match chunk {
+                Chunk::FnDecl(decl) => {
+                    refuel_block(&mut decl.body);
+                    let guard = statement("if __fuel <= 0 then return end");
+                    decl.body.statements.splice(0..0, [guard, burn_fuel()]);
+                }
+                Chunk::Statement(statement) => refuel_statement(statement),
+            }
+        }
+        let fuel = statement(&format!("__fuel = {FUEL}"));
+        module.chunks.insert(0, Chunk::Statement(fuel));
+        module
+    }
+
+    /// The module, with the semantics of the dialect emulated on the reference implementation
+    pub fn reference_module(&self) -> Module {
+        let mut module = self.module();
+        dialect::emulate_in_reference(&mut module);
+        module
+    }
+
+    /// None of the engines implements `repeat` loops yet, and the reference implementation would
+    /// be the only one to run such a program.
+    pub fn has_repeat_loops(&self) -> bool {
+        self.0 .0.chunks.iter().any(|chunk| match chunk {
+            Chunk::FnDecl(decl) => block_has_repeat_loops(&decl.body),
+            Chunk::Statement(statement) => has_repeat_loops(statement),
+        })
+    }
+
+    /// Lua does not define the order in which targets of a multiple assignment are assigned,
+    /// so `a, a = 1, 2` may leave `a` either `1` or `2`. Neither does it define whether the keys
+    /// of the targets are evaluated before the values, which is observable if evaluating either
+    /// of them fails or has side effects.
+    pub fn has_unspecified_behavior(&self) -> bool {
+        self.0 .0.chunks.iter().any(|chunk| match chunk {
+            Chunk::FnDecl(decl) => block_has_unspecified_order(&decl.body),
+            Chunk::Statement(statement) => has_unspecified_order(statement),
+        })
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Program:")?;
+        fmt::Display::fmt(&self.module(), f)
+    }
+}
+
+impl Arbitrary for Program {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Program(WellFormedModule::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.0.shrink().map(Program))
+    }
+}
+
+fn statement(source: &str) -> Statement {
+    lua_parser::statement(source).unwrap()
+}
+
+fn burn_fuel() -> Statement {
+    statement("__fuel = __fuel - 1")
+}
+
+fn refuel_block(block: &mut Block) {
+    for statement in &mut block.statements {
+        refuel_statement(statement);
+    }
+}
+
+fn refuel_statement(statement: &mut Statement) {
+    match statement {
+        Statement::While(while_loop) => {
+            refuel_block(&mut while_loop.body);
+            while_loop.body.statements.insert(0, burn_fuel());
+            let condition = std::mem::replace(&mut while_loop.condition, Expression::Nil);
+            while_loop.condition = Expression::BinaryOperator {
+                lhs: Box::new(condition),
+                op: BinaryOperator::And,
+                rhs: Box::new(lua_parser::expression("__fuel > 0").unwrap()),
+            };
+        }
+        // Programs with `repeat` loops are discarded, see `Program::has_repeat_loops`
+        Statement::Repeat(_) => {}
+        Statement::If(conditional) => refuel_conditional(conditional),
+        Statement::Assignment(_) | Statement::LocalDeclaration(_) | Statement::FunctionCall(_) => {}
+    }
+}
+
+fn refuel_conditional(conditional: &mut Conditional) {
+    refuel_block(&mut conditional.body);
+    match &mut conditional.tail {
+        ConditionalTail::End => {}
+        ConditionalTail::Else(block) => refuel_block(block),
+        ConditionalTail::ElseIf(conditional) => refuel_conditional(conditional),
+    }
+}
+
+fn block_has_repeat_loops(block: &Block) -> bool {
+    block.statements.iter().any(has_repeat_loops)
+}
+
+fn has_repeat_loops(statement: &Statement) -> bool {
+    match statement {
+        Statement::Repeat(_) => true,
+        Statement::While(while_loop) => block_has_repeat_loops(&while_loop.body),
+        Statement::If(conditional) => conditional_has_repeat_loops(conditional),
+        Statement::Assignment(_) | Statement::LocalDeclaration(_) | Statement::FunctionCall(_) => {
+            false
+        }
+    }
+}
+
+fn conditional_has_repeat_loops(conditional: &Conditional) -> bool {
+    block_has_repeat_loops(&conditional.body)
+        || match &conditional.tail {
+            ConditionalTail::End => false,
+            ConditionalTail::Else(block) => block_has_repeat_loops(block),
+            ConditionalTail::ElseIf(conditional) => conditional_has_repeat_loops(conditional),
+        }
+}
+
+fn block_has_unspecified_order(block: &Block) -> bool {
+    block.statements.iter().any(has_unspecified_order)
+}
+
+fn has_unspecified_order(statement: &Statement) -> bool {
+    match statement {
+        Statement::Assignment(assignment) => {
+            let names = &assignment.names;
+            let duplicate_targets = names
+                .iter()
+                .enumerate()
+                .any(|(idx, name)| names[idx + 1..].contains(name));
+            let evaluated_targets = names.iter().any(target_evaluates);
+            duplicate_targets || evaluated_targets && !assignment.values.iter().all(is_plain)
+        }
+        Statement::While(while_loop) => block_has_unspecified_order(&while_loop.body),
+        Statement::Repeat(repeat_loop) => block_has_unspecified_order(&repeat_loop.body),
+        Statement::If(conditional) => conditional_has_unspecified_order(conditional),
+        Statement::LocalDeclaration(_) | Statement::FunctionCall(_) => false,
+    }
+}
+
+fn conditional_has_unspecified_order(conditional: &Conditional) -> bool {
+    block_has_unspecified_order(&conditional.body)
+        || match &conditional.tail {
+            ConditionalTail::End => false,
+            ConditionalTail::Else(block) => block_has_unspecified_order(block),
+            ConditionalTail::ElseIf(conditional) => conditional_has_unspecified_order(conditional),
+        }
+}
+
+/// Whether evaluating the target does more than reading a variable, which might fail
+fn target_evaluates(var: &Var) -> bool {
+    match var {
+        Var::Named(_) => false,
+        Var::PropertyAccess { from, .. } => !matches!(from.as_ref(), Var::Named(_)),
+        Var::MemberLookup { from, value } => {
+            !matches!(from.as_ref(), Var::Named(_)) || !is_plain(value)
+        }
+    }
+}
+
+/// Whether evaluating the expression can neither fail, nor have side effects
+fn is_plain(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::Nil
+            | Expression::String(_)
+            | Expression::Number(_)
+            | Expression::Variable(Var::Named(_))
+    )
+}
@@ -29,3 +29,9 @@ function nan_equality()
     assert(nan ~= 0)
     assert(nan ~= 1)
 end
+
+function strings_with_surrounding_whitespace_are_coerced_to_numbers()
+    assert(1 - "\t  9" == -8)
+    assert(" 2\n" * 3 == 6)
+    assert(-" 4 " == -4)
+end
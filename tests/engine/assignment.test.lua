@@ -30,3 +30,17 @@ function deep_assignment()
     table.foo.bar.baz = 42
     assert(table.foo.bar.baz == 42)
 end
+
+function _set_globals()
+  global_a, global_b = 1, 2
+end
+
+function targets_without_values_are_assigned_nil()
+  _set_globals()
+  local filler = 42
+  global_a, global_b = "first"
+  assert(global_a == "first")
+  assert(global_b == nil, "second target was not assigned nil")
+  local a, b, c = 1
+  assert(b == nil and c == nil, "locals without values are not nil")
+end
@@ -27,3 +27,13 @@ function multiple_return_values_are_correctly_set()
   assert(c == 1, "first return of multi-return list is not set correctly")
   assert(d == 2, "second return of multi-return list is not set correctly")
 end
+
+function _second(first, second)
+  return second
+end
+
+function last_argument_call_passes_all_of_its_values()
+  assert(_second(_return_multi(1)) == 2, "second return value is not passed on")
+  assert(_second(0, _return_multi(1)) == 1, "values of the call do not follow the preceding arguments")
+  assert(_second(_return_multi(1), 0) == 0, "call before the last argument is not truncated to one value")
+end
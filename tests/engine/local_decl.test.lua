@@ -64,4 +64,18 @@ function redeclaring_local_with_new_value_creates_new_local_with_that_value()
     local foo = 42
     local foo = 69
     assert(foo == 69)
+end
+
+
+
+
+function _local_of_the_block(x)
+    if x then
+        local y = x + 1
+        return y
+    end
+end
+
+function return_in_a_block_sees_locals_of_the_block()
+    assert(_local_of_the_block(1) == 2)
 end
\ No newline at end of file
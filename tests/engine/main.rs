@@ -45,4 +45,9 @@ mod ast_vm {
 
 mod reggie {
     run_tests!(crate::reggie_test_harness::run_lua_test);
+
+    #[cfg(feature = "jit")]
+    mod jit {
+        run_tests!(crate::reggie_test_harness::run_lua_test_jit);
+    }
 }
@@ -11,9 +11,41 @@ macro_rules! run_lua_test {
 
 pub(crate) use run_lua_test;
 
+/// Same as [`run_lua_test`], except that every block is compiled by the JIT before its first call
+#[cfg(feature = "jit")]
+macro_rules! run_lua_test_jit {
+    ($group_name: expr, $module_str: expr) => {
+        $crate::reggie_test_harness::run_lua_test_on(
+            $crate::reggie_test_harness::forced_jit_machine,
+            module_path!(),
+            $group_name,
+            $module_str,
+        );
+    };
+}
+
+#[cfg(feature = "jit")]
+pub(crate) use run_lua_test_jit;
+
+#[cfg(feature = "jit")]
+pub fn forced_jit_machine() -> Machine {
+    let mut machine = Machine::with_stdlib();
+    machine.jit.call_threshold = 0;
+    machine
+}
+
 pub fn run_lua_test_impl(module_path: &str, group_name: &str, module_str: &str) {
+    run_lua_test_on(Machine::with_stdlib, module_path, group_name, module_str)
+}
+
+pub fn run_lua_test_on(
+    new_machine: fn() -> Machine,
+    module_path: &str,
+    group_name: &str,
+    module_str: &str,
+) {
     let res = catch_unwind(|| {
-        let mut machine = Machine::with_stdlib();
+        let mut machine = new_machine();
         let module = lua_parser::module(module_str).unwrap();
         let res = eval_module::<()>(&module, &mut machine);
         if let Err(err) = res {
@@ -24,7 +56,7 @@ pub fn run_lua_test_impl(module_path: &str, group_name: &str, module_str: &str)
         let test_cases: Vec<_> = (&machine.global_values)
             .into_iter()
             .map(|value| (value.name.clone(), value.value.clone()))
-            .filter_map(|(name, value)| LuaValue::as_lua_function(value).map(|func| (name, func)))
+            .filter_map(|(name, value)| LuaValue::as_lua_function(&value).map(|func| (name, func)))
             .filter(|(name, _)| !name.starts_with('_'))
             .collect();
         let mut error_occurred = false;
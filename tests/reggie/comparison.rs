@@ -8,8 +8,8 @@ type LuaValue4 = (LuaValue, LuaValue, LuaValue, LuaValue);
 fn comparing_numbers_behave_according_to_IEEE754(lhs: f64, rhs: f64) -> Result<(), LuaError> {
     let module = lua_parser::module("return a > b, a < b, a >= b, a <= b")?;
     let mut machine = Machine::new();
-    machine.global_values.set("a", LuaValue::Float(lhs));
-    machine.global_values.set("b", LuaValue::Float(rhs));
+    machine.global_values.set("a", LuaValue::float(lhs));
+    machine.global_values.set("b", LuaValue::float(rhs));
     let expected = (
         LuaValue::from_bool(lhs > rhs),
         LuaValue::from_bool(lhs < rhs),
@@ -57,7 +57,7 @@ fn comparing_strings_and_numbers_coerces_numbers_to_strings(
         machine
             .global_values
             .set("a", LuaValue::string(&str));
-        machine.global_values.set("b", LuaValue::Float(num));
+        machine.global_values.set("b", LuaValue::float(num));
         let lhs = &str;
         let rhs = &format!("{}", num);
         let expected = (
@@ -70,7 +70,7 @@ fn comparing_strings_and_numbers_coerces_numbers_to_strings(
         assert_eq!(res, expected);
     }
     {
-        machine.global_values.set("a", LuaValue::Float(num));
+        machine.global_values.set("a", LuaValue::float(num));
         machine
             .global_values
             .set("b", LuaValue::string(&str));
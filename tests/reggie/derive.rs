@@ -0,0 +1,174 @@
+use reggie::{
+    eval_str, EvalError, ExpectedType, FromLua, IntoLua, LuaError, LuaValue, Machine, TypeError,
+};
+
+#[derive(Debug, PartialEq, FromLua, IntoLua)]
+struct Config {
+    name: String,
+    port: i32,
+    verbose: bool,
+    ratio: Option<f64>,
+    paths: Vec<String>,
+    server: Server,
+    level: Level,
+}
+
+#[derive(Debug, PartialEq, FromLua, IntoLua)]
+struct Server {
+    host: String,
+    timeout: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, FromLua, IntoLua)]
+struct Point(f64, f64);
+
+#[derive(Debug, PartialEq, FromLua, IntoLua)]
+struct Item {
+    r#type: String,
+    count: i32,
+}
+
+#[derive(Debug, PartialEq, FromLua, IntoLua)]
+enum Level {
+    Debug,
+    Info,
+}
+
+#[test]
+fn tables_convert_into_structs() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let config: Config = eval_str(
+        "
+        return {
+            name = 'app',
+            port = 8080,
+            verbose = 1,
+            paths = { 'a', 'b' },
+            server = { host = 'localhost' },
+            level = 'Info',
+        }
+        ",
+        &mut machine,
+    )?;
+    assert_eq!(
+        config,
+        Config {
+            name: "app".to_owned(),
+            port: 8080,
+            verbose: true,
+            ratio: None,
+            paths: vec!["a".to_owned(), "b".to_owned()],
+            server: Server {
+                host: "localhost".to_owned(),
+                timeout: None,
+            },
+            level: Level::Info,
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn structs_convert_into_tables() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    machine.set_global(
+        "server",
+        Server {
+            host: "example.com".to_owned(),
+            timeout: Some(30),
+        },
+    );
+    machine.set_global("origin", Point(1.5, -2.0));
+    machine.set_global("level", Level::Debug);
+    let res: (String, i32, f64, f64, String) = eval_str(
+        "return server.host, server.timeout, origin[1], origin[2], level",
+        &mut machine,
+    )?;
+    assert_eq!(
+        res,
+        ("example.com".to_owned(), 30, 1.5, -2.0, "Debug".to_owned())
+    );
+    Ok(())
+}
+
+#[test]
+fn native_functions_take_and_return_derived_types() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    machine.set_global("scale", |point: Point, factor: f64| {
+        Point(point.0 * factor, point.1 * factor)
+    });
+    let res: Point = eval_str("return scale({ 1, 2 }, 3)", &mut machine)?;
+    assert_eq!(res, Point(3.0, 6.0));
+
+    let res = eval_str::<LuaValue>("return scale({ 1, 'x' }, 3)", &mut machine);
+    assert!(matches!(
+        res,
+        Err(LuaError::Eval(EvalError::TypeError(err))) if matches!(
+            *err,
+            TypeError::ArgumentType { position: 0, ref expected, .. }
+                if *expected == field("2", ExpectedType::Number)
+        )
+    ));
+    Ok(())
+}
+
+fn field(name: &'static str, expected: ExpectedType) -> ExpectedType {
+    ExpectedType::Field {
+        name,
+        expected: Box::new(expected),
+    }
+}
+
+#[test]
+fn mismatched_fields_fail_the_conversion() {
+    let table = |source: &str| eval_str::<LuaValue>(source, &mut Machine::new()).unwrap();
+    assert_eq!(
+        Server::from_lua(&table("return { host = 1 }")),
+        Err(field("host", ExpectedType::String))
+    );
+    assert_eq!(
+        Config::from_lua(&table(
+            "return { name = 'app', port = 1, paths = {}, server = {}, level = 'Info' }"
+        )),
+        Err(field("server", field("host", ExpectedType::String)))
+    );
+    assert_eq!(
+        Level::from_lua(&table("return 'Warning'")),
+        Err(ExpectedType::String)
+    );
+    assert_eq!(
+        Point::from_lua(&table("return 'x'")),
+        Err(ExpectedType::Table)
+    );
+    assert_eq!(
+        Server::from_lua(
+            &Server {
+                host: "h".to_owned(),
+                timeout: Some(1)
+            }
+            .into_lua()
+        ),
+        Ok(Server {
+            host: "h".to_owned(),
+            timeout: Some(1)
+        })
+    );
+}
+
+#[test]
+fn raw_identifiers_are_stored_without_the_prefix() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let item: Item = eval_str("return { type = 'sword', count = 2 }", &mut machine)?;
+    assert_eq!(
+        item,
+        Item {
+            r#type: "sword".to_owned(),
+            count: 2,
+        }
+    );
+
+    machine.set_global("item", item);
+    let res: String = eval_str("return item.type", &mut machine)?;
+    assert_eq!(res, "sword");
+    Ok(())
+}
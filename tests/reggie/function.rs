@@ -5,7 +5,7 @@ use luar_lex::Ident;
 use luar_syn::lua_parser;
 use non_empty::NonEmptyVec;
 use quickcheck::TestResult;
-use reggie::{eval_module, LuaError, LuaValue, Machine, NativeFunction, Strict, TypeError, call_block, assert_type_error};
+use reggie::{eval_module, eval_str, EvalError, LuaError, LuaKey, LuaValue, Machine, NativeFunction, Strict, TypeError, call_block, call_value, assert_type_error};
 
 #[test]
 fn eval_fn_call() -> Result<(), LuaError> {
@@ -21,7 +21,7 @@ fn eval_fn_call() -> Result<(), LuaError> {
     let mut machine = Machine::new();
     machine
         .global_values
-        .set("myfn", LuaValue::NativeFunction(myfn));
+        .set("myfn", LuaValue::native_function(myfn));
     eval_module::<Strict<()>>(&module, &mut machine)?;
     let called = called_with.borrow();
     assert_eq!(*called, 42);
@@ -38,7 +38,7 @@ fn eval_fn_return(ret_value: LuaValue) -> Result<(), LuaError> {
     });
     machine
         .global_values
-        .set("myfn", LuaValue::NativeFunction(myfn));
+        .set("myfn", LuaValue::native_function(myfn));
     let Strict(res) = eval_module(&module, &mut machine)?;
     assert!(ret_value.total_eq(&res));
     Ok(())
@@ -69,7 +69,7 @@ fn eval_fn_call_multiple_returns(value1: LuaValue, value2: LuaValue) -> Result<(
     });
     machine
         .global_values
-        .set("myfn", LuaValue::NativeFunction(myfn));
+        .set("myfn", LuaValue::native_function(myfn));
     let Strict((res1, res2)) =
         eval_module::<Strict<(&LuaValue, &LuaValue)>>(&module, &mut machine)?;
     assert!(res1.total_eq(&value1));
@@ -163,7 +163,7 @@ fn arguments_passed_in_are_defined_as_local_variables_inside_fn(
     let Strict((func_return, arg)) =
         eval_module::<Strict<(&LuaValue, &LuaValue)>>(&module, &mut machine)?;
     assert!(func_return.total_eq(&value));
-    assert_eq!(arg, &LuaValue::Nil);
+    assert_eq!(arg, &LuaValue::NIL);
     Ok(())
 }
 
@@ -179,10 +179,10 @@ fn not_passed_arguments_are_set_to_nil() -> Result<(), LuaError> {
     let Strict(res) =
         eval_module::<Strict<(&LuaValue, &LuaValue, &LuaValue, &LuaValue)>>(&module, &mut machine)?;
     let expected = (
-        &LuaValue::Int(1),
-        &LuaValue::Int(2),
-        &LuaValue::Nil,
-        &LuaValue::Nil,
+        &LuaValue::int(1),
+        &LuaValue::int(2),
+        &LuaValue::NIL,
+        &LuaValue::NIL,
     );
     assert_eq!(res, expected);
     Ok(())
@@ -198,7 +198,7 @@ fn passing_more_arguments_than_stated_just_gets_arglist_truncated() -> Result<()
     )?;
     let mut machine = Machine::new();
     let Strict(res) = eval_module::<Strict<(&LuaValue, &LuaValue)>>(&module, &mut machine)?;
-    let expected = (&LuaValue::Int(1), &LuaValue::Int(2));
+    let expected = (&LuaValue::int(1), &LuaValue::int(2));
     assert_eq!(res, expected);
     Ok(())
 }
@@ -238,3 +238,292 @@ fn multiple_return_is_propagated() -> Result<(), LuaError> {
 
     Ok(())
 }
+
+#[test]
+fn falling_off_the_end_returns_nothing() -> Result<(), LuaError> {
+    let module = lua_parser::module(
+        "function maybe(x)
+                if x then
+                    return 1, 2
+                end
+            end
+            function fallthrough()
+                maybe(1)
+            end",
+    )?;
+    let mut machine = Machine::new();
+    eval_module::<Strict<()>>(&module, &mut machine)?;
+    for func in ["maybe", "fallthrough"] {
+        let block_id = machine.global_values.get(func).unwrap_lua_function();
+        let res = call_block::<&[LuaValue]>(block_id, &mut machine)?;
+        assert!(res.is_empty(), "{func} returned {res:?}");
+    }
+
+    let module = lua_parser::module("maybe(1)")?;
+    let res = eval_module::<&[LuaValue]>(&module, &mut machine)?;
+    assert!(res.is_empty(), "module returned {res:?}");
+    Ok(())
+}
+
+#[test]
+fn tail_calls_return_values_of_the_callee() -> Result<(), LuaError> {
+    let module = lua_parser::module(
+        "function mult(a)
+                return a, a + 1
+            end
+            function even(n)
+                if n == 0 then
+                    return 'even'
+                end
+                return odd(n - 1)
+            end
+            function odd(n)
+                if n == 0 then
+                    return 'odd'
+                end
+                return even(n - 1)
+            end
+            function native(a)
+                return myfn(a)
+            end
+            function dynamic(f, a)
+                return f(a)
+            end
+            return even(100001), native(42), dynamic(mult, 1)",
+    )?;
+    let mut machine = Machine::new();
+    let myfn = NativeFunction::new(|value: LuaValue| value);
+    machine
+        .global_values
+        .set("myfn", LuaValue::native_function(myfn));
+    let res = eval_module::<&[LuaValue]>(&module, &mut machine)?;
+    let expected = [
+        LuaValue::string("odd"),
+        LuaValue::int(42),
+        LuaValue::int(1),
+        LuaValue::int(2),
+    ];
+    assert_eq!(res, expected);
+    Ok(())
+}
+
+#[test]
+fn calls_are_not_limited_by_the_argument_register_count() -> Result<(), LuaError> {
+    let args = (1..=150).map(|arg| arg.to_string()).join(", ");
+    let params = (1..=150).map(|arg| format!("a{arg}")).join(", ");
+    let source = format!(
+        "function sum({params})
+            return a1 + a75 + a150
+        end
+        return count({args}), sum({args})"
+    );
+    let module = lua_parser::module(&source)?;
+    let mut machine = Machine::new();
+    let count = NativeFunction::new(|args: &[LuaValue]| LuaValue::int(args.len() as i32));
+    machine
+        .global_values
+        .set("count", LuaValue::native_function(count));
+    let Strict((count, sum)) =
+        eval_module::<Strict<(&LuaValue, &LuaValue)>>(&module, &mut machine)?;
+    assert_eq!(count, &LuaValue::int(150));
+    assert_eq!(sum, &LuaValue::int(226));
+    Ok(())
+}
+
+#[test]
+fn multiple_return_is_not_limited_by_the_argument_register_count() -> Result<(), LuaError> {
+    let values = (1..=120).map(|value| value.to_string()).join(", ");
+    let source = format!(
+        "function many()
+            return {values}
+        end
+        function shifted()
+            return -1, 0, many()
+        end
+        return shifted()"
+    );
+    let module = lua_parser::module(&source)?;
+    let mut machine = Machine::new();
+    let res = eval_module::<&[LuaValue]>(&module, &mut machine)?;
+    assert!(res.iter().map(LuaValue::unwrap_int).eq(-1..=120));
+    Ok(())
+}
+
+
+fn reentrant_machine() -> Machine {
+    let mut machine = Machine::with_stdlib();
+    let foreach = NativeFunction::reentrant(|machine: &mut Machine, args: &[LuaValue]| {
+        let table = args[0].as_table().unwrap();
+        let mut index = 1;
+        loop {
+            let value = table.get(&LuaKey::Int(index));
+            if value == LuaValue::NIL {
+                return Ok::<_, EvalError>(());
+            }
+            call_value::<()>(&args[1], &[LuaValue::int(index), value], machine)?;
+            index += 1;
+        }
+    });
+    let protect = NativeFunction::reentrant(|machine: &mut Machine, args: &[LuaValue]| {
+        match call_value::<()>(&args[0], &args[1..], machine) {
+            Ok(()) => LuaValue::int(1),
+            Err(_) => LuaValue::NIL,
+        }
+    });
+    machine
+        .global_values
+        .set("foreach", LuaValue::native_function(foreach));
+    machine
+        .global_values
+        .set("protect", LuaValue::native_function(protect));
+    machine
+}
+
+#[test]
+fn native_functions_call_back_into_lua() -> Result<(), LuaError> {
+    let mut machine = reentrant_machine();
+    let res: LuaValue = eval_str(
+        "sum = 0
+        function add(index, value)
+            sum = sum + index * value
+        end
+        local before = 100
+        foreach({10, 20, 30}, add)
+        return before + sum",
+        &mut machine,
+    )?;
+    assert_eq!(res, LuaValue::int(240));
+    Ok(())
+}
+
+#[test]
+fn native_functions_sort_with_lua_comparator() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let sort = NativeFunction::reentrant(|machine: &mut Machine, args: &[LuaValue]| {
+        let mut table = args[0].as_table().unwrap();
+        let mut values: Vec<_> = (1..)
+            .map(|index| table.get(&LuaKey::Int(index)))
+            .take_while(|value| *value != LuaValue::NIL)
+            .collect();
+        // Insertion sort, since sort_by can't fail
+        for sorted in 1..values.len() {
+            let mut index = sorted;
+            while index > 0 {
+                let pair = [values[index].clone(), values[index - 1].clone()];
+                let less: LuaValue = call_value(&args[1], &pair, machine)?;
+                if less.is_falsy() {
+                    break;
+                }
+                values.swap(index, index - 1);
+                index -= 1;
+            }
+        }
+        for (index, value) in (1..).zip(values) {
+            table.set(LuaKey::Int(index), value);
+        }
+        Ok::<_, EvalError>(())
+    });
+    machine
+        .global_values
+        .set("sort", LuaValue::native_function(sort));
+    let res = eval_str::<&[LuaValue]>(
+        "function descending(a, b) return a > b end
+        local t = {3, 1, 4, 1, 5, 9, 2, 6}
+        sort(t, descending)
+        return t[1], t[2], t[3], t[8]",
+        &mut machine,
+    )?;
+    assert!(res.iter().map(LuaValue::unwrap_int).eq([9, 6, 5, 1]));
+    Ok(())
+}
+
+#[test]
+fn nested_calls_keep_the_frames_of_the_callers() -> Result<(), LuaError> {
+    let mut machine = reentrant_machine();
+    let res: LuaValue = eval_str(
+        "function inner(index, value)
+            local scaled = value * 10
+            total = total + scaled + index
+        end
+        function outer(index, row)
+            local before = total
+            foreach(row, inner)
+            total = total + (total - before)
+        end
+        total = 0
+        local local_before = 7
+        foreach({{1, 2}, {3}}, outer)
+        return total + local_before",
+        &mut machine,
+    )?;
+    // Rows add 10 + 1 + 20 + 2 and 30 + 1, each doubled
+    assert_eq!(res, LuaValue::int(2 * 33 + 2 * 31 + 7));
+    Ok(())
+}
+
+#[test]
+fn native_functions_cannot_replace_the_running_machine() {
+    let mut machine = Machine::with_stdlib();
+    let replace = NativeFunction::reentrant(|machine: &mut Machine, _: &[LuaValue]| {
+        // Frames of the running machine can't be dropped, same as if its stack was forgotten
+        std::mem::forget(std::mem::replace(machine, Machine::with_stdlib()));
+    });
+    machine
+        .global_values
+        .set("replace", LuaValue::native_function(replace));
+    let res = eval_str::<LuaValue>("local x = 1 replace() return x", &mut machine);
+    assert!(matches!(
+        res,
+        Err(LuaError::Eval(EvalError::CorruptedCallStack))
+    ));
+}
+
+#[test]
+fn errors_of_callbacks_unwind_only_the_nested_frames() -> Result<(), LuaError> {
+    let mut machine = reentrant_machine();
+    let res = eval_str::<&[LuaValue]>(
+        "function fails(a) local b = 2 return a + {} end
+        function works(a) local b = 2 return a + b end
+        local x, y = 'x', 'y'
+        return protect(fails, 1), protect(works, 1), x, y",
+        &mut machine,
+    )?;
+    assert_eq!(
+        res,
+        [
+            LuaValue::NIL,
+            LuaValue::int(1),
+            LuaValue::string("x"),
+            LuaValue::string("y")
+        ]
+    );
+
+    let res = eval_str::<()>("foreach({1}, fails)", &mut machine);
+    assert!(matches!(
+        res,
+        Err(LuaError::Eval(EvalError::TypeError(err))) if matches!(*err, TypeError::Arithmetic(_))
+    ));
+    assert!(!machine.is_running());
+    let res: LuaValue = eval_str("return works(40)", &mut machine)?;
+    assert_eq!(res, LuaValue::int(42));
+    Ok(())
+}
+
+#[test]
+fn host_calls_function_values() -> Result<(), LuaError> {
+    let mut machine = reentrant_machine();
+    eval_str::<()>("function add(a, b) return a + b end", &mut machine)?;
+    let add = machine.global_values.get("add").clone();
+    let Strict(res) = call_value::<Strict<&LuaValue>>(
+        &add,
+        &[LuaValue::int(1), LuaValue::int(2)],
+        &mut machine,
+    )?;
+    assert_eq!(res, &LuaValue::int(3));
+    let floor = machine.global_values.get("floor").clone();
+    let res: LuaValue = call_value(&floor, &[LuaValue::float(2.5)], &mut machine)?;
+    assert_eq!(res, LuaValue::int(2));
+    let res = call_value::<()>(&LuaValue::int(1), &[], &mut machine);
+    assert!(matches!(res, Err(EvalError::TypeError(_))));
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use reggie::{eval_str, EvalError, LuaError, LuaValue, Machine};
+
+#[test]
+fn values_round_trip_through_json() -> Result<(), LuaError> {
+    let mut machine = Machine::with_stdlib();
+    let res: (String, i32, f64, String, i32, LuaValue) = eval_str(
+        "
+        local encoded = json_encode({ 1, 2.5, 'three' })
+        local decoded = json_decode('{ \"name\": \"lua\", \"ok\": true, \"no\": false, \"items\": [10, null] }')
+        return encoded, decoded.items[1], json_decode('2.0'), decoded.name, decoded.ok, decoded.no
+        ",
+        &mut machine,
+    )?;
+    assert_eq!(
+        res,
+        (
+            r#"[1,2.5,"three"]"#.to_owned(),
+            10,
+            2.0,
+            "lua".to_owned(),
+            1,
+            LuaValue::NIL
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn tables_with_a_hash_part_are_encoded_as_objects() -> Result<(), LuaError> {
+    let mut machine = Machine::with_stdlib();
+    let res: String = eval_str(
+        "
+        local t = { 1, 2 }
+        t.name = 'x'
+        return json_encode(t)
+        ",
+        &mut machine,
+    )?;
+    let json: serde_json::Value = serde_json::from_str(&res).unwrap();
+    assert_eq!(json, serde_json::json!({ "1": 1, "2": 2, "name": "x" }));
+    Ok(())
+}
+
+#[test]
+fn invalid_json_is_reported_with_its_position() {
+    let mut machine = Machine::with_stdlib();
+    let res = eval_str::<LuaValue>("return json_decode('[1,\\n 2,,]')", &mut machine);
+    match res {
+        Err(LuaError::Eval(EvalError::JsonDecode { line, column, .. })) => {
+            assert_eq!((line, column), (2, 4));
+        }
+        res => panic!("expected a json error, got {:?}", res),
+    }
+}
+
+#[test]
+fn cycles_and_functions_can_not_be_encoded() {
+    let mut machine = Machine::with_stdlib();
+    let res = eval_str::<LuaValue>("local t = {} t.t = t return json_encode(t)", &mut machine);
+    assert!(matches!(res, Err(LuaError::Eval(EvalError::JsonEncode(_)))));
+    let res = eval_str::<LuaValue>("return json_encode({ print })", &mut machine);
+    assert!(matches!(res, Err(LuaError::Eval(EvalError::JsonEncode(_)))));
+}
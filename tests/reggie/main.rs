@@ -2,15 +2,17 @@
 extern crate quickcheck_macros;
 
 use luar_lex::{NumberLiteral, StringLiteral, Token};
-use luar_string::lua_format;
 use quickcheck::TestResult;
 use reggie::{eval_module, eval_str, value::Strict, LuaError, LuaValue, Machine};
 
 mod assignment;
 mod boolean_ops;
 mod comparison;
+mod derive;
 mod function;
+mod json;
 mod local_decl;
+mod serde_values;
 mod table;
 mod table_constructor;
 mod unary_op;
@@ -37,7 +39,7 @@ fn eval_nil() -> Result<(), LuaError> {
     let mut machine = Machine::new();
     assert_eq!(
         eval_str::<Strict<LuaValue>>("return nil", &mut machine)?.0,
-        LuaValue::Nil
+        LuaValue::NIL
     );
     Ok(())
 }
@@ -77,7 +79,7 @@ fn eval_string_literal(str: String) -> Result<(), LuaError> {
 
 #[quickcheck]
 fn value_is_equal_to_itself(value: LuaValue) -> Result<TestResult, LuaError> {
-    if let LuaValue::Float(num) = value {
+    if let Some(num) = value.as_float() {
         if num.is_nan() {
             // NaN does not equal itself
             return Ok(TestResult::discard());
@@ -86,8 +88,8 @@ fn value_is_equal_to_itself(value: LuaValue) -> Result<TestResult, LuaError> {
 
     let mut machine = Machine::new();
     machine.global_values.set("value", value);
-    let res = eval_str("return value == value", &mut machine)?;
-    assert_eq!(LuaValue::true_value(), res);
+    let res = eval_str::<LuaValue>("return value == value", &mut machine)?;
+    assert_eq!(LuaValue::TRUE, res);
     Ok(TestResult::passed())
 }
 
@@ -97,7 +99,7 @@ fn different_values_do_not_equal_themselves(lhs: LuaValue, rhs: LuaValue) -> Res
     let mut machine = Machine::new();
     machine.global_values.set("lhs", lhs);
     machine.global_values.set("rhs", rhs);
-    let res = eval_str("return lhs == rhs", &mut machine)?;
+    let res = eval_str::<LuaValue>("return lhs == rhs", &mut machine)?;
     assert_eq!(expected, res);
     Ok(())
 }
@@ -107,8 +109,8 @@ fn not_equals_is_the_negation_of_equality(lhs: LuaValue, rhs: LuaValue) -> Resul
     let mut machine = Machine::new();
     machine.global_values.set("lhs", lhs);
     machine.global_values.set("rhs", rhs);
-    let res = eval_str("return (not (lhs ~= rhs)) == (lhs == rhs)", &mut machine)?;
-    assert_eq!(LuaValue::true_value(), res);
+    let res = eval_str::<LuaValue>("return (not (lhs ~= rhs)) == (lhs == rhs)", &mut machine)?;
+    assert_eq!(LuaValue::TRUE, res);
     Ok(())
 }
 
@@ -121,7 +123,7 @@ fn concat(lhs: LuaValue, rhs: LuaValue) {
     let res = eval_str::<LuaValue>("return lhs .. rhs", &mut machine);
     if let (Some(lhs), Some(rhs)) = (lhs.coerce_to_string(), rhs.coerce_to_string()) {
         let res = res.unwrap();
-        assert!(res.total_eq(&LuaValue::String(lua_format!("{lhs}{rhs}"))));
+        assert!(res.total_eq(&LuaValue::string(format!("{lhs}{rhs}"))));
     } else {
         assert!(res.is_err());
     }
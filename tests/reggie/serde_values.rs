@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use reggie::{eval_str, from_value, LuaError, LuaValue, Machine};
+use serde::Deserialize;
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Config {
+    name: String,
+    workers: u32,
+    ratio: f64,
+    #[serde(default)]
+    debug: bool,
+    tags: Vec<String>,
+    limits: HashMap<String, i64>,
+    database: Database,
+    log: Log,
+    backup: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Database {
+    url: String,
+    pool: Option<u16>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+enum Log {
+    Stdout,
+    File { path: String },
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+enum Chain {
+    Link(Box<Chain>),
+    End,
+}
+
+#[test]
+fn configs_are_read_out_of_scripts() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let value: LuaValue = eval_str(
+        "
+        local workers = 8
+        return {
+            name = 'service',
+            workers = workers / 2,
+            ratio = 1,
+            debug = nil,
+            tags = { 'a', 'b' },
+            limits = { requests = 100, bytes = 4096 },
+            database = { url = 'postgres://localhost' },
+            log = { File = { path = '/var/log/service.log' } },
+        }
+        ",
+        &mut machine,
+    )?;
+    let config: Config = from_value(&value).unwrap();
+    assert_eq!(
+        config,
+        Config {
+            name: "service".to_owned(),
+            workers: 4,
+            ratio: 1.0,
+            debug: false,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            limits: HashMap::from([("requests".to_owned(), 100), ("bytes".to_owned(), 4096)]),
+            database: Database {
+                url: "postgres://localhost".to_owned(),
+                pool: None,
+            },
+            log: Log::File {
+                path: "/var/log/service.log".to_owned()
+            },
+            backup: None,
+        }
+    );
+
+    let log: Log = from_value(&LuaValue::string("Stdout")).unwrap();
+    assert_eq!(log, Log::Stdout);
+    Ok(())
+}
+
+#[test]
+fn mismatched_values_are_errors() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let value: LuaValue = eval_str("return { url = 42 }", &mut machine)?;
+    let err = from_value::<Database>(&value).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid type: integer `42`, expected a string"
+    );
+    assert!(from_value::<u8>(&LuaValue::float(1.5)).is_err());
+    assert!(from_value::<u8>(&LuaValue::int(300)).is_err());
+    Ok(())
+}
+
+#[test]
+fn tables_serialize_into_arrays_and_objects() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let value: LuaValue = eval_str(
+        "
+        local mixed = { 10, 20 }
+        mixed.n = 2
+        return { list = { 1, 2.5, 'x' }, mixed = mixed, empty = {} }
+        ",
+        &mut machine,
+    )?;
+    let json = serde_json::to_value(&value).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "list": [1, 2.5, "x"],
+            "mixed": { "1": 10, "2": 20, "n": 2 },
+            "empty": [],
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn values_deserialize_from_json() -> Result<(), LuaError> {
+    let value: LuaValue =
+        serde_json::from_str(r#"{ "items": [1, 2.5, "x"], "big": 10000000000 }"#).unwrap();
+    let mut machine = Machine::new();
+    machine.set_global("value", value);
+    let res: (i32, f64, String, f64) = eval_str(
+        "return value.items[1], value.items[2], value.items[3], value.big",
+        &mut machine,
+    )?;
+    assert_eq!(res, (1, 2.5, "x".to_owned(), 1e10));
+    Ok(())
+}
+
+#[test]
+fn recursive_tables_and_functions_are_errors() -> Result<(), LuaError> {
+    let mut machine = Machine::new();
+    let value: LuaValue = eval_str(
+        "local t = { shared = {} } t.also = t.shared t.self = t return t",
+        &mut machine,
+    )?;
+    let err = serde_json::to_string(&value).unwrap_err();
+    assert!(err.to_string().contains("recursive"));
+    let err = from_value::<LuaValue>(&value).unwrap_err();
+    assert!(err.to_string().contains("recursive"));
+
+    let value: LuaValue = eval_str("local t = {} t.Link = t return t", &mut machine)?;
+    let err = from_value::<Chain>(&value).unwrap_err();
+    assert!(err.to_string().contains("recursive"));
+    let value: LuaValue = eval_str("return { Link = { Link = 'End' } }", &mut machine)?;
+    assert_eq!(
+        from_value::<Chain>(&value).unwrap(),
+        Chain::Link(Box::new(Chain::Link(Box::new(Chain::End))))
+    );
+
+    let value: LuaValue = eval_str(
+        "local t = { shared = {} } t.also = t.shared return t",
+        &mut machine,
+    )?;
+    assert_eq!(
+        serde_json::to_value(&value).unwrap(),
+        serde_json::json!({ "shared": [], "also": [] })
+    );
+
+    let value: LuaValue = eval_str("function f() end return { f = f }", &mut machine)?;
+    assert!(serde_json::to_string(&value).is_err());
+    Ok(())
+}
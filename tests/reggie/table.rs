@@ -1,7 +1,7 @@
 use luar_lex::Ident;
 use luar_syn::lua_parser;
 use quickcheck::TestResult;
-use reggie::{assert_type_error, eval_module, LuaValue, Machine, TypeError};
+use reggie::{assert_type_error, eval_module, EvalError, LuaValue, Machine, TypeError};
 
 #[quickcheck]
 fn accessing_non_table_property_is_an_error(value: LuaValue, property: Ident) -> TestResult {
@@ -27,13 +27,13 @@ fn accessing_non_table_member_is_an_error(value: LuaValue) -> TestResult {
     let mut machine = Machine::new();
     machine.global_values.set("value", value);
     let res = eval_module::<()>(&module, &mut machine);
-    assert_type_error!(
-        TypeError::CannotAccessMember {
-            member: LuaValue::Int(42),
-            ..
-        },
-        res
-    );
+    let Err(EvalError::TypeError(err)) = res else {
+        panic!("Unexpected result type");
+    };
+    let TypeError::CannotAccessMember { member, .. } = err.as_ref() else {
+        panic!("Unexpected result type");
+    };
+    assert_eq!(member, &LuaValue::int(42));
     TestResult::passed()
 }
 
@@ -61,13 +61,13 @@ fn assigning_to_a_non_table_member_is_an_error(value: LuaValue) -> TestResult {
     machine.global_values.set("value", value);
     let module = lua_parser::module("value[42] = 69").unwrap();
     let res = eval_module::<()>(&module, &mut machine);
-    assert_type_error!(
-        TypeError::CannotAssignMember {
-            member: LuaValue::Int(42),
-            ..
-        },
-        res
-    );
+    let Err(EvalError::TypeError(err)) = res else {
+        panic!("Unexpected result type");
+    };
+    let TypeError::CannotAssignMember { member, .. } = err.as_ref() else {
+        panic!("Unexpected result type");
+    };
+    assert_eq!(member, &LuaValue::int(42));
     TestResult::passed()
 }
 
@@ -76,7 +76,13 @@ fn assigning_to_nil_member_is_an_error() {
     let mut machine = Machine::new();
     let module = lua_parser::module("local tbl = {} tbl[nil] = 42").unwrap();
     let res = eval_module::<()>(&module, &mut machine);
-    assert_type_error!(TypeError::NilAssign(LuaValue::Int(42)), res);
+    let Err(EvalError::TypeError(err)) = res else {
+        panic!("Unexpected result type");
+    };
+    let TypeError::NilAssign(value) = err.as_ref() else {
+        panic!("Unexpected result type");
+    };
+    assert_eq!(value, &LuaValue::int(42));
 }
 
 #[test]
@@ -84,5 +90,38 @@ fn assigning_to_a_nan_member_is_an_error() {
     let mut machine = Machine::new();
     let module = lua_parser::module("local tbl, nan = {}, 0/0 tbl[nan] = 42").unwrap();
     let res = eval_module::<()>(&module, &mut machine);
-    assert_type_error!(TypeError::NaNAssign(LuaValue::Int(42)), res);
+    let Err(EvalError::TypeError(err)) = res else {
+        panic!("Unexpected result type");
+    };
+    let TypeError::NaNAssign(value) = err.as_ref() else {
+        panic!("Unexpected result type");
+    };
+    assert_eq!(value, &LuaValue::int(42));
+}
+
+#[test]
+fn property_access_is_correct_for_tables_of_different_layouts() {
+    let mut machine = Machine::new();
+    let module = lua_parser::module(
+        "function get_x(point)
+            return point.x
+        end
+        function set_x(point, x)
+            point.x = x
+        end
+        local a, b, c = {x = 1, y = 2}, {y = 3, x = 4}, {y = 5}
+        set_x(c, 6)
+        set_x(a, 7)
+        return get_x(a), get_x(b), get_x(c), get_x({}), get_x(a)",
+    )
+    .unwrap();
+    let res = eval_module::<&[LuaValue]>(&module, &mut machine).unwrap();
+    let expected = [
+        LuaValue::int(7),
+        LuaValue::int(4),
+        LuaValue::int(6),
+        LuaValue::NIL,
+        LuaValue::int(7),
+    ];
+    assert_eq!(res, expected);
 }
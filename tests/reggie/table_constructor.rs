@@ -9,7 +9,7 @@ fn empty_table_constructor_creates_empty_table() -> Result<(), LuaError> {
     let mut machine = Machine::new();
     let Strict(res) = eval_module::<Strict<LuaValue>>(&module, &mut machine)?;
     assert!(res.is_table());
-    assert!(res.unwrap_table().is_empty());
+    assert!(res.as_table().unwrap().is_empty());
     Ok(())
 }
 
@@ -47,7 +47,7 @@ fn list_table_constructor_creates_table_with_integer_indices_from_one_upwards(
     }
     drop(machine);
 
-    assert!(res.unwrap_table().unwrap_or_clone().total_eq(&expected));
+    assert!(res.as_table().unwrap().unwrap_or_clone().total_eq(&expected));
 
     Ok(())
 }
@@ -95,7 +95,7 @@ fn key_value_pairs_constructor_creates_table_with_corresponding_key_value_associ
     }
     drop(machine);
 
-    assert!(res.unwrap_table().unwrap_or_clone().total_eq(&expected));
+    assert!(res.as_table().unwrap().unwrap_or_clone().total_eq(&expected));
 
     Ok(())
 }